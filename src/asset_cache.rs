@@ -0,0 +1,286 @@
+//! [Cache Storage](https://developer.mozilla.org/en-US/docs/Web/API/CacheStorage) interop
+//!
+//! Features required: `cache-storage`
+//!
+//! Service worker asset caches almost always need bookkeeping the Cache API doesn't offer, such
+//! as when an asset was last used. An [AssetCache] pairs a named cache with an object store
+//! holding an [AssetMeta] record per cached URL and keeps the two consistent.
+//!
+//! Cache Storage operations are promise-based and would let an IndexedDB transaction auto-commit
+//! if awaited in the middle of it, so every metadata update runs in its own short transaction.
+
+use std::collections::HashSet;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{Cache, CacheStorage, DomException, IdbTransactionMode, Request, Response};
+
+use crate::clock::{Clock, SharedClock};
+use crate::idb_database::IdbDatabase;
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::{await_promise, js_error_into_dom_exception};
+
+/// Metadata tracked for every cached asset
+///
+/// Features required: `cache-storage`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssetMeta {
+    /// The absolute URL of the cached request
+    pub url: String,
+    /// The response's `ETag` header
+    pub etag: Option<String>,
+    /// The response's `Content-Length` header
+    pub size: Option<f64>,
+    /// When the asset was last stored or retrieved, in milliseconds since the epoch
+    pub last_used: f64,
+}
+
+/// Result of an [AssetCache::sweep]
+///
+/// Features required: `cache-storage`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SweepReport {
+    /// Metadata records removed because their cache entry no longer exists
+    pub removed_metadata: u32,
+    /// Cache entries removed because they had no metadata record
+    pub removed_cache_entries: u32,
+}
+
+/// A Cache Storage cache paired with an IndexedDB metadata store. The store must use
+/// out-of-line keys; records are keyed by absolute URL.
+///
+/// Features required: `cache-storage`
+#[derive(Debug)]
+pub struct AssetCache<'a> {
+    db: &'a IdbDatabase,
+    store_name: String,
+    cache_name: String,
+    clock: SharedClock,
+}
+
+impl<'a> AssetCache<'a> {
+    /// Pair the given Cache Storage cache with the given object store
+    pub fn new(db: &'a IdbDatabase, store_name: &str, cache_name: &str) -> Self {
+        Self {
+            db,
+            store_name: store_name.into(),
+            cache_name: cache_name.into(),
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Read last used times from the given clock instead of the system time
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// Store the response in the cache and record its metadata
+    pub async fn put(&self, url: &str, response: &Response) -> Result<AssetMeta, DomException> {
+        let url = absolute_url(url)?;
+        let headers = response.headers();
+        let meta = AssetMeta {
+            etag: headers.get("ETag")?,
+            size: headers
+                .get("Content-Length")?
+                .and_then(|v| v.trim().parse().ok()),
+            last_used: self.clock.now(),
+            url,
+        };
+
+        await_promise(self.open_cache().await?.put_with_str(&meta.url, response)).await?;
+        self.write_meta(&meta).await?;
+
+        Ok(meta)
+    }
+
+    /// Get the cached response for the given URL, bumping its last used time. Returns `None` if
+    /// the URL isn't cached.
+    pub async fn get(&self, url: &str) -> Result<Option<Response>, DomException> {
+        let url = absolute_url(url)?;
+        let matched = await_promise(self.open_cache().await?.match_with_str(&url)).await?;
+        if matched.is_undefined() {
+            return Ok(None);
+        }
+
+        let mut meta = self.metadata(&url).await?.unwrap_or(AssetMeta {
+            url,
+            etag: None,
+            size: None,
+            last_used: 0.0,
+        });
+        meta.last_used = self.clock.now();
+        self.write_meta(&meta).await?;
+
+        Ok(Some(matched.unchecked_into()))
+    }
+
+    /// Remove the URL from both the cache and the metadata store
+    pub async fn delete(&self, url: &str) -> Result<(), DomException> {
+        let url = absolute_url(url)?;
+        await_promise(self.open_cache().await?.delete_with_str(&url)).await?;
+
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        tx.object_store(&self.store_name)?
+            .delete_owned(url.as_str())?;
+        tx.await.into_result()
+    }
+
+    /// Get the metadata record for the given URL
+    pub async fn metadata(&self, url: &str) -> Result<Option<AssetMeta>, DomException> {
+        let url = absolute_url(url)?;
+        let tx = self.db.transaction_on_one(&self.store_name)?;
+        let store = tx.object_store(&self.store_name)?;
+        let raw = store.get_owned(url.as_str())?.await?;
+        Ok(raw.as_ref().and_then(AssetMeta::from_js))
+    }
+
+    /// Get all metadata records
+    pub async fn all_metadata(&self) -> Result<Vec<AssetMeta>, DomException> {
+        let tx = self.db.transaction_on_one(&self.store_name)?;
+        let store = tx.object_store(&self.store_name)?;
+        let raw = store.get_all()?.await?;
+        Ok(raw.iter().filter_map(|v| AssetMeta::from_js(&v)).collect())
+    }
+
+    /// Remove metadata records whose cache entry has disappeared (e.g. through eviction or
+    /// another script clearing the cache) and cache entries without a metadata record.
+    pub async fn sweep(&self) -> Result<SweepReport, DomException> {
+        let cache = self.open_cache().await?;
+        let cached: js_sys::Array = await_promise(cache.keys()).await?.unchecked_into();
+        let cached: HashSet<String> = cached
+            .iter()
+            .map(|req| req.unchecked_into::<Request>().url())
+            .collect();
+
+        let tx = self.db.transaction_on_one(&self.store_name)?;
+        let recorded: HashSet<String> = tx
+            .object_store(&self.store_name)?
+            .get_all_keys()?
+            .await?
+            .iter()
+            .filter_map(|k| k.as_string())
+            .collect();
+        drop(tx);
+
+        let mut report = SweepReport::default();
+
+        let orphaned_meta: Vec<&String> = recorded.difference(&cached).collect();
+        if !orphaned_meta.is_empty() {
+            let tx = self
+                .db
+                .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+            let store = tx.object_store(&self.store_name)?;
+            for url in orphaned_meta.iter() {
+                store.delete_owned(url.as_str())?;
+            }
+            tx.await.into_result()?;
+            report.removed_metadata = orphaned_meta.len() as u32;
+        }
+
+        for url in cached.difference(&recorded) {
+            await_promise(cache.delete_with_str(url)).await?;
+            report.removed_cache_entries += 1;
+        }
+
+        Ok(report)
+    }
+
+    async fn open_cache(&self) -> Result<Cache, DomException> {
+        let caches: CacheStorage = js_sys::Reflect::get(&js_sys::global(), &"caches".into())
+            .map_err(js_error_into_dom_exception)?
+            .unchecked_into();
+        Ok(await_promise(caches.open(&self.cache_name))
+            .await?
+            .unchecked_into())
+    }
+
+    async fn write_meta(&self, meta: &AssetMeta) -> Result<(), DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        tx.object_store(&self.store_name)?
+            .put_key_val_owned(meta.url.as_str(), &meta.to_js())?;
+        tx.await.into_result()
+    }
+}
+
+impl AssetMeta {
+    fn to_js(&self) -> JsValue {
+        let obj = js_sys::Object::new();
+        let set = |k: &str, v: JsValue| js_sys::Reflect::set(&obj, &k.into(), &v);
+        let _ = set("url", self.url.as_str().into());
+        let _ = set(
+            "etag",
+            self.etag
+                .as_deref()
+                .map(JsValue::from)
+                .unwrap_or(JsValue::NULL),
+        );
+        let _ = set(
+            "size",
+            self.size.map(JsValue::from).unwrap_or(JsValue::NULL),
+        );
+        let _ = set("lastUsed", self.last_used.into());
+        obj.into()
+    }
+
+    fn from_js(raw: &JsValue) -> Option<Self> {
+        let get = |k: &str| js_sys::Reflect::get(raw, &k.into()).ok();
+        Some(Self {
+            url: get("url")?.as_string()?,
+            etag: get("etag")?.as_string(),
+            size: get("size")?.as_f64(),
+            last_used: get("lastUsed")?.as_f64()?,
+        })
+    }
+}
+
+/// Resolve the URL the same way the Cache API does so that metadata keys line up with cache keys
+fn absolute_url(url: &str) -> Result<String, DomException> {
+    let req = Request::new_with_str(url).map_err(js_error_into_dom_exception)?;
+    Ok(req.url())
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::test_utils::{open_any_db, unique_name};
+
+    use super::*;
+
+    test_mod_init!();
+
+    fn response(body: &str, etag: &str) -> Response {
+        let mut init = web_sys::ResponseInit::new();
+        let headers = web_sys::Headers::new().unwrap();
+        headers.set("ETag", etag).unwrap();
+        headers
+            .set("Content-Length", &body.len().to_string())
+            .unwrap();
+        init.headers(&headers);
+        Response::new_with_opt_str_and_init(Some(body), &init).unwrap()
+    }
+
+    test_case!(async put_get_sweep => {
+        let (db, store_name) = open_any_db().await;
+        let cache_name = unique_name();
+        let assets = AssetCache::new(&db, &store_name, &cache_name);
+
+        let meta = assets.put("/foo.js", &response("foo", "\"v1\"")).await.expect("put");
+        assert_eq!(meta.etag.as_deref(), Some("\"v1\""), "etag");
+        assert_eq!(meta.size, Some(3.0), "size");
+        assert!(assets.get("/foo.js").await.expect("get").is_some(), "get");
+        assert!(assets.get("/missing.js").await.expect("get missing").is_none(), "get missing");
+
+        // Orphaned metadata
+        let orphan = AssetMeta { url: absolute_url("/gone.js").unwrap(), ..meta.clone() };
+        assets.write_meta(&orphan).await.expect("write orphan");
+
+        let report = assets.sweep().await.expect("sweep");
+        let remaining: Vec<String> = assets.all_metadata().await.expect("all").into_iter().map(|m| m.url).collect();
+
+        assert_eq!(report, SweepReport { removed_metadata: 1, removed_cache_entries: 0 }, "report");
+        assert_eq!(remaining, vec![meta.url], "remaining");
+    });
+}
@@ -0,0 +1,300 @@
+//! Blob attachments linked to parent records
+//!
+//! Large [Blob]s mixed into document records slow down every read of those records, so
+//! [Attachments] keeps them in a dedicated store instead. Each attachment is a
+//! `{parent, name, blob, size, content_type}` record keyed by `[parent key, name]`, so listing a
+//! parent's attachments is a key range scan and one store can serve any number of parent stores
+//! as long as their keys don't collide.
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::attachments::Attachments;
+//! use web_sys::DomException;
+//!
+//! async fn example(photo: &web_sys::Blob) -> Result<(), DomException> {
+//!     let mut req = IdbDatabase::open("notes")?;
+//!     req.set_on_upgrade_needed(Some(|evt: &IdbVersionChangeEvent| {
+//!         evt.db().create_object_store("notes")?;
+//!         Ok(Attachments::create_store(evt.db(), "attachments")?)
+//!     }));
+//!     let db = req.into_future().await?;
+//!
+//!     let attachments = Attachments::new(&db, "attachments");
+//!     attachments.put(&1.into(), "photo.jpg", photo).await?;
+//!     let _bytes: f64 = attachments.size(&1.into()).await?;
+//!     attachments.delete_parent("notes", &1.into()).await?;
+//!     Ok(())
+//! }
+//! ```
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{Blob, DomException, IdbKeyRange, IdbTransactionMode};
+
+use crate::idb_database::IdbDatabase;
+use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::dom_exception;
+
+/// A stored attachment
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    /// The key of the record it's attached to
+    pub parent: JsValue,
+    /// Its name, unique per parent
+    pub name: String,
+    /// Its contents
+    pub blob: Blob,
+    /// Its size in bytes
+    pub size: f64,
+    /// Its MIME type; empty if unknown
+    pub content_type: String,
+}
+
+impl Attachment {
+    fn from_record(record: &JsValue) -> Result<Self, DomException> {
+        let get = |field: &str| js_sys::Reflect::get(record, &field.into());
+        Ok(Self {
+            parent: get("parent")?,
+            name: get("name")?.as_string().unwrap_or_default(),
+            blob: get("blob")?
+                .dyn_into()
+                .map_err(|_| dom_exception("Attachment record has no blob", "DataError"))?,
+            size: get("size")?.as_f64().unwrap_or(0.0),
+            content_type: get("content_type")?.as_string().unwrap_or_default(),
+        })
+    }
+}
+
+/// A store of blob attachments. See the [module docs][self].
+#[derive(Debug)]
+pub struct Attachments<'a> {
+    db: &'a IdbDatabase,
+    store_name: String,
+}
+
+impl<'a> Attachments<'a> {
+    /// Use the given attachment store, which must use out-of-line keys
+    pub fn new(db: &'a IdbDatabase, store_name: &str) -> Self {
+        Self {
+            db,
+            store_name: store_name.into(),
+        }
+    }
+
+    /// Create the attachment store. Must be called during an upgrade.
+    pub fn create_store(db: &IdbDatabase, store_name: &str) -> Result<(), DomException> {
+        db.create_object_store(store_name)?;
+        Ok(())
+    }
+
+    /// Attach the blob to the parent under the given name, replacing any attachment of the same
+    /// name
+    pub async fn put(&self, parent: &JsValue, name: &str, blob: &Blob) -> Result<(), DomException> {
+        let record = js_sys::Object::new();
+        js_sys::Reflect::set(&record, &"parent".into(), parent)?;
+        js_sys::Reflect::set(&record, &"name".into(), &name.into())?;
+        js_sys::Reflect::set(&record, &"blob".into(), blob)?;
+        js_sys::Reflect::set(&record, &"size".into(), &blob.size().into())?;
+        js_sys::Reflect::set(&record, &"content_type".into(), &blob.type_().into())?;
+
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        tx.object_store(&self.store_name)?
+            .put_key_val(&attachment_key(parent, name), &record)?;
+        tx.await.into_result()
+    }
+
+    /// The parent's attachment with the given name
+    pub async fn get(
+        &self,
+        parent: &JsValue,
+        name: &str,
+    ) -> Result<Option<Attachment>, DomException> {
+        let tx = self.db.transaction_on_one(&self.store_name)?;
+        let record = tx
+            .object_store(&self.store_name)?
+            .get(&attachment_key(parent, name))?
+            .await?;
+        record.as_ref().map(Attachment::from_record).transpose()
+    }
+
+    /// The parent's attachments, ordered by name
+    pub async fn list(&self, parent: &JsValue) -> Result<Vec<Attachment>, DomException> {
+        let tx = self.db.transaction_on_one(&self.store_name)?;
+        let records = tx
+            .object_store(&self.store_name)?
+            .get_all_with_key(&parent_range(parent)?)?
+            .await?;
+        records
+            .iter()
+            .map(|r| Attachment::from_record(&r))
+            .collect()
+    }
+
+    /// The names of the parent's attachments, in order, without loading the attachments
+    pub async fn names(&self, parent: &JsValue) -> Result<Vec<String>, DomException> {
+        let tx = self.db.transaction_on_one(&self.store_name)?;
+        let keys = tx
+            .object_store(&self.store_name)?
+            .get_all_keys_with_key(&parent_range(parent)?)?
+            .await?;
+        Ok(keys
+            .iter()
+            .filter_map(|k| k.unchecked_ref::<js_sys::Array>().get(1).as_string())
+            .collect())
+    }
+
+    /// Remove the parent's attachment with the given name, if there is one
+    pub async fn remove(&self, parent: &JsValue, name: &str) -> Result<(), DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        tx.object_store(&self.store_name)?
+            .delete(&attachment_key(parent, name))?;
+        tx.await.into_result()
+    }
+
+    /// Remove all of the parent's attachments. Resolves to the number removed.
+    pub async fn remove_all(&self, parent: &JsValue) -> Result<u32, DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        let removed = remove_attachments(&tx.object_store(&self.store_name)?, parent).await?;
+        tx.await.into_result()?;
+        Ok(removed)
+    }
+
+    /// Delete the parent record from its store together with all of its attachments, in one
+    /// transaction. Resolves to the number of attachments removed.
+    pub async fn delete_parent(
+        &self,
+        parent_store: &str,
+        parent: &JsValue,
+    ) -> Result<u32, DomException> {
+        let tx = self.db.transaction_on_multi_with_mode(
+            &[parent_store, &self.store_name],
+            IdbTransactionMode::Readwrite,
+        )?;
+        tx.object_store(parent_store)?.delete(parent)?;
+        let removed = remove_attachments(&tx.object_store(&self.store_name)?, parent).await?;
+        tx.await.into_result()?;
+        Ok(removed)
+    }
+
+    /// The total size of the parent's attachments in bytes
+    pub async fn size(&self, parent: &JsValue) -> Result<f64, DomException> {
+        let tx = self.db.transaction_on_one(&self.store_name)?;
+        let records = tx
+            .object_store(&self.store_name)?
+            .get_all_with_key(&parent_range(parent)?)?
+            .await?;
+        Ok(total_size(&records))
+    }
+
+    /// The total size of every attachment in the store in bytes
+    pub async fn total_size(&self) -> Result<f64, DomException> {
+        let tx = self.db.transaction_on_one(&self.store_name)?;
+        let records = tx.object_store(&self.store_name)?.get_all()?.await?;
+        Ok(total_size(&records))
+    }
+}
+
+fn attachment_key(parent: &JsValue, name: &str) -> JsValue {
+    js_sys::Array::of2(parent, &name.into()).into()
+}
+
+/// Every attachment of the parent. Arrays sort after strings, so `[parent, []]` follows every
+/// `[parent, name]` key.
+fn parent_range(parent: &JsValue) -> Result<IdbKeyRange, JsValue> {
+    IdbKeyRange::bound(
+        &js_sys::Array::of1(parent),
+        &js_sys::Array::of2(parent, &js_sys::Array::new()),
+    )
+}
+
+async fn remove_attachments(
+    store: &IdbObjectStore<'_>,
+    parent: &JsValue,
+) -> Result<u32, DomException> {
+    let range = parent_range(parent)?;
+    let count = store.count_with_key(&range)?.await?;
+    store.delete(&range)?;
+    Ok(count)
+}
+
+fn total_size(records: &js_sys::Array) -> f64 {
+    records
+        .iter()
+        .filter_map(|r| js_sys::Reflect::get(&r, &"size".into()).ok()?.as_f64())
+        .sum()
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::open_db_with;
+
+    use super::*;
+
+    test_mod_init!();
+
+    async fn open_attachment_db() -> IdbDatabase {
+        open_db_with(|evt: &IdbVersionChangeEvent| {
+            evt.db().create_object_store("notes")?;
+            Ok(Attachments::create_store(evt.db(), "attachments")?)
+        })
+        .await
+    }
+
+    fn blob(contents: &str) -> Blob {
+        Blob::new_with_str_sequence(&js_sys::Array::of1(&contents.into())).unwrap()
+    }
+
+    test_case!(async attach_and_list => {
+        let db = open_attachment_db().await;
+        let attachments = Attachments::new(&db, "attachments");
+        attachments.put(&1.into(), "b.txt", &blob("hello")).await.expect("put b");
+        attachments.put(&1.into(), "a.txt", &blob("hi")).await.expect("put a");
+        attachments.put(&2.into(), "c.txt", &blob("world!")).await.expect("put c");
+        attachments.put(&1.into(), "b.txt", &blob("hey")).await.expect("replace b");
+
+        assert_eq!(attachments.names(&1.into()).await.expect("names"), vec!["a.txt", "b.txt"], "names");
+        let list = attachments.list(&1.into()).await.expect("list");
+        assert_eq!(list.len(), 2, "list");
+        assert_eq!(list[1].size, 3.0, "replaced size");
+        assert_eq!(attachments.size(&1.into()).await.expect("size"), 5.0, "size");
+        assert_eq!(attachments.total_size().await.expect("total_size"), 11.0, "total_size");
+
+        let c = attachments.get(&2.into(), "c.txt").await.expect("get").expect("c");
+        assert_eq!(c.parent, JsValue::from(2), "parent");
+        assert_eq!(c.blob.size(), 6.0, "blob");
+        assert!(attachments.get(&2.into(), "missing").await.expect("get missing").is_none(), "missing");
+
+        attachments.remove(&1.into(), "a.txt").await.expect("remove");
+        assert_eq!(attachments.names(&1.into()).await.expect("names after"), vec!["b.txt"], "removed");
+    });
+
+    test_case!(async cascade_delete => {
+        let db = open_attachment_db().await;
+        let tx = db.transaction_on_one_with_mode("notes", IdbTransactionMode::Readwrite).unwrap();
+        let notes = tx.object_store("notes").unwrap();
+        notes.put_key_val_owned(1, &JsValue::from("note 1")).expect("put 1");
+        notes.put_key_val_owned(2, &JsValue::from("note 2")).expect("put 2");
+        tx.await.into_result().expect("tx");
+
+        let attachments = Attachments::new(&db, "attachments");
+        attachments.put(&1.into(), "a", &blob("a")).await.expect("put a");
+        attachments.put(&1.into(), "b", &blob("b")).await.expect("put b");
+        attachments.put(&2.into(), "c", &blob("c")).await.expect("put c");
+
+        assert_eq!(attachments.delete_parent("notes", &1.into()).await.expect("delete_parent"), 2, "removed");
+        assert!(attachments.list(&1.into()).await.expect("list 1").is_empty(), "attachments gone");
+        assert_eq!(attachments.names(&2.into()).await.expect("list 2"), vec!["c"], "others kept");
+
+        let tx = db.transaction_on_one("notes").unwrap();
+        let notes = tx.object_store("notes").unwrap();
+        assert!(notes.get_owned(1).unwrap().await.unwrap().is_none(), "parent gone");
+        assert_eq!(attachments.remove_all(&2.into()).await.expect("remove_all"), 1, "remove_all");
+    });
+}
@@ -0,0 +1,206 @@
+//! Throughput measurements for common access patterns
+//!
+//! Features required: `bench`
+//!
+//! Meant to be run from `wasm-bindgen-test` in a real browser so that regressions in the
+//! request and listener plumbing show up as numbers rather than hunches. Each pattern writes
+//! or reads `n` records of the given value in a single transaction and reports how long that
+//! took, from the first request to the transaction completing.
+//!
+//! ```rust
+//! use indexed_db_futures::bench;
+//! use indexed_db_futures::prelude::*;
+//! use wasm_bindgen::prelude::*;
+//! use web_sys::DomException;
+//!
+//! async fn example(db: &IdbDatabase) -> Result<Vec<String>, DomException> {
+//!     let value = JsValue::from("x".repeat(1024));
+//!     let reports = bench::run_all(db, "bench", 1000, &value).await?;
+//!     Ok(reports.iter().map(ToString::to_string).collect())
+//! }
+//! ```
+
+use std::fmt;
+use std::future::Future;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::idb_database::IdbDatabase;
+use crate::idb_query_source::IdbQuerySource;
+
+/// The timing of one measured pattern
+///
+/// Features required: `bench`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    /// The pattern's name
+    pub name: String,
+    /// The number of records it wrote or read
+    pub ops: u32,
+    /// How long it took in milliseconds
+    pub millis: f64,
+}
+
+impl BenchReport {
+    /// Records per second
+    pub fn ops_per_sec(&self) -> f64 {
+        if self.millis > 0.0 {
+            f64::from(self.ops) * 1000.0 / self.millis
+        } else {
+            f64::INFINITY
+        }
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} ops in {:.1}ms ({:.0} ops/s)",
+            self.name,
+            self.ops,
+            self.millis,
+            self.ops_per_sec()
+        )
+    }
+}
+
+/// A high-resolution timestamp in milliseconds: `performance.now()` where it's available,
+/// `Date.now()` otherwise
+pub fn now() -> f64 {
+    let performance = js_sys::Reflect::get(&js_sys::global(), &"performance".into())
+        .unwrap_or(JsValue::UNDEFINED);
+    if performance.is_object() {
+        if let Ok(now) = js_sys::Reflect::get(&performance, &"now".into()) {
+            if let Ok(now) = now.dyn_into::<js_sys::Function>() {
+                if let Some(now) = now.call0(&performance).ok().and_then(|t| t.as_f64()) {
+                    return now;
+                }
+            }
+        }
+    }
+    js_sys::Date::now()
+}
+
+/// Time an arbitrary operation covering `ops` records
+pub async fn measure<F, Fut>(name: &str, ops: u32, f: F) -> Result<BenchReport, DomException>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<(), DomException>>,
+{
+    let started = now();
+    f().await?;
+    Ok(BenchReport {
+        name: name.into(),
+        ops,
+        millis: now() - started,
+    })
+}
+
+/// Put `n` records keyed `0..n` into the store, which must use out-of-line keys
+pub async fn put(
+    db: &IdbDatabase,
+    store_name: &str,
+    n: u32,
+    value: &JsValue,
+) -> Result<BenchReport, DomException> {
+    measure("put", n, || async move {
+        let tx = db.transaction_on_one_with_mode(store_name, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(store_name)?;
+        for i in 0..n {
+            store.put_key_val_owned(i, value)?;
+        }
+        tx.await.into_result()
+    })
+    .await
+}
+
+/// Get the records keyed `0..n` one by one, awaiting each
+pub async fn get(db: &IdbDatabase, store_name: &str, n: u32) -> Result<BenchReport, DomException> {
+    measure("get", n, || async move {
+        let tx = db.transaction_on_one(store_name)?;
+        let store = tx.object_store(store_name)?;
+        for i in 0..n {
+            store.get_owned(i)?.await?;
+        }
+        tx.await.into_result()
+    })
+    .await
+}
+
+/// Read every record with a single `getAll`
+pub async fn get_all(db: &IdbDatabase, store_name: &str) -> Result<BenchReport, DomException> {
+    let tx = db.transaction_on_one(store_name)?;
+    let n = tx.object_store(store_name)?.count()?.await?;
+    drop(tx);
+    measure("get_all", n, || async move {
+        let tx = db.transaction_on_one(store_name)?;
+        tx.object_store(store_name)?.get_all()?.await?;
+        tx.await.into_result()
+    })
+    .await
+}
+
+/// Walk every record with a cursor
+///
+/// Features required: `bench`, `cursors`
+#[cfg(feature = "cursors")]
+pub async fn cursor(db: &IdbDatabase, store_name: &str) -> Result<BenchReport, DomException> {
+    let tx = db.transaction_on_one(store_name)?;
+    let n = tx.object_store(store_name)?.count()?.await?;
+    drop(tx);
+    measure("cursor", n, || async move {
+        let tx = db.transaction_on_one(store_name)?;
+        let store = tx.object_store(store_name)?;
+        if let Some(cursor) = store.open_cursor()?.await? {
+            while cursor.continue_cursor()?.await? {}
+        }
+        tx.await.into_result()
+    })
+    .await
+}
+
+/// Clear the store, then run every pattern in turn over `n` records of the given value
+pub async fn run_all(
+    db: &IdbDatabase,
+    store_name: &str,
+    n: u32,
+    value: &JsValue,
+) -> Result<Vec<BenchReport>, DomException> {
+    let tx = db.transaction_on_one_with_mode(store_name, IdbTransactionMode::Readwrite)?;
+    tx.object_store(store_name)?.clear()?;
+    tx.await.into_result()?;
+
+    let mut reports = vec![
+        put(db, store_name, n, value).await?,
+        get(db, store_name, n).await?,
+        get_all(db, store_name).await?,
+    ];
+    #[cfg(feature = "cursors")]
+    reports.push(cursor(db, store_name).await?);
+    Ok(reports)
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::test_utils::open_any_db;
+
+    use super::*;
+
+    test_mod_init!();
+
+    test_case!(report => {
+        let report = BenchReport { name: "put".into(), ops: 500, millis: 250.0 };
+        assert_eq!(report.ops_per_sec(), 2000.0, "ops/s");
+        assert_eq!(report.to_string(), "put: 500 ops in 250.0ms (2000 ops/s)", "display");
+    });
+
+    test_case!(async run_all => {
+        let (db, store_name) = open_any_db().await;
+        let reports = super::run_all(&db, &store_name, 20, &JsValue::from("value")).await.expect("run_all");
+        let names: Vec<&str> = reports.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(&names[..3], &["put", "get", "get_all"], "names");
+        assert!(reports.iter().all(|r| r.ops == 20 && r.millis >= 0.0), "reports");
+    });
+}
@@ -0,0 +1,256 @@
+//! Content-addressed blob storage
+//!
+//! A [CasStore] stores byte blobs under the hex SHA-256 hash of their contents, computed via
+//! [WebCrypto](https://developer.mozilla.org/en-US/docs/Web/API/SubtleCrypto/digest), so storing
+//! the same attachment twice only stores it once. Every blob has a reference count:
+//! [put][CasStore::put] and [retain][CasStore::retain] add a reference,
+//! [release][CasStore::release] drops one and [gc][CasStore::gc] deletes the blobs that have none
+//! left.
+//!
+//! Hashing is promise-based and would let a transaction auto-commit if awaited in the middle of
+//! it, so it happens before the transaction that stores the blob. Blobs live in one store and
+//! their reference counts in another, both with out-of-line keys, so that counting doesn't load
+//! blob contents.
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::cas::CasStore;
+//! use web_sys::DomException;
+//!
+//! async fn example() -> Result<(), DomException> {
+//!     let mut req = IdbDatabase::open("attachments")?;
+//!     req.set_on_upgrade_needed(Some(|evt: &IdbVersionChangeEvent| {
+//!         Ok(CasStore::create_stores(evt.db(), "blobs", "blob_refs")?)
+//!     }));
+//!     let db = req.into_future().await?;
+//!
+//!     let cas = CasStore::new(&db, "blobs", "blob_refs");
+//!     let hash = cas.put(b"hello").await?;
+//!     let _bytes: Option<Vec<u8>> = cas.get(&hash).await?;
+//!     cas.release(&hash).await?;
+//!     let _deleted: u32 = cas.gc().await?;
+//!     Ok(())
+//! }
+//! ```
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{Blob, DomException, IdbTransactionMode};
+
+use crate::idb_database::IdbDatabase;
+use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::{await_promise, dom_exception};
+
+/// A content-addressed, reference-counted blob store. See the [module docs][self].
+#[derive(Debug)]
+pub struct CasStore<'a> {
+    db: &'a IdbDatabase,
+    blob_store: String,
+    ref_store: String,
+}
+
+impl<'a> CasStore<'a> {
+    /// Use the given stores, which must have been set up by
+    /// [create_stores][CasStore::create_stores]
+    pub fn new(db: &'a IdbDatabase, blob_store: &str, ref_store: &str) -> Self {
+        Self {
+            db,
+            blob_store: blob_store.into(),
+            ref_store: ref_store.into(),
+        }
+    }
+
+    /// Create the blob and reference count stores. Must be called during an upgrade.
+    pub fn create_stores(
+        db: &IdbDatabase,
+        blob_store: &str,
+        ref_store: &str,
+    ) -> Result<(), DomException> {
+        db.create_object_store(blob_store)?;
+        db.create_object_store(ref_store)?;
+        Ok(())
+    }
+
+    /// Store the bytes, or add a reference to them if they're already stored. Resolves to their
+    /// hash.
+    pub async fn put(&self, data: &[u8]) -> Result<String, DomException> {
+        let hash = sha256(data).await?;
+        let tx = self.db.transaction_on_multi_with_mode(
+            &[&self.blob_store, &self.ref_store],
+            IdbTransactionMode::Readwrite,
+        )?;
+        let refs = tx.object_store(&self.ref_store)?;
+        let key = JsValue::from_str(&hash);
+        let count = read_refs(&refs, &key).await?;
+        if count == 0 {
+            tx.object_store(&self.blob_store)?
+                .put_key_val(&key, &js_sys::Uint8Array::from(data))?;
+        }
+        refs.put_key_val(&key, &JsValue::from(count + 1))?;
+        tx.await.into_result()?;
+        Ok(hash)
+    }
+
+    /// Store the contents of the [Blob]. See [put][CasStore::put].
+    pub async fn put_blob(&self, blob: &Blob) -> Result<String, DomException> {
+        let buffer = await_promise(blob.array_buffer()).await?;
+        self.put(&js_sys::Uint8Array::new(&buffer).to_vec()).await
+    }
+
+    /// The bytes stored under the hash
+    pub async fn get(&self, hash: &str) -> Result<Option<Vec<u8>>, DomException> {
+        let tx = self.db.transaction_on_one(&self.blob_store)?;
+        let value = tx
+            .object_store(&self.blob_store)?
+            .get(&JsValue::from_str(hash))?
+            .await?;
+        Ok(value.map(|v| js_sys::Uint8Array::new(&v).to_vec()))
+    }
+
+    /// Whether bytes are stored under the hash, whether or not they're still referenced
+    pub async fn contains(&self, hash: &str) -> Result<bool, DomException> {
+        let tx = self.db.transaction_on_one(&self.blob_store)?;
+        let key = tx
+            .object_store(&self.blob_store)?
+            .get_key(&JsValue::from_str(hash))?
+            .await?;
+        Ok(key.is_some())
+    }
+
+    /// The number of references to the blob
+    pub async fn refs(&self, hash: &str) -> Result<u32, DomException> {
+        let tx = self.db.transaction_on_one(&self.ref_store)?;
+        read_refs(&tx.object_store(&self.ref_store)?, &JsValue::from_str(hash)).await
+    }
+
+    /// Add a reference to a stored blob. Fails with a `NotFoundError` if there's no blob under the
+    /// hash.
+    pub async fn retain(&self, hash: &str) -> Result<u32, DomException> {
+        let tx = self.db.transaction_on_multi_with_mode(
+            &[&self.blob_store, &self.ref_store],
+            IdbTransactionMode::Readwrite,
+        )?;
+        let key = JsValue::from_str(hash);
+        if tx
+            .object_store(&self.blob_store)?
+            .get_key(&key)?
+            .await?
+            .is_none()
+        {
+            tx.abort()?;
+            return Err(dom_exception(
+                &format!("No blob stored under {}", hash),
+                "NotFoundError",
+            ));
+        }
+        let refs = tx.object_store(&self.ref_store)?;
+        let count = read_refs(&refs, &key).await? + 1;
+        refs.put_key_val(&key, &JsValue::from(count))?;
+        tx.await.into_result()?;
+        Ok(count)
+    }
+
+    /// Drop a reference to the blob. Resolves to the number of references left; blobs with none
+    /// left stay stored until the next [gc][CasStore::gc].
+    pub async fn release(&self, hash: &str) -> Result<u32, DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.ref_store, IdbTransactionMode::Readwrite)?;
+        let refs = tx.object_store(&self.ref_store)?;
+        let key = JsValue::from_str(hash);
+        let count = read_refs(&refs, &key).await?.saturating_sub(1);
+        refs.put_key_val(&key, &JsValue::from(count))?;
+        tx.await.into_result()?;
+        Ok(count)
+    }
+
+    /// Delete every blob without references. Resolves to the number of blobs deleted.
+    pub async fn gc(&self) -> Result<u32, DomException> {
+        let tx = self.db.transaction_on_multi_with_mode(
+            &[&self.blob_store, &self.ref_store],
+            IdbTransactionMode::Readwrite,
+        )?;
+        let blobs = tx.object_store(&self.blob_store)?;
+        let refs = tx.object_store(&self.ref_store)?;
+        let keys = blobs.get_all_keys()?.await?;
+        let mut deleted = 0;
+        for key in keys.iter() {
+            if read_refs(&refs, &key).await? == 0 {
+                blobs.delete(&key)?;
+                refs.delete(&key)?;
+                deleted += 1;
+            }
+        }
+        tx.await.into_result()?;
+        Ok(deleted)
+    }
+}
+
+async fn read_refs(refs: &IdbObjectStore<'_>, key: &JsValue) -> Result<u32, DomException> {
+    let count = refs.get(key)?.await?;
+    Ok(count.and_then(|c| c.as_f64()).unwrap_or(0.0) as u32)
+}
+
+/// The hex SHA-256 hash of the data
+pub async fn sha256(data: &[u8]) -> Result<String, DomException> {
+    let crypto = js_sys::Reflect::get(&js_sys::global(), &"crypto".into())?;
+    let subtle = match crypto.is_object() {
+        true => js_sys::Reflect::get(&crypto, &"subtle".into())?,
+        false => JsValue::UNDEFINED,
+    };
+    if !subtle.is_object() {
+        return Err(dom_exception(
+            "WebCrypto is unavailable; it requires a secure context",
+            "NotSupportedError",
+        ));
+    }
+    let digest = js_sys::Reflect::get(&subtle, &"digest".into())?
+        .unchecked_into::<js_sys::Function>()
+        .call2(&subtle, &"SHA-256".into(), &js_sys::Uint8Array::from(data))?;
+    let buffer = await_promise(digest.unchecked_into()).await?;
+    let hash = js_sys::Uint8Array::new(&buffer).to_vec();
+    Ok(hash.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::open_db_with;
+
+    use super::*;
+
+    test_mod_init!();
+
+    async fn open_cas_db() -> IdbDatabase {
+        open_db_with(|evt: &IdbVersionChangeEvent| {
+            Ok(CasStore::create_stores(evt.db(), "blobs", "refs")?)
+        })
+        .await
+    }
+
+    test_case!(async hashing => {
+        assert_eq!(
+            sha256(b"abc").await.expect("sha256"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    });
+
+    test_case!(async dedup_and_gc => {
+        let db = open_cas_db().await;
+        let cas = CasStore::new(&db, "blobs", "refs");
+        let a = cas.put(b"hello").await.expect("put a");
+        let again = cas.put(b"hello").await.expect("put a again");
+        let b = cas.put(b"world").await.expect("put b");
+        assert_eq!(a, again, "same hash");
+        assert_eq!(cas.refs(&a).await.expect("refs a"), 2, "refs a");
+        assert_eq!(cas.get(&a).await.expect("get").as_deref(), Some(&b"hello"[..]), "get");
+
+        assert_eq!(cas.release(&a).await.expect("release a"), 1, "release a");
+        assert_eq!(cas.release(&b).await.expect("release b"), 0, "release b");
+        assert_eq!(cas.gc().await.expect("gc"), 1, "gc");
+        assert!(cas.contains(&a).await.expect("contains a"), "a kept");
+        assert!(!cas.contains(&b).await.expect("contains b"), "b collected");
+        assert!(cas.retain(&b).await.is_err(), "retain collected");
+        assert_eq!(cas.retain(&a).await.expect("retain a"), 2, "retain a");
+    });
+}
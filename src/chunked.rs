@@ -0,0 +1,242 @@
+//! Large values stored in chunks
+//!
+//! Reading a record always reads all of it, so a media file stored as a single value has to be
+//! loaded in full just to seek into it. A [ChunkedStore] splits values into fixed-size chunks
+//! instead: a manifest record, `{size, chunk_size, chunks}`, keyed by `[key]`, followed by the
+//! chunks keyed by `[key, index]`, so [read_range][ChunkedStore::read_range] only fetches the
+//! chunks overlapping the requested bytes. The store must use out-of-line keys.
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::chunked::ChunkedStore;
+//! use web_sys::DomException;
+//!
+//! async fn example(db: &IdbDatabase, video: &[u8]) -> Result<(), DomException> {
+//!     let videos = ChunkedStore::new(db, "videos");
+//!     videos.put(&"intro".into(), video).await?;
+//!     let _header: Option<Vec<u8>> = videos.read_range(&"intro".into(), 0..1024).await?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::ops::Range;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbKeyRange, IdbTransactionMode};
+
+use crate::idb_database::IdbDatabase;
+use crate::idb_query_source::IdbQuerySource;
+
+/// The default chunk size, 256 KiB
+pub const DEFAULT_CHUNK_SIZE: u32 = 256 * 1024;
+
+/// A store of values split into chunks. See the [module docs][self].
+#[derive(Debug)]
+pub struct ChunkedStore<'a> {
+    db: &'a IdbDatabase,
+    store_name: String,
+    chunk_size: u32,
+}
+
+/// Where a stored value's chunks are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Manifest {
+    size: u64,
+    chunk_size: u64,
+    chunks: u32,
+}
+
+impl<'a> ChunkedStore<'a> {
+    /// Use the given store, splitting values into [DEFAULT_CHUNK_SIZE] chunks
+    pub fn new(db: &'a IdbDatabase, store_name: &str) -> Self {
+        Self {
+            db,
+            store_name: store_name.into(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Split values written from now on into chunks of the given size. Values already stored
+    /// keep the chunk size they were written with.
+    pub fn chunk_size(mut self, bytes: u32) -> Self {
+        self.chunk_size = bytes.max(1);
+        self
+    }
+
+    /// Store the bytes at the key, replacing any value already there
+    pub async fn put(&self, key: &JsValue, data: &[u8]) -> Result<(), DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(&self.store_name)?;
+        store.delete(&value_range(key)?)?;
+
+        let chunks = data.chunks(self.chunk_size as usize);
+        let manifest = Manifest {
+            size: data.len() as u64,
+            chunk_size: u64::from(self.chunk_size),
+            chunks: chunks.len() as u32,
+        };
+        for (i, chunk) in chunks.enumerate() {
+            store.put_key_val(&chunk_key(key, i as u32), &js_sys::Uint8Array::from(chunk))?;
+        }
+        store.put_key_val(&manifest_key(key), &manifest.to_js()?)?;
+        tx.await.into_result()
+    }
+
+    /// The size in bytes of the value at the key
+    pub async fn len(&self, key: &JsValue) -> Result<Option<u64>, DomException> {
+        let tx = self.db.transaction_on_one(&self.store_name)?;
+        let manifest = tx
+            .object_store(&self.store_name)?
+            .get(&manifest_key(key))?
+            .await?;
+        Ok(manifest.map(|m| Manifest::from_js(&m).size))
+    }
+
+    /// The whole value at the key
+    pub async fn get(&self, key: &JsValue) -> Result<Option<Vec<u8>>, DomException> {
+        self.read_range(key, 0..u64::MAX).await
+    }
+
+    /// The given byte range of the value at the key, fetching only the chunks it overlaps. The
+    /// range is clamped to the value's size, so reading past its end returns fewer bytes.
+    pub async fn read_range(
+        &self,
+        key: &JsValue,
+        range: Range<u64>,
+    ) -> Result<Option<Vec<u8>>, DomException> {
+        let tx = self.db.transaction_on_one(&self.store_name)?;
+        let store = tx.object_store(&self.store_name)?;
+        let manifest = match store.get(&manifest_key(key))?.await? {
+            Some(manifest) => Manifest::from_js(&manifest),
+            None => return Ok(None),
+        };
+
+        let end = range.end.min(manifest.size);
+        let start = range.start.min(end);
+        if start == end {
+            return Ok(Some(Vec::new()));
+        }
+        let first = (start / manifest.chunk_size) as u32;
+        let last = ((end - 1) / manifest.chunk_size) as u32;
+        let chunks = store
+            .get_all_with_key(&IdbKeyRange::bound(
+                &chunk_key(key, first),
+                &chunk_key(key, last),
+            )?)?
+            .await?;
+
+        let mut out = Vec::with_capacity((end - start) as usize);
+        let mut offset = u64::from(first) * manifest.chunk_size;
+        for chunk in chunks.iter() {
+            let chunk = js_sys::Uint8Array::new(&chunk).to_vec();
+            let from = start.saturating_sub(offset) as usize;
+            let to = ((end - offset) as usize).min(chunk.len());
+            out.extend_from_slice(&chunk[from..to]);
+            offset += manifest.chunk_size;
+        }
+        Ok(Some(out))
+    }
+
+    /// Delete the value at the key and all of its chunks
+    pub async fn delete(&self, key: &JsValue) -> Result<(), DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        tx.object_store(&self.store_name)?
+            .delete(&value_range(key)?)?;
+        tx.await.into_result()
+    }
+}
+
+impl Manifest {
+    fn to_js(self) -> Result<JsValue, JsValue> {
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"size".into(), &(self.size as f64).into())?;
+        js_sys::Reflect::set(&obj, &"chunk_size".into(), &(self.chunk_size as f64).into())?;
+        js_sys::Reflect::set(&obj, &"chunks".into(), &self.chunks.into())?;
+        Ok(obj.into())
+    }
+
+    fn from_js(value: &JsValue) -> Self {
+        let number = |field: &str| {
+            js_sys::Reflect::get(value, &field.into())
+                .ok()
+                .and_then(|v| v.as_f64())
+                .unwrap_or(0.0)
+        };
+        Self {
+            size: number("size") as u64,
+            chunk_size: (number("chunk_size") as u64).max(1),
+            chunks: number("chunks") as u32,
+        }
+    }
+}
+
+fn manifest_key(key: &JsValue) -> JsValue {
+    js_sys::Array::of1(key).into()
+}
+
+fn chunk_key(key: &JsValue, index: u32) -> JsValue {
+    js_sys::Array::of2(key, &index.into()).into()
+}
+
+/// The manifest and every chunk of the value. Arrays sort after numbers, so `[key, []]` follows
+/// every `[key, index]` key.
+fn value_range(key: &JsValue) -> Result<IdbKeyRange, JsValue> {
+    IdbKeyRange::bound(
+        &manifest_key(key),
+        &js_sys::Array::of2(key, &js_sys::Array::new()),
+    )
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::test_utils::open_any_db;
+
+    use super::*;
+
+    test_mod_init!();
+
+    fn bytes(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    test_case!(async read_range => {
+        let (db, store_name) = open_any_db().await;
+        let store = ChunkedStore::new(&db, &store_name).chunk_size(10);
+        let data = bytes(95);
+        let key = JsValue::from("video");
+        store.put(&key, &data).await.expect("put");
+
+        assert_eq!(store.len(&key).await.expect("len"), Some(95), "len");
+        assert_eq!(store.get(&key).await.expect("get"), Some(data.clone()), "get");
+        let mid = store.read_range(&key, 15..42).await.expect("mid");
+        assert_eq!(mid.as_deref(), Some(&data[15..42]), "mid");
+        let aligned = store.read_range(&key, 20..30).await.expect("aligned");
+        assert_eq!(aligned.as_deref(), Some(&data[20..30]), "aligned");
+        let tail = store.read_range(&key, 90..200).await.expect("tail");
+        assert_eq!(tail.as_deref(), Some(&data[90..]), "clamped");
+        let empty = store.read_range(&key, 300..400).await.expect("past end");
+        assert_eq!(empty, Some(Vec::new()), "past end");
+        assert_eq!(store.read_range(&"missing".into(), 0..1).await.expect("missing"), None, "missing");
+    });
+
+    test_case!(async replace_and_delete => {
+        let (db, store_name) = open_any_db().await;
+        let key = JsValue::from("file");
+        ChunkedStore::new(&db, &store_name).chunk_size(4).put(&key, &bytes(30)).await.expect("put");
+        let store = ChunkedStore::new(&db, &store_name).chunk_size(8);
+        store.put(&key, &bytes(5)).await.expect("replace");
+        assert_eq!(store.get(&key).await.expect("get"), Some(bytes(5)), "replaced");
+
+        let tx = db.transaction_on_one(&store_name).unwrap();
+        let count = tx.object_store(&store_name).unwrap().count().unwrap().await.unwrap();
+        assert_eq!(count, 2, "stale chunks removed");
+        drop(tx);
+
+        store.delete(&key).await.expect("delete");
+        assert_eq!(store.len(&key).await.expect("len"), None, "deleted");
+    });
+}
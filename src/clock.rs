@@ -0,0 +1,126 @@
+//! Where timestamps and expiry cutoffs get the current time from
+//!
+//! Everything that stamps records with the current time or compares their age against it, such
+//! as audit records, access stats, retention, asset metadata and soft-delete tombstones, reads it
+//! from a [Clock], which defaults to [SystemClock]. Tests can swap in a [FakeClock] to simulate
+//! expiry without waiting, and apps can pass a closure to use server-corrected time:
+//!
+//! ```rust
+//! use indexed_db_futures::idb_object_store::AuditLog;
+//!
+//! fn example(server_offset_ms: f64) -> AuditLog {
+//!     AuditLog::new("audit")
+//!         .track("docs")
+//!         .with_clock(move || js_sys::Date::now() + server_offset_ms)
+//! }
+//! ```
+
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// A source of the current time
+pub trait Clock {
+    /// The current time in milliseconds since the epoch
+    fn now(&self) -> f64;
+}
+
+impl<F: Fn() -> f64> Clock for F {
+    #[inline]
+    fn now(&self) -> f64 {
+        self()
+    }
+}
+
+/// The system time, from `Date.now()`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> f64 {
+        js_sys::Date::now()
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time, so a test can keep one
+/// and hand another to the code under test.
+#[derive(Debug, Clone, Default)]
+pub struct FakeClock {
+    now: Rc<Cell<f64>>,
+}
+
+impl FakeClock {
+    /// Start at the given time, in milliseconds since the epoch
+    pub fn new(now: f64) -> Self {
+        Self {
+            now: Rc::new(Cell::new(now)),
+        }
+    }
+
+    /// Jump to the given time
+    #[inline]
+    pub fn set(&self, now: f64) {
+        self.now.set(now);
+    }
+
+    /// Move the time forward
+    #[inline]
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by.as_secs_f64() * 1000.0);
+    }
+}
+
+impl Clock for FakeClock {
+    #[inline]
+    fn now(&self) -> f64 {
+        self.now.get()
+    }
+}
+
+/// A cheaply cloneable clock held by the types that take one
+#[derive(Clone)]
+pub(crate) struct SharedClock(Rc<dyn Clock>);
+
+impl SharedClock {
+    #[inline]
+    pub fn new<C: Clock + 'static>(clock: C) -> Self {
+        Self(Rc::new(clock))
+    }
+
+    #[inline]
+    pub fn now(&self) -> f64 {
+        self.0.now()
+    }
+}
+
+impl Default for SharedClock {
+    #[inline]
+    fn default() -> Self {
+        Self::new(SystemClock)
+    }
+}
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Clock").field(&self.now()).finish()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    test_mod_init!();
+
+    test_case!(fake_clock => {
+        let clock = FakeClock::new(1_000.0);
+        let shared = SharedClock::new(clock.clone());
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(shared.now(), 1_500.0, "advanced");
+        clock.set(10.0);
+        assert_eq!(shared.now(), 10.0, "set");
+        assert_eq!(SharedClock::new(|| 42.0).now(), 42.0, "closure");
+    });
+}
@@ -0,0 +1,314 @@
+//! Counters kept up to date with the writes to a store
+//!
+//! Features required: `indices`
+//!
+//! A [Counter] counts the records of a data store, optionally only the ones matching a filter
+//! and optionally per bucket, the value at a key path, e.g. unread messages per conversation.
+//! Writes made through the counter adjust the counts in the same transaction, so they never drift
+//! from the data and reading one is a single `get` rather than a count over a cursor.
+//!
+//! Counts live in a counter store with out-of-line keys, keyed by `[name, 0]` for the total and
+//! `[name, 1, bucket]` per bucket, where the name defaults to the data store's; one counter store
+//! can serve any number of counters. Writes that bypass the counter aren't counted;
+//! [rebuild][Counter::rebuild] recounts from scratch.
+
+use std::fmt;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbKeyRange};
+
+use crate::idb_key_path::IdbKeyPath;
+use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+use crate::idb_transaction::IdbTransaction;
+use crate::internal_utils::keys_eq;
+
+type Filter = Rc<dyn Fn(&JsValue) -> bool>;
+
+/// Counts a data store's records. See the [module docs][self].
+///
+/// Features required: `indices`
+#[derive(Clone)]
+pub struct Counter {
+    counter_store: String,
+    data_store: String,
+    name: String,
+    bucket: Option<IdbKeyPath>,
+    filter: Option<Filter>,
+}
+
+impl fmt::Debug for Counter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Counter")
+            .field("counter_store", &self.counter_store)
+            .field("data_store", &self.data_store)
+            .field("name", &self.name)
+            .field("bucket", &self.bucket)
+            .field("filter", &self.filter.is_some())
+            .finish()
+    }
+}
+
+impl Counter {
+    /// Count the records of `data_store`, keeping the counts in `counter_store`
+    pub fn new(counter_store: &str, data_store: &str) -> Self {
+        Self {
+            counter_store: counter_store.into(),
+            data_store: data_store.into(),
+            name: data_store.into(),
+            bucket: None,
+            filter: None,
+        }
+    }
+
+    /// Name the counter, which every counter kept in the same counter store needs a distinct
+    /// one of. Defaults to the data store's name.
+    pub fn named(mut self, name: &str) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Also count records per value at the given dotted key path. Records without a value there
+    /// only count towards the total.
+    pub fn per_bucket(mut self, key_path: &str) -> Self {
+        self.bucket = Some(IdbKeyPath::str(key_path));
+        self
+    }
+
+    /// Only count records for which the filter returns true, e.g. unread messages
+    pub fn filter<F: Fn(&JsValue) -> bool + 'static>(mut self, filter: F) -> Self {
+        self.filter = Some(Rc::new(filter));
+        self
+    }
+
+    /// Put the value at the key in the data store and adjust the counts. The transaction must
+    /// be a readwrite one over both stores.
+    pub async fn put(
+        &self,
+        tx: &IdbTransaction<'_>,
+        key: &JsValue,
+        value: &JsValue,
+    ) -> Result<(), DomException> {
+        let data = tx.object_store(&self.data_store)?;
+        let old = data.get(key)?.await?;
+        data.put_key_val(key, value)?;
+        self.adjust(tx, old.as_ref(), Some(value)).await
+    }
+
+    /// Delete the record at the key from the data store and adjust the counts. The transaction
+    /// must be a readwrite one over both stores.
+    pub async fn delete(&self, tx: &IdbTransaction<'_>, key: &JsValue) -> Result<(), DomException> {
+        let data = tx.object_store(&self.data_store)?;
+        let old = data.get(key)?.await?;
+        data.delete(key)?;
+        self.adjust(tx, old.as_ref(), None).await
+    }
+
+    /// The number of counted records
+    pub async fn total(&self, tx: &IdbTransaction<'_>) -> Result<u32, DomException> {
+        let counters = tx.object_store(&self.counter_store)?;
+        read_count(&counters, &self.total_key()).await
+    }
+
+    /// The number of counted records in the bucket
+    pub async fn bucket(
+        &self,
+        tx: &IdbTransaction<'_>,
+        bucket: &JsValue,
+    ) -> Result<u32, DomException> {
+        let counters = tx.object_store(&self.counter_store)?;
+        read_count(&counters, &self.bucket_key(bucket)).await
+    }
+
+    /// Recount every record of the data store, e.g. after writes that bypassed the counter or
+    /// when introducing a counter on existing data. The transaction must be a readwrite one over
+    /// both stores.
+    pub async fn rebuild(&self, tx: &IdbTransaction<'_>) -> Result<(), DomException> {
+        let values = tx.object_store(&self.data_store)?.get_all()?.await?;
+        let mut total = 0u32;
+        let mut buckets: Vec<(JsValue, u32)> = Vec::new();
+        for value in values.iter() {
+            if !self.counts(&value) {
+                continue;
+            }
+            total += 1;
+            if let Some(bucket) = self.bucket_of(&value) {
+                match buckets.iter_mut().find(|(b, _)| keys_eq(b, &bucket)) {
+                    Some((_, count)) => *count += 1,
+                    None => buckets.push((bucket, 1)),
+                }
+            }
+        }
+
+        let counters = tx.object_store(&self.counter_store)?;
+        let name = JsValue::from_str(&self.name);
+        counters.delete(&IdbKeyRange::bound(
+            &js_sys::Array::of1(&name),
+            &js_sys::Array::of2(&name, &JsValue::from(2u8)),
+        )?)?;
+        write_count(&counters, &self.total_key(), total)?;
+        for (bucket, count) in buckets {
+            write_count(&counters, &self.bucket_key(&bucket), count)?;
+        }
+        Ok(())
+    }
+
+    async fn adjust(
+        &self,
+        tx: &IdbTransaction<'_>,
+        old: Option<&JsValue>,
+        new: Option<&JsValue>,
+    ) -> Result<(), DomException> {
+        let old = old.filter(|v| self.counts(v));
+        let new = new.filter(|v| self.counts(v));
+        let counters = tx.object_store(&self.counter_store)?;
+
+        match (old.is_some(), new.is_some()) {
+            (false, true) => add_to(&counters, &self.total_key(), 1).await?,
+            (true, false) => add_to(&counters, &self.total_key(), -1).await?,
+            _ => {}
+        }
+
+        let old_bucket = old.and_then(|v| self.bucket_of(v));
+        let new_bucket = new.and_then(|v| self.bucket_of(v));
+        match (old_bucket, new_bucket) {
+            (Some(old), Some(new)) if keys_eq(&old, &new) => {}
+            (old, new) => {
+                if let Some(old) = old {
+                    add_to(&counters, &self.bucket_key(&old), -1).await?;
+                }
+                if let Some(new) = new {
+                    add_to(&counters, &self.bucket_key(&new), 1).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn counts(&self, value: &JsValue) -> bool {
+        match self.filter {
+            Some(ref filter) => filter(value),
+            None => true,
+        }
+    }
+
+    fn bucket_of(&self, value: &JsValue) -> Option<JsValue> {
+        self.bucket.as_ref()?.evaluate(value)
+    }
+
+    fn total_key(&self) -> JsValue {
+        js_sys::Array::of2(&JsValue::from_str(&self.name), &JsValue::from(0u8)).into()
+    }
+
+    fn bucket_key(&self, bucket: &JsValue) -> JsValue {
+        js_sys::Array::of3(&JsValue::from_str(&self.name), &JsValue::from(1u8), bucket).into()
+    }
+}
+
+async fn read_count(counters: &IdbObjectStore<'_>, key: &JsValue) -> Result<u32, DomException> {
+    let count = counters.get(key)?.await?;
+    Ok(count.and_then(|c| c.as_f64()).unwrap_or(0.0) as u32)
+}
+
+fn write_count(
+    counters: &IdbObjectStore<'_>,
+    key: &JsValue,
+    count: u32,
+) -> Result<(), DomException> {
+    if count == 0 {
+        counters.delete(key)?;
+    } else {
+        counters.put_key_val(key, &JsValue::from(count))?;
+    }
+    Ok(())
+}
+
+async fn add_to(
+    counters: &IdbObjectStore<'_>,
+    key: &JsValue,
+    delta: i64,
+) -> Result<(), DomException> {
+    let count = i64::from(read_count(counters, key).await?) + delta;
+    write_count(counters, key, count.max(0) as u32)
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::open_db_with;
+
+    use super::*;
+
+    test_mod_init!();
+
+    async fn open_counter_db() -> IdbDatabase {
+        open_db_with(|evt: &IdbVersionChangeEvent| {
+            evt.db().create_object_store("messages")?;
+            evt.db().create_object_store("counters")?;
+            Ok(())
+        })
+        .await
+    }
+
+    fn message(conversation: &str, unread: bool) -> JsValue {
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"conversation".into(), &conversation.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"unread".into(), &unread.into()).unwrap();
+        obj.into()
+    }
+
+    fn unread_counter() -> Counter {
+        Counter::new("counters", "messages")
+            .per_bucket("conversation")
+            .filter(|v| {
+                js_sys::Reflect::get(v, &"unread".into())
+                    .unwrap_or(JsValue::FALSE)
+                    .is_truthy()
+            })
+    }
+
+    test_case!(async maintained_with_writes => {
+        let db = open_counter_db().await;
+        let counter = unread_counter();
+        let tx = db.transaction_on_multi_with_mode(&["messages", "counters"], IdbTransactionMode::Readwrite)
+            .expect("tx");
+        counter.put(&tx, &1.into(), &message("a", true)).await.expect("put 1");
+        counter.put(&tx, &2.into(), &message("a", true)).await.expect("put 2");
+        counter.put(&tx, &3.into(), &message("b", true)).await.expect("put 3");
+        counter.put(&tx, &4.into(), &message("b", false)).await.expect("put 4");
+        // Read, moved to another conversation
+        counter.put(&tx, &1.into(), &message("b", false)).await.expect("mark read");
+        counter.put(&tx, &4.into(), &message("a", true)).await.expect("mark unread");
+        counter.delete(&tx, &3.into()).await.expect("delete");
+        counter.delete(&tx, &99.into()).await.expect("delete missing");
+
+        assert_eq!(counter.total(&tx).await.expect("total"), 2, "total");
+        assert_eq!(counter.bucket(&tx, &"a".into()).await.expect("a"), 2, "a");
+        assert_eq!(counter.bucket(&tx, &"b".into()).await.expect("b"), 0, "b");
+        tx.await.into_result().expect("tx await");
+    });
+
+    test_case!(async rebuild => {
+        let db = open_counter_db().await;
+        let tx = db.transaction_on_multi_with_mode(&["messages", "counters"], IdbTransactionMode::Readwrite)
+            .expect("tx");
+        let messages = tx.object_store("messages").expect("messages");
+        messages.put_key_val_owned(1u8, &message("a", true)).expect("put 1");
+        messages.put_key_val_owned(2u8, &message("b", true)).expect("put 2");
+        messages.put_key_val_owned(3u8, &message("b", true)).expect("put 3");
+        messages.put_key_val_owned(4u8, &message("b", false)).expect("put 4");
+
+        let counter = unread_counter();
+        assert_eq!(counter.total(&tx).await.expect("total before"), 0, "before");
+        counter.rebuild(&tx).await.expect("rebuild");
+        assert_eq!(counter.total(&tx).await.expect("total"), 3, "total");
+        assert_eq!(counter.bucket(&tx, &"b".into()).await.expect("b"), 2, "b");
+
+        let all = Counter::new("counters", "messages").named("all_messages");
+        all.rebuild(&tx).await.expect("rebuild all");
+        assert_eq!(all.total(&tx).await.expect("total all"), 4, "all");
+        assert_eq!(counter.total(&tx).await.expect("total after"), 3, "separate counters");
+        tx.await.into_result().expect("tx await");
+    });
+}
@@ -0,0 +1,456 @@
+//! Best-effort atomic writes across several databases.
+//!
+//! IndexedDB transactions can't span databases, so [cross_db_write] sequences one transaction per
+//! database instead. Before any of them starts, a journal record containing every write and the
+//! value it replaces gets stored in a journal store with out-of-line keys. If a later transaction
+//! fails, the ones that already committed get rolled back from the journal; if the tab gets
+//! killed midway, the record is still there for [recover_cross_db_writes] to roll back or retry
+//! on the next startup.
+//!
+//! Writes made to the same keys by other code between the write and its rollback get overwritten
+//! by the rollback.
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::cross_db::{cross_db_write, recover_cross_db_writes, DbWrite, Recovery};
+//! use wasm_bindgen::prelude::*;
+//! use web_sys::DomException;
+//!
+//! async fn example(
+//!     journal: &IdbDatabase,
+//!     users: &IdbDatabase,
+//!     billing: &IdbDatabase,
+//! ) -> Result<(), DomException> {
+//!     // On startup
+//!     recover_cross_db_writes(journal, "journal", &[users, billing], Recovery::Rollback).await?;
+//!
+//!     let user = JsValue::from_str("alice");
+//!     let account = JsValue::from_str("acc-1");
+//!     let writes = [
+//!         DbWrite::put(&users.name(), "users", 1u8, &user),
+//!         DbWrite::put(&billing.name(), "accounts", 1u8, &account),
+//!     ];
+//!     cross_db_write(journal, "journal", &[users, billing], &writes).await
+//! }
+//! ```
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::idb_database::IdbDatabase;
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::dom_exception;
+use crate::keygen::Ulid;
+
+/// A single write in a [cross_db_write]
+#[derive(Debug, Clone)]
+pub struct DbWrite {
+    db: String,
+    store: String,
+    key: JsValue,
+    value: Option<JsValue>,
+}
+
+impl DbWrite {
+    /// Put the value at the key in the given database and store
+    pub fn put<K: Into<JsValue>>(db: &str, store: &str, key: K, value: &JsValue) -> Self {
+        Self {
+            db: db.into(),
+            store: store.into(),
+            key: key.into(),
+            value: Some(value.clone()),
+        }
+    }
+
+    /// Delete the key in the given database and store
+    pub fn delete<K: Into<JsValue>>(db: &str, store: &str, key: K) -> Self {
+        Self {
+            db: db.into(),
+            store: store.into(),
+            key: key.into(),
+            value: None,
+        }
+    }
+}
+
+/// What [recover_cross_db_writes] should do with writes that were interrupted
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Recovery {
+    /// Restore the values the writes replaced
+    Rollback,
+    /// Apply the writes again
+    Retry,
+}
+
+/// A write plus the value it replaces, as stored in the journal. Puts are flagged explicitly,
+/// since a put of `undefined` can't be told apart from a delete by its value.
+struct Entry {
+    write: DbWrite,
+    previous: Option<JsValue>,
+}
+
+impl Entry {
+    fn to_js(&self) -> Result<JsValue, DomException> {
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"db".into(), &JsValue::from_str(&self.write.db))?;
+        js_sys::Reflect::set(&obj, &"store".into(), &JsValue::from_str(&self.write.store))?;
+        js_sys::Reflect::set(&obj, &"key".into(), &self.write.key)?;
+        js_sys::Reflect::set(&obj, &"put".into(), &self.write.value.is_some().into())?;
+        if let Some(value) = self.write.value.as_ref() {
+            js_sys::Reflect::set(&obj, &"value".into(), value)?;
+        }
+        if let Some(previous) = self.previous.as_ref() {
+            js_sys::Reflect::set(&obj, &"previous".into(), previous)?;
+        }
+        Ok(obj.into())
+    }
+
+    fn from_js(obj: &JsValue) -> Result<Self, DomException> {
+        let field = |name: &str| {
+            js_sys::Reflect::get(obj, &name.into())
+                .map(crate::internal_utils::optional_jsvalue_undefined)
+        };
+        let string = |name: &str| -> Result<String, DomException> {
+            field(name)?
+                .and_then(|v| v.as_string())
+                .ok_or_else(|| dom_exception("Malformed journal record", "DataError"))
+        };
+        let value = match field("put")?.and_then(|v| v.as_bool()) {
+            Some(true) => Some(js_sys::Reflect::get(obj, &"value".into())?),
+            Some(false) => None,
+            None => return Err(dom_exception("Malformed journal record", "DataError")),
+        };
+        Ok(Self {
+            write: DbWrite {
+                db: string("db")?,
+                store: string("store")?,
+                key: field("key")?
+                    .ok_or_else(|| dom_exception("Malformed journal record", "DataError"))?,
+                value,
+            },
+            previous: field("previous")?,
+        })
+    }
+}
+
+/// Apply the writes across the given open databases, journaled in `journal_store` of `journal`.
+/// See the [module docs][self].
+///
+/// If a database's transaction fails, the databases written before it get rolled back and the
+/// error is returned. If the rollback, or removing the journal record afterwards, fails too, the
+/// record is left behind for [recover_cross_db_writes]: recovering with [Recovery::Rollback]
+/// finishes the rollback, while [Recovery::Retry] applies the writes even though this call
+/// failed.
+pub async fn cross_db_write(
+    journal: &IdbDatabase,
+    journal_store: &str,
+    dbs: &[&IdbDatabase],
+    writes: &[DbWrite],
+) -> Result<(), DomException> {
+    let groups = group_by_db(dbs, writes.to_vec())?;
+
+    let mut entries = Vec::with_capacity(writes.len());
+    for (db, writes) in groups.iter() {
+        entries.extend(read_previous(db, writes).await?);
+    }
+    let groups = group_by_db(dbs, entries)?;
+
+    let id = JsValue::from(Ulid::new());
+    let record = js_sys::Array::new();
+    for (_, entries) in groups.iter() {
+        for entry in entries.iter() {
+            record.push(&entry.to_js()?);
+        }
+    }
+    put_journal(journal, journal_store, &id, Some(&record.into())).await?;
+
+    for (idx, (db, entries)) in groups.iter().enumerate() {
+        if let Err(e) = apply(db, entries, Recovery::Retry).await {
+            for (db, entries) in groups[..idx].iter().rev() {
+                if apply(db, entries, Recovery::Rollback).await.is_err() {
+                    return Err(e);
+                }
+            }
+            let _ = put_journal(journal, journal_store, &id, None).await;
+            return Err(e);
+        }
+    }
+
+    put_journal(journal, journal_store, &id, None).await
+}
+
+/// Roll back or retry every write in the journal that was interrupted, e.g. by the tab getting
+/// killed. Every database the journal refers to must be among `dbs`. Resolves to the number of
+/// interrupted [cross_db_write] calls.
+pub async fn recover_cross_db_writes(
+    journal: &IdbDatabase,
+    journal_store: &str,
+    dbs: &[&IdbDatabase],
+    recovery: Recovery,
+) -> Result<usize, DomException> {
+    let records = {
+        let tx = journal.transaction_on_one(journal_store)?;
+        let store = tx.object_store(journal_store)?;
+        let keys = store.get_all_keys()?.await?;
+        let values = store.get_all()?.await?;
+        keys.iter().zip(values.iter()).collect::<Vec<_>>()
+    };
+
+    for (id, record) in records.iter() {
+        let entries = js_sys::Array::from(record)
+            .iter()
+            .map(|entry| Entry::from_js(&entry))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut groups = group_by_db(dbs, entries)?;
+        if recovery == Recovery::Rollback {
+            groups.reverse();
+        }
+        for (db, entries) in groups.iter() {
+            apply(db, entries, recovery).await?;
+        }
+        put_journal(journal, journal_store, id, None).await?;
+    }
+
+    Ok(records.len())
+}
+
+/// Split the items up by database, in order of each database's first appearance
+fn group_by_db<'a, T: AsRef<DbWrite>>(
+    dbs: &[&'a IdbDatabase],
+    items: Vec<T>,
+) -> Result<Vec<(&'a IdbDatabase, Vec<T>)>, DomException> {
+    let mut groups: Vec<(&'a IdbDatabase, Vec<T>)> = Vec::new();
+    for item in items {
+        let name = &item.as_ref().db;
+        match groups.iter_mut().find(|(db, _)| &db.name() == name) {
+            Some((_, group)) => group.push(item),
+            None => {
+                let db = dbs.iter().find(|db| &db.name() == name).ok_or_else(|| {
+                    dom_exception(&format!("Database {} is not open", name), "NotFoundError")
+                })?;
+                groups.push((db, vec![item]));
+            }
+        }
+    }
+    Ok(groups)
+}
+
+impl AsRef<DbWrite> for DbWrite {
+    fn as_ref(&self) -> &DbWrite {
+        self
+    }
+}
+
+impl AsRef<DbWrite> for Entry {
+    fn as_ref(&self) -> &DbWrite {
+        &self.write
+    }
+}
+
+fn store_names<T: AsRef<DbWrite>>(items: &[T]) -> Vec<&str> {
+    let mut names: Vec<&str> = Vec::new();
+    for item in items {
+        let name = item.as_ref().store.as_str();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+async fn read_previous(db: &IdbDatabase, writes: &[DbWrite]) -> Result<Vec<Entry>, DomException> {
+    let tx = db.transaction_on_multi(&store_names(writes))?;
+    let mut entries = Vec::with_capacity(writes.len());
+    for write in writes {
+        let store = tx.object_store(&write.store)?;
+        let previous = store.get(&write.key)?.await?;
+        entries.push(Entry {
+            write: write.clone(),
+            previous,
+        });
+    }
+    Ok(entries)
+}
+
+/// Write either the new values or, when rolling back, the previous ones, in one transaction
+async fn apply(
+    db: &IdbDatabase,
+    entries: &[Entry],
+    recovery: Recovery,
+) -> Result<(), DomException> {
+    let tx =
+        db.transaction_on_multi_with_mode(&store_names(entries), IdbTransactionMode::Readwrite)?;
+    let mut ordered: Box<dyn Iterator<Item = &Entry>> = match recovery {
+        Recovery::Retry => Box::new(entries.iter()),
+        Recovery::Rollback => Box::new(entries.iter().rev()),
+    };
+    let result = ordered.try_for_each(|entry| -> Result<(), DomException> {
+        let store = tx.object_store(&entry.write.store)?;
+        let value = match recovery {
+            Recovery::Retry => entry.write.value.as_ref(),
+            Recovery::Rollback => entry.previous.as_ref(),
+        };
+        match value {
+            Some(value) if store.key_path().is_some() => {
+                store.put_val(value)?;
+            }
+            Some(value) => {
+                store.put_key_val(&entry.write.key, value)?;
+            }
+            None => {
+                store.delete(&entry.write.key)?;
+            }
+        }
+        Ok(())
+    });
+    if let Err(e) = result {
+        // Don't let the requests made so far commit
+        let _ = tx.abort();
+        return Err(e);
+    }
+    tx.await.into_result()
+}
+
+/// Store the journal record at the id, or delete it if there's no record
+async fn put_journal(
+    journal: &IdbDatabase,
+    journal_store: &str,
+    id: &JsValue,
+    record: Option<&JsValue>,
+) -> Result<(), DomException> {
+    let tx = journal.transaction_on_one_with_mode(journal_store, IdbTransactionMode::Readwrite)?;
+    let store = tx.object_store(journal_store)?;
+    match record {
+        Some(record) => {
+            store.put_key_val(id, record)?;
+        }
+        None => {
+            store.delete(id)?;
+        }
+    }
+    tx.await.into_result()
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::{open_db_with, open_db_with_stores};
+
+    use super::*;
+
+    test_mod_init!();
+
+    async fn open_db(store: &str) -> IdbDatabase {
+        open_db_with_stores(&[store]).await
+    }
+
+    async fn open_db_with_key_path(store: &str, key_path: Option<&'static str>) -> IdbDatabase {
+        let store = store.to_string();
+        open_db_with(move |evt: &IdbVersionChangeEvent| {
+            let mut params = IdbObjectStoreParameters::new();
+            if let Some(key_path) = key_path {
+                params.key_path(Some(&IdbKeyPath::str(key_path)));
+            }
+            evt.db().create_object_store_with_params(&store, &params)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn read(db: &IdbDatabase, store: &str, key: u8) -> Option<JsValue> {
+        let tx = db.transaction_on_one(store).unwrap();
+        let store = tx.object_store(store).unwrap();
+        store.get_owned(key).unwrap().await.unwrap()
+    }
+
+    async fn journal_len(journal: &IdbDatabase) -> u32 {
+        let tx = journal.transaction_on_one("journal").unwrap();
+        let store = tx.object_store("journal").unwrap();
+        store.count().unwrap().await.unwrap()
+    }
+
+    test_case!(async commit => {
+        let journal = open_db("journal").await;
+        let a = open_db("a").await;
+        let b = open_db("b").await;
+        let writes = [
+            DbWrite::put(&a.name(), "a", 1u8, &JsValue::from(10u8)),
+            DbWrite::put(&b.name(), "b", 1u8, &JsValue::from(20u8)),
+        ];
+        cross_db_write(&journal, "journal", &[&a, &b], &writes).await.expect("write");
+
+        assert_eq!(read(&a, "a", 1).await, Some(JsValue::from(10u8)), "a");
+        assert_eq!(read(&b, "b", 1).await, Some(JsValue::from(20u8)), "b");
+        assert_eq!(journal_len(&journal).await, 0, "journal");
+    });
+
+    test_case!(async rollback_on_failure => {
+        let journal = open_db("journal").await;
+        let a = open_db("a").await;
+        // Values without an id get rejected by b
+        let b = open_db_with_key_path("b", Some("id")).await;
+        let tx = a.transaction_on_one_with_mode("a", IdbTransactionMode::Readwrite).unwrap();
+        tx.object_store("a").unwrap().put_key_val_owned(1u8, &JsValue::from(1u8)).unwrap();
+        tx.await.into_result().unwrap();
+
+        let writes = [
+            DbWrite::put(&a.name(), "a", 1u8, &JsValue::from(10u8)),
+            DbWrite::put(&a.name(), "a", 2u8, &JsValue::from(11u8)),
+            DbWrite::put(&b.name(), "b", 1u8, &JsValue::from(20u8)),
+        ];
+        cross_db_write(&journal, "journal", &[&a, &b], &writes)
+            .await
+            .expect_err("write");
+
+        assert_eq!(read(&a, "a", 1).await, Some(JsValue::from(1u8)), "restored");
+        assert_eq!(read(&a, "a", 2).await, None, "deleted");
+        assert_eq!(journal_len(&journal).await, 0, "journal");
+    });
+
+    test_case!(async recover_undefined_put => {
+        let journal = open_db("journal").await;
+        let a = open_db("a").await;
+        let entries = js_sys::Array::new();
+        for entry in [
+            Entry { write: DbWrite::put(&a.name(), "a", 1u8, &JsValue::UNDEFINED), previous: None },
+            Entry { write: DbWrite::delete(&a.name(), "a", 2u8), previous: Some(JsValue::from(20u8)) },
+        ] {
+            let js = entry.to_js().unwrap();
+            let parsed = Entry::from_js(&js).expect("parse");
+            assert_eq!(parsed.write.value.is_some(), entry.write.value.is_some(), "put flag");
+            entries.push(&js);
+        }
+        put_journal(&journal, "journal", &"id".into(), Some(&entries.into()))
+            .await
+            .unwrap();
+
+        recover_cross_db_writes(&journal, "journal", &[&a], Recovery::Retry)
+            .await
+            .expect("recover");
+        let tx = a.transaction_on_one("a").unwrap();
+        let store = tx.object_store("a").unwrap();
+        assert_eq!(store.count_with_key_owned(1u8).unwrap().await.unwrap(), 1, "undefined put");
+        assert_eq!(store.count_with_key_owned(2u8).unwrap().await.unwrap(), 0, "delete");
+    });
+
+    test_case!(async recover => {
+        let journal = open_db("journal").await;
+        let a = open_db("a").await;
+        let entries = js_sys::Array::new();
+        let entry = Entry {
+            write: DbWrite::put(&a.name(), "a", 1u8, &JsValue::from(10u8)),
+            previous: None,
+        };
+        entries.push(&entry.to_js().unwrap());
+        put_journal(&journal, "journal", &"id".into(), Some(&entries.into()))
+            .await
+            .unwrap();
+
+        let recovered = recover_cross_db_writes(&journal, "journal", &[&a], Recovery::Retry)
+            .await
+            .expect("recover");
+        assert_eq!(recovered, 1, "recovered");
+        assert_eq!(read(&a, "a", 1).await, Some(JsValue::from(10u8)), "retried");
+        assert_eq!(journal_len(&journal).await, 0, "journal");
+    });
+}
@@ -1,39 +1,87 @@
+//! Iterating over the names of object stores and indices
+
+use std::iter::FusedIterator;
+
 use web_sys::DomStringList;
 
-/// An [Iterator] for a [DomStringList]
-pub(crate) struct DomStringIterator {
+/// An [Iterator] for a [DomStringList], e.g. [IdbDatabase::object_store_names] or
+/// [IdbObjectStore::index_names]. Knows its length and iterates from both ends without
+/// collecting the list.
+///
+/// [IdbDatabase::object_store_names]: crate::IdbDatabase::object_store_names
+/// [IdbObjectStore::index_names]: crate::idb_object_store::IdbObjectStore
+#[derive(Debug, Clone)]
+pub struct DomStringIterator {
     inner: DomStringList,
     idx: u32,
+    end: u32,
 }
 
 impl From<DomStringList> for DomStringIterator {
     #[inline]
     fn from(inner: DomStringList) -> Self {
-        Self { inner, idx: 0 }
+        let end = inner.length();
+        Self { inner, idx: 0, end }
     }
 }
 
 impl DomStringIterator {
+    /// The underlying web_sys list
     #[inline]
-    fn len(&self) -> u32 {
-        self.inner.length()
+    pub fn as_web_sys(&self) -> &DomStringList {
+        &self.inner
     }
 }
 
-impl<'a> Iterator for DomStringIterator {
+impl Iterator for DomStringIterator {
     type Item = String;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(v) = self.inner.item(self.idx) {
-            self.idx += 1;
-            Some(v)
-        } else {
-            None
+        if self.idx >= self.end {
+            return None;
         }
+        let v = self.inner.item(self.idx)?;
+        self.idx += 1;
+        Some(v)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let out = (self.len() - self.idx) as usize;
+        let out = (self.end - self.idx) as usize;
         (out, Some(out))
     }
 }
+
+impl DoubleEndedIterator for DomStringIterator {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.idx >= self.end {
+            return None;
+        }
+        let v = self.inner.item(self.end - 1)?;
+        self.end -= 1;
+        Some(v)
+    }
+}
+
+impl ExactSizeIterator for DomStringIterator {}
+
+impl FusedIterator for DomStringIterator {}
+
+#[cfg(test)]
+pub mod test {
+    use crate::test_utils::open_db_with_stores;
+
+    test_mod_init!();
+
+    test_case!(async double_ended => {
+        let db = open_db_with_stores(&["a", "b", "c"]).await;
+
+        let mut names = db.object_store_names();
+        assert_eq!(names.len(), 3, "len");
+        assert_eq!(names.next_back().as_deref(), Some("c"), "back");
+        assert_eq!(names.next().as_deref(), Some("a"), "front");
+        assert_eq!(names.len(), 1, "remaining");
+        assert_eq!(names.next_back().as_deref(), Some("b"), "middle");
+        assert_eq!((names.next(), names.next_back()), (None, None), "exhausted");
+        assert_eq!(db.object_store_names().rev().collect::<Vec<_>>(), vec!["c", "b", "a"], "rev");
+    });
+}
@@ -0,0 +1,206 @@
+//! Seeding databases from JSON descriptions, for reproducible browser tests and demos
+//!
+//! Features required: `serde_json`
+//!
+//! A fixture maps object store names to their records and, for [load_in_upgrade], to how the
+//! store should be created. Stores with a `keyPath` take their records as they are; stores
+//! without one take `{"key": ..., "value": ...}` entries.
+//!
+//! ```json
+//! {
+//!     "users": {
+//!         "keyPath": "id",
+//!         "indices": {"by_email": {"keyPath": "email", "unique": true}},
+//!         "records": [{"id": 1, "email": "a@example.com"}]
+//!     },
+//!     "settings": {
+//!         "records": [{"key": "theme", "value": "dark"}]
+//!     }
+//! }
+//! ```
+//!
+//! The optional `autoIncrement` flag and `indices` only matter when the store gets created;
+//! indices need the `indices` feature.
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::idb_database::{IdbDatabase, IdbVersionChangeEvent};
+use crate::idb_key_path::IdbKeyPath;
+use crate::idb_object_store::{IdbObjectStore, IdbObjectStoreParameters};
+use crate::internal_utils::dom_exception;
+
+type Map = serde_json::Map<String, serde_json::Value>;
+
+/// Insert the fixture's records into an open database in a single transaction, overwriting
+/// records with the same keys. Every store must already exist. Resolves to the number of
+/// records inserted.
+///
+/// Features required: `serde_json`
+pub async fn load(db: &IdbDatabase, fixture: &serde_json::Value) -> Result<u32, DomException> {
+    let stores = stores(fixture)?;
+    if stores.is_empty() {
+        return Ok(0);
+    }
+    let names = stores.keys().map(String::as_str).collect::<Vec<_>>();
+    let tx = db.transaction_on_multi_with_mode(&names, IdbTransactionMode::Readwrite)?;
+    let mut count = 0;
+    for (name, spec) in stores.iter() {
+        count += put_records(&tx.object_store(name)?, name, spec)?;
+    }
+    tx.await.into_result()?;
+    Ok(count)
+}
+
+/// Create the fixture's stores that don't exist yet, along with their indices, and insert its
+/// records through the upgrade's `versionchange` transaction. Call from an `upgradeneeded`
+/// callback; the records are written once the upgrade completes. Returns the number of records
+/// inserted.
+///
+/// Features required: `serde_json`
+pub fn load_in_upgrade(
+    evt: &IdbVersionChangeEvent,
+    fixture: &serde_json::Value,
+) -> Result<u32, DomException> {
+    let stores = stores(fixture)?;
+    let db = evt.db();
+    for (name, spec) in stores.iter() {
+        if !db.has_store(name) {
+            create_store(db, name, object(spec, name)?)?;
+        }
+    }
+
+    let tx = evt
+        .transaction()
+        .ok_or_else(|| dom_exception("Not in an upgrade", "InvalidStateError"))?;
+    let mut count = 0;
+    for (name, spec) in stores.iter() {
+        count += put_records(&tx.object_store(name)?, name, spec)?;
+    }
+    Ok(count)
+}
+
+fn stores(fixture: &serde_json::Value) -> Result<&Map, DomException> {
+    fixture
+        .as_object()
+        .ok_or_else(|| invalid("the fixture must map store names to stores"))
+}
+
+fn object<'a>(spec: &'a serde_json::Value, store: &str) -> Result<&'a Map, DomException> {
+    spec.as_object()
+        .ok_or_else(|| invalid(&format!("store {} must be an object", store)))
+}
+
+fn create_store(db: &IdbDatabase, name: &str, spec: &Map) -> Result<(), DomException> {
+    let mut params = IdbObjectStoreParameters::new();
+    let store_key_path = spec.get("keyPath").map(key_path).transpose()?;
+    params.key_path(store_key_path.as_ref());
+    if let Some(auto_increment) = spec.get("autoIncrement").and_then(|v| v.as_bool()) {
+        params.auto_increment(auto_increment);
+    }
+    #[allow(unused_variables)]
+    let store = db.create_object_store_with_params(name, &params)?;
+
+    #[cfg(feature = "indices")]
+    if let Some(indices) = spec.get("indices") {
+        let indices = indices
+            .as_object()
+            .ok_or_else(|| invalid(&format!("indices of store {} must be an object", name)))?;
+        for (index, index_spec) in indices.iter() {
+            let index_key_path = index_spec
+                .get("keyPath")
+                .ok_or_else(|| invalid(&format!("index {} needs a keyPath", index)))?;
+            let mut params = web_sys::IdbIndexParameters::new();
+            params.unique(index_spec.get("unique").and_then(|v| v.as_bool()) == Some(true));
+            params
+                .multi_entry(index_spec.get("multiEntry").and_then(|v| v.as_bool()) == Some(true));
+            store.create_index_with_params(index, &key_path(index_key_path)?, &params)?;
+        }
+    }
+    Ok(())
+}
+
+fn put_records(
+    store: &IdbObjectStore<'_>,
+    name: &str,
+    spec: &serde_json::Value,
+) -> Result<u32, DomException> {
+    let records = match object(spec, name)?.get("records") {
+        Some(records) => records
+            .as_array()
+            .ok_or_else(|| invalid(&format!("records of store {} must be an array", name)))?,
+        None => return Ok(0),
+    };
+    let in_line = !store.as_web_sys().key_path().map_or(true, |p| p.is_null());
+    for record in records.iter() {
+        if in_line {
+            store.put_val(&to_js(record)?)?;
+        } else {
+            let key = record
+                .get("key")
+                .ok_or_else(|| invalid(&format!("records of store {} need a key", name)))?;
+            let value = record.get("value").unwrap_or(&serde_json::Value::Null);
+            store.put_key_val(&to_js(key)?, &to_js(value)?)?;
+        }
+    }
+    Ok(records.len() as u32)
+}
+
+fn key_path(value: &serde_json::Value) -> Result<IdbKeyPath, DomException> {
+    Ok(IdbKeyPath::new(to_js(value)?))
+}
+
+fn to_js(value: &serde_json::Value) -> Result<JsValue, DomException> {
+    crate::values::from_json(value).map_err(|e| invalid(&e.to_string()))
+}
+
+fn invalid(message: &str) -> DomException {
+    dom_exception(&format!("Invalid fixture: {}", message), "DataError")
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::test_utils::open_db_with;
+    use serde_json::json;
+
+    use crate::prelude::*;
+
+    use super::*;
+
+    test_mod_init!();
+
+    #[cfg(feature = "indices")]
+    test_case!(async load_fixture => {
+        let fixture = json!({
+            "users": {
+                "keyPath": "id",
+                "indices": {"by_email": {"keyPath": "email", "unique": true}},
+                "records": [{"id": 1, "email": "a@example.com"}, {"id": 2, "email": "b@example.com"}]
+            },
+            "settings": {
+                "records": [{"key": "theme", "value": "dark"}]
+            }
+        });
+        let fixture_cb = fixture.clone();
+        let db = open_db_with(move |evt: &IdbVersionChangeEvent| {
+            assert_eq!(load_in_upgrade(evt, &fixture_cb)?, 3, "loaded in upgrade");
+            Ok(())
+        })
+        .await;
+        assert!(db.has_store("users") && db.has_store("settings"), "stores created");
+
+        let tx = db.transaction_on_multi(&["users", "settings"]).unwrap();
+        let users = tx.object_store("users").unwrap();
+        let by_email = users.index("by_email").unwrap().get_owned("b@example.com").unwrap().await.unwrap();
+        assert_eq!(crate::values::to_json(&by_email.expect("indexed")).unwrap()["id"], json!(2), "index");
+        let theme = tx.object_store("settings").unwrap().get_owned("theme").unwrap().await.unwrap();
+        assert_eq!(theme, Some(JsValue::from("dark")), "out-of-line");
+        drop(users);
+        drop(tx);
+
+        let more = json!({"settings": {"records": [{"key": "lang", "value": "en"}]}});
+        assert_eq!(load(&db, &more).await.expect("load"), 1, "loaded");
+        let err = load(&db, &json!({"settings": {"records": [{"value": 1}]}})).await.expect_err("no key");
+        assert_eq!(err.name(), "DataError", "invalid");
+    });
+}
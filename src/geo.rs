@@ -0,0 +1,369 @@
+//! Location queries over a geohash index
+//!
+//! Features required: `geo`
+//!
+//! A [geohash](https://en.wikipedia.org/wiki/Geohash) encodes a latitude/longitude pair as a
+//! string in which every additional character narrows the cell down further, so all the points in
+//! a cell share its hash as a prefix and a cell is a single key range of a string index. A
+//! [GeoIndex] stores each record's geohash in a shadow field, `__geo.<index name>`, indexes it,
+//! and answers [near][GeoIndex::near] queries by scanning the cell around the query point and its
+//! eight neighbours, then filtering by precise distance.
+
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{DomException, IdbIndexParameters, IdbKeyRange};
+
+use crate::idb_index::IdbIndex;
+use crate::idb_key_path::IdbKeyPath;
+use crate::idb_object_store::{IdbObjectStore, Middleware, MiddlewareContext};
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::dom_exception;
+
+const SHADOW_FIELD: &str = "__geo";
+const BASE32: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+const EARTH_RADIUS: f64 = 6_371_008.8;
+const METERS_PER_DEGREE: f64 = EARTH_RADIUS * std::f64::consts::PI / 180.0;
+
+/// Encode the coordinates as a geohash with the given number of characters
+///
+/// Features required: `geo`
+pub fn encode_geohash(lat: f64, lng: f64, precision: usize) -> String {
+    let mut lat_range = (-90.0, 90.0);
+    let mut lng_range = (-180.0, 180.0);
+    let mut out = String::with_capacity(precision);
+    let mut lng_bit = true;
+    let (mut bits, mut ch) = (0, 0usize);
+
+    while out.len() < precision {
+        let (range, value) = if lng_bit {
+            (&mut lng_range, lng)
+        } else {
+            (&mut lat_range, lat)
+        };
+        let mid = (range.0 + range.1) / 2.0;
+        ch <<= 1;
+        if value >= mid {
+            ch |= 1;
+            range.0 = mid;
+        } else {
+            range.1 = mid;
+        }
+        lng_bit = !lng_bit;
+        bits += 1;
+        if bits == 5 {
+            out.push(char::from(BASE32[ch]));
+            bits = 0;
+            ch = 0;
+        }
+    }
+    out
+}
+
+/// The great-circle distance between two points in metres
+///
+/// Features required: `geo`
+pub fn distance(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let d_phi = (lat2 - lat1).to_radians();
+    let d_lambda = (lng2 - lng1).to_radians();
+    let a = (d_phi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (d_lambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+}
+
+/// The height and width in degrees of a geohash cell with the given number of characters
+fn cell_size(precision: usize) -> (f64, f64) {
+    let bits = 5 * precision as i32;
+    let lat_bits = bits / 2;
+    let lng_bits = bits - lat_bits;
+    (180.0 / 2f64.powi(lat_bits), 360.0 / 2f64.powi(lng_bits))
+}
+
+/// A record found by [GeoIndex::near]
+///
+/// Features required: `geo`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoMatch {
+    /// The record's primary key
+    pub primary_key: JsValue,
+    /// The record
+    pub value: JsValue,
+    /// The record's distance from the query point in metres
+    pub distance: f64,
+}
+
+/// A geohash index on records with latitude and longitude fields. See the [module docs][self].
+///
+/// Index names must not contain dots.
+///
+/// Features required: `geo`
+#[derive(Debug, Clone)]
+pub struct GeoIndex {
+    index_name: String,
+    lat: IdbKeyPath,
+    lng: IdbKeyPath,
+    precision: usize,
+}
+
+impl GeoIndex {
+    /// Describe a geohash index populated from the given dotted latitude and longitude key paths
+    pub fn new(index_name: &str, lat_path: &str, lng_path: &str) -> Self {
+        Self {
+            index_name: index_name.into(),
+            lat: IdbKeyPath::str(lat_path),
+            lng: IdbKeyPath::str(lng_path),
+            precision: 9,
+        }
+    }
+
+    /// Set how many characters of geohash to store, between 1 and 12. Defaults to 9, or cells of
+    /// roughly 5 metres. Changing it requires rewriting every record.
+    #[inline]
+    pub fn precision(mut self, precision: usize) -> Self {
+        self.precision = precision.clamp(1, 12);
+        self
+    }
+
+    /// The key path the index is created on
+    pub fn shadow_path(&self) -> String {
+        format!("{}.{}", SHADOW_FIELD, self.index_name)
+    }
+
+    /// Create the index on the shadow field. Must be called during an upgrade.
+    pub fn create_index<'a>(
+        &self,
+        store: &'a IdbObjectStore<'a>,
+        params: Option<&IdbIndexParameters>,
+    ) -> Result<IdbIndex<'a>, DomException> {
+        let key_path = IdbKeyPath::str(&self.shadow_path());
+        match params {
+            Some(params) => store.create_index_with_params(&self.index_name, &key_path, params),
+            None => store.create_index(&self.index_name, &key_path),
+        }
+    }
+
+    /// Write the geohash of a value that's about to be written into its shadow field, removing it
+    /// if the value no longer has valid coordinates. Non-object values are left alone.
+    pub fn prepare(&self, value: &JsValue) -> Result<(), DomException> {
+        if !value.is_object() {
+            return Ok(());
+        }
+        let shadow = js_sys::Reflect::get(value, &JsValue::from_str(SHADOW_FIELD))?;
+        match self.coordinates(value) {
+            Some((lat, lng)) => {
+                let shadow = if shadow.is_object() {
+                    shadow
+                } else {
+                    let obj: JsValue = js_sys::Object::new().into();
+                    js_sys::Reflect::set(value, &JsValue::from_str(SHADOW_FIELD), &obj)?;
+                    obj
+                };
+                let hash = encode_geohash(lat, lng, self.precision);
+                js_sys::Reflect::set(
+                    &shadow,
+                    &JsValue::from_str(&self.index_name),
+                    &JsValue::from(hash),
+                )?;
+            }
+            None if shadow.is_object() => {
+                js_sys::Reflect::delete_property(
+                    shadow.unchecked_ref(),
+                    &JsValue::from_str(&self.index_name),
+                )?;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Find the records within `radius` metres of the given point, nearest first. The store must
+    /// have the index [created][GeoIndex::create_index] on it.
+    pub async fn near(
+        &self,
+        store: &IdbObjectStore<'_>,
+        lat: f64,
+        lng: f64,
+        radius: f64,
+    ) -> Result<Vec<GeoMatch>, DomException> {
+        if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lng) || radius.is_nan() {
+            return Err(dom_exception("Invalid coordinates or radius", "DataError"));
+        }
+        let index = store.index(&self.index_name)?;
+
+        let mut matches = Vec::new();
+        match self.search_precision(lat, radius) {
+            Some(precision) => {
+                let (height, width) = cell_size(precision);
+                let mut cells = BTreeSet::new();
+                for d_lat in [-1.0, 0.0, 1.0] {
+                    let cell_lat = lat + d_lat * height;
+                    if !(-90.0..=90.0).contains(&cell_lat) {
+                        continue;
+                    }
+                    for d_lng in [-1.0, 0.0, 1.0] {
+                        let cell_lng = (lng + d_lng * width + 540.0) % 360.0 - 180.0;
+                        cells.insert(encode_geohash(cell_lat, cell_lng, precision));
+                    }
+                }
+                for cell in cells {
+                    let range = IdbKeyRange::bound(
+                        &JsValue::from_str(&cell),
+                        &JsValue::from(format!("{}\u{ffff}", cell)),
+                    )?;
+                    self.collect(&index, &range, lat, lng, radius, &mut matches)
+                        .await?;
+                }
+            }
+            None => {
+                self.collect(&index, &JsValue::UNDEFINED, lat, lng, radius, &mut matches)
+                    .await?
+            }
+        }
+
+        matches.sort_by(|a, b| {
+            a.distance
+                .partial_cmp(&b.distance)
+                .unwrap_or(Ordering::Equal)
+        });
+        Ok(matches)
+    }
+
+    /// The longest hash prefix whose cells are at least `radius` across everywhere the search
+    /// reaches, so that the nine cells around the query point cover the whole circle. `None` if
+    /// even the largest cells don't, e.g. near the poles, in which case the whole index gets
+    /// scanned.
+    fn search_precision(&self, lat: f64, radius: f64) -> Option<usize> {
+        let lat_radius = radius / METERS_PER_DEGREE;
+        let max_lat = (lat.abs() + lat_radius).min(90.0);
+        let lng_radius = radius / (METERS_PER_DEGREE * max_lat.to_radians().cos());
+        (1..=self.precision).rev().find(|&precision| {
+            let (height, width) = cell_size(precision);
+            height >= lat_radius && width >= lng_radius
+        })
+    }
+
+    async fn collect<K: JsCast>(
+        &self,
+        index: &IdbIndex<'_>,
+        range: &K,
+        lat: f64,
+        lng: f64,
+        radius: f64,
+        out: &mut Vec<GeoMatch>,
+    ) -> Result<(), DomException> {
+        let keys = index.get_all_keys_with_key(range)?;
+        let values = index.get_all_with_key(range)?;
+        let (keys, values) = (keys.await?, values.await?);
+        for (primary_key, value) in keys.iter().zip(values.iter()) {
+            if let Some((point_lat, point_lng)) = self.coordinates(&value) {
+                let distance = distance(lat, lng, point_lat, point_lng);
+                if distance <= radius {
+                    out.push(GeoMatch {
+                        primary_key,
+                        value,
+                        distance,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn coordinates(&self, value: &JsValue) -> Option<(f64, f64)> {
+        let lat = self.lat.evaluate(value)?.as_f64()?;
+        let lng = self.lng.evaluate(value)?.as_f64()?;
+        if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lng) {
+            Some((lat, lng))
+        } else {
+            None
+        }
+    }
+}
+
+impl Middleware for GeoIndex {
+    fn on_write(&self, _: &MiddlewareContext, value: JsValue) -> Result<JsValue, DomException> {
+        self.prepare(&value)?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::open_db_with;
+
+    use super::*;
+
+    test_mod_init!();
+
+    test_case!(geohash => {
+        assert_eq!(encode_geohash(57.64911, 10.40744, 11), "u4pruydqqvj", "wikipedia example");
+        assert_eq!(encode_geohash(0.0, 0.0, 1), "s", "origin");
+        assert_eq!(encode_geohash(-90.0, -180.0, 3), "000", "corner");
+    });
+
+    test_case!(distances => {
+        let paris_london = distance(48.8566, 2.3522, 51.5074, -0.1278);
+        assert!((paris_london - 343_500.0).abs() < 1_000.0, "paris-london: {}", paris_london);
+        assert_eq!(distance(10.0, 10.0, 10.0, 10.0), 0.0, "same point");
+    });
+
+    fn place(name: &str, lat: f64, lng: f64) -> JsValue {
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"name".into(), &name.into()).unwrap();
+        let pos = js_sys::Object::new();
+        js_sys::Reflect::set(&pos, &"lat".into(), &lat.into()).unwrap();
+        js_sys::Reflect::set(&pos, &"lng".into(), &lng.into()).unwrap();
+        js_sys::Reflect::set(&obj, &"pos".into(), &pos).unwrap();
+        obj.into()
+    }
+
+    test_case!(async near => {
+        let geo = GeoIndex::new("by_location", "pos.lat", "pos.lng");
+        let upgrade_geo = geo.clone();
+        let db = open_db_with(move |evt: &IdbVersionChangeEvent| {
+            let store = evt.db().create_object_store("places")?;
+            upgrade_geo.create_index(&store, None)?;
+            Ok(())
+        }).await;
+
+        let tx = db.transaction_on_one_with_mode("places", IdbTransactionMode::Readwrite).expect("tx");
+        let store = tx.object_store("places").expect("store");
+        let places = [
+            ("louvre", 48.8606, 2.3376),
+            ("notre dame", 48.8530, 2.3499),
+            ("eiffel", 48.8584, 2.2945),
+            ("london", 51.5074, -0.1278),
+        ];
+        for (i, (name, lat, lng)) in places.iter().enumerate() {
+            let value = place(name, *lat, *lng);
+            geo.prepare(&value).expect("prepare");
+            store.put_key_val_owned(i as u32, &value).expect("put");
+        }
+        let no_coords = JsValue::from(js_sys::Object::new());
+        geo.prepare(&no_coords).expect("prepare no coords");
+        store.put_key_val_owned(99u32, &no_coords).expect("put no coords");
+
+        let name = |m: &GeoMatch| {
+            js_sys::Reflect::get(&m.value, &"name".into()).unwrap().as_string().unwrap()
+        };
+        let center = (48.8566, 2.3522);
+
+        let close = geo.near(&store, center.0, center.1, 2_000.0).await.expect("2km");
+        assert_eq!(close.iter().map(name).collect::<Vec<_>>(), vec!["notre dame", "louvre"], "2km");
+        assert!(close[0].distance < close[1].distance, "ordered");
+
+        let city = geo.near(&store, center.0, center.1, 5_000.0).await.expect("5km");
+        assert_eq!(city.len(), 3, "5km");
+
+        let far = geo.near(&store, center.0, center.1, 1_000_000.0).await.expect("1000km");
+        assert_eq!(far.len(), 4, "1000km");
+        assert_eq!(far[3].primary_key, JsValue::from(3u32), "furthest");
+
+        let distant = geo.near(&store, -33.86, 151.21, 10_000.0).await.expect("sydney");
+        assert!(distant.is_empty(), "sydney");
+
+        assert!(geo.near(&store, 91.0, 0.0, 10.0).await.is_err(), "invalid");
+    });
+}
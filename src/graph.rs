@@ -0,0 +1,316 @@
+//! A small graph layer over two object stores
+//!
+//! Features required: `indices`
+//!
+//! A [Graph] keeps its nodes in one store, keyed by node ID, and its edges in another, keyed by
+//! `[from, to]` compound keys so that a node's outgoing edges are a single key range. Each edge
+//! record also holds both IDs so that an index on `to` can answer reverse lookups. Node IDs can be
+//! any valid key except arrays.
+//!
+//! Every operation runs in its own transaction; the ones touching several records, such as
+//! [remove_node_cascade][Graph::remove_node_cascade], are atomic.
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::graph::Graph;
+//! use wasm_bindgen::prelude::*;
+//! use web_sys::DomException;
+//!
+//! async fn example() -> Result<(), DomException> {
+//!     let mut req = IdbDatabase::open("social")?;
+//!     req.set_on_upgrade_needed(Some(|evt: &IdbVersionChangeEvent| {
+//!         Ok(Graph::create_stores(evt.db(), "people", "follows")?)
+//!     }));
+//!     let db = req.into_future().await?;
+//!
+//!     let graph = Graph::new(&db, "people", "follows");
+//!     graph.put_node(&"alice".into(), &JsValue::NULL).await?;
+//!     graph.put_node(&"bob".into(), &JsValue::NULL).await?;
+//!     graph.add_edge(&"alice".into(), &"bob".into(), &JsValue::NULL).await?;
+//!     let _followers: Vec<JsValue> = graph.incoming(&"bob".into()).await?;
+//!     Ok(())
+//! }
+//! ```
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{DomException, IdbKeyRange, IdbTransactionMode};
+
+use crate::idb_database::IdbDatabase;
+use crate::idb_key_path::IdbKeyPath;
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::{dom_exception, keys_eq};
+
+/// The name of the reverse index on the edges store
+pub const REVERSE_INDEX: &str = "by_to";
+
+/// An edge as stored in the edges store
+///
+/// Features required: `indices`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    /// The node the edge starts at
+    pub from: JsValue,
+    /// The node the edge points to
+    pub to: JsValue,
+    /// Data attached to the edge
+    pub data: JsValue,
+}
+
+impl Edge {
+    fn to_js(&self) -> Result<JsValue, DomException> {
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"from".into(), &self.from)?;
+        js_sys::Reflect::set(&obj, &"to".into(), &self.to)?;
+        js_sys::Reflect::set(&obj, &"data".into(), &self.data)?;
+        Ok(obj.into())
+    }
+
+    fn from_js(value: &JsValue) -> Result<Self, DomException> {
+        Ok(Self {
+            from: js_sys::Reflect::get(value, &"from".into())?,
+            to: js_sys::Reflect::get(value, &"to".into())?,
+            data: js_sys::Reflect::get(value, &"data".into())?,
+        })
+    }
+}
+
+/// A graph stored in a nodes store and an edges store. See the [module docs][self].
+///
+/// Features required: `indices`
+#[derive(Debug)]
+pub struct Graph<'a> {
+    db: &'a IdbDatabase,
+    nodes: String,
+    edges: String,
+}
+
+impl<'a> Graph<'a> {
+    /// Use the given stores, which must have been set up by [create_stores][Graph::create_stores]
+    pub fn new(db: &'a IdbDatabase, nodes_store: &str, edges_store: &str) -> Self {
+        Self {
+            db,
+            nodes: nodes_store.into(),
+            edges: edges_store.into(),
+        }
+    }
+
+    /// Create the nodes store and the edges store with its [reverse index][REVERSE_INDEX]. Must be
+    /// called during an upgrade.
+    pub fn create_stores(
+        db: &IdbDatabase,
+        nodes_store: &str,
+        edges_store: &str,
+    ) -> Result<(), DomException> {
+        db.create_object_store(nodes_store)?;
+        db.create_object_store(edges_store)?
+            .create_index(REVERSE_INDEX, &IdbKeyPath::str("to"))?;
+        Ok(())
+    }
+
+    /// Insert or replace a node
+    pub async fn put_node(&self, id: &JsValue, value: &JsValue) -> Result<(), DomException> {
+        check_id(id)?;
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.nodes, IdbTransactionMode::Readwrite)?;
+        tx.object_store(&self.nodes)?.put_key_val(id, value)?;
+        tx.await.into_result()
+    }
+
+    /// Get a node's value
+    pub async fn get_node(&self, id: &JsValue) -> Result<Option<JsValue>, DomException> {
+        let tx = self.db.transaction_on_one(&self.nodes)?;
+        let store = tx.object_store(&self.nodes)?;
+        let value = store.get(id)?.await?;
+        Ok(value)
+    }
+
+    /// Add an edge between two existing nodes, replacing any existing edge between them. Fails
+    /// with a `NotFoundError` if either node doesn't exist.
+    pub async fn add_edge(
+        &self,
+        from: &JsValue,
+        to: &JsValue,
+        data: &JsValue,
+    ) -> Result<(), DomException> {
+        let tx = self.db.transaction_on_multi_with_mode(
+            &[&self.nodes, &self.edges],
+            IdbTransactionMode::Readwrite,
+        )?;
+        let nodes = tx.object_store(&self.nodes)?;
+        let from_exists = nodes.get_key(from)?;
+        let to_exists = nodes.get_key(to)?;
+        if from_exists.await?.is_none() || to_exists.await?.is_none() {
+            tx.abort()?;
+            return Err(dom_exception(
+                "Both nodes must exist to add an edge between them",
+                "NotFoundError",
+            ));
+        }
+
+        let edge = Edge {
+            from: from.clone(),
+            to: to.clone(),
+            data: data.clone(),
+        };
+        tx.object_store(&self.edges)?
+            .put_key_val(&edge_key(from, to), &edge.to_js()?)?;
+        tx.await.into_result()
+    }
+
+    /// Remove the edge between two nodes, if there is one
+    pub async fn remove_edge(&self, from: &JsValue, to: &JsValue) -> Result<(), DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.edges, IdbTransactionMode::Readwrite)?;
+        tx.object_store(&self.edges)?.delete(&edge_key(from, to))?;
+        tx.await.into_result()
+    }
+
+    /// Get the edge between two nodes
+    pub async fn edge(&self, from: &JsValue, to: &JsValue) -> Result<Option<Edge>, DomException> {
+        let tx = self.db.transaction_on_one(&self.edges)?;
+        let store = tx.object_store(&self.edges)?;
+        let value = store.get(&edge_key(from, to))?.await?;
+        value.as_ref().map(Edge::from_js).transpose()
+    }
+
+    /// The IDs of the nodes the node has edges to
+    pub async fn neighbors(&self, node: &JsValue) -> Result<Vec<JsValue>, DomException> {
+        let tx = self.db.transaction_on_one(&self.edges)?;
+        let store = tx.object_store(&self.edges)?;
+        let keys = store.get_all_keys_with_key(&outgoing_range(node)?)?.await?;
+        Ok(keys.iter().map(|key| key_part(&key, 1)).collect())
+    }
+
+    /// The node's outgoing edges
+    pub async fn edges_from(&self, node: &JsValue) -> Result<Vec<Edge>, DomException> {
+        let tx = self.db.transaction_on_one(&self.edges)?;
+        let store = tx.object_store(&self.edges)?;
+        let values = store.get_all_with_key(&outgoing_range(node)?)?.await?;
+        values.iter().map(|v| Edge::from_js(&v)).collect()
+    }
+
+    /// The IDs of the nodes with edges to the node
+    pub async fn incoming(&self, node: &JsValue) -> Result<Vec<JsValue>, DomException> {
+        let tx = self.db.transaction_on_one(&self.edges)?;
+        let store = tx.object_store(&self.edges)?;
+        let keys = store
+            .index(REVERSE_INDEX)?
+            .get_all_keys_with_key(node)?
+            .await?;
+        Ok(keys.iter().map(|key| key_part(&key, 0)).collect())
+    }
+
+    /// Remove the node along with all of its incoming and outgoing edges in one transaction.
+    /// Resolves to the number of edges removed.
+    pub async fn remove_node_cascade(&self, node: &JsValue) -> Result<u32, DomException> {
+        let tx = self.db.transaction_on_multi_with_mode(
+            &[&self.nodes, &self.edges],
+            IdbTransactionMode::Readwrite,
+        )?;
+        let edges = tx.object_store(&self.edges)?;
+        let outgoing = outgoing_range(node)?;
+        let outgoing_count = edges.count_with_key(&outgoing)?;
+        let incoming = edges.index(REVERSE_INDEX)?.get_all_keys_with_key(node)?;
+        let (outgoing_count, incoming) = (outgoing_count.await?, incoming.await?);
+
+        tx.object_store(&self.nodes)?.delete(node)?;
+        edges.delete(&outgoing)?;
+        let mut removed = outgoing_count;
+        for key in incoming.iter() {
+            // Self-loops were already removed with the outgoing edges
+            if !keys_eq(&key_part(&key, 0), node) {
+                edges.delete(&key)?;
+                removed += 1;
+            }
+        }
+        tx.await.into_result()?;
+        Ok(removed)
+    }
+}
+
+fn check_id(id: &JsValue) -> Result<(), DomException> {
+    if js_sys::Array::is_array(id) {
+        Err(dom_exception("Node IDs can't be arrays", "DataError"))
+    } else {
+        Ok(())
+    }
+}
+
+fn edge_key(from: &JsValue, to: &JsValue) -> JsValue {
+    js_sys::Array::of2(from, to).into()
+}
+
+/// `[node]` sorts before every `[node, to]` key and, as long as `to` isn't an array, `[node, []]`
+/// after every one
+fn outgoing_range(node: &JsValue) -> Result<IdbKeyRange, DomException> {
+    check_id(node)?;
+    let end = js_sys::Array::of2(node, &js_sys::Array::new());
+    Ok(IdbKeyRange::bound(&js_sys::Array::of1(node), &end)?)
+}
+
+fn key_part(key: &JsValue, idx: u32) -> JsValue {
+    key.unchecked_ref::<js_sys::Array>().get(idx)
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::open_db_with;
+
+    use super::*;
+
+    test_mod_init!();
+
+    async fn open_graph_db() -> IdbDatabase {
+        open_db_with(|evt: &IdbVersionChangeEvent| {
+            Ok(Graph::create_stores(evt.db(), "nodes", "edges")?)
+        })
+        .await
+    }
+
+    fn ids(values: &[JsValue]) -> Vec<String> {
+        values.iter().map(|v| v.as_string().unwrap()).collect()
+    }
+
+    test_case!(async edges => {
+        let db = open_graph_db().await;
+        let graph = Graph::new(&db, "nodes", "edges");
+        for id in &["a", "b", "c"] {
+            graph.put_node(&(*id).into(), &JsValue::NULL).await.expect("put_node");
+        }
+        graph.add_edge(&"a".into(), &"b".into(), &JsValue::from(1u8)).await.expect("a->b");
+        graph.add_edge(&"a".into(), &"c".into(), &JsValue::from(2u8)).await.expect("a->c");
+        graph.add_edge(&"c".into(), &"b".into(), &JsValue::NULL).await.expect("c->b");
+
+        assert_eq!(ids(&graph.neighbors(&"a".into()).await.expect("neighbors")), vec!["b", "c"], "neighbors");
+        assert_eq!(ids(&graph.incoming(&"b".into()).await.expect("incoming")), vec!["a", "c"], "incoming");
+        let edge = graph.edge(&"a".into(), &"c".into()).await.expect("edge").expect("some edge");
+        assert_eq!(edge.data, JsValue::from(2u8), "edge data");
+        assert_eq!(graph.edges_from(&"c".into()).await.expect("edges_from").len(), 1, "edges_from");
+
+        let missing = graph.add_edge(&"a".into(), &"x".into(), &JsValue::NULL).await;
+        assert_eq!(missing.err().map(|e| e.name()), Some("NotFoundError".into()), "missing node");
+
+        graph.remove_edge(&"a".into(), &"b".into()).await.expect("remove_edge");
+        assert_eq!(ids(&graph.neighbors(&"a".into()).await.expect("neighbors 2")), vec!["c"], "removed");
+    });
+
+    test_case!(async remove_node_cascade => {
+        let db = open_graph_db().await;
+        let graph = Graph::new(&db, "nodes", "edges");
+        for id in &["a", "b", "c"] {
+            graph.put_node(&(*id).into(), &JsValue::NULL).await.expect("put_node");
+        }
+        for (from, to) in &[("a", "b"), ("b", "c"), ("c", "b"), ("b", "b"), ("a", "c")] {
+            graph.add_edge(&(*from).into(), &(*to).into(), &JsValue::NULL).await.expect("add_edge");
+        }
+
+        let removed = graph.remove_node_cascade(&"b".into()).await.expect("cascade");
+        assert_eq!(removed, 4, "removed");
+        assert_eq!(graph.get_node(&"b".into()).await.expect("get_node"), None, "node gone");
+        assert_eq!(ids(&graph.neighbors(&"a".into()).await.expect("neighbors")), vec!["c"], "a");
+        assert!(graph.neighbors(&"c".into()).await.expect("neighbors c").is_empty(), "c");
+    });
+}
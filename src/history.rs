@@ -0,0 +1,287 @@
+//! Undo and redo for writes on selected object stores
+//!
+//! A [History] records, for every write made through it, the record's value before and after the
+//! write in a history store with out-of-line keys. The old value gets read in the same
+//! transaction as the write, so the two can't drift apart. [undo][History::undo] restores the
+//! value from before the most recent write that hasn't been undone yet and [redo][History::redo]
+//! reapplies the earliest undone one. Making a new write discards the writes that were undone,
+//! like in any editor, and only the most recent [max_entries][History::max_entries] writes are
+//! kept.
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::history::History;
+//! use wasm_bindgen::prelude::*;
+//! use web_sys::DomException;
+//!
+//! async fn example(db: &IdbDatabase) -> Result<(), DomException> {
+//!     let history = History::new("history").track("docs");
+//!
+//!     let tx = db.transaction_on_multi_with_mode(&["docs", "history"], IdbTransactionMode::Readwrite)?;
+//!     history.put(&tx, "docs", &String::from("intro"), &JsValue::from("Hello"))?.await?;
+//!     tx.await.into_result()?;
+//!
+//!     history.undo(db).await?;
+//!     history.redo(db).await?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::future::Future;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::idb_database::IdbDatabase;
+use crate::idb_key::IdbKey;
+use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+use crate::idb_transaction::IdbTransaction;
+
+const STORE: &str = "store";
+const KEY: &str = "key";
+const BEFORE: &str = "before";
+const AFTER: &str = "after";
+const UNDONE: &str = "undone";
+
+/// Undo/redo history of writes on selected object stores. See the [module docs][self].
+#[derive(Debug, Clone)]
+pub struct History {
+    history_store: String,
+    stores: Vec<String>,
+    max_entries: u32,
+}
+
+impl History {
+    /// Record history in the given store, which must use out-of-line keys. No stores are tracked
+    /// until [track][History::track]ed.
+    pub fn new(history_store: &str) -> Self {
+        Self {
+            history_store: history_store.into(),
+            stores: Vec::new(),
+            max_entries: 100,
+        }
+    }
+
+    /// Record the writes made through the history on the given store
+    pub fn track(mut self, store: &str) -> Self {
+        self.stores.push(store.into());
+        self
+    }
+
+    /// Keep at most this many writes, dropping the oldest. Defaults to 100.
+    pub fn max_entries(mut self, max_entries: u32) -> Self {
+        self.max_entries = max_entries.max(1);
+        self
+    }
+
+    /// Whether writes on the store get recorded
+    #[inline]
+    pub fn is_tracked(&self, store: &str) -> bool {
+        self.stores.iter().any(|s| s == store)
+    }
+
+    /// Put the value at the key, recording the previous value if the store is tracked. The
+    /// transaction must be a readwrite one on the store and, for tracked stores, the history
+    /// store. The returned future must be awaited before the transaction is, and resolves once
+    /// both the write and the history entry have been issued.
+    pub fn put<'a, K: IdbKey>(
+        &'a self,
+        tx: &'a IdbTransaction<'a>,
+        store: &'a str,
+        key: &K,
+        value: &JsValue,
+    ) -> Result<impl Future<Output = Result<(), DomException>> + 'a, DomException> {
+        Ok(self.write(tx, store, key.to_js_key(), value.clone()))
+    }
+
+    /// Delete the record at the key, recording its value if the store is tracked. The same
+    /// requirements as for [put][History::put] apply.
+    pub fn delete<'a, K: IdbKey>(
+        &'a self,
+        tx: &'a IdbTransaction<'a>,
+        store: &'a str,
+        key: &K,
+    ) -> Result<impl Future<Output = Result<(), DomException>> + 'a, DomException> {
+        Ok(self.write(tx, store, key.to_js_key(), JsValue::UNDEFINED))
+    }
+
+    /// Whether there's a write to undo
+    pub async fn can_undo(&self, db: &IdbDatabase) -> Result<bool, DomException> {
+        Ok(self
+            .entries_in(db)
+            .await?
+            .iter()
+            .any(|(_, e)| !is_undone(e)))
+    }
+
+    /// Whether there's an undone write to redo
+    pub async fn can_redo(&self, db: &IdbDatabase) -> Result<bool, DomException> {
+        Ok(self.entries_in(db).await?.iter().any(|(_, e)| is_undone(e)))
+    }
+
+    /// Restore the value from before the most recent write that hasn't been undone, in one
+    /// transaction. Resolves to false if there's nothing to undo.
+    pub async fn undo(&self, db: &IdbDatabase) -> Result<bool, DomException> {
+        self.step(db, true).await
+    }
+
+    /// Reapply the earliest undone write, in one transaction. Resolves to false if there's
+    /// nothing to redo.
+    pub async fn redo(&self, db: &IdbDatabase) -> Result<bool, DomException> {
+        self.step(db, false).await
+    }
+
+    /// Forget every recorded write
+    pub async fn clear(&self, db: &IdbDatabase) -> Result<(), DomException> {
+        let tx =
+            db.transaction_on_one_with_mode(&self.history_store, IdbTransactionMode::Readwrite)?;
+        tx.object_store(&self.history_store)?.clear()?;
+        tx.await.into_result()
+    }
+
+    async fn write(
+        &self,
+        tx: &IdbTransaction<'_>,
+        store_name: &str,
+        key: JsValue,
+        after: JsValue,
+    ) -> Result<(), DomException> {
+        let store = tx.object_store(store_name)?;
+        if !self.is_tracked(store_name) {
+            apply(&store, &key, &after)?;
+            return Ok(());
+        }
+
+        let before = store.get(&key)?.await?.unwrap_or(JsValue::UNDEFINED);
+        apply(&store, &key, &after)?;
+
+        let history = tx.object_store(&self.history_store)?;
+        let entries = entries(&history).await?;
+        let mut next = 1.0;
+        let mut kept = Vec::with_capacity(entries.len());
+        for (seq, entry) in entries.into_iter() {
+            next = seq.as_f64().unwrap_or(0.0) + 1.0;
+            if is_undone(&entry) {
+                history.delete(&seq)?;
+            } else {
+                kept.push(seq);
+            }
+        }
+
+        let entry = js_sys::Object::new();
+        js_sys::Reflect::set(&entry, &STORE.into(), &store_name.into())?;
+        js_sys::Reflect::set(&entry, &KEY.into(), &key)?;
+        js_sys::Reflect::set(&entry, &BEFORE.into(), &before)?;
+        js_sys::Reflect::set(&entry, &AFTER.into(), &after)?;
+        js_sys::Reflect::set(&entry, &UNDONE.into(), &false.into())?;
+        history.put_key_val(&JsValue::from(next), &entry)?;
+
+        let excess = (kept.len() + 1).saturating_sub(self.max_entries as usize);
+        for seq in kept.iter().take(excess) {
+            history.delete(seq)?;
+        }
+        Ok(())
+    }
+
+    async fn step(&self, db: &IdbDatabase, undo: bool) -> Result<bool, DomException> {
+        let mut names = self.stores.iter().map(String::as_str).collect::<Vec<_>>();
+        names.push(&self.history_store);
+        let tx = db.transaction_on_multi_with_mode(&names, IdbTransactionMode::Readwrite)?;
+        let history = tx.object_store(&self.history_store)?;
+        let entries = entries(&history).await?;
+        let target = if undo {
+            entries.iter().rev().find(|(_, e)| !is_undone(e))
+        } else {
+            entries.iter().find(|(_, e)| is_undone(e))
+        };
+        let (seq, entry) = match target {
+            Some(target) => target,
+            None => return Ok(false),
+        };
+
+        let get = |field: &str| js_sys::Reflect::get(entry, &field.into());
+        let store_name = get(STORE)?.as_string().unwrap_or_default();
+        let store = tx.object_store(&store_name)?;
+        apply(&store, &get(KEY)?, &get(if undo { BEFORE } else { AFTER })?)?;
+        js_sys::Reflect::set(entry, &UNDONE.into(), &undo.into())?;
+        history.put_key_val(seq, entry)?;
+        tx.await.into_result()?;
+        Ok(true)
+    }
+
+    async fn entries_in(&self, db: &IdbDatabase) -> Result<Vec<(JsValue, JsValue)>, DomException> {
+        let tx = db.transaction_on_one(&self.history_store)?;
+        entries(&tx.object_store(&self.history_store)?).await
+    }
+}
+
+/// Every `(sequence number, entry)` pair, oldest first
+async fn entries(history: &IdbObjectStore<'_>) -> Result<Vec<(JsValue, JsValue)>, DomException> {
+    let keys = history.get_all_keys()?.await?;
+    let values = history.get_all()?.await?;
+    Ok(keys.iter().zip(values.iter()).collect())
+}
+
+/// Put the value at the key, or delete the record if the value is `undefined`
+fn apply(store: &IdbObjectStore<'_>, key: &JsValue, value: &JsValue) -> Result<(), DomException> {
+    if value.is_undefined() {
+        store.delete(key)?;
+    } else if store.key_path().is_some() {
+        store.put_val(value)?;
+    } else {
+        store.put_key_val(key, value)?;
+    }
+    Ok(())
+}
+
+fn is_undone(entry: &JsValue) -> bool {
+    js_sys::Reflect::get(entry, &UNDONE.into())
+        .map(|v| v.is_truthy())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::test_utils::open_db_with_stores;
+
+    use super::*;
+
+    test_mod_init!();
+
+    test_case!(async undo_redo => {
+        let db = open_db_with_stores(&["docs", "history"]).await;
+        let history = History::new("history").track("docs").max_entries(2);
+        let key = String::from("k");
+
+        for value in 1..=3 {
+            let tx = db.transaction_on_multi_with_mode(&["docs", "history"], IdbTransactionMode::Readwrite).unwrap();
+            history.put(&tx, "docs", &key, &JsValue::from(value)).unwrap().await.expect("put");
+            tx.await.into_result().expect("tx");
+        }
+        let read = || async {
+            let tx = db.transaction_on_one("docs").unwrap();
+            let value = tx.object_store("docs").unwrap().get_owned("k").unwrap().await.unwrap();
+            value
+        };
+        assert_eq!(read().await, Some(JsValue::from(3)), "written");
+        assert!(!history.can_redo(&db).await.unwrap(), "nothing to redo");
+
+        assert!(history.undo(&db).await.expect("undo"), "undo 3");
+        assert_eq!(read().await, Some(JsValue::from(2)), "undone");
+        assert!(history.undo(&db).await.expect("undo"), "undo 2");
+        assert_eq!(read().await, Some(JsValue::from(1)), "undone twice");
+        assert!(!history.undo(&db).await.expect("undo"), "bounded");
+
+        assert!(history.redo(&db).await.expect("redo"), "redo 2");
+        assert_eq!(read().await, Some(JsValue::from(2)), "redone");
+
+        let tx = db.transaction_on_multi_with_mode(&["docs", "history"], IdbTransactionMode::Readwrite).unwrap();
+        history.delete(&tx, "docs", &key).unwrap().await.expect("delete");
+        tx.await.into_result().expect("tx");
+        assert!(!history.can_redo(&db).await.unwrap(), "new write drops redo");
+        assert_eq!(read().await, None, "deleted");
+        assert!(history.undo(&db).await.expect("undo delete"), "undo delete");
+        assert_eq!(read().await, Some(JsValue::from(2)), "restored");
+    });
+}
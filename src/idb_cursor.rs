@@ -36,13 +36,20 @@
 //         Ok(())
 //     }
 
+use std::collections::BTreeMap;
 use std::future::Future;
 use std::rc::Rc;
 
 use wasm_bindgen::{prelude::*, JsCast};
 use web_sys::{DomException, IdbCursorDirection};
 
+#[cfg(feature = "streams")]
+pub use cursor_stream::*;
 pub use idb_cursor_with_value::*;
+#[cfg(feature = "indices")]
+pub use index_page::*;
+#[cfg(feature = "streams")]
+pub use scan::*;
 
 use crate::idb_query_source::IdbQuerySource;
 use crate::internal_utils::optional_jsvalue_undefined;
@@ -50,7 +57,18 @@ use crate::request::{
     IdbCursorAdvancementFuture, IdbRequestFuture, IdbRequestRef, JsCastRequestFuture, VoidRequest,
 };
 
+#[cfg(feature = "streams")]
+mod cursor_stream;
 mod idb_cursor_with_value;
+#[cfg(feature = "indices")]
+mod index_page;
+mod key_set;
+mod sample;
+#[cfg(feature = "streams")]
+mod scan;
+mod sorted;
+#[cfg(feature = "indices")]
+mod version_vector;
 
 /// An interface for an IndexedDB cursor
 ///
@@ -88,14 +106,14 @@ impl<'a, T: IdbQuerySource> IdbCursor<'a, T> {
     /// Get the key at the cursor's current position. Returns `None` if the cursor is outside its
     /// range.
     pub fn key(&self) -> Option<JsValue> {
-        optional_jsvalue_undefined(self.inner.key().unwrap())
+        optional_jsvalue_undefined(self.inner.key().ok()?)
     }
 
     /// Get the cursor's current effective primary key. Returns `None` if the cursor is currently
     /// being iterated or has iterated outside its range.
     #[inline]
     pub fn primary_key(&self) -> Option<JsValue> {
-        optional_jsvalue_undefined(self.inner.primary_key().unwrap())
+        optional_jsvalue_undefined(self.inner.primary_key().ok()?)
     }
 
     /// Common code for continue methods
@@ -122,7 +140,15 @@ impl<'a, T: IdbQuerySource> IdbCursor<'a, T> {
         Ok(self.continue_common())
     }
 
-    /// Sets the cursor to the given index key and primary key given as arguments.
+    /// Sets the cursor to the given index key and primary key given as arguments, or to the first
+    /// record after them in the cursor's direction if there's no such record, so that an index
+    /// scan can resume at an exact record. Wraps
+    /// [IDBCursor.continuePrimaryKey](https://developer.mozilla.org/en-US/docs/Web/API/IDBCursor/continuePrimaryKey).
+    ///
+    /// Only works on index cursors with the `next` or `prev` direction; fails with an
+    /// `InvalidAccessError` otherwise. Fails with a `DataError` if the position isn't ahead of the
+    /// cursor's current one. [IdbIndex::page][crate::idb_index::IdbIndex::page] builds paging on
+    /// top of this.
     pub fn continue_primary_key<K: JsCast, PK: JsCast>(
         &self,
         key: &K,
@@ -146,26 +172,34 @@ impl<'a, T: IdbQuerySource> IdbCursor<'a, T> {
     async fn handle_into_vec<F, O>(&self, skip: u32, mapper: F) -> Result<Vec<O>, DomException>
     where
         F: Fn(JsValue) -> O,
+    {
+        self.handle_fold(skip, Vec::new(), move |mut out, key| {
+            out.push(mapper(key));
+            out
+        })
+        .await
+    }
+
+    /// Internal handler for folding the remainder of the cursor into an accumulator
+    async fn handle_fold<A, F>(&self, skip: u32, init: A, mut fold: F) -> Result<A, DomException>
+    where
+        F: FnMut(A, JsValue) -> A,
     {
         if skip != 0 && !self.advance(skip)?.await? {
-            return Ok(Vec::new());
+            return Ok(init);
         }
 
-        let mut out = match self.key() {
-            Some(v) => {
-                let mut out = Vec::with_capacity(1);
-                out.push(mapper(v));
-                out
-            }
+        let mut acc = match self.key() {
+            Some(key) => fold(init, key),
             None => {
-                return Ok(Vec::new());
+                return Ok(init);
             }
         };
 
         while self.continue_cursor()?.await? {
             match self.key() {
                 Some(key) => {
-                    out.push(mapper(key));
+                    acc = fold(acc, key);
                 }
                 None => {
                     break;
@@ -173,7 +207,7 @@ impl<'a, T: IdbQuerySource> IdbCursor<'a, T> {
             }
         }
 
-        Ok(out)
+        Ok(acc)
     }
 
     /// Consume the remainder of the cursor, collecting each key into a vector.
@@ -190,6 +224,39 @@ impl<'a, T: IdbQuerySource> IdbCursor<'a, T> {
         self.handle_into_vec(skip, passthrough).await
     }
 
+    /// Consume the remainder of the cursor, folding each key into an accumulator. Nothing but the
+    /// keys crosses the wasm boundary, which makes this a cheap way of computing aggregates over
+    /// a [key cursor][IdbQuerySource::open_key_cursor].
+    ///
+    /// ### Arguments
+    ///
+    /// - **init** - the initial accumulator value, returned as-is if the cursor is exhausted.
+    /// - **fold** - called with the accumulator and every key the cursor visits, starting with the
+    ///   current one.
+    pub async fn aggregate<A, F>(self, init: A, fold: F) -> Result<A, DomException>
+    where
+        F: FnMut(A, JsValue) -> A,
+    {
+        self.handle_fold(0, init, fold).await
+    }
+
+    /// Consume the remainder of the cursor, counting the keys that share each string prefix of
+    /// `prefix_len` characters. Keys shorter than the prefix length are counted under the whole
+    /// key; non-string keys are ignored.
+    pub async fn count_by_prefix(
+        self,
+        prefix_len: usize,
+    ) -> Result<BTreeMap<String, u32>, DomException> {
+        self.aggregate(BTreeMap::new(), move |mut out, key| {
+            if let Some(key) = key.as_string() {
+                let prefix: String = key.chars().take(prefix_len).collect();
+                *out.entry(prefix).or_insert(0) += 1;
+            }
+            out
+        })
+        .await
+    }
+
     /// Delete the record at the cursor's position, without changing the cursor's position
     pub fn delete(&self) -> Result<VoidRequest, DomException> {
         Ok(VoidRequest::new(self.inner.delete()?))
@@ -209,8 +276,8 @@ pub mod test {
     use wasm_bindgen::prelude::*;
     use web_sys::DomException;
 
-    use crate::internal_utils::open_any_db;
     use crate::prelude::*;
+    use crate::test_utils::open_any_db;
 
     test_mod_init!();
 
@@ -278,6 +345,339 @@ pub mod test {
         assert_eq!(cur, exp);
     });
 
+    #[cfg(feature = "streams")]
+    pub mod streams {
+        test_mod_init!();
+        use std::pin::Pin;
+
+        async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+            std::future::poll_fn(|ctx| Pin::new(&mut *stream).poll_next(ctx)).await
+        }
+
+        test_case!(async key_val_stream => {
+            let (db, store_name) = open_dummy_db().await;
+            let tx = db.transaction_on_one(&store_name).unwrap();
+            let store = tx.object_store(&store_name).unwrap();
+            let mut stream = open_cur(&store).await.into_stream();
+
+            let mut out = Vec::new();
+            while let Some(kv) = next(&mut stream).await {
+                let kv = kv.expect("stream item");
+                out.push((map_key(Some(kv.key().clone())).unwrap(), map_value(kv.value().clone())));
+            }
+            let exp: Vec<(String, u8)> = vec![("k1".into(), 1), ("k2".into(), 2), ("k3".into(), 3), ("k4".into(), 4)];
+            assert_eq!(out, exp);
+            assert!(next(&mut stream).await.is_none(), "fused");
+        });
+
+        test_case!(async projected_stream => {
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).unwrap();
+            let store = tx.object_store(&store_name).unwrap();
+            let record = js_sys::JSON::parse(r#"{"a":1,"b":2}"#).unwrap();
+            store.put_key_val_owned("k", &record).expect("put");
+
+            let mut stream = open_cur(&store).await.into_projected_stream(Projection::new(&["b"]));
+            let kv = next(&mut stream).await.expect("item").expect("item ok");
+            assert_eq!(js_sys::JSON::stringify(kv.value()).unwrap(), r#"{"b":2}"#);
+            assert!(next(&mut stream).await.is_none(), "end");
+        });
+
+        test_case!(async scan_where => {
+            let (db, store_name) = open_dummy_db().await;
+            let tx = db.transaction_on_one(&store_name).unwrap();
+            let store = tx.object_store(&store_name).unwrap();
+            let range = web_sys::IdbKeyRange::lower_bound(&"k2".into()).unwrap();
+            let mut stream = store
+                .scan_where(&range, |v| map_value(v.clone()) != 3)
+                .await
+                .expect("scan_where");
+
+            let mut keys = Vec::new();
+            while let Some(kv) = next(&mut stream).await {
+                keys.push(map_key(Some(kv.expect("item").key().clone())).unwrap());
+            }
+            assert_eq!(keys, vec!["k2".to_string(), "k4".to_string()]);
+
+            let range = web_sys::IdbKeyRange::lower_bound(&"z".into()).unwrap();
+            let mut empty = store.scan_where(&range, |_| true).await.expect("empty scan_where");
+            assert!(next(&mut empty).await.is_none(), "empty");
+        });
+
+        test_case!(async key_stream => {
+            let (db, store_name) = open_dummy_db().await;
+            let tx = db.transaction_on_one(&store_name).unwrap();
+            let store = tx.object_store(&store_name).unwrap();
+            let cur = store.open_key_cursor().unwrap().await.unwrap().unwrap();
+            let mut stream = cur.into_stream();
+
+            let mut count = 0;
+            while let Some(key) = next(&mut stream).await {
+                key.expect("stream item");
+                count += 1;
+            }
+            assert_eq!(count, 4);
+        });
+    }
+
+    pub mod sorted {
+        test_mod_init!();
+        use std::cmp::Ordering;
+
+        test_case!(async collect_sorted_by => {
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).unwrap();
+            let store = tx.object_store(&store_name).unwrap();
+            for (i, score) in [5u8, 9, 1, 7, 3, 8].iter().enumerate() {
+                store.put_key_val_owned(i as u32, &JsValue::from(*score)).expect("put");
+            }
+
+            let desc = |a: &JsValue, b: &JsValue| b.as_f64().partial_cmp(&a.as_f64()).unwrap_or(Ordering::Equal);
+            let top = store.collect_sorted_by(&JsValue::UNDEFINED, desc, 3).await.expect("top 3");
+            let scores: Vec<u8> = top.iter().map(|kv| map_value(kv.value().clone())).collect();
+            assert_eq!(scores, vec![9, 8, 7], "top 3");
+            assert_eq!(top[0].key(), &JsValue::from(1u32), "top key");
+
+            let range = web_sys::IdbKeyRange::upper_bound(&JsValue::from(2u32)).unwrap();
+            let all = store.collect_sorted_by(&range, desc, 10).await.expect("range");
+            let scores: Vec<u8> = all.iter().map(|kv| map_value(kv.value().clone())).collect();
+            assert_eq!(scores, vec![9, 5, 1], "range");
+
+            assert!(store.collect_sorted_by(&range, desc, 0).await.expect("limit 0").is_empty());
+        });
+    }
+
+    pub mod key_set {
+        test_mod_init!();
+
+        test_case!(async get_all_in => {
+            let (db, store_name) = open_dummy_db().await;
+            let tx = db.transaction_on_one(&store_name).unwrap();
+            let store = tx.object_store(&store_name).unwrap();
+
+            let keys = ["k3", "k9", "k1", "k0", "k3", "k4"];
+            let keys: Vec<String> = keys.iter().map(|k| String::from(*k)).collect();
+            let values: Vec<Option<u8>> = store.get_all_in(&keys).await.expect("get_all_in")
+                .into_iter()
+                .map(|v| v.map(map_value))
+                .collect();
+            assert_eq!(values, vec![Some(3), None, Some(1), None, Some(3), Some(4)], "values");
+            assert!(store.get_all_in::<String>(&[]).await.expect("empty").is_empty(), "empty");
+
+            let invalid = [JsValue::from("k1"), JsValue::NULL];
+            assert!(store.get_all_in(&invalid).await.is_err(), "invalid key");
+        });
+
+        test_case!(async missing_keys => {
+            let (db, store_name) = open_dummy_db().await;
+            let tx = db.transaction_on_one(&store_name).unwrap();
+            let store = tx.object_store(&store_name).unwrap();
+
+            let keys: Vec<String> = ["k5", "k2", "k0", "k5", "k4"].iter().map(|k| String::from(*k)).collect();
+            let missing = store.missing_keys(&keys).await.expect("missing_keys");
+            assert_eq!(missing, vec![String::from("k5"), "k0".into(), "k5".into()], "missing");
+            let present: Vec<String> = vec!["k1".into(), "k3".into()];
+            assert!(store.missing_keys(&present).await.expect("present").is_empty(), "present");
+        });
+
+        #[cfg(feature = "indices")]
+        test_case!(async get_all_in_index => {
+            let db = crate::test_utils::open_db_with(|evt: &IdbVersionChangeEvent| {
+                let store = evt.db().create_object_store("items")?;
+                store.create_index("by_value", &IdbKeyPath::str(""))?;
+                Ok(())
+            }).await;
+
+            let tx = db.transaction_on_one_with_mode("items", IdbTransactionMode::Readwrite).unwrap();
+            let store = tx.object_store("items").unwrap();
+            for (key, value) in [(1u8, 10u8), (2, 10), (3, 30)].iter() {
+                store.put_key_val_owned(*key, &JsValue::from(*value)).expect("put");
+            }
+            let index = store.index("by_value").unwrap();
+            let values = index.get_all_in(&[30u8, 20, 10]).await.expect("get_all_in");
+            let values: Vec<Option<u8>> = values.into_iter().map(|v| v.map(map_value)).collect();
+            assert_eq!(values, vec![Some(30), None, Some(10)], "values");
+        });
+    }
+
+    pub mod sample {
+        test_mod_init!();
+
+        test_case!(async sample_keys => {
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).unwrap();
+            let store = tx.object_store(&store_name).unwrap();
+            for i in 0..100u32 {
+                store.put_key_val_owned(i, &JsValue::from(i)).expect("put");
+            }
+
+            let keys: Vec<u8> = store.sample_keys(4).await.expect("sample").into_iter().map(map_value).collect();
+            assert_eq!(keys, vec![0, 25, 50, 75], "spread");
+            let keys: Vec<u8> = store.sample_keys(3).await.expect("uneven").into_iter().map(map_value).collect();
+            assert_eq!(keys, vec![0, 33, 66], "uneven");
+            assert_eq!(store.sample_keys(500).await.expect("all").len(), 100, "all");
+            assert!(store.sample_keys(0).await.expect("none").is_empty(), "none");
+        });
+    }
+
+    #[cfg(feature = "indices")]
+    pub mod version_vector {
+        use crate::test_utils::open_db_with;
+        test_mod_init!();
+
+        test_case!(async version_vector => {
+            let db = open_db_with(|evt: &IdbVersionChangeEvent| {
+                evt.db().create_object_store("plain")?;
+                let store = evt.db().create_object_store("docs")?;
+                store.create_index("by_title", &IdbKeyPath::str("title"))?;
+                store.create_index("by_rev", &IdbKeyPath::str("_rev"))?;
+                Ok(())
+            }).await;
+
+            let tx = db.transaction_on_multi_with_mode(&["docs", "plain"], IdbTransactionMode::Readwrite).unwrap();
+            let store = tx.object_store("docs").unwrap();
+            for (key, rev) in [(1u8, 3u8), (2, 1), (3, 2)].iter() {
+                let doc = js_sys::JSON::parse(&format!(r#"{{"title":"doc","_rev":{}}}"#, rev)).unwrap();
+                store.put_key_val_owned(*key, &doc).expect("put");
+            }
+
+            let all = store.version_vector(&JsValue::UNDEFINED).await.expect("all");
+            let pairs = |v: Vec<(JsValue, JsValue)>| -> Vec<(u8, u8)> {
+                v.into_iter().map(|(k, r)| (map_value(k), map_value(r))).collect()
+            };
+            assert_eq!(pairs(all), vec![(2, 1), (3, 2), (1, 3)], "all");
+
+            let since = web_sys::IdbKeyRange::lower_bound_with_open(&2.into(), true).unwrap();
+            let delta = store.version_vector(&since).await.expect("delta");
+            assert_eq!(pairs(delta), vec![(1, 3)], "delta");
+
+            let err = tx.object_store("plain").unwrap().version_vector(&JsValue::UNDEFINED).await.expect_err("no index");
+            assert_eq!(err.name(), "NotFoundError", "no index");
+        });
+    }
+
+    #[cfg(feature = "indices")]
+    pub mod index_page {
+        use crate::test_utils::open_db_with;
+        test_mod_init!();
+
+        test_case!(async page_with_shared_keys => {
+            let db = open_db_with(|evt: &IdbVersionChangeEvent| {
+                let store = evt.db().create_object_store("items")?;
+                store.create_index("by_value", &IdbKeyPath::str(""))?;
+                Ok(())
+            }).await;
+
+            let tx = db.transaction_on_one_with_mode("items", IdbTransactionMode::Readwrite).unwrap();
+            let store = tx.object_store("items").unwrap();
+            for (key, value) in [(1u8, 1u8), (2, 1), (3, 1), (4, 1), (5, 2), (6, 2), (7, 3)].iter() {
+                store.put_key_val_owned(*key, &JsValue::from(*value)).expect("put");
+            }
+            let index = store.index("by_value").unwrap();
+
+            let primary_keys = |page: &IndexPage| -> Vec<u8> {
+                page.records.iter().map(|r| map_value(r.primary_key.clone())).collect()
+            };
+
+            let first = index.page(&JsValue::UNDEFINED, None, 3).await.expect("first");
+            assert_eq!(primary_keys(&first), vec![1, 2, 3], "first");
+            let token = first.next.expect("first token");
+            assert_eq!(token.key, JsValue::from(1u8), "token key");
+
+            store.delete_owned(3u8).expect("delete");
+            let second = index.page(&JsValue::UNDEFINED, Some(&token), 3).await.expect("second");
+            assert_eq!(primary_keys(&second), vec![4, 5, 6], "second");
+
+            let third = index
+                .page(&JsValue::UNDEFINED, second.next.as_ref(), 3)
+                .await
+                .expect("third");
+            assert_eq!(primary_keys(&third), vec![7], "third");
+            assert!(third.next.is_none(), "last page");
+
+            let prev = index
+                .page_with_direction(&JsValue::UNDEFINED, None, 2, IdbCursorDirection::Prev)
+                .await
+                .expect("prev");
+            assert_eq!(primary_keys(&prev), vec![7, 6], "prev");
+            let prev = index
+                .page_with_direction(&JsValue::UNDEFINED, prev.next.as_ref(), 2, IdbCursorDirection::Prev)
+                .await
+                .expect("prev 2");
+            assert_eq!(primary_keys(&prev), vec![5, 4], "prev 2");
+        });
+
+        test_case!(async continue_primary_key => {
+            let db = open_db_with(|evt: &IdbVersionChangeEvent| {
+                let store = evt.db().create_object_store("items")?;
+                store.create_index("by_value", &IdbKeyPath::str(""))?;
+                Ok(())
+            }).await;
+
+            let tx = db.transaction_on_one_with_mode("items", IdbTransactionMode::Readwrite).unwrap();
+            let store = tx.object_store("items").unwrap();
+            for (key, value) in [(1u8, 1u8), (2, 1), (3, 1), (4, 2)].iter() {
+                store.put_key_val_owned(*key, &JsValue::from(*value)).expect("put");
+            }
+            let index = store.index("by_value").unwrap();
+            let cursor = index.open_cursor().unwrap().await.unwrap().expect("cursor");
+
+            let found = cursor
+                .continue_primary_key(&JsValue::from(1u8), &JsValue::from(3u8))
+                .expect("exact")
+                .await
+                .expect("exact await");
+            assert!(found, "exact");
+            assert_eq!(cursor.primary_key(), Some(JsValue::from(3u8)), "exact position");
+
+            let behind = cursor.continue_primary_key(&JsValue::from(1u8), &JsValue::from(2u8));
+            assert_eq!(behind.err().map(|e| e.name()), Some("DataError".into()), "behind");
+
+            let found = cursor
+                .continue_primary_key(&JsValue::from(1u8), &JsValue::from(9u8))
+                .expect("gap")
+                .await
+                .expect("gap await");
+            assert!(found, "gap");
+            assert_eq!(cursor.key(), Some(JsValue::from(2u8)), "gap key");
+            assert_eq!(cursor.primary_key(), Some(JsValue::from(4u8)), "gap position");
+        });
+    }
+
+    pub mod aggregation {
+        test_mod_init!();
+
+        test_case!(async aggregate => {
+            let (db, store_name) = open_dummy_db().await;
+            let tx = db.transaction_on_one(&store_name).unwrap();
+            let store = tx.object_store(&store_name).unwrap();
+            let cur = store.open_key_cursor().unwrap().await.unwrap().unwrap();
+            let joined = cur
+                .aggregate(String::new(), |acc, key| acc + &key.as_string().unwrap())
+                .await
+                .expect("aggregate");
+
+            assert_eq!(joined, "k1k2k3k4");
+        });
+
+        test_case!(async count_by_prefix => {
+            let (db, store_name) = open_dummy_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite)
+                .unwrap();
+            let store = tx.object_store(&store_name).unwrap();
+            store.add_key_val_owned("x1", &JsValue::from(5u8)).expect("add");
+            store.add_key_val_owned(10u8, &JsValue::from(6u8)).expect("add");
+            let cur = store.open_key_cursor().unwrap().await.unwrap().unwrap();
+            let counts = cur.count_by_prefix(1).await.expect("count_by_prefix");
+
+            let mut exp = std::collections::BTreeMap::new();
+            exp.insert(String::from("k"), 4);
+            exp.insert(String::from("x"), 1);
+
+            assert_eq!(counts, exp);
+        });
+    }
+
     test_case!(async delete_and_update => {
         let (db, store_name) = open_dummy_db().await;
         let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite)
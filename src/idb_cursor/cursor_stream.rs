@@ -0,0 +1,135 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use wasm_bindgen::prelude::*;
+use web_sys::DomException;
+
+use crate::idb_object_store::Projection;
+use crate::idb_query_source::IdbQuerySource;
+use crate::request::IdbCursorAdvancementFuture;
+
+use super::{IdbCursor, IdbCursorWithValue, KeyVal};
+
+/// A [Stream] over the remainder of a cursor, created by [IdbCursor::into_stream] or
+/// [IdbCursorWithValue::into_stream]. The stream ends after the first error.
+///
+/// Features required: `streams`
+pub struct CursorStream<'a, T: IdbQuerySource, O> {
+    cursor: IdbCursor<'a, T>,
+    read: ReadFn<'a, T, O>,
+    pending: Option<IdbCursorAdvancementFuture>,
+    started: bool,
+    done: bool,
+}
+
+type ReadFn<'a, T, O> = Box<dyn Fn(&IdbCursor<'a, T>) -> Option<O> + 'a>;
+
+impl<'a, T: IdbQuerySource, O> CursorStream<'a, T, O> {
+    fn new<F>(cursor: IdbCursor<'a, T>, read: F) -> Self
+    where
+        F: Fn(&IdbCursor<'a, T>) -> Option<O> + 'a,
+    {
+        Self {
+            cursor,
+            read: Box::new(read),
+            pending: None,
+            started: false,
+            done: false,
+        }
+    }
+
+    /// Read the cursor's current position, ending the stream if it's outside its range
+    fn emit(&mut self) -> Poll<Option<Result<O, DomException>>> {
+        match (self.read)(&self.cursor) {
+            Some(out) => Poll::Ready(Some(Ok(out))),
+            None => self.fail(None),
+        }
+    }
+
+    fn fail(&mut self, err: Option<DomException>) -> Poll<Option<Result<O, DomException>>> {
+        self.done = true;
+        self.pending = None;
+        Poll::Ready(err.map(Err))
+    }
+}
+
+impl<T: IdbQuerySource + std::fmt::Debug, O> std::fmt::Debug for CursorStream<'_, T, O> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CursorStream")
+            .field("cursor", &self.cursor)
+            .field("started", &self.started)
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+impl<'a, T: IdbQuerySource, O> Stream for CursorStream<'a, T, O> {
+    type Item = Result<O, DomException>;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.done {
+            return Poll::Ready(None);
+        }
+        if !this.started {
+            this.started = true;
+            return this.emit();
+        }
+
+        if this.pending.is_none() {
+            if let Err(e) = this.cursor.inner.continue_() {
+                return this.fail(Some(e.into()));
+            }
+            this.pending = Some(this.cursor.continue_common());
+        }
+
+        let polled = match this.pending.as_mut() {
+            Some(fut) => Pin::new(fut).poll(ctx),
+            None => Poll::Pending,
+        };
+        match polled {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Ok(true)) => {
+                this.pending = None;
+                this.emit()
+            }
+            Poll::Ready(Ok(false)) => this.fail(None),
+            Poll::Ready(Err(e)) => this.fail(Some(e)),
+        }
+    }
+}
+
+impl<'a, T: IdbQuerySource> IdbCursor<'a, T> {
+    /// Turn the remainder of the cursor, starting with the current key, into a [Stream] of keys
+    ///
+    /// Features required: `streams`
+    pub fn into_stream(self) -> CursorStream<'a, T, JsValue> {
+        CursorStream::new(self, IdbCursor::key)
+    }
+}
+
+impl<'a, T: IdbQuerySource> IdbCursorWithValue<'a, T> {
+    /// Turn the remainder of the cursor, starting with the current record, into a [Stream] of
+    /// key-value pairs
+    ///
+    /// Features required: `streams`
+    pub fn into_stream(self) -> CursorStream<'a, T, KeyVal> {
+        CursorStream::new(self.into_cursor(), move |cursor| {
+            let value = cursor.inner_as_cursor_with_value().value().ok()?;
+            Some(KeyVal::new(cursor.key()?, value))
+        })
+    }
+
+    /// Like [into_stream][IdbCursorWithValue::into_stream], but with each value projected to
+    /// the given fields
+    ///
+    /// Features required: `streams`
+    pub fn into_projected_stream(self, projection: Projection) -> CursorStream<'a, T, KeyVal> {
+        CursorStream::new(self.into_cursor(), move |cursor| {
+            let value = cursor.inner_as_cursor_with_value().value().ok()?;
+            Some(KeyVal::new(cursor.key()?, projection.apply(&value)))
+        })
+    }
+}
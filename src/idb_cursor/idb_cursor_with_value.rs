@@ -40,6 +40,12 @@ impl<'a, T: IdbQuerySource> IdbCursorWithValue<'a, T> {
         Self(inner)
     }
 
+    #[cfg(feature = "streams")]
+    #[inline]
+    pub(super) fn into_cursor(self) -> IdbCursor<'a, T> {
+        self.0
+    }
+
     /// Consume the remainder of the cursor, collecting each key-value pair into a vector.
     ///
     /// ### Arguments
@@ -52,9 +58,17 @@ impl<'a, T: IdbQuerySource> IdbCursorWithValue<'a, T> {
             .await
     }
 
-    /// Get the cursor's current value
+    /// Get the cursor's current value. With the `no-panic` feature, `undefined` is returned
+    /// instead of panicking if the value can't be read.
     pub fn value(&self) -> JsValue {
-        self.inner_as_cursor_with_value().value().unwrap()
+        let value = self.inner_as_cursor_with_value().value();
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "no-panic")] {
+                value.unwrap_or(JsValue::UNDEFINED)
+            } else {
+                value.unwrap()
+            }
+        }
     }
 }
 
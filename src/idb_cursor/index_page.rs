@@ -0,0 +1,147 @@
+use std::cmp::Ordering;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{DomException, IdbCursorDirection};
+
+use crate::idb_index::IdbIndex;
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::require;
+use crate::key_order::compare_keys;
+
+/// Where an [IndexPage] left off: the index key and primary key of its last record
+///
+/// Features required: `cursors`, `indices`
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexPageToken {
+    /// The last record's index key
+    pub key: JsValue,
+    /// The last record's primary key
+    pub primary_key: JsValue,
+}
+
+/// A record on an [IndexPage]
+///
+/// Features required: `cursors`, `indices`
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexRecord {
+    /// The record's index key
+    pub key: JsValue,
+    /// The record's primary key
+    pub primary_key: JsValue,
+    /// The record's value
+    pub value: JsValue,
+}
+
+/// A page of records returned by [IdbIndex::page]
+///
+/// Features required: `cursors`, `indices`
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexPage {
+    /// The records on the page, in cursor order
+    pub records: Vec<IndexRecord>,
+    /// Token for the next page; `None` if this is the last one
+    pub next: Option<IndexPageToken>,
+}
+
+impl IndexRecord {
+    fn token(&self) -> IndexPageToken {
+        IndexPageToken {
+            key: self.key.clone(),
+            primary_key: self.primary_key.clone(),
+        }
+    }
+}
+
+impl IdbIndex<'_> {
+    /// Get up to `limit` records from the given key range, or all records if the range is
+    /// `undefined`, starting after the record the token points at, or from the start if there's
+    /// no token.
+    ///
+    /// Pages continue from the `(index key, primary key)` pair of the previous page's last record
+    /// via [continuePrimaryKey](https://developer.mozilla.org/en-US/docs/Web/API/IDBCursor/continuePrimaryKey),
+    /// so records that share an index key are neither skipped nor repeated across pages, even if
+    /// the previous page's last record has since been deleted.
+    ///
+    /// Features required: `cursors`, `indices`
+    pub async fn page<K: JsCast>(
+        &self,
+        range: &K,
+        after: Option<&IndexPageToken>,
+        limit: u32,
+    ) -> Result<IndexPage, DomException> {
+        self.page_with_direction(range, after, limit, IdbCursorDirection::Next)
+            .await
+    }
+
+    /// Like [page][IdbIndex::page], but walks the index in the given direction. Only `next` and
+    /// `prev` are supported; the `unique` directions fail with an `InvalidAccessError` when
+    /// continuing from a token.
+    ///
+    /// Features required: `cursors`, `indices`
+    pub async fn page_with_direction<K: JsCast>(
+        &self,
+        range: &K,
+        after: Option<&IndexPageToken>,
+        limit: u32,
+        direction: IdbCursorDirection,
+    ) -> Result<IndexPage, DomException> {
+        let mut page = IndexPage {
+            records: Vec::new(),
+            next: None,
+        };
+        if limit == 0 {
+            return Ok(page);
+        }
+        let cursor = match self
+            .open_cursor_with_range_and_direction(range, direction)?
+            .await?
+        {
+            Some(cursor) => cursor,
+            None => return Ok(page),
+        };
+
+        let forward = matches!(
+            direction,
+            IdbCursorDirection::Next | IdbCursorDirection::Nextunique
+        );
+        let mut more = true;
+        if let Some(after) = after {
+            let key = require(cursor.key())?;
+            let primary_key = require(cursor.primary_key())?;
+            let mut ord = compare_keys(&key, &after.key)?
+                .then(compare_keys(&primary_key, &after.primary_key)?);
+            if !forward {
+                ord = ord.reverse();
+            }
+            if ord == Ordering::Less {
+                more = cursor
+                    .continue_primary_key(&after.key, &after.primary_key)?
+                    .await?;
+                if more {
+                    let key = require(cursor.key())?;
+                    let primary_key = require(cursor.primary_key())?;
+                    ord = compare_keys(&key, &after.key)?
+                        .then(compare_keys(&primary_key, &after.primary_key)?);
+                }
+            }
+            if more && ord == Ordering::Equal {
+                more = cursor.continue_cursor()?.await?;
+            }
+        }
+
+        while more {
+            if page.records.len() == limit as usize {
+                page.next = page.records.last().map(IndexRecord::token);
+                break;
+            }
+            page.records.push(IndexRecord {
+                key: require(cursor.key())?,
+                primary_key: require(cursor.primary_key())?,
+                value: cursor.value(),
+            });
+            more = cursor.continue_cursor()?.await?;
+        }
+
+        Ok(page)
+    }
+}
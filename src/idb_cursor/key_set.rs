@@ -0,0 +1,172 @@
+use std::cmp::Ordering;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbCursorDirection, IdbKeyRange};
+
+#[cfg(feature = "indices")]
+use crate::idb_index::IdbIndex;
+use crate::idb_key::IdbKey;
+use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+use crate::key_order::compare_keys;
+
+use super::IdbCursor;
+
+/// A key to look up and the positions in the caller's slice it came from
+struct Target {
+    key: JsValue,
+    positions: Vec<usize>,
+}
+
+/// The keys in IndexedDB order with duplicates merged. Fails with a `DataError` if any of them
+/// isn't a valid key.
+fn sort_keys<K: IdbKey>(keys: &[K]) -> Result<Vec<Target>, DomException> {
+    let mut targets = Vec::with_capacity(keys.len());
+    for (position, key) in keys.iter().enumerate() {
+        let key = key.to_js_key();
+        // Comparing a key to itself validates it, so the sort below can't fail
+        compare_keys(&key, &key)?;
+        targets.push(Target {
+            key,
+            positions: vec![position],
+        });
+    }
+    targets.sort_by(|a, b| compare_keys(&a.key, &b.key).unwrap_or(Ordering::Equal));
+    targets.dedup_by(|next, prev| {
+        let same = compare_keys(&next.key, &prev.key) == Ok(Ordering::Equal);
+        if same {
+            prev.positions.append(&mut next.positions);
+        }
+        same
+    });
+    Ok(targets)
+}
+
+/// The range spanning every target
+fn span(targets: &[Target]) -> Result<Option<IdbKeyRange>, DomException> {
+    match (targets.first(), targets.last()) {
+        (Some(first), Some(last)) => Ok(Some(IdbKeyRange::bound(&first.key, &last.key)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Walk the cursor over the targets in order, jumping straight to each target's key, and call
+/// `on_hit` with the index of every target that has a record
+async fn merge<T, F>(
+    cursor: &IdbCursor<'_, T>,
+    targets: &[Target],
+    mut on_hit: F,
+) -> Result<(), DomException>
+where
+    T: IdbQuerySource,
+    F: FnMut(usize),
+{
+    let mut i = 0;
+    while let (Some(target), Some(key)) = (targets.get(i), cursor.key()) {
+        let advanced = match compare_keys(&key, &target.key)? {
+            Ordering::Less => cursor.continue_cursor_with_key(&target.key)?.await?,
+            Ordering::Equal => {
+                on_hit(i);
+                i += 1;
+                match targets.get(i) {
+                    Some(next) => cursor.continue_cursor_with_key(&next.key)?.await?,
+                    None => false,
+                }
+            }
+            // The cursor has jumped past the target, so there's no record with its key
+            Ordering::Greater => {
+                i += 1;
+                true
+            }
+        };
+        if !advanced {
+            break;
+        }
+    }
+    Ok(())
+}
+
+async fn get_all_in<T: IdbQuerySource, K: IdbKey>(
+    source: &T,
+    keys: &[K],
+) -> Result<Vec<Option<JsValue>>, DomException> {
+    let mut out = vec![None; keys.len()];
+    let targets = sort_keys(keys)?;
+    let range = match span(&targets)? {
+        Some(range) => range,
+        None => return Ok(out),
+    };
+    let cursor = source
+        .open_cursor_with_range_and_direction(&range, IdbCursorDirection::Nextunique)?
+        .await?;
+    if let Some(ref cursor) = cursor {
+        merge(cursor, &targets, |i| {
+            let value = cursor.value();
+            for &position in targets[i].positions.iter() {
+                out[position] = Some(value.clone());
+            }
+        })
+        .await?;
+    }
+    Ok(out)
+}
+
+async fn missing_keys<T: IdbQuerySource, K: IdbKey + Clone>(
+    source: &T,
+    keys: &[K],
+) -> Result<Vec<K>, DomException> {
+    let targets = sort_keys(keys)?;
+    let mut found = vec![false; keys.len()];
+    if let Some(range) = span(&targets)? {
+        if let Some(cursor) = source.open_key_cursor_with_range(&range)?.await? {
+            merge(&cursor, &targets, |i| {
+                for &position in targets[i].positions.iter() {
+                    found[position] = true;
+                }
+            })
+            .await?;
+        }
+    }
+    Ok(keys
+        .iter()
+        .zip(found)
+        .filter(|(_, found)| !found)
+        .map(|(key, _)| key.clone())
+        .collect())
+}
+
+macro_rules! impl_key_set {
+    ($for: ty) => {
+        impl $for {
+            /// Get the records at the given keys in a single cursor pass that jumps from one
+            /// key to the next in sorted order, which is cheaper than one `get` per key for
+            /// medium-sized key sets. The result for each key ends up at its position; `None`
+            /// where there's no record; on an index, the first record with each index key. Fails
+            /// with a `DataError` if any key is invalid.
+            ///
+            /// Features required: `cursors`
+            pub async fn get_all_in<K: IdbKey>(
+                &self,
+                keys: &[K],
+            ) -> Result<Vec<Option<JsValue>>, DomException> {
+                get_all_in(self, keys).await
+            }
+
+            /// The given keys that have no record, in their original order, found by merging
+            /// them with a key cursor, e.g. to work out what still needs fetching from the
+            /// network. Fails with a `DataError` if any key is invalid.
+            ///
+            /// Features required: `cursors`
+            pub async fn missing_keys<K: IdbKey + Clone>(
+                &self,
+                keys: &[K],
+            ) -> Result<Vec<K>, DomException> {
+                missing_keys(self, keys).await
+            }
+        }
+    };
+}
+
+impl_key_set!(IdbObjectStore<'_>);
+#[cfg(feature = "indices")]
+impl_key_set!(IdbIndex<'_>);
@@ -0,0 +1,58 @@
+use wasm_bindgen::prelude::*;
+use web_sys::DomException;
+
+#[cfg(feature = "indices")]
+use crate::idb_index::IdbIndex;
+use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+
+async fn sample_keys<T: IdbQuerySource>(source: &T, n: u32) -> Result<Vec<JsValue>, DomException> {
+    let count = source.count()?.await?;
+    if n == 0 || count == 0 {
+        return Ok(Vec::new());
+    }
+    let cursor = match source.open_key_cursor()?.await? {
+        Some(cursor) => cursor,
+        None => return Ok(Vec::new()),
+    };
+    if n >= count {
+        return cursor.into_vec(0).await;
+    }
+
+    let stride = f64::from(count) / f64::from(n);
+    let mut keys = Vec::with_capacity(n as usize);
+    let mut position = 0;
+    for i in 0..n {
+        let target = (f64::from(i) * stride) as u32;
+        if target > position && !cursor.advance(target - position)?.await? {
+            break;
+        }
+        position = target;
+        match cursor.key() {
+            Some(key) => keys.push(key),
+            None => break,
+        }
+    }
+    Ok(keys)
+}
+
+macro_rules! impl_sample_keys {
+    ($for: ty) => {
+        impl $for {
+            /// Pick about `n` keys spread evenly across the whole key space, e.g. to draw a
+            /// histogram of a very large store. Counts the records, then skips between the picks
+            /// with [advance][crate::idb_cursor::IdbCursor::advance] instead of visiting every
+            /// record. Keys come out in ascending order; records written concurrently can shift
+            /// the picks slightly.
+            ///
+            /// Features required: `cursors`
+            pub async fn sample_keys(&self, n: u32) -> Result<Vec<JsValue>, DomException> {
+                sample_keys(self, n).await
+            }
+        }
+    };
+}
+
+impl_sample_keys!(IdbObjectStore<'_>);
+#[cfg(feature = "indices")]
+impl_sample_keys!(IdbIndex<'_>);
@@ -0,0 +1,96 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+#[cfg(feature = "indices")]
+use crate::idb_index::IdbIndex;
+use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+
+use super::{CursorStream, KeyVal};
+
+/// A [Stream] of the records matching a predicate, returned by
+/// [IdbObjectStore::scan_where] and [IdbIndex::scan_where]. The stream ends after the first
+/// error.
+///
+/// Features required: `streams`
+pub struct ScanStream<'a, T: IdbQuerySource, P> {
+    inner: Option<CursorStream<'a, T, KeyVal>>,
+    predicate: P,
+}
+
+impl<T: IdbQuerySource, P> Unpin for ScanStream<'_, T, P> {}
+
+impl<T: IdbQuerySource + std::fmt::Debug, P> std::fmt::Debug for ScanStream<'_, T, P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScanStream")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<'a, T: IdbQuerySource, P: FnMut(&JsValue) -> bool> Stream for ScanStream<'a, T, P> {
+    type Item = Result<KeyVal, DomException>;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let inner = match this.inner.as_mut() {
+            Some(inner) => inner,
+            None => return Poll::Ready(None),
+        };
+        loop {
+            match Pin::new(&mut *inner).poll_next(ctx) {
+                Poll::Ready(Some(Ok(kv))) if !(this.predicate)(kv.value()) => continue,
+                other => return other,
+            }
+        }
+    }
+}
+
+/// Open a cursor on the source and filter its records
+async fn scan<'a, T, K, P>(
+    source: &'a T,
+    range: &K,
+    predicate: P,
+) -> Result<ScanStream<'a, T, P>, DomException>
+where
+    T: IdbQuerySource,
+    K: JsCast,
+    P: FnMut(&JsValue) -> bool,
+{
+    let cursor = source.open_cursor_with_range(range)?.await?;
+    Ok(ScanStream {
+        inner: cursor.map(|cursor| cursor.into_stream()),
+        predicate,
+    })
+}
+
+macro_rules! impl_scan_where {
+    ($for: ty) => {
+        impl $for {
+            /// Stream the records in the given key range, or all records if the range is
+            /// `undefined`, for which the predicate returns true. The predicate gets evaluated
+            /// per record on a cursor - the fallback query path when no index exists.
+            ///
+            /// Features required: `streams`
+            pub async fn scan_where<K, P>(
+                &self,
+                range: &K,
+                predicate: P,
+            ) -> Result<ScanStream<'_, Self, P>, DomException>
+            where
+                K: JsCast,
+                P: FnMut(&JsValue) -> bool,
+            {
+                scan(self, range, predicate).await
+            }
+        }
+    };
+}
+
+impl_scan_where!(IdbObjectStore<'_>);
+#[cfg(feature = "indices")]
+impl_scan_where!(IdbIndex<'_>);
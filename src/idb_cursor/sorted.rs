@@ -0,0 +1,137 @@
+use std::cmp::Ordering;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+#[cfg(feature = "indices")]
+use crate::idb_index::IdbIndex;
+use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+
+use super::KeyVal;
+
+/// A binary heap ordered by a comparator, keeping the greatest retained item at the top so that
+/// it's the one evicted when a smaller one comes along
+struct BoundedHeap<C> {
+    items: Vec<KeyVal>,
+    limit: usize,
+    cmp: C,
+}
+
+impl<C: FnMut(&JsValue, &JsValue) -> Ordering> BoundedHeap<C> {
+    fn new(limit: usize, cmp: C) -> Self {
+        Self {
+            items: Vec::with_capacity(limit),
+            limit,
+            cmp,
+        }
+    }
+
+    fn greater(&mut self, a: usize, b: usize) -> bool {
+        (self.cmp)(self.items[a].value(), self.items[b].value()) == Ordering::Greater
+    }
+
+    fn push(&mut self, item: KeyVal) {
+        if self.items.len() < self.limit {
+            self.items.push(item);
+            self.sift_up(self.items.len() - 1);
+        } else if (self.cmp)(item.value(), self.items[0].value()) == Ordering::Less {
+            self.items[0] = item;
+            self.sift_down(0);
+        }
+    }
+
+    fn sift_up(&mut self, mut idx: usize) {
+        while idx > 0 {
+            let parent = (idx - 1) / 2;
+            if !self.greater(idx, parent) {
+                break;
+            }
+            self.items.swap(idx, parent);
+            idx = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut idx: usize) {
+        loop {
+            let mut largest = idx;
+            for child in [2 * idx + 1, 2 * idx + 2] {
+                if child < self.items.len() && self.greater(child, largest) {
+                    largest = child;
+                }
+            }
+            if largest == idx {
+                break;
+            }
+            self.items.swap(idx, largest);
+            idx = largest;
+        }
+    }
+
+    fn into_sorted_vec(self) -> Vec<KeyVal> {
+        let Self {
+            mut items, mut cmp, ..
+        } = self;
+        items.sort_by(|a, b| cmp(a.value(), b.value()));
+        items
+    }
+}
+
+/// Walk a cursor over the source, retaining the `limit` smallest records
+async fn collect_sorted<T, K, C>(
+    source: &T,
+    range: &K,
+    cmp: C,
+    limit: usize,
+) -> Result<Vec<KeyVal>, DomException>
+where
+    T: IdbQuerySource,
+    K: JsCast,
+    C: FnMut(&JsValue, &JsValue) -> Ordering,
+{
+    if limit == 0 {
+        return Ok(Vec::new());
+    }
+    let mut heap = BoundedHeap::new(limit, cmp);
+    if let Some(cursor) = source.open_cursor_with_range(range)?.await? {
+        loop {
+            match cursor.key() {
+                Some(key) => heap.push(KeyVal::new(key, cursor.value())),
+                None => break,
+            }
+            if !cursor.continue_cursor()?.await? {
+                break;
+            }
+        }
+    }
+    Ok(heap.into_sorted_vec())
+}
+
+macro_rules! impl_collect_sorted_by {
+    ($for: ty) => {
+        impl $for {
+            /// Walk the records in the given key range, or all records if the range is
+            /// `undefined`, and collect the first `limit` records in the order defined by the
+            /// comparator, e.g. "top 20 by score" without an index. Only `limit` records are
+            /// retained at a time.
+            ///
+            /// Features required: `cursors`
+            pub async fn collect_sorted_by<K, C>(
+                &self,
+                range: &K,
+                cmp: C,
+                limit: usize,
+            ) -> Result<Vec<KeyVal>, DomException>
+            where
+                K: JsCast,
+                C: FnMut(&JsValue, &JsValue) -> Ordering,
+            {
+                collect_sorted(self, range, cmp, limit).await
+            }
+        }
+    };
+}
+
+impl_collect_sorted_by!(IdbObjectStore<'_>);
+#[cfg(feature = "indices")]
+impl_collect_sorted_by!(IdbIndex<'_>);
@@ -0,0 +1,65 @@
+use wasm_bindgen::prelude::*;
+use web_sys::DomException;
+
+use crate::idb_key_path::IdbKeyPath;
+use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::dom_exception;
+
+/// Key paths [IdbObjectStore::version_vector] looks for an index on, in order
+const VERSION_FIELDS: [&str; 3] = ["_rev", "updated_at", "updatedAt"];
+
+impl IdbObjectStore<'_> {
+    /// The `(primary key, version)` pair of every record whose version falls in the given range,
+    /// or of every record if the range is `undefined`, read with a key cursor on the store's
+    /// index on `_rev`, `updated_at` or `updatedAt` so that no values get loaded. That's all a
+    /// sync protocol needs to compute a delta, e.g. everything changed since the last sync with
+    /// a lower bound. Pairs come out in version order. Fails with a `NotFoundError` if the store
+    /// has no such index.
+    ///
+    /// Features required: `cursors`, `indices`
+    pub async fn version_vector<K: JsCast>(
+        &self,
+        range: &K,
+    ) -> Result<Vec<(JsValue, JsValue)>, DomException> {
+        for field in VERSION_FIELDS.iter() {
+            let key_path = IdbKeyPath::str(field);
+            for name in self.index_names() {
+                let index = self.index(&name)?;
+                if index.key_path() == Some(key_path.clone()) {
+                    return self.version_vector_with_index(&name, range).await;
+                }
+            }
+        }
+        Err(dom_exception(
+            &format!(
+                "Object store {} has no index on {}",
+                self.name(),
+                VERSION_FIELDS.join(", ")
+            ),
+            "NotFoundError",
+        ))
+    }
+
+    /// [version_vector][IdbObjectStore::version_vector] using the given index on the records'
+    /// version field
+    ///
+    /// Features required: `cursors`, `indices`
+    pub async fn version_vector_with_index<K: JsCast>(
+        &self,
+        index: &str,
+        range: &K,
+    ) -> Result<Vec<(JsValue, JsValue)>, DomException> {
+        let index = self.index(index)?;
+        let mut out = Vec::new();
+        if let Some(cursor) = index.open_key_cursor_with_range(range)?.await? {
+            while let (Some(version), Some(key)) = (cursor.key(), cursor.primary_key()) {
+                out.push((key, version));
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+        Ok(out)
+    }
+}
@@ -1,21 +1,39 @@
 //! Database-related code
 
+use std::fmt;
+
 use wasm_bindgen::{prelude::*, JsCast};
 use web_sys::{DomException, IdbTransactionMode};
 
+pub use chunked_put::*;
+pub use databases::*;
+pub use db_stats::*;
 pub(crate) use idb_version_change_event::IdbVersionChangeCallback;
 pub use idb_version_change_event::IdbVersionChangeEvent;
+pub use open_options::*;
+pub use read_only::*;
+pub use shared_open::*;
+pub use store_handle::*;
 
 use crate::dom_string_iterator::DomStringIterator;
 use crate::idb_object_store::{IdbObjectStore, IdbObjectStoreParameters};
 use crate::idb_transaction::IdbTransaction;
 use crate::internal_utils::arrayify_slice;
-use crate::request::{OpenDbRequest, VoidOpenDbRequest};
+use crate::request::{OpenDbRequest, PanicSlot, VoidOpenDbRequest};
 
+mod chunked_put;
+mod databases;
+mod db_stats;
 mod idb_version_change_event;
+mod open_options;
+mod read_only;
+mod shared_open;
+mod store_handle;
 
 /// Wrapper for an IndexedDB database
-#[derive(Debug)]
+///
+/// Displays as its name, version and object stores, e.g. `my_db v2 [posts, users]`, for logs and
+/// error messages.
 pub struct IdbDatabase {
     inner: web_sys::IdbDatabase,
     on_version_change: Option<IdbVersionChangeCallback>,
@@ -32,19 +50,27 @@ impl IdbDatabase {
         }
     }
 
-    /// Open the database with the given name
+    /// Wrap a database connection opened outside of this crate, e.g. by an existing JS setup, so
+    /// that it can be used through the Rust API.
+    #[inline]
+    pub fn from_js(inner: web_sys::IdbDatabase) -> Self {
+        Self::new(inner)
+    }
+
+    /// Open the database with the given name. See [OpenOptions] for opening with timeouts and
+    /// retries.
     pub fn open(name: &str) -> OpenDbResult {
-        Ok(OpenDbRequest::new(factory().open(name)?))
+        Ok(OpenDbRequest::new(factory()?.open(name)?))
     }
 
     /// Open the database with the given name and u32 version
     pub fn open_u32(name: &str, version: u32) -> OpenDbResult {
-        Ok(OpenDbRequest::new(factory().open_with_u32(name, version)?))
+        Ok(OpenDbRequest::new(factory()?.open_with_u32(name, version)?))
     }
 
     /// Open the database with the given name and f64 version
     pub fn open_f64(name: &str, version: f64) -> OpenDbResult {
-        Ok(OpenDbRequest::new(factory().open_with_f64(name, version)?))
+        Ok(OpenDbRequest::new(factory()?.open_with_f64(name, version)?))
     }
 
     #[inline]
@@ -52,27 +78,51 @@ impl IdbDatabase {
         &self.inner
     }
 
-    /// List the names of the object stores within this database
+    /// The underlying web_sys database
     #[inline]
-    pub fn object_store_names(&self) -> impl Iterator<Item = String> + 'static {
+    pub fn as_web_sys(&self) -> &web_sys::IdbDatabase {
+        self.inner()
+    }
+
+    /// Unwrap the underlying web_sys database. Any callbacks set through this wrapper get removed.
+    #[inline]
+    pub fn into_web_sys(self) -> web_sys::IdbDatabase {
+        self.inner.clone()
+    }
+
+    /// List the names of the object stores within this database. The names are read from the
+    /// connection on every call, so they include the stores created or deleted by an upgrade as
+    /// soon as it has run, including from within the upgrade callback.
+    #[inline]
+    pub fn object_store_names(&self) -> DomStringIterator {
         DomStringIterator::from(self.inner().object_store_names())
     }
 
+    /// Whether the database has an object store with the given name, e.g. to check for an
+    /// optional store before starting a transaction on it, which would fail with a
+    /// `NotFoundError`
+    #[inline]
+    pub fn has_store(&self, name: &str) -> bool {
+        self.inner().object_store_names().contains(name)
+    }
+
     /// Get the database name
     #[inline]
     pub fn name(&self) -> String {
         self.inner().name()
     }
 
-    /// Get the database version
+    /// Get the database version. Versions are integers, even though the DOM API reports them as
+    /// floats.
     #[inline]
-    pub fn version(&self) -> f64 {
-        self.inner().version()
+    pub fn version(&self) -> u64 {
+        self.inner().version() as u64
     }
 
     /// Close the database connection
     #[inline]
     pub fn close(&self) {
+        shared_open::evict(&self.inner);
         self.inner().close();
     }
 
@@ -91,17 +141,22 @@ impl IdbDatabase {
 
     /// Delete the database with the given name
     pub fn delete_by_name(name: &str) -> Result<VoidOpenDbRequest, DomException> {
-        Ok(VoidOpenDbRequest::new(factory().delete_database(name)?))
+        Ok(VoidOpenDbRequest::new(factory()?.delete_database(name)?))
     }
 
-    /// Set the callback to execute when the versionchange event is fired
+    /// Set the callback to execute when the versionchange event is fired, replacing any previous
+    /// one. `None` removes it.
     pub fn set_on_version_change<F>(&mut self, callback: Option<F>)
     where
-        F: Fn(&IdbVersionChangeEvent) -> Result<(), JsValue> + 'static,
+        F: FnMut(&IdbVersionChangeEvent) -> Result<(), JsValue> + 'static,
     {
         self.on_version_change = match callback {
             Some(callback) => {
-                let cb = IdbVersionChangeEvent::wrap_callback(callback);
+                let cb = IdbVersionChangeEvent::wrap_callback(
+                    callback,
+                    "versionchange",
+                    PanicSlot::default(),
+                );
                 self.inner
                     .set_onversionchange(Some(cb.as_ref().unchecked_ref()));
                 Some(cb)
@@ -113,6 +168,12 @@ impl IdbDatabase {
         };
     }
 
+    /// Remove the versionchange callback, if any
+    #[inline]
+    pub fn clear_on_version_change(&mut self) {
+        self.set_on_version_change(None::<crate::request::NoVersionChangeCallback>);
+    }
+
     /// Start a transaction on the given object store
     pub fn transaction_on_one(&self, name: &str) -> Result<IdbTransaction, DomException> {
         let inner = self.inner().transaction_with_str(name)?;
@@ -195,10 +256,49 @@ impl Drop for IdbDatabase {
     }
 }
 
-impl_display_for_named!(IdbDatabase);
+impl From<web_sys::IdbDatabase> for IdbDatabase {
+    #[inline]
+    fn from(inner: web_sys::IdbDatabase) -> Self {
+        Self::from_js(inner)
+    }
+}
+
+impl fmt::Display for IdbDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stores = self.object_store_names().collect::<Vec<_>>();
+        write!(
+            f,
+            "{} v{} [{}]",
+            self.name(),
+            self.version(),
+            stores.join(", ")
+        )
+    }
+}
+
+impl fmt::Debug for IdbDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IdbDatabase")
+            .field("name", &self.name())
+            .field("version", &self.version())
+            .field("stores", &self.object_store_names().collect::<Vec<_>>())
+            .field("on_version_change", &self.on_version_change.is_some())
+            .finish()
+    }
+}
 
-fn factory() -> web_sys::IdbFactory {
-    web_sys::window().unwrap().indexed_db().unwrap().unwrap()
+/// The IDB factory of the global scope, which may be a window or a worker. Fails with a
+/// `NotSupportedError` if the scope has no IndexedDB, e.g. in a sandboxed iframe.
+pub(crate) fn factory() -> Result<web_sys::IdbFactory, DomException> {
+    let factory = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("indexedDB"))
+        .map_err(crate::internal_utils::js_error_into_dom_exception)?;
+    if factory.is_undefined() || factory.is_null() {
+        return Err(crate::internal_utils::dom_exception(
+            "IndexedDB is not available",
+            "NotSupportedError",
+        ));
+    }
+    Ok(factory.unchecked_into())
 }
 
 #[cfg(test)]
@@ -207,15 +307,12 @@ pub mod test {
     use core::future::Future;
 
     use super::*;
-    use crate::{IdbKeyPath, IdbQuerySource};
+    use crate::test_utils::{open_db_with, open_db_with_stores, unique_name};
+    use crate::{test_case, IdbKeyPath, IdbQuerySource};
     use std::cell::RefCell;
     use std::ops::Deref;
     use std::rc::Rc;
 
-    fn db_name() -> String {
-        uuid::Uuid::new_v4().to_string()
-    }
-
     async fn open_db(req: OpenDbRequest) -> IdbDatabase {
         req.into_future().await.expect("Future failed")
     }
@@ -228,47 +325,456 @@ pub mod test {
         test_mod_init!();
 
         test_case!(async empty_iter => {
-            let db = open_db_req(IdbDatabase::open(&db_name())).await;
+            let db = open_db_req(IdbDatabase::open(&unique_name())).await;
             let stores: Vec<String> = db.object_store_names().collect();
             assert_eq!(stores, Vec::<String>::new());
         });
 
         test_case!(async iter_with_two => {
-            let mut req = IdbDatabase::open(&db_name()).expect("Base open");
-            fn on_upgrade_needed(evt: &IdbVersionChangeEvent) -> Result<(), JsValue> {
+            let db = open_db_with_stores(&["store1", "store2"]).await;
+            let stores: Vec<String> = db.object_store_names().collect();
+
+            assert_eq!(stores, vec![String::from("store1"), String::from("store2")]);
+        });
+
+        test_case!(async has_store => {
+            let name = unique_name();
+            let mut req = IdbDatabase::open_u32(&name, 1).expect("open v1");
+            req.set_on_upgrade_needed(Some(|evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+                evt.db().create_object_store("a")?;
+                assert!(evt.db().has_store("a"), "within upgrade");
+                Ok(())
+            }));
+            let db = open_db(req).await;
+            assert!(db.has_store("a") && !db.has_store("b"), "v1");
+            db.close();
+
+            let mut req = IdbDatabase::open_u32(&name, 2).expect("open v2");
+            req.set_on_upgrade_needed(Some(|evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+                evt.db().create_object_store("b")?;
+                evt.db().delete_object_store("a")?;
+                Ok(())
+            }));
+            let db = open_db(req).await;
+            assert!(!db.has_store("a") && db.has_store("b"), "v2");
+            assert_eq!(db.to_string(), format!("{} v2 [b]", name), "display");
+            assert!(format!("{:?}", db).contains("version: 2"), "debug");
+        });
+    }
+
+    pub mod open_options {
+        test_mod_init!();
+        use std::time::Duration;
+
+        test_case!(async upgrade => {
+            let name = unique_name();
+            let db = OpenOptions::new(&name)
+                .version(2)
+                .on_upgrade(|evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+                    evt.db().create_object_store("s")?;
+                    Ok(())
+                })
+                .timeout(Duration::from_secs(5))
+                .open()
+                .await
+                .expect("open");
+
+            assert_eq!(db.version(), 2, "version");
+            assert_eq!(db.object_store_names().collect::<Vec<_>>(), vec!["s".to_string()]);
+        });
+
+        test_case!(async blocked_timeout => {
+            let name = unique_name();
+            let _db1 = IdbDatabase::open_u32(&name, 1).expect("open1").into_future().await.expect("db1");
+            let blocked = Rc::new(RefCell::new(0u8));
+            let blocked_cb = blocked.clone();
+
+            let err = OpenOptions::new(&name)
+                .version(2)
+                .on_blocked(move || *blocked_cb.borrow_mut() += 1)
+                .timeout(Duration::from_millis(100))
+                .retry_on_blocked(1)
+                .open()
+                .await
+                .expect_err("open2");
+
+            match err {
+                OpenError::Dom(e) => assert_eq!(e.name(), "TimeoutError", "error name"),
+                e => panic!("Unexpected error: {}", e),
+            }
+            assert_eq!(*blocked.borrow(), 2, "blocked calls");
+        });
+
+        test_case!(async version_downgrade => {
+            let name = unique_name();
+            IdbDatabase::open_u32(&name, 3).expect("open3").into_future().await.expect("db3").close();
+
+            let err = OpenOptions::new(&name).version(2).open().await.expect_err("open2");
+            assert_eq!(err, OpenError::VersionDowngrade { requested: 2, existing: 3 });
+        });
+
+        test_case!(async session_stores => {
+            let name = unique_name();
+            let options = OpenOptions::new(&name)
+                .version(1)
+                .on_upgrade(|evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+                    evt.db().create_object_store("cache")?;
+                    evt.db().create_object_store("data")?;
+                    Ok(())
+                })
+                .session_stores(&["cache", "missing"]);
+            async fn count(db: &IdbDatabase, store: &str) -> u32 {
+                let tx = db.transaction_on_one(store).unwrap();
+                let count = tx.object_store(store).unwrap().count().unwrap().await.unwrap();
+                count
+            }
+
+            let db = options.open().await.expect("open1");
+            for store in &["cache", "data"] {
+                let tx = db.transaction_on_one_with_mode(store, IdbTransactionMode::Readwrite).unwrap();
+                tx.object_store(store).unwrap().put_key_val_owned("k", &JsValue::from(1)).unwrap();
+                tx.await.into_result().unwrap();
+            }
+            db.close();
+
+            let db = options.open().await.expect("open2");
+            assert_eq!(count(&db, "cache").await, 1, "kept within the session");
+            db.close();
+
+            let marker = format!("indexed_db_futures:session:{}", name);
+            let storage = web_sys::window().unwrap().session_storage().unwrap().unwrap();
+            storage.remove_item(&marker).unwrap();
+            let db = options.open().await.expect("open3");
+            assert_eq!(count(&db, "cache").await, 0, "cleared in a new session");
+            assert_eq!(count(&db, "data").await, 1, "other stores kept");
+        });
+    }
+
+    pub mod read_only {
+        test_mod_init!();
+
+        test_case!(async read_only => {
+            let name = unique_name();
+            let db = OpenOptions::new(&name)
+                .version(1)
+                .on_upgrade(|evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+                    evt.db().create_object_store("s")?;
+                    Ok(())
+                })
+                .open_read_only()
+                .await
+                .expect("open");
+
+            let tx = db.transaction_on_one("s").expect("readonly tx");
+            let store = tx.object_store("s").expect("store");
+            let err = store.put_key_val_owned(1u8, &JsValue::from(1u8)).expect_err("put");
+            assert_eq!(err.name(), "ReadOnlyError", "write rejected");
+
+            let err = db
+                .transaction_on_one_with_mode("s", IdbTransactionMode::Readwrite)
+                .expect_err("readwrite tx");
+            assert_eq!(err.name(), "ReadOnlyError", "mode rejected");
+            db.transaction_on_multi_with_mode(&["s"], IdbTransactionMode::Readonly)
+                .expect("readonly mode");
+        });
+    }
+
+    pub mod shared_open {
+        test_mod_init!();
+
+        test_case!(async concurrent_opens => {
+            let name = unique_name();
+            let upgrades = Rc::new(RefCell::new(0u8));
+            let on_upgrade = |upgrades: Rc<RefCell<u8>>| {
+                move |evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+                    *upgrades.borrow_mut() += 1;
+                    evt.db().create_object_store("s")?;
+                    Ok(())
+                }
+            };
+
+            let fut1 = IdbDatabase::open_shared(&name, 1, Some(on_upgrade(upgrades.clone())));
+            let fut2 = IdbDatabase::open_shared(&name, 1, Some(on_upgrade(upgrades.clone())));
+            let db1 = fut1.await.expect("db1");
+            let db2 = fut2.await.expect("db2");
+            let db3 = IdbDatabase::open_shared(&name, 1, Some(on_upgrade(upgrades.clone())))
+                .await
+                .expect("db3");
+
+            assert!(Rc::ptr_eq(&db1, &db2), "concurrent share");
+            assert!(Rc::ptr_eq(&db1, &db3), "later share");
+            assert_eq!(*upgrades.borrow(), 1, "upgrades");
+        });
+
+        test_case!(async closed_connections_are_evicted => {
+            let name = unique_name();
+            let on_upgrade = |evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+                evt.db().create_object_store("s")?;
+                Ok(())
+            };
+
+            let db1 = IdbDatabase::open_shared(&name, 1, Some(on_upgrade)).await.expect("db1");
+            db1.close();
+            let db2 = IdbDatabase::open_shared(&name, 1, None::<fn(&IdbVersionChangeEvent) -> Result<(), JsValue>>)
+                .await
+                .expect("db2");
+            assert!(!Rc::ptr_eq(&db1, &db2), "new connection after close");
+            db2.transaction_on_one("s").expect("db2 usable");
+
+            let db3 = IdbDatabase::open_u32(&name, 2).unwrap().into_future().await.expect("upgrade not blocked");
+            let err = IdbDatabase::open_shared(&name, 1, None::<fn(&IdbVersionChangeEvent) -> Result<(), JsValue>>)
+                .await
+                .expect_err("evicted on versionchange");
+            assert_eq!(DomException::from(err).name(), "VersionError", "reopened");
+            db3.close();
+        });
+    }
+
+    pub mod databases {
+        test_mod_init!();
+
+        test_case!(async delete_matching => {
+            let prefix = unique_name();
+            let keep = unique_name();
+            for suffix in &["a", "b"] {
+                open_db_req(IdbDatabase::open(&format!("{}-{}", prefix, suffix))).await.close();
+            }
+            open_db_req(IdbDatabase::open(&keep)).await.close();
+
+            let mut deleted = IdbDatabase::delete_databases_matching(|name| name.starts_with(&prefix))
+                .await
+                .expect("delete");
+            deleted.sort();
+            let remaining: Vec<String> = IdbDatabase::databases()
+                .await
+                .expect("databases")
+                .into_iter()
+                .map(|info| info.name)
+                .collect();
+
+            assert_eq!(deleted, vec![format!("{}-a", prefix), format!("{}-b", prefix)], "deleted");
+            assert!(remaining.contains(&keep), "kept");
+            assert!(!remaining.iter().any(|n| n.starts_with(&prefix)), "removed");
+        });
+    }
+
+    #[cfg(feature = "indices")]
+    pub mod stats {
+        test_mod_init!();
+
+        test_case!(async stats => {
+            let db = open_db_with(|evt: &IdbVersionChangeEvent| {
                 evt.db().create_object_store("store1")?;
-                evt.db().create_object_store("store2")?;
+                let store2 = evt.db().create_object_store("store2")?;
+                store2.create_index("idx", &IdbKeyPath::str("foo"))?;
                 Ok(())
+            })
+            .await;
+
+            let tx = db.transaction_on_one_with_mode("store1", IdbTransactionMode::Readwrite).expect("tx");
+            let store = tx.object_store("store1").expect("store");
+            store.put_key_val_owned("a", &JsValue::from(1)).expect("put a");
+            store.put_key_val_owned("b", &JsValue::from(2)).expect("put b");
+            tx.await.into_result().expect("tx await");
+
+            let stats = db.stats().await.expect("stats");
+            let summary: Vec<(&str, u32, u32)> = stats.stores
+                .iter()
+                .map(|s| (s.name.as_str(), s.records, s.indices))
+                .collect();
+
+            assert_eq!(summary, vec![("store1", 2, 0), ("store2", 0, 1)], "summary");
+            assert_eq!(stats.total_records(), 2, "total records");
+
+            #[cfg(feature = "cursors")]
+            {
+                let stats = db.stats_with_sizes(10).await.expect("stats with sizes");
+                assert_eq!(stats.total_bytes(), Some(36.0), "total bytes");
+                assert_eq!(db.stats().await.expect("stats").total_bytes(), None, "no sizes");
             }
-            req.set_on_upgrade_needed(Some(on_upgrade_needed));
-            let db = open_db(req).await;
-            let stores: Vec<String> = db.object_store_names().collect();
+        });
+    }
 
-            assert_eq!(stores, vec![String::from("store1"), String::from("store2")]);
+    pub mod chunked_put {
+        use crate::test_utils::open_any_db;
+        test_mod_init!();
+
+        fn records(keys: std::ops::Range<u32>) -> Vec<(JsValue, JsValue)> {
+            keys.map(|i| (JsValue::from(i), JsValue::from(i * 10)))
+                .collect()
+        }
+
+        test_case!(async chunked => {
+            let (db, store_name) = open_any_db().await;
+            let mut reports = Vec::new();
+            let last = db.put_all_chunked(&store_name, records(0..25), 10)
+                .run(|p| reports.push((p.chunk, p.chunk_len, p.written)))
+                .await
+                .expect("run")
+                .expect("last");
+            assert_eq!(reports, vec![(0, 10, 10), (1, 10, 20), (2, 5, 25)], "reports");
+            assert_eq!((last.written, last.failed), (25, 0), "last");
+
+            let tx = db.transaction_on_one(&store_name).unwrap();
+            let store = tx.object_store(&store_name).unwrap();
+            assert_eq!(store.count().unwrap().await.unwrap(), 25, "count");
+            assert!(db.put_all_chunked(&store_name, Vec::new(), 10).run(|_| {}).await.expect("empty").is_none(), "empty");
+        });
+
+        test_case!(async failure_modes => {
+            let (db, store_name) = open_any_db().await;
+            // An array key containing an object is invalid, failing its chunk
+            let mut bad = records(0..6);
+            bad[3].0 = js_sys::Array::of1(&js_sys::Object::new()).into();
+
+            let mut put = db.put_all_chunked(&store_name, bad.clone(), 2);
+            assert!(put.next_chunk().await.expect("chunk 0").is_ok(), "chunk 0");
+            assert!(put.next_chunk().await.expect("chunk 1").is_err(), "chunk 1 stops");
+            assert!(put.next_chunk().await.is_none(), "stopped");
+
+            let last = db.put_all_chunked(&store_name, bad, 2)
+                .on_failure(FailureMode::Log)
+                .deadline(std::time::Duration::from_secs(10))
+                .run(|_| {})
+                .await
+                .expect("logged run")
+                .expect("last");
+            assert_eq!((last.written, last.failed), (4, 2), "logged");
+        });
+
+        #[cfg(feature = "streams")]
+        test_case!(async stream => {
+            use std::future::Future;
+            use std::pin::Pin;
+            use std::task::{Context, Poll};
+
+            struct Next<'s, S>(&'s mut S);
+            impl<S: futures_core::Stream + Unpin> Future for Next<'_, S> {
+                type Output = Option<S::Item>;
+                fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+                    Pin::new(&mut *self.0).poll_next(ctx)
+                }
+            }
+
+            let (db, store_name) = open_any_db().await;
+            let mut stream = Box::pin(db.put_all_chunked(&store_name, records(0..5), 2).into_stream());
+            let mut written = Vec::new();
+            while let Some(progress) = Next(&mut stream).await {
+                written.push(progress.expect("progress").written);
+            }
+            assert_eq!(written, vec![2, 4, 5], "written");
+        });
+    }
+
+    pub mod store_handle {
+        use crate::test_utils::open_any_db;
+        test_mod_init!();
+
+        async fn write_and_read(store: StoreHandle) -> Vec<JsValue> {
+            store
+                .put(&JsValue::from("a"), &JsValue::from(1u8))
+                .await
+                .expect("put a");
+            store
+                .put(&JsValue::from("b"), &JsValue::from(2u8))
+                .await
+                .expect("put b");
+            store.get_all().await.expect("get_all")
+        }
+
+        test_case!(async store_handle => {
+            let (db, store_name) = open_any_db().await;
+            let store = db.store(&store_name);
+            let values = write_and_read(store.clone()).await;
+            assert_eq!(values, vec![JsValue::from(1u8), JsValue::from(2u8)], "values");
+
+            assert_eq!(store.get(&JsValue::from("a")).await.expect("get"), Some(JsValue::from(1u8)), "get");
+            assert!(store.contains(&JsValue::from("b")).await.expect("contains"), "contains");
+            assert_eq!(store.get_all_keys().await.expect("keys"), vec![JsValue::from("a"), JsValue::from("b")], "keys");
+            store.delete(&JsValue::from("a")).await.expect("delete");
+            assert_eq!(store.count().await.expect("count"), 1, "count");
+            store.clear().await.expect("clear");
+            assert_eq!(store.count().await.expect("count cleared"), 0, "cleared");
+
+            let err = db.store("nope").count().await.expect_err("missing store");
+            assert_eq!(err.name(), "NotFoundError", "missing store");
         });
     }
 
     pub mod open {
         test_mod_init!();
 
-        fn test_version(db: &IdbDatabase, version_expected: f64, name_expected: String) {
+        fn test_version(db: &IdbDatabase, version_expected: u64, name_expected: String) {
             assert_eq!(db.name(), name_expected, "name");
             assert_eq!(db.version(), version_expected, "version");
         }
 
         test_case!(async should_open_without_version => {
-            let name = db_name();
-            test_version(&open_db_req(IdbDatabase::open(&name)).await, 1.0, name);
+            let name = unique_name();
+            test_version(&open_db_req(IdbDatabase::open(&name)).await, 1, name);
         });
 
         test_case!(async should_open_with_u32 => {
-            let name = db_name();
-            test_version(&open_db_req(IdbDatabase::open_u32(&name, 101)).await, 101.0, name);
+            let name = unique_name();
+            test_version(&open_db_req(IdbDatabase::open_u32(&name, 101)).await, 101, name);
         });
 
         test_case!(async should_open_with_f64 => {
-            let name = db_name();
-            test_version(&open_db_req(IdbDatabase::open_f64(&name, 42.0)).await, 42.0, name);
+            let name = unique_name();
+            test_version(&open_db_req(IdbDatabase::open_f64(&name, 42.0)).await, 42, name);
+        });
+
+        test_case!(async stateful_callbacks => {
+            fn create(evt: &IdbVersionChangeEvent) -> Result<(), DomException> {
+                evt.db().create_object_store("s")?;
+                Ok(())
+            }
+
+            let mut upgrades = 0u8;
+            let mut req = IdbDatabase::open_u32(&unique_name(), 1).expect("open1");
+            req.set_on_upgrade_needed_with_err(Some(move |evt: &IdbVersionChangeEvent| {
+                upgrades += 1;
+                assert_eq!(upgrades, 1, "called once");
+                create(evt)
+            }));
+            let db = req.into_future().await.expect("db1");
+            assert!(db.has_store("s"), "upgraded through a DomException callback");
+
+            let db = IdbDatabase::open_u32(&unique_name(), 1)
+                .expect("open2")
+                .with_on_upgrade_needed(|evt: &IdbVersionChangeEvent| {
+                    evt.db().create_object_store("s")?;
+                    Ok(())
+                })
+                .into_future()
+                .await
+                .expect("db2");
+            assert!(db.has_store("s"), "builder-style callback");
+
+            let mut req = IdbDatabase::open_u32(&unique_name(), 1).expect("open3");
+            req.set_on_upgrade_needed(Some(|evt: &IdbVersionChangeEvent| {
+                evt.db().create_object_store("removed")?;
+                Ok(())
+            }));
+            req.clear_on_upgrade_needed();
+            let db = req.into_future().await.expect("db3");
+            assert!(!db.has_store("removed"), "removed callback");
+
+            let calls = Rc::new(RefCell::new(Vec::new()));
+            let options = {
+                let calls = calls.clone();
+                let mut attempt = 0u8;
+                OpenOptions::new(&unique_name())
+                    .on_upgrade(|_: &IdbVersionChangeEvent| -> Result<(), JsValue> { panic!("replaced") })
+                    .on_upgrade(move |_: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+                        attempt += 1;
+                        calls.borrow_mut().push(attempt);
+                        Ok(())
+                    })
+            };
+            options.clone().version(1).open().await.expect("open3").close();
+            options.version(2).open().await.expect("open4");
+            assert_eq!(*calls.borrow(), vec![1, 2], "shared between clones");
         });
     }
 
@@ -276,15 +782,8 @@ pub mod test {
         test_mod_init!();
 
         test_case!(async delete_object_store => {
-            let db_name = db_name();
-
-            let mut req = IdbDatabase::open_u32(&db_name, 1).expect("open 1");
-            req.set_on_upgrade_needed(Some(move |evt: &IdbVersionChangeEvent| {
-                evt.db().create_object_store("s1")?;
-                evt.db().create_object_store("s2")?;
-                Ok(())
-            }));
-            let db = req.into_future().await.expect("db await 1");
+            let db = open_db_with_stores(&["s1", "s2"]).await;
+            let db_name = db.name();
             db.close();
 
             let mut req = IdbDatabase::open_u32(&db_name, 2).expect("open 2");
@@ -300,7 +799,7 @@ pub mod test {
         });
 
         test_case!(async delete_by_name => {
-            let db_name = db_name();
+            let db_name = unique_name();
             let calls = Rc::new(RefCell::new(0));
 
             async fn do_open(name: &str, v: u32, calls: Rc<RefCell<u8>>) -> IdbDatabase {
@@ -337,13 +836,7 @@ pub mod test {
         }
 
         async fn open_db() -> IdbDatabase {
-            let mut req = IdbDatabase::open(&db_name()).expect("open");
-            req.set_on_upgrade_needed(Some(move |evt: &IdbVersionChangeEvent| {
-                evt.db().create_object_store("s1")?;
-                evt.db().create_object_store("s2")?;
-                Ok(())
-            }));
-            req.into_future().await.expect("db await 1")
+            open_db_with_stores(&["s1", "s2"]).await
         }
 
         test_case!(async transaction_on_one => {
@@ -392,9 +885,19 @@ pub mod test {
         });
     }
 
+    test_case!(async from_js => {
+        let raw = open_db_with_stores(&["s1"]).await.into_web_sys();
+        let db = IdbDatabase::from_js(raw.clone());
+
+        assert_eq!(db.name(), raw.name(), "name");
+        let tx = db.transaction_on_one_with_mode("s1", IdbTransactionMode::Readwrite).expect("tx");
+        let store = tx.object_store("s1").expect("store");
+        store.put_key_val_owned("foo", &JsValue::from("bar")).expect("put");
+        tx.await.into_result().expect("tx await");
+    });
+
     test_case!(async create_object_store_with_params => {
-        let mut req = IdbDatabase::open(&db_name()).expect("req");
-        req.set_on_upgrade_needed(Some(move |evt: &IdbVersionChangeEvent| {
+        let db = open_db_with(move |evt: &IdbVersionChangeEvent| {
             evt.db().create_object_store_with_params(
                 "s1",
                 IdbObjectStoreParameters::new()
@@ -402,8 +905,7 @@ pub mod test {
                 .key_path(Some(&IdbKeyPath::str("foo")))
             )?;
             Ok(())
-        }));
-        let db = req.into_future().await.expect("db");
+        }).await;
         let tx = db.transaction_on_one("s1").expect("tx");
         let store = tx.object_store("s1").expect("store");
 
@@ -0,0 +1,294 @@
+use std::fmt;
+use std::time::Duration;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbTransactionMode};
+
+use super::IdbDatabase;
+use crate::maintenance::OpPriority;
+
+/// What a [ChunkedPut] does when a chunk's transaction fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureMode {
+    /// Stop at the failing chunk and return its error. Chunks before it stay committed.
+    Stop,
+    /// Report the failing chunk's error in its [ChunkProgress] and carry on with the next chunk
+    Log,
+}
+
+/// Progress of a [ChunkedPut], reported after every chunk
+#[derive(Debug, Clone)]
+pub struct ChunkProgress {
+    /// The chunk's zero-based index
+    pub chunk: u32,
+    /// The number of records in the chunk
+    pub chunk_len: u32,
+    /// Records committed so far, across all chunks
+    pub written: u32,
+    /// Records in failed chunks so far
+    pub failed: u32,
+    /// The chunk's error if its transaction failed in [FailureMode::Log]
+    pub error: Option<DomException>,
+}
+
+/// A large write split into a series of transactions, created by
+/// [IdbDatabase::put_all_chunked].
+///
+/// One enormous transaction makes the browser hold every write in memory until it commits,
+/// blocks other transactions on the store for its whole duration and loses everything if it
+/// aborts. Each chunk here is its own transaction, and with a [deadline][ChunkedPut::deadline]
+/// chunks shrink whenever one takes longer than that to commit and grow back when they're fast,
+/// so no single transaction hogs the store.
+///
+/// Records are `(key, value)` pairs; pass [JsValue::UNDEFINED] as the key for stores with
+/// in-line keys.
+pub struct ChunkedPut<'a, I> {
+    db: &'a IdbDatabase,
+    store_name: String,
+    records: I,
+    max_chunk: u32,
+    chunk_size: u32,
+    deadline: Option<Duration>,
+    on_failure: FailureMode,
+    priority: OpPriority,
+    chunk: u32,
+    written: u32,
+    failed: u32,
+    done: bool,
+}
+
+impl<I> fmt::Debug for ChunkedPut<'_, I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChunkedPut")
+            .field("store_name", &self.store_name)
+            .field("chunk_size", &self.chunk_size)
+            .field("deadline", &self.deadline)
+            .field("on_failure", &self.on_failure)
+            .field("priority", &self.priority)
+            .field("chunk", &self.chunk)
+            .field("written", &self.written)
+            .field("failed", &self.failed)
+            .finish()
+    }
+}
+
+impl IdbDatabase {
+    /// Put a large number of records into the store in sequential transactions of up to
+    /// `chunk_size` records each. See [ChunkedPut].
+    pub fn put_all_chunked<I>(
+        &self,
+        store_name: &str,
+        records: I,
+        chunk_size: u32,
+    ) -> ChunkedPut<'_, I::IntoIter>
+    where
+        I: IntoIterator<Item = (JsValue, JsValue)>,
+    {
+        let chunk_size = chunk_size.max(1);
+        ChunkedPut {
+            db: self,
+            store_name: store_name.into(),
+            records: records.into_iter(),
+            max_chunk: chunk_size,
+            chunk_size,
+            deadline: None,
+            on_failure: FailureMode::Stop,
+            priority: OpPriority::Interactive,
+            chunk: 0,
+            written: 0,
+            failed: 0,
+            done: false,
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = (JsValue, JsValue)>> ChunkedPut<'a, I> {
+    /// Adapt the chunk size so that each chunk's transaction commits within the deadline,
+    /// never exceeding the initial chunk size
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Set what happens when a chunk fails. Defaults to [FailureMode::Stop].
+    pub fn on_failure(mut self, mode: FailureMode) -> Self {
+        self.on_failure = mode;
+        self
+    }
+
+    /// Set whether chunks run right away or wait for the page to be idle. Defaults to
+    /// [OpPriority::Interactive].
+    pub fn priority(mut self, priority: OpPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Write the next chunk. Resolves to `None` once every record has been written or after a
+    /// chunk failed in [FailureMode::Stop].
+    pub async fn next_chunk(&mut self) -> Option<Result<ChunkProgress, DomException>> {
+        if self.done {
+            return None;
+        }
+        let records: Vec<(JsValue, JsValue)> = self
+            .records
+            .by_ref()
+            .take(self.chunk_size as usize)
+            .collect();
+        if records.is_empty() {
+            self.done = true;
+            return None;
+        }
+        if let Err(e) = self.priority.ready().await {
+            self.done = true;
+            return Some(Err(e));
+        }
+
+        let chunk_len = records.len() as u32;
+        let started = js_sys::Date::now();
+        let result = self.write(records).await;
+        let elapsed = js_sys::Date::now() - started;
+        if let Some(deadline) = self.deadline {
+            let deadline = deadline.as_secs_f64() * 1000.0;
+            if elapsed > deadline {
+                self.chunk_size = (self.chunk_size / 2).max(1);
+            } else if elapsed < deadline / 2.0 {
+                self.chunk_size = self.chunk_size.saturating_mul(2).min(self.max_chunk);
+            }
+        }
+
+        let error = match result {
+            Ok(()) => {
+                self.written += chunk_len;
+                None
+            }
+            Err(e) if self.on_failure == FailureMode::Stop => {
+                self.done = true;
+                return Some(Err(e));
+            }
+            Err(e) => {
+                self.failed += chunk_len;
+                Some(e)
+            }
+        };
+        let progress = ChunkProgress {
+            chunk: self.chunk,
+            chunk_len,
+            written: self.written,
+            failed: self.failed,
+            error,
+        };
+        self.chunk += 1;
+        Some(Ok(progress))
+    }
+
+    /// Write every chunk, calling `on_progress` after each one. Resolves to the last chunk's
+    /// progress, or `None` if there were no records.
+    pub async fn run<F: FnMut(&ChunkProgress)>(
+        mut self,
+        mut on_progress: F,
+    ) -> Result<Option<ChunkProgress>, DomException> {
+        let mut last = None;
+        while let Some(progress) = self.next_chunk().await {
+            let progress = progress?;
+            on_progress(&progress);
+            last = Some(progress);
+        }
+        Ok(last)
+    }
+
+    /// Turn the write into a [Stream][futures_core::Stream] of per-chunk progress
+    ///
+    /// Features required: `streams`
+    #[cfg(feature = "streams")]
+    pub fn into_stream(
+        self,
+    ) -> impl futures_core::Stream<Item = Result<ChunkProgress, DomException>> + 'a
+    where
+        I: 'a,
+    {
+        stream::ChunkedPutStream::new(self)
+    }
+
+    async fn write(&self, records: Vec<(JsValue, JsValue)>) -> Result<(), DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(&self.store_name)?;
+        let queued = records.iter().try_for_each(|(key, value)| {
+            if key.is_undefined() {
+                store.put_val(value).map(drop)
+            } else {
+                store.put_key_val(key, value).map(drop)
+            }
+        });
+        if let Err(e) = queued {
+            // Don't let the writes queued before the failing one commit
+            let _ = tx.abort();
+            return Err(e);
+        }
+        tx.await.into_result()
+    }
+}
+
+#[cfg(feature = "streams")]
+mod stream {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use futures_core::Stream;
+    use wasm_bindgen::prelude::*;
+    use web_sys::DomException;
+
+    use super::{ChunkProgress, ChunkedPut};
+
+    type Step<'a, I> = (
+        ChunkedPut<'a, I>,
+        Option<Result<ChunkProgress, DomException>>,
+    );
+
+    pub(super) struct ChunkedPutStream<'a, I> {
+        idle: Option<ChunkedPut<'a, I>>,
+        pending: Option<Pin<Box<dyn Future<Output = Step<'a, I>> + 'a>>>,
+    }
+
+    impl<'a, I: Iterator<Item = (JsValue, JsValue)> + 'a> ChunkedPutStream<'a, I> {
+        pub(super) fn new(put: ChunkedPut<'a, I>) -> Self {
+            Self {
+                idle: Some(put),
+                pending: None,
+            }
+        }
+    }
+
+    // Neither field is ever pinned in place
+    impl<I> Unpin for ChunkedPutStream<'_, I> {}
+
+    impl<'a, I: Iterator<Item = (JsValue, JsValue)> + 'a> Stream for ChunkedPutStream<'a, I> {
+        type Item = Result<ChunkProgress, DomException>;
+
+        fn poll_next(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let this = self.get_mut();
+            if let Some(mut put) = this.idle.take() {
+                this.pending = Some(Box::pin(async move {
+                    let step = put.next_chunk().await;
+                    (put, step)
+                }));
+            }
+            let pending = match this.pending.as_mut() {
+                Some(pending) => pending,
+                None => return Poll::Ready(None),
+            };
+            match pending.as_mut().poll(ctx) {
+                Poll::Ready((put, step)) => {
+                    this.pending = None;
+                    if step.is_some() {
+                        this.idle = Some(put);
+                    }
+                    Poll::Ready(step)
+                }
+                Poll::Pending => Poll::Pending,
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+use crate::internal_utils::{await_promise, dom_exception};
+
+use super::{factory, IdbDatabase};
+
+/// An existing database, as listed by [IdbDatabase::databases]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseInfo {
+    /// The database name
+    pub name: String,
+    /// The database version
+    pub version: f64,
+}
+
+impl IdbDatabase {
+    /// List the databases available to the current origin via
+    /// [IDBFactory.databases](https://developer.mozilla.org/en-US/docs/Web/API/IDBFactory/databases).
+    /// Fails with a `NotSupportedError` in browsers that don't implement it.
+    pub async fn databases() -> Result<Vec<DatabaseInfo>, DomException> {
+        let factory = factory()?;
+        let func = js_sys::Reflect::get(&factory, &JsValue::from_str("databases"))?;
+        let func = match func.dyn_into::<js_sys::Function>() {
+            Ok(func) => func,
+            Err(_) => {
+                return Err(dom_exception(
+                    "IDBFactory.databases() is not supported",
+                    "NotSupportedError",
+                ));
+            }
+        };
+        let promise: js_sys::Promise = func.call0(&factory)?.unchecked_into();
+        let list: js_sys::Array = await_promise(promise).await?.unchecked_into();
+
+        let out = list
+            .iter()
+            .filter_map(|info| {
+                let name = js_sys::Reflect::get(&info, &JsValue::from_str("name")).ok()?;
+                let version = js_sys::Reflect::get(&info, &JsValue::from_str("version")).ok()?;
+                Some(DatabaseInfo {
+                    name: name.as_string()?,
+                    version: version.as_f64().unwrap_or(0.0),
+                })
+            })
+            .collect();
+        Ok(out)
+    }
+
+    /// Delete every database whose name matches the predicate, e.g. ones belonging to accounts
+    /// that no longer exist. Databases are deleted one at a time; a deletion only completes once
+    /// all open connections to that database have been closed. Resolves to the names of the
+    /// deleted databases.
+    pub async fn delete_databases_matching<F>(mut predicate: F) -> Result<Vec<String>, DomException>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        let mut deleted = Vec::new();
+        for info in Self::databases().await? {
+            if predicate(&info.name) {
+                Self::delete_by_name(&info.name)?.into_future().await?;
+                deleted.push(info.name);
+            }
+        }
+        Ok(deleted)
+    }
+}
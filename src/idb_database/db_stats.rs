@@ -0,0 +1,96 @@
+use web_sys::DomException;
+
+#[cfg(feature = "cursors")]
+use crate::idb_object_store::SizeEstimate;
+use crate::idb_query_source::IdbQuerySource;
+
+use super::IdbDatabase;
+
+/// Statistics about a database, returned by [IdbDatabase::stats]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DbStats {
+    /// The database name
+    pub name: String,
+    /// The database version
+    pub version: u64,
+    /// Per-store statistics, in store name order
+    pub stores: Vec<StoreStats>,
+}
+
+/// Statistics about an object store
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StoreStats {
+    /// The object store name
+    pub name: String,
+    /// The number of records in the store
+    pub records: u32,
+    /// The number of indices on the store
+    pub indices: u32,
+    /// The estimated size of the store's records; only populated by
+    /// [IdbDatabase::stats_with_sizes].
+    ///
+    /// Features required: `cursors`
+    #[cfg(feature = "cursors")]
+    pub size: Option<SizeEstimate>,
+}
+
+impl DbStats {
+    /// The total number of records across all stores
+    pub fn total_records(&self) -> u64 {
+        self.stores.iter().map(|s| s.records as u64).sum()
+    }
+
+    /// The total estimated size of all stores, in bytes, if sizes were estimated
+    ///
+    /// Features required: `cursors`
+    #[cfg(feature = "cursors")]
+    pub fn total_bytes(&self) -> Option<f64> {
+        self.stores.iter().map(|s| s.size.map(|s| s.bytes)).sum()
+    }
+}
+
+impl IdbDatabase {
+    /// Collect record and index counts for every object store in a single readonly transaction
+    pub async fn stats(&self) -> Result<DbStats, DomException> {
+        self.collect_stats(None).await
+    }
+
+    /// Like [stats][IdbDatabase::stats], but also estimates each store's size by sampling at most
+    /// `max_samples` records per store. See [IdbObjectStore::estimate_size_sampled][crate::idb_object_store::IdbObjectStore::estimate_size_sampled].
+    ///
+    /// Features required: `cursors`
+    #[cfg(feature = "cursors")]
+    pub async fn stats_with_sizes(&self, max_samples: u32) -> Result<DbStats, DomException> {
+        self.collect_stats(Some(max_samples)).await
+    }
+
+    async fn collect_stats(&self, _max_samples: Option<u32>) -> Result<DbStats, DomException> {
+        let names: Vec<String> = self.object_store_names().collect();
+        let mut stats = DbStats {
+            name: self.name(),
+            version: self.version(),
+            stores: Vec::with_capacity(names.len()),
+        };
+        if names.is_empty() {
+            return Ok(stats);
+        }
+
+        let name_refs: Vec<&str> = names.iter().map(String::as_str).collect();
+        let tx = self.transaction_on_multi(&name_refs)?;
+        for name in names.iter() {
+            let store = tx.object_store(name)?;
+            stats.stores.push(StoreStats {
+                name: name.clone(),
+                records: store.count()?.await?,
+                indices: store.as_web_sys().index_names().length(),
+                #[cfg(feature = "cursors")]
+                size: match _max_samples {
+                    Some(max) => Some(store.estimate_size_sampled(max).await?),
+                    None => None,
+                },
+            });
+        }
+
+        Ok(stats)
+    }
+}
@@ -2,6 +2,9 @@ use wasm_bindgen::{prelude::*, JsCast};
 use web_sys::IdbOpenDbRequest;
 
 use crate::idb_database::IdbDatabase;
+use crate::idb_transaction::IdbTransaction;
+use crate::internal_utils::{ClosureKind, ClosureToken};
+use crate::request::{guard, PanicSlot};
 
 /// The DB version has changed
 #[derive(Debug)]
@@ -14,27 +17,42 @@ pub(crate) type IdbVersionChangeCallback =
     Closure<dyn FnMut(web_sys::IdbVersionChangeEvent) -> Result<(), JsValue> + 'static>;
 
 impl IdbVersionChangeEvent {
-    pub(crate) fn new(event: web_sys::IdbVersionChangeEvent) -> Self {
+    /// Fails if the event target isn't an open request with a database result
+    pub(crate) fn new(event: web_sys::IdbVersionChangeEvent) -> Result<Self, JsValue> {
         let req: IdbOpenDbRequest = event
             .target()
-            .expect("Failed to unwrap IdbOpenDbRequest event target")
-            .unchecked_into();
-        let base_db: web_sys::IdbDatabase = req
-            .result()
-            .expect("Failed to unwrap IdbOpenDbRequest result")
+            .ok_or_else(|| JsValue::from_str("IdbVersionChangeEvent has no target"))?
             .unchecked_into();
+        let base_db: web_sys::IdbDatabase = req.result()?.unchecked_into();
 
-        Self {
+        Ok(Self {
             event,
             db: IdbDatabase::new(base_db),
-        }
+        })
     }
 
-    pub(crate) fn wrap_callback<F>(cb: F) -> IdbVersionChangeCallback
+    /// Panics in the callback abort the event's transaction, if any, and get recorded in the
+    /// slot; see [HandlerPanicked][crate::request::HandlerPanicked]
+    pub(crate) fn wrap_callback<F, E>(
+        mut cb: F,
+        handler: &'static str,
+        panicked: PanicSlot,
+    ) -> IdbVersionChangeCallback
     where
-        F: Fn(&Self) -> Result<(), JsValue> + 'static,
+        F: FnMut(&Self) -> Result<(), E> + 'static,
+        E: Into<JsValue>,
     {
-        let b = Box::new(move |event: web_sys::IdbVersionChangeEvent| cb(&Self::new(event)));
+        let token = ClosureToken::new(ClosureKind::Database);
+        let b = Box::new(move |event: web_sys::IdbVersionChangeEvent| {
+            token.hold();
+            let tx = event
+                .target()
+                .and_then(|t| t.dyn_into::<IdbOpenDbRequest>().ok())
+                .and_then(|req| req.transaction());
+            guard(handler, tx, &panicked, || {
+                cb(&Self::new(event)?).map_err(Into::into)
+            })
+        });
         Closure::wrap(b)
     }
 
@@ -44,18 +62,31 @@ impl IdbVersionChangeEvent {
         self.event.old_version()
     }
 
-    /// New DB version
+    /// New DB version. With the `no-panic` feature, 0 is returned instead of panicking if the
+    /// event has no new version.
     #[inline]
     pub fn new_version(&self) -> f64 {
-        self.event
-            .new_version()
-            .expect("Unable to unwrap new version")
+        let version = self.event.new_version();
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "no-panic")] {
+                version.unwrap_or_default()
+            } else {
+                version.expect("Unable to unwrap new version")
+            }
+        }
     }
 
     #[inline]
     pub fn db(&self) -> &IdbDatabase {
         &self.db
     }
+
+    /// The `versionchange` transaction the upgrade is running in. Returns `None` outside of an
+    /// `upgradeneeded` event.
+    pub fn transaction(&self) -> Option<IdbTransaction<'_>> {
+        let req: IdbOpenDbRequest = self.event.target()?.unchecked_into();
+        Some(IdbTransaction::new(req.transaction()?, self.db()))
+    }
 }
 
 impl AsRef<IdbDatabase> for IdbVersionChangeEvent {
@@ -0,0 +1,409 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::internal_utils::{dom_exception, ClosureKind, ClosureToken, WithTimeout};
+use crate::request::{HandlerPanicked, IdbOpenDbRequestLike, OpenDbError, OpenDbRequest};
+#[cfg(feature = "schema")]
+use crate::schema::RecoveryPolicy;
+
+use super::{IdbDatabase, IdbVersionChangeEvent, ReadOnlyDatabase};
+
+/// Error returned by [OpenOptions::open]
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpenError {
+    /// The requested version is lower than the version of the database on disk, e.g. because a
+    /// newer build of the app has already upgraded it. Apps can migrate up or reset the database.
+    VersionDowngrade {
+        /// The version that was requested
+        requested: u32,
+        /// The version of the database on disk
+        existing: u64,
+    },
+    /// The database looked corrupted and recreating it through the
+    /// [recovery policy][OpenOptions::recover_with] failed as well
+    RecoveryFailed {
+        /// The error the database originally failed to open with
+        cause: DomException,
+        /// The error recreating it failed with
+        error: DomException,
+    },
+    /// The upgrade callback panicked
+    HandlerPanicked(HandlerPanicked),
+    /// Any other error, including a `TimeoutError` if the open timed out
+    Dom(DomException),
+}
+
+impl From<DomException> for OpenError {
+    #[inline]
+    fn from(e: DomException) -> Self {
+        Self::Dom(e)
+    }
+}
+
+impl From<OpenDbError> for OpenError {
+    fn from(e: OpenDbError) -> Self {
+        match e {
+            OpenDbError::HandlerPanicked(e) => Self::HandlerPanicked(e),
+            OpenDbError::Dom(e) => Self::Dom(e),
+        }
+    }
+}
+
+impl std::fmt::Display for OpenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::VersionDowngrade {
+                requested,
+                existing,
+            } => write!(
+                f,
+                "Requested database version {} is lower than the existing version {}",
+                requested, existing
+            ),
+            Self::RecoveryFailed { cause, error } => write!(
+                f,
+                "Failed to recreate corrupted database ({}: {}): {}: {}",
+                cause.name(),
+                cause.message(),
+                error.name(),
+                error.message()
+            ),
+            Self::HandlerPanicked(e) => std::fmt::Display::fmt(e, f),
+            Self::Dom(e) => write!(f, "{}: {}", e.name(), e.message()),
+        }
+    }
+}
+
+impl std::error::Error for OpenError {}
+
+type UpgradeCb = Rc<RefCell<dyn FnMut(&IdbVersionChangeEvent) -> Result<(), JsValue>>>;
+type BlockedCb = Rc<RefCell<dyn FnMut()>>;
+
+/// Options for opening a database through a single configurable entry point, as an alternative to
+/// [IdbDatabase::open_u32] followed by setting callbacks on the request.
+///
+/// ```rust
+/// use indexed_db_futures::prelude::*;
+/// use std::time::Duration;
+/// use wasm_bindgen::prelude::*;
+///
+/// async fn example() -> Result<IdbDatabase, OpenError> {
+///     OpenOptions::new("my_db")
+///         .version(2)
+///         .on_upgrade(|evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+///             evt.db().create_object_store("my_store")?;
+///             Ok(())
+///         })
+///         .on_blocked(|| {
+///             // Ask the user to close their other tabs
+///         })
+///         .timeout(Duration::from_secs(5))
+///         .retry_on_blocked(2)
+///         .open()
+///         .await
+/// }
+/// ```
+#[derive(Clone)]
+pub struct OpenOptions {
+    name: String,
+    version: Option<u32>,
+    on_upgrade: Option<UpgradeCb>,
+    on_blocked: Option<BlockedCb>,
+    timeout: Option<Duration>,
+    retry_on_blocked: u32,
+    session_stores: Vec<String>,
+    #[cfg(feature = "schema")]
+    recovery: Option<RecoveryPolicy>,
+}
+
+impl OpenOptions {
+    /// Open the database with the given name at its current version, without callbacks, a timeout
+    /// or retries
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            version: None,
+            on_upgrade: None,
+            on_blocked: None,
+            timeout: None,
+            retry_on_blocked: 0,
+            session_stores: Vec::new(),
+            #[cfg(feature = "schema")]
+            recovery: None,
+        }
+    }
+
+    /// Open the database at the given version
+    #[inline]
+    pub fn version(mut self, version: u32) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Set the callback for the `upgradeneeded` event, replacing any previous one. Every attempt
+    /// and every clone of the options shares the same callback, so it can keep state across
+    /// retries.
+    pub fn on_upgrade<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&IdbVersionChangeEvent) -> Result<(), JsValue> + 'static,
+    {
+        self.on_upgrade = Some(Rc::new(RefCell::new(callback)));
+        self
+    }
+
+    /// Remove the callback for the `upgradeneeded` event, if any
+    #[inline]
+    pub fn clear_on_upgrade(mut self) -> Self {
+        self.on_upgrade = None;
+        self
+    }
+
+    /// Set the callback for the `blocked` event, fired when other connections to the database stay
+    /// open while this one wants to upgrade it. Called once per attempt. Replaces any previous
+    /// callback and, like the [upgrade callback][OpenOptions::on_upgrade], is shared between
+    /// attempts and clones.
+    pub fn on_blocked<F: FnMut() + 'static>(mut self, callback: F) -> Self {
+        self.on_blocked = Some(Rc::new(RefCell::new(callback)));
+        self
+    }
+
+    /// Remove the callback for the `blocked` event, if any
+    #[inline]
+    pub fn clear_on_blocked(mut self) -> Self {
+        self.on_blocked = None;
+        self
+    }
+
+    /// Fail with a `TimeoutError` if an attempt doesn't complete within the given duration
+    #[inline]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Start a new attempt, up to the given number of times, when an attempt times out while
+    /// blocked. Has no effect without a [timeout][OpenOptions::timeout], as a blocked open
+    /// otherwise waits until it gets unblocked.
+    #[inline]
+    pub fn retry_on_blocked(mut self, retries: u32) -> Self {
+        self.retry_on_blocked = retries;
+        self
+    }
+
+    /// Mark the given stores as session stores, which get cleared on the first open of the
+    /// database per session, e.g. caches that mustn't outlive a login.
+    ///
+    /// Sessions are tracked through a marker entry in `sessionStorage`, which lives as long as the
+    /// tab does, survives reloads and gets copied into duplicated tabs; a newly opened tab starts
+    /// a new session. Where `sessionStorage` is unavailable, e.g. in workers, every open starts a
+    /// new session. Stores that don't exist get skipped.
+    pub fn session_stores(mut self, stores: &[&str]) -> Self {
+        self.session_stores = stores.iter().map(|s| String::from(*s)).collect();
+        self
+    }
+
+    /// Delete and recreate the database according to the policy if it fails to open with an
+    /// error that [points at corruption][RecoveryPolicy::is_corruption], instead of failing
+    ///
+    /// Features required: `schema`
+    #[cfg(feature = "schema")]
+    pub fn recover_with(mut self, policy: RecoveryPolicy) -> Self {
+        self.recovery = Some(policy);
+        self
+    }
+
+    /// Open the database.
+    ///
+    /// Timed out attempts get abandoned: should they still get unblocked later, their upgrade is
+    /// aborted and the connection closed. If the requested version is lower than the existing one,
+    /// the existing version gets probed and returned as [OpenError::VersionDowngrade]. With a
+    /// [recovery policy][OpenOptions::recover_with], a corrupted database gets recreated.
+    /// [Session stores][OpenOptions::session_stores] get cleared before the database is returned.
+    pub async fn open(&self) -> Result<IdbDatabase, OpenError> {
+        let db = self.connect().await?;
+        self.start_session(&db).await?;
+        Ok(db)
+    }
+
+    async fn connect(&self) -> Result<IdbDatabase, OpenError> {
+        let mut attempt = 0;
+        loop {
+            let req = self.request()?;
+            let raw = req.as_web_sys().clone();
+
+            let blocked = Rc::new(Cell::new(false));
+            let on_blocked = {
+                let blocked = blocked.clone();
+                let callback = self.on_blocked.clone();
+                let token = ClosureToken::new(ClosureKind::Database);
+                Closure::wrap(Box::new(move || {
+                    token.hold();
+                    blocked.set(true);
+                    if let Some(callback) = callback.as_ref() {
+                        (callback.borrow_mut())();
+                    }
+                }) as Box<dyn Fn()>)
+            };
+            raw.set_onblocked(Some(on_blocked.as_ref().unchecked_ref()));
+
+            let outcome = WithTimeout::new(req.into_future(), self.timeout)?.await;
+            raw.set_onblocked(None);
+
+            match outcome {
+                Some(Ok(db)) => return Ok(db),
+                Some(Err(OpenDbError::HandlerPanicked(e))) => {
+                    return Err(OpenError::HandlerPanicked(e))
+                }
+                Some(Err(OpenDbError::Dom(e))) => return self.recover(e).await,
+                None => {
+                    abandon(&raw);
+                    if !blocked.get() || attempt >= self.retry_on_blocked {
+                        return Err(dom_exception(
+                            &format!("Timed out opening database {}", self.name),
+                            "TimeoutError",
+                        )
+                        .into());
+                    }
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// [Open][OpenOptions::open] the database and wrap the connection so that only readonly
+    /// transactions can be started on it. The upgrade callback, if any, still runs.
+    pub async fn open_read_only(&self) -> Result<ReadOnlyDatabase, OpenError> {
+        Ok(self.open().await?.into_read_only())
+    }
+
+    /// Clear the session stores if this is the first open of the database in this session
+    async fn start_session(&self, db: &IdbDatabase) -> Result<(), DomException> {
+        if self.session_stores.is_empty() {
+            return Ok(());
+        }
+        let marker = format!("indexed_db_futures:session:{}", self.name);
+        let storage = web_sys::window().and_then(|w| w.session_storage().ok().flatten());
+        if let Some(storage) = storage.as_ref() {
+            if storage.get_item(&marker)?.is_some() {
+                return Ok(());
+            }
+        }
+
+        let stores = self
+            .session_stores
+            .iter()
+            .map(String::as_str)
+            .filter(|s| db.has_store(s))
+            .collect::<Vec<_>>();
+        if !stores.is_empty() {
+            let tx = db.transaction_on_multi_with_mode(&stores, IdbTransactionMode::Readwrite)?;
+            for store in stores.iter() {
+                tx.object_store(store)?.clear()?;
+            }
+            tx.await.into_result()?;
+        }
+        if let Some(storage) = storage {
+            storage.set_item(&marker, &js_sys::Date::now().to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Recreate the database if the error points at corruption and there's a recovery policy
+    async fn recover(&self, e: DomException) -> Result<IdbDatabase, OpenError> {
+        #[cfg(feature = "schema")]
+        if let Some(policy) = self.recovery.as_ref() {
+            if RecoveryPolicy::is_corruption(&e) {
+                return policy
+                    .recreate(&self.name, self.version)
+                    .await
+                    .map_err(|error| OpenError::RecoveryFailed { cause: e, error });
+            }
+        }
+        Err(self.check_downgrade(e).await)
+    }
+
+    /// Turn a `VersionError` into [OpenError::VersionDowngrade] by opening the database
+    /// without a version to find out the existing one
+    async fn check_downgrade(&self, e: DomException) -> OpenError {
+        let requested = match self.version {
+            Some(requested) if e.name() == "VersionError" => requested,
+            _ => return e.into(),
+        };
+        let existing = match IdbDatabase::open(&self.name) {
+            Ok(req) => match req.into_future().await {
+                Ok(db) => {
+                    let version = db.version();
+                    db.close();
+                    version
+                }
+                Err(_) => return e.into(),
+            },
+            Err(_) => return e.into(),
+        };
+
+        OpenError::VersionDowngrade {
+            requested,
+            existing,
+        }
+    }
+
+    fn request(&self) -> Result<OpenDbRequest, DomException> {
+        let mut req = match self.version {
+            Some(version) => IdbDatabase::open_u32(&self.name, version)?,
+            None => IdbDatabase::open(&self.name)?,
+        };
+        if let Some(callback) = self.on_upgrade.clone() {
+            req.set_on_upgrade_needed(Some(move |evt: &IdbVersionChangeEvent| {
+                (callback.borrow_mut())(evt)
+            }));
+        }
+        Ok(req)
+    }
+}
+
+impl std::fmt::Debug for OpenOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut s = f.debug_struct("OpenOptions");
+        s.field("name", &self.name)
+            .field("version", &self.version)
+            .field("on_upgrade", &self.on_upgrade.is_some())
+            .field("on_blocked", &self.on_blocked.is_some())
+            .field("timeout", &self.timeout)
+            .field("retry_on_blocked", &self.retry_on_blocked)
+            .field("session_stores", &self.session_stores);
+        #[cfg(feature = "schema")]
+        s.field("recovery", &self.recovery);
+        s.finish()
+    }
+}
+
+/// Make sure an open request we stopped waiting for doesn't upgrade the database or leave a
+/// connection open if it gets unblocked later
+fn abandon(req: &web_sys::IdbOpenDbRequest) {
+    let on_upgrade = {
+        let req = req.clone();
+        let token = ClosureToken::new(ClosureKind::Database);
+        Closure::once_into_js(move || {
+            token.hold();
+            if let Some(tx) = req.transaction() {
+                let _ = tx.abort();
+            }
+        })
+    };
+    let on_success = {
+        let req = req.clone();
+        let token = ClosureToken::new(ClosureKind::Database);
+        Closure::once_into_js(move || {
+            token.hold();
+            if let Ok(db) = req.result() {
+                db.unchecked_into::<web_sys::IdbDatabase>().close();
+            }
+        })
+    };
+    req.set_onupgradeneeded(Some(on_upgrade.unchecked_ref()));
+    req.set_onsuccess(Some(on_success.unchecked_ref()));
+}
@@ -0,0 +1,113 @@
+use wasm_bindgen::JsCast;
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::dom_string_iterator::DomStringIterator;
+use crate::idb_transaction::IdbTransaction;
+use crate::internal_utils::dom_exception;
+
+use super::IdbDatabase;
+
+/// A database connection that can only start readonly transactions, for analytics or
+/// diagnostic code that must never modify user data. Obtained from
+/// [IdbDatabase::into_read_only] or [OpenOptions::open_read_only][super::OpenOptions::open_read_only].
+///
+/// There's no way to start a readwrite transaction or to create or delete object stores through
+/// it, and the mode-taking transaction methods reject any mode other than
+/// [Readonly][IdbTransactionMode::Readonly] with a `ReadOnlyError`. Writes attempted on the
+/// stores of its transactions get rejected by the browser.
+#[derive(Debug)]
+pub struct ReadOnlyDatabase {
+    db: IdbDatabase,
+}
+
+impl IdbDatabase {
+    /// Wrap the connection so that only readonly transactions can be started on it
+    #[inline]
+    pub fn into_read_only(self) -> ReadOnlyDatabase {
+        ReadOnlyDatabase { db: self }
+    }
+}
+
+impl ReadOnlyDatabase {
+    /// List the names of the object stores within this database
+    #[inline]
+    pub fn object_store_names(&self) -> DomStringIterator {
+        self.db.object_store_names()
+    }
+
+    /// Whether the database has an object store with the given name
+    #[inline]
+    pub fn has_store(&self, name: &str) -> bool {
+        self.db.has_store(name)
+    }
+
+    /// Get the database name
+    #[inline]
+    pub fn name(&self) -> String {
+        self.db.name()
+    }
+
+    /// Get the database version
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.db.version()
+    }
+
+    /// Close the database connection
+    #[inline]
+    pub fn close(&self) {
+        self.db.close();
+    }
+
+    /// Start a readonly transaction on the given object store
+    #[inline]
+    pub fn transaction_on_one(&self, name: &str) -> Result<IdbTransaction<'_>, DomException> {
+        self.db.transaction_on_one(name)
+    }
+
+    /// Start a readonly transaction on the given object stores
+    #[inline]
+    pub fn transaction_on_multi(&self, names: &[&str]) -> Result<IdbTransaction<'_>, DomException> {
+        self.db.transaction_on_multi(names)
+    }
+
+    /// Start a readonly transaction on the given JS array of object store names
+    #[inline]
+    pub fn transaction_on_multi_with_array<V: JsCast>(
+        &self,
+        names: &V,
+    ) -> Result<IdbTransaction<'_>, DomException> {
+        self.db.transaction_on_multi_with_array(names)
+    }
+
+    /// Start a transaction on the given object store, failing unless the mode is readonly
+    pub fn transaction_on_one_with_mode(
+        &self,
+        name: &str,
+        mode: IdbTransactionMode,
+    ) -> Result<IdbTransaction<'_>, DomException> {
+        check_mode(mode)?;
+        self.db.transaction_on_one_with_mode(name, mode)
+    }
+
+    /// Start a transaction on the given object stores, failing unless the mode is readonly
+    pub fn transaction_on_multi_with_mode(
+        &self,
+        names: &[&str],
+        mode: IdbTransactionMode,
+    ) -> Result<IdbTransaction<'_>, DomException> {
+        check_mode(mode)?;
+        self.db.transaction_on_multi_with_mode(names, mode)
+    }
+}
+
+fn check_mode(mode: IdbTransactionMode) -> Result<(), DomException> {
+    if mode == IdbTransactionMode::Readonly {
+        Ok(())
+    } else {
+        Err(dom_exception(
+            "Only readonly transactions can be started on a read-only connection",
+            "ReadOnlyError",
+        ))
+    }
+}
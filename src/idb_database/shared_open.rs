@@ -0,0 +1,202 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::{Rc, Weak};
+use std::task::{Context, Poll, Waker};
+
+use wasm_bindgen::prelude::*;
+
+use crate::request::{IdbOpenDbRequestLike, OpenDbError};
+
+use super::{IdbDatabase, IdbVersionChangeEvent};
+
+type SharedResult = Result<Rc<IdbDatabase>, OpenDbError>;
+
+#[derive(Default)]
+struct Slot {
+    result: Option<SharedResult>,
+    /// One entry per waiting [SharedOpenFuture], emptied once it's dropped
+    wakers: Vec<Option<Waker>>,
+}
+
+enum Entry {
+    Pending(Rc<RefCell<Slot>>),
+    Open(Weak<IdbDatabase>),
+}
+
+thread_local! {
+    static REGISTRY: RefCell<HashMap<(String, u32), Entry>> = RefCell::new(HashMap::new());
+}
+
+impl IdbDatabase {
+    /// Open the database with the given name and version, sharing the connection with every other
+    /// `open_shared` call for the same name and version. If an open is already in progress, no
+    /// second open or upgrade is started: the call waits for the first one and resolves to the
+    /// same connection, as does any call made while that connection is still referenced.
+    ///
+    /// The upgrade handler only runs for the call that actually opens the database; handlers
+    /// passed to concurrent calls are dropped. The shared connection closes itself when another
+    /// connection requests a version change, and calls made after it's been closed open a new one.
+    pub fn open_shared<F>(
+        name: &str,
+        version: u32,
+        on_upgrade_needed: Option<F>,
+    ) -> SharedOpenFuture
+    where
+        F: FnMut(&IdbVersionChangeEvent) -> Result<(), JsValue> + 'static,
+    {
+        let key = (name.to_string(), version);
+        let slot = REGISTRY.with(move |registry| {
+            let mut registry = registry.borrow_mut();
+            match registry.get(&key) {
+                Some(Entry::Pending(slot)) => return slot.clone(),
+                Some(Entry::Open(db)) => {
+                    if let Some(db) = db.upgrade() {
+                        return Rc::new(RefCell::new(Slot {
+                            result: Some(Ok(db)),
+                            wakers: Vec::new(),
+                        }));
+                    }
+                }
+                None => {}
+            }
+
+            let slot = Rc::new(RefCell::new(Slot::default()));
+            registry.insert(key.clone(), Entry::Pending(slot.clone()));
+            wasm_bindgen_futures::spawn_local(drive_open(key, on_upgrade_needed, slot.clone()));
+            slot
+        });
+
+        SharedOpenFuture { slot, waiter: None }
+    }
+}
+
+/// Forget the connection, if it's registered, so that later calls open a new one
+pub(crate) fn evict(db: &web_sys::IdbDatabase) {
+    let _ = REGISTRY.try_with(|registry| {
+        if let Ok(mut registry) = registry.try_borrow_mut() {
+            registry.retain(|_, entry| match entry {
+                Entry::Open(open) => open.upgrade().is_some_and(|open| open.inner != *db),
+                Entry::Pending(_) => true,
+            });
+        }
+    });
+}
+
+/// Close every connection opened through [IdbDatabase::open_shared] that's still referenced and
+/// forget them, so that later calls open a new connection
+#[cfg(feature = "wipe")]
+pub(crate) fn close_shared() -> usize {
+    let open = REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        let open = registry
+            .values()
+            .filter_map(|entry| match entry {
+                Entry::Open(db) => db.upgrade(),
+                Entry::Pending(_) => None,
+            })
+            .collect::<Vec<_>>();
+        registry.retain(|_, entry| matches!(entry, Entry::Pending(_)));
+        open
+    });
+    for db in open.iter() {
+        db.close();
+    }
+    open.len()
+}
+
+/// Run the actual open and hand the result to everyone waiting on the slot
+async fn drive_open<F>(key: (String, u32), on_upgrade_needed: Option<F>, slot: Rc<RefCell<Slot>>)
+where
+    F: FnMut(&IdbVersionChangeEvent) -> Result<(), JsValue> + 'static,
+{
+    let result = match IdbDatabase::open_u32(&key.0, key.1) {
+        Ok(mut req) => {
+            req.set_on_upgrade_needed(on_upgrade_needed);
+            req.into_future().await.map(|mut db| {
+                // Nothing else can set a handler once the connection is shared
+                let inner = db.inner.clone();
+                db.set_on_version_change(Some(
+                    move |_: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+                        evict(&inner);
+                        inner.close();
+                        Ok(())
+                    },
+                ));
+                Rc::new(db)
+            })
+        }
+        Err(e) => Err(e.into()),
+    };
+
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        match result.as_ref() {
+            Ok(db) => {
+                registry.insert(key, Entry::Open(Rc::downgrade(db)));
+            }
+            Err(_) => {
+                registry.remove(&key);
+            }
+        }
+    });
+
+    let wakers = {
+        let mut slot = slot.borrow_mut();
+        slot.result = Some(result);
+        std::mem::take(&mut slot.wakers)
+    };
+    for waker in wakers.into_iter().flatten() {
+        waker.wake();
+    }
+}
+
+/// A [Future] returned by [IdbDatabase::open_shared]
+pub struct SharedOpenFuture {
+    slot: Rc<RefCell<Slot>>,
+    /// This future's index in the slot's wakers, once it's been polled
+    waiter: Option<usize>,
+}
+
+impl Future for SharedOpenFuture {
+    type Output = SharedResult;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut slot = this.slot.borrow_mut();
+        if let Some(result) = slot.result.as_ref() {
+            return Poll::Ready(result.clone());
+        }
+        match this.waiter {
+            Some(waiter) => match &mut slot.wakers[waiter] {
+                Some(waker) if waker.will_wake(ctx.waker()) => {}
+                waker => *waker = Some(ctx.waker().clone()),
+            },
+            None => {
+                this.waiter = Some(slot.wakers.len());
+                slot.wakers.push(Some(ctx.waker().clone()));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for SharedOpenFuture {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter {
+            if let Some(waker) = self.slot.borrow_mut().wakers.get_mut(waiter) {
+                *waker = None;
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for SharedOpenFuture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let done = self.slot.borrow().result.is_some();
+        f.debug_struct("SharedOpenFuture")
+            .field("done", &done)
+            .finish()
+    }
+}
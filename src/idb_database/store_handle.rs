@@ -0,0 +1,126 @@
+use std::fmt;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+
+use super::IdbDatabase;
+
+/// An owned handle to an object store that runs every operation in its own transaction, created
+/// by [IdbDatabase::store].
+///
+/// Handles don't borrow the database or a transaction, so they can be cloned, kept in structs
+/// and moved into futures without lifetime annotations. The trade-off is that operations can't
+/// be grouped atomically; use [IdbDatabase::transaction_on_one] and friends for that. Handles
+/// don't check that the store exists; operations on a missing store fail with a
+/// `NotFoundError`.
+pub struct StoreHandle {
+    db: IdbDatabase,
+    name: String,
+}
+
+impl IdbDatabase {
+    /// Get an owned [StoreHandle] for the store with the given name
+    pub fn store(&self, name: &str) -> StoreHandle {
+        StoreHandle {
+            db: IdbDatabase::new(self.inner.clone()),
+            name: name.into(),
+        }
+    }
+}
+
+impl StoreHandle {
+    /// The store's name
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The database the store belongs to
+    #[inline]
+    pub fn db(&self) -> &IdbDatabase {
+        &self.db
+    }
+
+    /// Get the value at the given key
+    pub async fn get<K: JsCast>(&self, key: &K) -> Result<Option<JsValue>, DomException> {
+        let tx = self.db.transaction_on_one(&self.name)?;
+        tx.object_store(&self.name)?.get(key)?.await
+    }
+
+    /// Get every value in the store
+    pub async fn get_all(&self) -> Result<Vec<JsValue>, DomException> {
+        let tx = self.db.transaction_on_one(&self.name)?;
+        let values = tx.object_store(&self.name)?.get_all()?.await?;
+        Ok(values.iter().collect())
+    }
+
+    /// Get every key in the store
+    pub async fn get_all_keys(&self) -> Result<Vec<JsValue>, DomException> {
+        let tx = self.db.transaction_on_one(&self.name)?;
+        let keys = tx.object_store(&self.name)?.get_all_keys()?.await?;
+        Ok(keys.iter().collect())
+    }
+
+    /// Whether there's a record at the given key
+    pub async fn contains<K: JsCast>(&self, key: &K) -> Result<bool, DomException> {
+        let tx = self.db.transaction_on_one(&self.name)?;
+        let key = tx.object_store(&self.name)?.get_key(key)?.await?;
+        Ok(key.is_some())
+    }
+
+    /// Count the records in the store
+    pub async fn count(&self) -> Result<u32, DomException> {
+        let tx = self.db.transaction_on_one(&self.name)?;
+        tx.object_store(&self.name)?.count()?.await
+    }
+
+    /// Put the value at the given key, for stores with out-of-line keys
+    pub async fn put<K: JsCast, V: JsCast>(&self, key: &K, val: &V) -> Result<(), DomException> {
+        self.write(|store| store.put_key_val(key, val).map(drop))
+            .await
+    }
+
+    /// Put the value, for stores with in-line keys or a key generator
+    pub async fn put_val<V: JsCast>(&self, val: &V) -> Result<(), DomException> {
+        self.write(|store| store.put_val(val).map(drop)).await
+    }
+
+    /// Delete the record at the given key
+    pub async fn delete<K: JsCast>(&self, key: &K) -> Result<(), DomException> {
+        self.write(|store| store.delete(key).map(drop)).await
+    }
+
+    /// Delete every record in the store
+    pub async fn clear(&self) -> Result<(), DomException> {
+        self.write(|store| store.clear().map(drop)).await
+    }
+
+    async fn write<F>(&self, op: F) -> Result<(), DomException>
+    where
+        F: FnOnce(&IdbObjectStore<'_>) -> Result<(), DomException>,
+    {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.name, IdbTransactionMode::Readwrite)?;
+        op(&tx.object_store(&self.name)?)?;
+        tx.await.into_result()
+    }
+}
+
+impl Clone for StoreHandle {
+    fn clone(&self) -> Self {
+        self.db.store(&self.name)
+    }
+}
+
+impl fmt::Debug for StoreHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StoreHandle")
+            .field("db", &self.db.name())
+            .field("name", &self.name)
+            .finish()
+    }
+}
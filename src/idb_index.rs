@@ -2,7 +2,12 @@
 //!
 //! Features required: `indices`
 
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
 use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::keys_eq;
 
 /// A wrapper around an IndexedDB index
 ///
@@ -19,6 +24,31 @@ impl<'a> IdbIndex<'a> {
         Self { inner, store }
     }
 
+    /// Count a request towards the transaction's stats
+    #[inline]
+    pub(crate) fn record_request(&self) {
+        self.store.record_request();
+    }
+
+    /// Wrap a web_sys index created outside of this crate. The index must belong to the given
+    /// object store.
+    #[inline]
+    pub fn from_raw(inner: web_sys::IdbIndex, store: &'a IdbObjectStore<'a>) -> Self {
+        Self::new(inner, store)
+    }
+
+    /// The underlying web_sys index
+    #[inline]
+    pub fn as_web_sys(&self) -> &web_sys::IdbIndex {
+        &self.inner
+    }
+
+    /// Unwrap the underlying web_sys index
+    #[inline]
+    pub fn into_web_sys(self) -> web_sys::IdbIndex {
+        self.inner
+    }
+
     /// The index's object store
     #[inline]
     pub fn object_store(&self) -> &'a IdbObjectStore<'a> {
@@ -38,6 +68,35 @@ impl<'a> IdbIndex<'a> {
     pub fn unique(&self) -> bool {
         self.inner.unique()
     }
+
+    /// Check whether writing a record with the given index key would violate this index's
+    /// uniqueness constraint, e.g. to show an "email already used" error before attempting a
+    /// write that would abort the whole transaction. Always `false` for non-unique indices.
+    pub async fn would_violate_unique<K: JsCast>(
+        &self,
+        index_key: &K,
+    ) -> Result<bool, DomException> {
+        if !self.unique() {
+            return Ok(false);
+        }
+        Ok(self.get_key(index_key)?.await?.is_some())
+    }
+
+    /// Like [would_violate_unique][IdbIndex::would_violate_unique], but ignores the record at
+    /// `primary_key` so that records can be checked before being updated in place.
+    pub async fn would_violate_unique_for<K: JsCast>(
+        &self,
+        index_key: &K,
+        primary_key: &JsValue,
+    ) -> Result<bool, DomException> {
+        if !self.unique() {
+            return Ok(false);
+        }
+        match self.get_key(index_key)?.await? {
+            Some(existing) => Ok(!keys_eq(&existing, primary_key)),
+            None => Ok(false),
+        }
+    }
 }
 
 impl_query_source!(IdbIndex<'_>);
@@ -0,0 +1,138 @@
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+/// A Rust type that can be used as an IndexedDB [key](https://www.w3.org/TR/IndexedDB/#key-construct).
+///
+/// Implemented for strings, numbers, [dates][js_sys::Date], vectors of keys and tuples of keys,
+/// the latter two producing array (compound) keys.
+pub trait IdbKey: Sized {
+    /// Convert the key into its JS representation
+    fn to_js_key(&self) -> JsValue;
+
+    /// Convert a JS key back into the Rust type. Returns `None` if the key is of a different type.
+    fn from_js_key(key: JsValue) -> Option<Self>;
+}
+
+/// Convert a JS key into `K`, failing with a `DataError` if it's of a different type
+pub(crate) fn js_key_into<K: IdbKey>(key: JsValue) -> Result<K, DomException> {
+    K::from_js_key(key).ok_or_else(key_type_mismatch)
+}
+
+/// Convert a JS array of keys into a vector of `K`
+pub(crate) fn js_keys_into<K: IdbKey>(keys: js_sys::Array) -> Result<Vec<K>, DomException> {
+    keys.iter().map(js_key_into).collect()
+}
+
+fn key_type_mismatch() -> DomException {
+    crate::internal_utils::dom_exception("Key is not of the expected type", "DataError")
+}
+
+impl IdbKey for JsValue {
+    #[inline]
+    fn to_js_key(&self) -> JsValue {
+        self.clone()
+    }
+
+    #[inline]
+    fn from_js_key(key: JsValue) -> Option<Self> {
+        Some(key)
+    }
+}
+
+impl IdbKey for String {
+    #[inline]
+    fn to_js_key(&self) -> JsValue {
+        JsValue::from_str(self)
+    }
+
+    #[inline]
+    fn from_js_key(key: JsValue) -> Option<Self> {
+        key.as_string()
+    }
+}
+
+impl IdbKey for f64 {
+    #[inline]
+    fn to_js_key(&self) -> JsValue {
+        JsValue::from_f64(*self)
+    }
+
+    #[inline]
+    fn from_js_key(key: JsValue) -> Option<Self> {
+        key.as_f64()
+    }
+}
+
+macro_rules! impl_idb_key_for_int {
+    ($($ty: ty),+) => {
+        $(
+            impl IdbKey for $ty {
+                #[inline]
+                fn to_js_key(&self) -> JsValue {
+                    JsValue::from_f64(*self as f64)
+                }
+
+                fn from_js_key(key: JsValue) -> Option<Self> {
+                    let num = key.as_f64()?;
+                    if num.fract() == 0.0 && num >= <$ty>::MIN as f64 && num <= <$ty>::MAX as f64 {
+                        Some(num as $ty)
+                    } else {
+                        None
+                    }
+                }
+            }
+        )+
+    };
+}
+
+impl_idb_key_for_int!(u8, u16, u32, i8, i16, i32);
+
+impl IdbKey for js_sys::Date {
+    #[inline]
+    fn to_js_key(&self) -> JsValue {
+        self.into()
+    }
+
+    #[inline]
+    fn from_js_key(key: JsValue) -> Option<Self> {
+        key.dyn_into().ok()
+    }
+}
+
+impl<T: IdbKey> IdbKey for Vec<T> {
+    fn to_js_key(&self) -> JsValue {
+        self.iter()
+            .map(IdbKey::to_js_key)
+            .collect::<js_sys::Array>()
+            .into()
+    }
+
+    fn from_js_key(key: JsValue) -> Option<Self> {
+        let arr: js_sys::Array = key.dyn_into().ok()?;
+        arr.iter().map(T::from_js_key).collect()
+    }
+}
+
+macro_rules! impl_idb_key_for_tuple {
+    ($len: literal => $($name: ident: $idx: tt),+) => {
+        impl<$($name: IdbKey),+> IdbKey for ($($name,)+) {
+            fn to_js_key(&self) -> JsValue {
+                let arr = js_sys::Array::new();
+                $(arr.push(&self.$idx.to_js_key());)+
+                arr.into()
+            }
+
+            fn from_js_key(key: JsValue) -> Option<Self> {
+                let arr: js_sys::Array = key.dyn_into().ok()?;
+                if arr.length() != $len {
+                    return None;
+                }
+                Some(($($name::from_js_key(arr.get($idx))?,)+))
+            }
+        }
+    };
+}
+
+impl_idb_key_for_tuple!(2 => A: 0, B: 1);
+impl_idb_key_for_tuple!(3 => A: 0, B: 1, C: 2);
+impl_idb_key_for_tuple!(4 => A: 0, B: 1, C: 2, D: 3);
@@ -34,6 +34,39 @@ impl IdbKeyPath {
         &self.0
     }
 
+    /// Evaluate the key path against the value, returning `None` if any part of it is missing
+    #[cfg(feature = "indices")]
+    pub(crate) fn evaluate(&self, value: &JsValue) -> Option<JsValue> {
+        fn evaluate_one(value: &JsValue, path: &str) -> Option<JsValue> {
+            if path.is_empty() {
+                return Some(value.clone());
+            }
+            let mut out = value.clone();
+            for segment in path.split('.') {
+                if !out.is_object() {
+                    return None;
+                }
+                out = js_sys::Reflect::get(&out, &JsValue::from_str(segment)).ok()?;
+            }
+            if out.is_undefined() {
+                None
+            } else {
+                Some(out)
+            }
+        }
+
+        if let Some(path) = self.as_js_value().as_string() {
+            evaluate_one(value, &path)
+        } else {
+            let paths: &js_sys::Array = self.as_js_value().unchecked_ref();
+            let out = js_sys::Array::new();
+            for path in paths.iter() {
+                out.push(&evaluate_one(value, &path.as_string()?)?);
+            }
+            Some(out.into())
+        }
+    }
+
     pub(crate) fn try_from_js(v: Result<JsValue, JsValue>) -> Option<Self> {
         let v = v.ok()?;
         if v.is_null() {
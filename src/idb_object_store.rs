@@ -1,21 +1,77 @@
 //! Object store-related code
 
+use std::ops::Range;
+
 use wasm_bindgen::{prelude::*, JsCast};
 use web_sys::DomException;
 
+pub use access_stats::*;
+pub use audit_log::*;
+#[cfg(feature = "indices")]
+pub use blind_index::*;
+pub use bloom_filter::*;
+#[cfg(feature = "serde")]
+pub use codec::*;
+pub use encryption::*;
 pub use idb_object_store_parameters::*;
+#[cfg(all(feature = "indices", feature = "cursors"))]
+pub use index_backfill::*;
+#[cfg(feature = "serde_json")]
+pub use json_patch::*;
+pub use merge::*;
+pub use middleware::*;
+pub use ordered::*;
+pub use projection::*;
+#[cfg(feature = "serde")]
+pub use serde_store::*;
+#[cfg(feature = "cursors")]
+pub use size_estimate::*;
+#[cfg(feature = "cursors")]
+pub use soft_delete::*;
+pub use typed_object_store::*;
+#[cfg(feature = "indices")]
+pub use unique_check::*;
 #[cfg(feature = "indices")]
 use {
-    crate::{idb_index::IdbIndex, idb_key_path::IdbKeyPath},
+    crate::{idb_index::IdbIndex, idb_key_path::IdbKeyPath, idb_query_source::IdbQuerySource},
     web_sys::IdbIndexParameters,
 };
 
 use crate::dom_string_iterator::DomStringIterator;
 use crate::idb_database::IdbDatabase;
+use crate::idb_key::IdbKey;
 use crate::idb_transaction::IdbTransaction;
-use crate::request::VoidRequest;
+use crate::internal_utils::{dom_exception, require};
+use crate::request::{JsCastRequestFuture, VoidRequest};
 
+mod access_stats;
+mod audit_log;
+#[cfg(feature = "indices")]
+mod blind_index;
+#[cfg(feature = "blob-streams")]
+mod blob_stream;
+mod bloom_filter;
+#[cfg(feature = "serde")]
+mod codec;
+mod encryption;
 mod idb_object_store_parameters;
+#[cfg(all(feature = "indices", feature = "cursors"))]
+mod index_backfill;
+#[cfg(feature = "serde_json")]
+mod json_patch;
+mod merge;
+mod middleware;
+mod ordered;
+mod projection;
+#[cfg(feature = "serde")]
+mod serde_store;
+#[cfg(feature = "cursors")]
+mod size_estimate;
+#[cfg(feature = "cursors")]
+mod soft_delete;
+mod typed_object_store;
+#[cfg(feature = "indices")]
+mod unique_check;
 
 #[derive(Debug)]
 pub struct IdbObjectStore<'a> {
@@ -26,37 +82,42 @@ pub struct IdbObjectStore<'a> {
 
 impl IdbObjectStore<'_> {
     /// Clear all the documents in the object store
+    #[cfg_attr(feature = "tx-diagnostics", track_caller)]
     pub fn clear(&self) -> Result<VoidRequest, DomException> {
-        Ok(VoidRequest::new(self.inner.clear()?))
+        Ok(VoidRequest::new(self.write(|s| s.clear())?))
     }
 
     /// Clone and store the value on the object store. Throws if the computed key already exists.
+    #[cfg_attr(feature = "tx-diagnostics", track_caller)]
     pub fn add_val<V: JsCast>(&self, val: &V) -> Result<VoidRequest, DomException> {
-        Ok(VoidRequest::new(self.inner.add(val.unchecked_ref())?))
+        Ok(VoidRequest::new(
+            self.write(|s| s.add(val.unchecked_ref()))?,
+        ))
     }
 
     /// Clone and store the value on the object store. Throws if the computed key already exists.
     #[inline]
+    #[cfg_attr(feature = "tx-diagnostics", track_caller)]
     pub fn add_val_owned<V: Into<JsValue>>(&self, val: V) -> Result<VoidRequest, DomException> {
         self.add_val(&val.into())
     }
 
     /// Clone and store the value in the object store at the given key. Throws if the key already
     /// exists.
+    #[cfg_attr(feature = "tx-diagnostics", track_caller)]
     pub fn add_key_val<K, V>(&self, key: &K, val: &V) -> Result<VoidRequest, DomException>
     where
         K: JsCast,
         V: JsCast,
     {
-        let base = self
-            .inner
-            .add_with_key(val.unchecked_ref(), key.unchecked_ref())?;
+        let base = self.write(|s| s.add_with_key(val.unchecked_ref(), key.unchecked_ref()))?;
         Ok(VoidRequest::new(base))
     }
 
     /// Clone and store the value in the object store at the given key. Throws if the key already
     /// exists.
     #[inline]
+    #[cfg_attr(feature = "tx-diagnostics", track_caller)]
     pub fn add_key_val_owned<K, V>(&self, key: K, val: &V) -> Result<VoidRequest, DomException>
     where
         K: Into<JsValue>,
@@ -66,32 +127,36 @@ impl IdbObjectStore<'_> {
     }
 
     /// Clone and store the value in the object store, overwriting any existing value.
+    #[cfg_attr(feature = "tx-diagnostics", track_caller)]
     pub fn put_val<V: JsCast>(&self, val: &V) -> Result<VoidRequest, DomException> {
-        Ok(VoidRequest::new(self.inner.put(val.unchecked_ref())?))
+        Ok(VoidRequest::new(
+            self.write(|s| s.put(val.unchecked_ref()))?,
+        ))
     }
 
     /// Clone and store the value in the object store, overwriting any existing value.
     #[inline]
+    #[cfg_attr(feature = "tx-diagnostics", track_caller)]
     pub fn put_val_owned<V: Into<JsValue>>(&self, val: V) -> Result<VoidRequest, DomException> {
         self.put_val(&val.into())
     }
 
     /// Clone and store the value in the object store at the given key, overwriting any existing
     /// value.
+    #[cfg_attr(feature = "tx-diagnostics", track_caller)]
     pub fn put_key_val<K, V>(&self, key: &K, val: &V) -> Result<VoidRequest, DomException>
     where
         K: JsCast,
         V: JsCast,
     {
-        let base = self
-            .inner
-            .put_with_key(val.unchecked_ref(), key.unchecked_ref())?;
+        let base = self.write(|s| s.put_with_key(val.unchecked_ref(), key.unchecked_ref()))?;
         Ok(VoidRequest::new(base))
     }
 
     /// Clone and store the value in the object store at the given key, overwriting any existing
     /// value.
     #[inline]
+    #[cfg_attr(feature = "tx-diagnostics", track_caller)]
     pub fn put_key_val_owned<K, V>(&self, key: K, val: &V) -> Result<VoidRequest, DomException>
     where
         K: Into<JsValue>,
@@ -106,6 +171,39 @@ impl IdbObjectStore<'_> {
         self.inner.auto_increment()
     }
 
+    /// Reserve a contiguous block of `count` keys from the store's key generator, e.g. for
+    /// assigning IDs to a batch of related records up front. This is done by adding and then
+    /// deleting `count` placeholder records, so it requires an auto-incrementing store and a
+    /// [readwrite][web_sys::IdbTransactionMode::Readwrite] transaction. The reserved keys will
+    /// never be handed out by the key generator again, but can be written to explicitly.
+    pub async fn reserve_keys(&self, count: u32) -> Result<Range<u64>, DomException> {
+        if count == 0 {
+            return Ok(0..0);
+        }
+
+        let first = self.add_placeholder()?;
+        let mut last = None;
+        for _ in 1..count {
+            last = Some(self.add_placeholder()?);
+        }
+
+        let first = key_as_u64(&first.await?)?;
+        let last = match last {
+            Some(last) => key_as_u64(&last.await?)?,
+            None => first,
+        };
+
+        let range =
+            web_sys::IdbKeyRange::bound(&JsValue::from(first as f64), &JsValue::from(last as f64))?;
+        self.delete(&range)?.into_future().await?;
+
+        Ok(first..(last + 1))
+    }
+
+    fn add_placeholder(&self) -> Result<JsCastRequestFuture<JsValue>, DomException> {
+        JsCastRequestFuture::new(Ok(self.write(|s| s.add(&js_sys::Object::new()))?))
+    }
+
     // Indices
     cfg_if::cfg_if! {
         if #[cfg(feature = "indices")] {
@@ -114,7 +212,7 @@ impl IdbObjectStore<'_> {
             ///
             /// Features required: `indices`
             #[inline]
-            pub fn index_names(&self) -> impl Iterator<Item = String> {
+            pub fn index_names(&self) -> DomStringIterator {
                 DomStringIterator::from(self.inner.index_names())
             }
 
@@ -145,6 +243,37 @@ impl IdbObjectStore<'_> {
                 self.create_idx_common(base)
             }
 
+            /// Look up the record whose `index_name` index key is `index_value` and put the value
+            /// over it, or add the value as a new record if there's no match. Useful for records
+            /// with a natural unique key stored in a field. For stores with in-line keys, the
+            /// matched record's primary key gets written into the value at the store's key path.
+            /// Resolves to the record's primary key.
+            ///
+            /// Requires a [readwrite][web_sys::IdbTransactionMode::Readwrite] transaction;
+            /// the lookup and the write happen in it back to back.
+            ///
+            /// Features required: `indices`
+            pub async fn upsert_by_index<K: JsCast, V: JsCast>(
+                &self,
+                index_name: &str,
+                index_value: &K,
+                value: &V,
+            ) -> Result<JsValue, DomException> {
+                let existing = self.index(index_name)?.get_key(index_value)?.await?;
+                let value: &JsValue = value.unchecked_ref();
+
+                let req = match (existing, self.key_path()) {
+                    (Some(key), Some(key_path)) => {
+                        set_at_key_path(value, key_path.as_js_value(), &key)?;
+                        self.write(|s| s.put(value))?
+                    }
+                    (Some(key), None) => self.write(|s| s.put_with_key(value, &key))?,
+                    (None, _) => self.write(|s| s.add(value))?,
+                };
+
+                JsCastRequestFuture::<JsValue>::new(Ok(req))?.await
+            }
+
             fn create_idx_common(
                 &self,
                 src: Result<web_sys::IdbIndex, JsValue>,
@@ -174,6 +303,25 @@ impl<'a> IdbObjectStore<'a> {
         }
     }
 
+    /// Wrap a web_sys object store created outside of this crate. The store must belong to the
+    /// given transaction.
+    #[inline]
+    pub fn from_raw(inner: web_sys::IdbObjectStore, tx: &'a IdbTransaction) -> Self {
+        Self::from_tx(inner, tx)
+    }
+
+    /// The underlying web_sys object store
+    #[inline]
+    pub fn as_web_sys(&self) -> &web_sys::IdbObjectStore {
+        &self.inner
+    }
+
+    /// Unwrap the underlying web_sys object store
+    #[inline]
+    pub fn into_web_sys(self) -> web_sys::IdbObjectStore {
+        self.inner
+    }
+
     /// The DB that spawned this store
     #[inline]
     pub fn db(&self) -> &'a IdbDatabase {
@@ -186,13 +334,99 @@ impl<'a> IdbObjectStore<'a> {
         &self.tx
     }
 
+    /// Whether the store's transaction has [finished][IdbTransaction::is_finished], e.g. because
+    /// it auto-committed while a non-IndexedDB future was being awaited. Writes on a frozen store
+    /// fail with a `TransactionInactiveError` naming the store. Always `false` for stores obtained
+    /// outside of a transaction wrapper, e.g. during an upgrade.
+    #[inline]
+    pub fn is_frozen(&self) -> bool {
+        self.tx.map(IdbTransaction::is_finished).unwrap_or(false)
+    }
+
+    /// Make a write request, turning a `TransactionInactiveError` into one that names the store
+    #[cfg_attr(feature = "tx-diagnostics", track_caller)]
+    fn write<F>(&self, op: F) -> Result<web_sys::IdbRequest, DomException>
+    where
+        F: FnOnce(&web_sys::IdbObjectStore) -> Result<web_sys::IdbRequest, JsValue>,
+    {
+        #[cfg(feature = "tx-diagnostics")]
+        let location = std::panic::Location::caller();
+
+        if self.is_frozen() {
+            return Err(self.inactive_error(
+                "has already finished",
+                #[cfg(feature = "tx-diagnostics")]
+                location,
+            ));
+        }
+        match op(&self.inner) {
+            Ok(req) => {
+                if let Some(tx) = self.tx {
+                    tx.record_request(&self.inner);
+                    #[cfg(feature = "tx-diagnostics")]
+                    tx.diagnostics().record_request(location);
+                }
+                Ok(req)
+            }
+            Err(e) => {
+                let e = DomException::from(e);
+                if e.name() == "TransactionInactiveError" {
+                    Err(self.inactive_error(
+                        "is no longer active",
+                        #[cfg(feature = "tx-diagnostics")]
+                        location,
+                    ))
+                } else {
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    /// Count a read request towards the transaction's stats
+    #[inline]
+    pub(crate) fn record_request(&self) {
+        if let Some(tx) = self.tx {
+            tx.record_request(&self.inner);
+        }
+    }
+
+    fn inactive_error(
+        &self,
+        state: &str,
+        #[cfg(feature = "tx-diagnostics")] location: &'static std::panic::Location<'static>,
+    ) -> DomException {
+        #[allow(unused_mut)]
+        let mut message = format!(
+            "The transaction of object store {} {}",
+            self.inner.name(),
+            state
+        );
+        #[cfg(feature = "tx-diagnostics")]
+        if let Some(report) = self.tx.and_then(|tx| tx.diagnostics().report(location)) {
+            message.push_str(". ");
+            message.push_str(&report);
+        }
+        dom_exception(&message, "TransactionInactiveError")
+    }
+
+    /// Restrict the store's keys to the given type. See [TypedObjectStore].
+    #[inline]
+    pub fn typed<K: IdbKey>(self) -> TypedObjectStore<'a, K> {
+        TypedObjectStore::new(self)
+    }
+
     /// Delete the record at the with the given key
+    #[cfg_attr(feature = "tx-diagnostics", track_caller)]
     pub fn delete<K: JsCast>(&self, key: &K) -> Result<VoidRequest, DomException> {
-        Ok(VoidRequest::new(self.inner.delete(key.unchecked_ref())?))
+        Ok(VoidRequest::new(
+            self.write(|s| s.delete(key.unchecked_ref()))?,
+        ))
     }
 
     /// Delete the record at the with the given key
     #[inline]
+    #[cfg_attr(feature = "tx-diagnostics", track_caller)]
     pub fn delete_owned<K: Into<JsValue>>(&self, key: K) -> Result<VoidRequest, DomException> {
         self.delete(&key.into())
     }
@@ -200,10 +434,49 @@ impl<'a> IdbObjectStore<'a> {
 
 impl_query_source!(IdbObjectStore<'_>);
 
+/// Convert a key produced by a key generator into a u64
+#[inline]
+fn key_as_u64(key: &JsValue) -> Result<u64, DomException> {
+    Ok(require(key.as_f64())? as u64)
+}
+
+/// Write the primary key into the value at the given string or string sequence key path
+#[cfg(feature = "indices")]
+fn set_at_key_path(value: &JsValue, key_path: &JsValue, key: &JsValue) -> Result<(), DomException> {
+    fn set_one(value: &JsValue, path: &str, key: &JsValue) -> Result<(), JsValue> {
+        let mut segments: Vec<&str> = path.split('.').collect();
+        let last = segments.pop().unwrap_or_default();
+        let mut target = value.clone();
+        for segment in segments {
+            let segment = JsValue::from_str(segment);
+            let mut next = js_sys::Reflect::get(&target, &segment)?;
+            if next.is_undefined() || next.is_null() {
+                next = js_sys::Object::new().into();
+                js_sys::Reflect::set(&target, &segment, &next)?;
+            }
+            target = next;
+        }
+        js_sys::Reflect::set(&target, &JsValue::from_str(last), key)?;
+        Ok(())
+    }
+
+    if let Some(path) = key_path.as_string() {
+        set_one(value, &path, key)?;
+    } else {
+        let paths: &js_sys::Array = key_path.unchecked_ref();
+        let parts: &js_sys::Array = key.unchecked_ref();
+        for (path, part) in paths.iter().zip(parts.iter()) {
+            set_one(value, &require(path.as_string())?, &part)?;
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 pub mod test {
     use crate::idb_query_source::IdbQuerySource;
-    use crate::internal_utils::open_any_db;
+    use crate::request::GetResult;
+    use crate::test_utils::{open_any_db, open_db_with};
     use web_sys::IdbTransactionMode as TxMode;
     test_mod_init!();
 
@@ -235,6 +508,25 @@ pub mod test {
         assert_eq!(bar, None);
     });
 
+    test_case!(async get_entry => {
+        let (db, store_name) = open_any_db().await;
+
+        let tx = db.transaction_on_one_with_mode(&store_name, TxMode::Readwrite).expect("tx open");
+        let store = tx.object_store(&store_name).expect("store open");
+        store.put_key_val_owned("undefined", &JsValue::UNDEFINED).expect("put undefined");
+        store.put_key_val_owned("value", &JsValue::from(1u8)).expect("put value");
+
+        let undefined = store.get_entry_owned("undefined").expect("get undefined");
+        let value = store.get_entry_owned("value").expect("get value");
+        let missing = store.get_entry_owned("missing").expect("get missing");
+        let conflated = store.get_owned("undefined").expect("get").await.expect("get await");
+
+        assert_eq!(undefined.await.expect("undefined await"), GetResult::Present(JsValue::UNDEFINED), "undefined");
+        assert_eq!(value.await.expect("value await").into_option(), Some(JsValue::from(1u8)), "value");
+        assert!(missing.await.expect("missing await").is_missing(), "missing");
+        assert_eq!(conflated, None, "get conflates undefined with missing");
+    });
+
     test_case!(async clear => {
         let (db, store_name) = open_any_db().await;
 
@@ -255,6 +547,183 @@ pub mod test {
         assert_eq!(all.length(), 0, "length");
     });
 
+    test_case!(async per_request_error => {
+        let (db, store_name) = open_any_db().await;
+
+        let tx = db.transaction_on_one_with_mode(&store_name, TxMode::Readwrite).expect("tx open");
+        let store = tx.object_store(&store_name).expect("store open");
+
+        store.add_key_val_owned("foo", &JsValue::from(1u8)).expect("add 1");
+        let req = store.add_key_val_owned("foo", &JsValue::from(2u8)).expect("add 2");
+        let raw = req.as_web_sys().clone();
+        let err = req.into_future().await.expect_err("add 2 await");
+
+        assert_eq!(err.name(), "ConstraintError");
+        let req_err = raw.error().expect("error()").expect("request error");
+        assert_eq!(JsValue::from(err), JsValue::from(req_err), "the request's own error");
+    });
+
+    test_case!(async frozen_after_commit => {
+        let (db, store_name) = open_any_db().await;
+
+        let tx = db.transaction_on_one_with_mode(&store_name, TxMode::Readwrite).expect("tx open");
+        let store = tx.object_store(&store_name).expect("store open");
+        store.put_key_val_owned("foo", &JsValue::from(1u8)).expect("put 1").into_future().await.expect("put 1 await");
+        assert!(!store.is_frozen(), "active");
+
+        // Awaiting something other than a request lets the transaction auto-commit
+        let timer = js_sys::Promise::new(&mut |resolve, _| {
+            web_sys::window()
+                .unwrap()
+                .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, 20)
+                .unwrap();
+        });
+        wasm_bindgen_futures::JsFuture::from(timer).await.expect("timer");
+
+        assert!(tx.is_finished(), "tx finished");
+        assert!(store.is_frozen(), "frozen");
+        let err = store.put_key_val_owned("foo", &JsValue::from(2u8)).expect_err("put 2");
+        assert_eq!(err.name(), "TransactionInactiveError", "error name");
+        assert!(err.message().contains(&store_name), "error message");
+
+        #[cfg(feature = "indices")]
+        {
+            let err = store
+                .put_key_val_checked(&JsValue::from("foo"), &JsValue::from(3u8))
+                .await
+                .expect_err("checked put");
+            match err {
+                CheckedPutError::Dom(e) => assert!(e.message().contains(&store_name), "checked put error"),
+                e => panic!("unexpected error: {}", e),
+            }
+        }
+    });
+
+    #[cfg(feature = "tx-diagnostics")]
+    test_case!(async inactivity_diagnostics => {
+        let (db, store_name) = open_any_db().await;
+
+        let tx = db.transaction_on_one_with_mode(&store_name, TxMode::Readwrite).expect("tx open");
+        let store = tx.object_store(&store_name).expect("store open");
+        store.put_key_val_owned("foo", &JsValue::from(1u8)).expect("put 1").into_future().await.expect("put 1 await");
+
+        let timer = js_sys::Promise::new(&mut |resolve, _| {
+            web_sys::window()
+                .unwrap()
+                .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, 20)
+                .unwrap();
+        });
+        wasm_bindgen_futures::JsFuture::from(timer).await.expect("timer");
+
+        let err = store.put_key_val_owned("foo", &JsValue::from(2u8)).expect_err("put 2");
+        assert!(err.message().contains("1 request(s)"), "request count");
+        assert_eq!(err.message().matches(file!()).count(), 2, "locations");
+    });
+
+    test_case!(async apply_ordered => {
+        let (db, store_name) = open_any_db().await;
+        let tx = db.transaction_on_one_with_mode(&store_name, TxMode::Readwrite).expect("tx open");
+        let store = tx.object_store(&store_name).expect("store open");
+
+        let results = store
+            .apply_ordered(&[
+                OrderedOp::Put { key: Some("parent".into()), value: JsValue::from(1u8) },
+                OrderedOp::Get("parent".into()),
+                OrderedOp::Put { key: Some("parent".into()), value: JsValue::from(2u8) },
+                OrderedOp::Get("parent".into()),
+                OrderedOp::Delete("parent".into()),
+                OrderedOp::Get("parent".into()),
+                OrderedOp::Add { key: Some("child".into()), value: JsValue::from(3u8) },
+            ])
+            .await
+            .expect("apply")
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("results");
+
+        assert_eq!(results, vec![
+            JsValue::from("parent"),
+            JsValue::from(1u8),
+            JsValue::from("parent"),
+            JsValue::from(2u8),
+            JsValue::UNDEFINED,
+            JsValue::UNDEFINED,
+            JsValue::from("child"),
+        ]);
+    });
+
+    test_case!(async apply_ordered_errors => {
+        let (db, store_name) = open_any_db().await;
+        let tx = db.transaction_on_one_with_mode(&store_name, TxMode::Readwrite).expect("tx open");
+        tx.set_ignore_request_errors(true);
+        let store = tx.object_store(&store_name).expect("store open");
+
+        let results = store
+            .apply_ordered(&[
+                OrderedOp::Add { key: Some("a".into()), value: JsValue::from(1u8) },
+                OrderedOp::Add { key: Some("a".into()), value: JsValue::from(2u8) },
+                OrderedOp::Get("a".into()),
+            ])
+            .await
+            .expect("apply");
+
+        assert!(results[0].is_ok(), "first add");
+        assert_eq!(results[1].as_ref().expect_err("second add").name(), "ConstraintError");
+        assert_eq!(results[2].as_ref().ok(), Some(&JsValue::from(1u8)), "get");
+    });
+
+    test_case!(async merge => {
+        let (db, store_name) = open_any_db().await;
+        let tx = db.transaction_on_one_with_mode(&store_name, TxMode::Readwrite).expect("tx");
+        let store = tx.object_store(&store_name).expect("store");
+
+        let record = js_sys::JSON::parse(r#"{"a":1,"nested":{"b":2,"c":3},"list":[1,2]}"#).unwrap();
+        store.put_key_val_owned("k", &record).expect("put");
+
+        let partial = js_sys::JSON::parse(r#"{"nested":{"b":20,"c":null},"list":[3],"d":4}"#).unwrap();
+        let deep = store.merge_with(&JsValue::from("k"), &partial, MergeMode::Deep).await.expect("deep");
+        assert_eq!(
+            js_sys::JSON::stringify(&deep).unwrap(),
+            r#"{"a":1,"nested":{"b":20},"list":[3],"d":4}"#,
+            "deep"
+        );
+
+        let partial = js_sys::JSON::parse(r#"{"nested":{"x":1}}"#).unwrap();
+        store.merge(&JsValue::from("k"), &partial).await.expect("shallow");
+        let stored = store.get_owned("k").unwrap().await.unwrap().unwrap();
+        assert_eq!(
+            js_sys::JSON::stringify(&stored).unwrap(),
+            r#"{"a":1,"nested":{"x":1},"list":[3],"d":4}"#,
+            "shallow"
+        );
+
+        let err = store.merge(&JsValue::from("missing"), &partial).await.expect_err("missing");
+        assert_eq!(err.name(), "NotFoundError");
+    });
+
+    test_case!(async projection => {
+        let (db, store_name) = open_any_db().await;
+        let tx = db.transaction_on_one_with_mode(&store_name, TxMode::Readwrite).expect("tx");
+        let store = tx.object_store(&store_name).expect("store");
+        let record = js_sys::JSON::parse(r#"{"a":1,"b":{"c":2,"d":3},"e":4}"#).unwrap();
+        store.put_key_val_owned("k", &record).expect("put");
+        store.put_key_val_owned("l", &JsValue::from(5u8)).expect("put");
+
+        let fields = store.get_fields(&JsValue::from("k"), &["a", "b.c", "missing.x"])
+            .expect("get_fields")
+            .await
+            .expect("get_fields await")
+            .expect("record");
+        assert_eq!(js_sys::JSON::stringify(&fields).unwrap(), r#"{"a":1,"b":{"c":2}}"#);
+
+        let all = store.get_all_projected(&Projection::new(&["e"]))
+            .expect("get_all_projected")
+            .await
+            .expect("get_all_projected await");
+        let all: Vec<String> = all.iter().map(|v| js_sys::JSON::stringify(v).unwrap().into()).collect();
+        assert_eq!(all, vec![r#"{"e":4}"#.to_string(), "{}".to_string()]);
+    });
+
     test_case!(async db_and_transaction => {
         let (db, store_name) = open_any_db().await;
         let tx = db.transaction_on_one(&store_name).expect("tx");
@@ -264,32 +733,708 @@ pub mod test {
         assert_eq!(store.db().name(), db.name(), "db");
     });
 
+    test_case!(async reserve_keys => {
+        let db = open_db_with(move |evt: &crate::IdbVersionChangeEvent| {
+            evt.db().create_object_store_with_params(
+                "s1",
+                IdbObjectStoreParameters::new().auto_increment(true),
+            )?;
+            Ok(())
+        }).await;
+
+        let tx = db.transaction_on_one_with_mode("s1", TxMode::Readwrite).expect("tx");
+        let store = tx.object_store("s1").expect("store");
+        let reserved = store.reserve_keys(5).await.expect("reserve_keys");
+        let empty = store.reserve_keys(0).await.expect("reserve_keys 0");
+        store.add_val_owned("foo").expect("add");
+        tx.await.into_result().expect("tx await");
+
+        let tx = db.transaction_on_one("s1").expect("tx2");
+        let store = tx.object_store("s1").expect("store2");
+        let keys = store.get_all_keys().expect("get_all_keys").await.expect("keys await");
+
+        assert_eq!(reserved, 1..6, "reserved");
+        assert!(empty.is_empty(), "empty");
+        assert_eq!(keys.length(), 1, "keys length");
+        assert_eq!(keys.get(0), JsValue::from(6), "generated key");
+    });
+
+    #[cfg(feature = "cursors")]
+    test_case!(async estimate_size => {
+        let (db, store_name) = open_any_db().await;
+        let tx = db.transaction_on_one_with_mode(&store_name, TxMode::Readwrite).expect("tx");
+        let store = tx.object_store(&store_name).expect("store");
+        for i in 0..10u8 {
+            // 2 bytes per key + 8 bytes per value
+            store.put_key_val_owned(format!("{}", i), &JsValue::from(i)).expect("put");
+        }
+
+        let full = store.estimate_size().await.expect("full");
+        let sampled = store.estimate_size_sampled(3).await.expect("sampled");
+        let empty = store.estimate_size_sampled(0).await.expect("empty");
+
+        assert_eq!(full, SizeEstimate { records: 10, sampled: 10, bytes: 100.0 }, "full");
+        assert_eq!(sampled.sampled, 3, "sampled");
+        assert_eq!(sampled.bytes, 100.0, "sampled bytes");
+        assert_eq!(empty.sampled, 0, "empty");
+    });
+
+    #[cfg(feature = "cursors")]
+    test_case!(async size_counter => {
+        let db = crate::test_utils::open_db_with_stores(&["data", "meta"]).await;
+        let counter = SizeCounter::new("meta");
+
+        let tx = db.transaction_on_multi_with_mode(&["data", "meta"], TxMode::Readwrite).expect("tx");
+        let store = tx.object_store("data").expect("store");
+        counter.put_key_val(&store, &JsValue::from("a"), &JsValue::from("xx")).await.expect("put 1");
+        counter.put_key_val(&store, &JsValue::from("a"), &JsValue::from("xxxx")).await.expect("put 2");
+        counter.put_key_val(&store, &JsValue::from("b"), &JsValue::from(1)).await.expect("put 3");
+        counter.delete(&store, &JsValue::from("b")).await.expect("delete");
+        let total = counter.get(&tx, "data").await.expect("get");
+        let recalculated = counter.recalculate(&store).await.expect("recalculate");
+
+        assert_eq!(total, 10.0, "total");
+        assert_eq!(recalculated, 10.0, "recalculated");
+    });
+
+    #[cfg(feature = "serde_json")]
+    pub mod json_patch {
+        use serde_json::json;
+        use web_sys::IdbTransactionMode as TxMode;
+
+        use crate::test_utils::open_any_db;
+        test_mod_init!();
+
+        fn ops(patch: serde_json::Value) -> Vec<PatchOp> {
+            serde_json::from_value(patch).expect("parse patch")
+        }
+
+        test_case!(apply_ops => {
+            let mut doc = json!({"a": {"b": [1, 2]}, "c": "x", "d~/e": 1});
+            let patch = ops(json!([
+                {"op": "add", "path": "/a/b/1", "value": 5},
+                {"op": "add", "path": "/a/b/-", "value": 6},
+                {"op": "remove", "path": "/c"},
+                {"op": "replace", "path": "/d~0~1e", "value": 2},
+                {"op": "copy", "from": "/a/b", "path": "/copy"},
+                {"op": "move", "from": "/copy", "path": "/moved"},
+                {"op": "test", "path": "/moved/0", "value": 1}
+            ]));
+            apply_json_patch(&mut doc, &patch).expect("apply");
+            assert_eq!(doc, json!({"a": {"b": [1, 5, 2, 6]}, "d~/e": 2, "moved": [1, 5, 2, 6]}));
+        });
+
+        test_case!(failed_ops_leave_doc_unchanged => {
+            let mut doc = json!({"a": 1});
+            let patch = ops(json!([
+                {"op": "replace", "path": "/a", "value": 2},
+                {"op": "test", "path": "/a", "value": 1}
+            ]));
+            match apply_json_patch(&mut doc, &patch) {
+                Err(PatchError::Failed { index, .. }) => assert_eq!(index, 1, "index"),
+                other => panic!("Unexpected result: {:?}", other),
+            }
+            assert_eq!(doc, json!({"a": 1}));
+
+            let patch = ops(json!([{"op": "remove", "path": "/missing"}]));
+            assert!(apply_json_patch(&mut doc, &patch).is_err(), "missing path");
+        });
+
+        test_case!(async apply_patch => {
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, TxMode::Readwrite).expect("tx");
+            let store = tx.object_store(&store_name).expect("store");
+            let record = crate::values::from_json(&json!({"n": 1, "tags": ["a"]})).unwrap();
+            store.put_key_val_owned("k", &record).expect("put");
+
+            let patch = ops(json!([
+                {"op": "replace", "path": "/n", "value": 2},
+                {"op": "add", "path": "/tags/-", "value": "b"}
+            ]));
+            store.apply_patch(&JsValue::from("k"), &patch).await.expect("apply_patch");
+
+            let stored = store.get_owned("k").unwrap().await.unwrap().unwrap();
+            assert_eq!(crate::values::to_json(&stored).unwrap(), json!({"n": 2, "tags": ["a", "b"]}));
+        });
+    }
+
+    pub mod access_stats {
+        use crate::prelude::*;
+        use crate::test_utils::open_any_db;
+        use std::time::Duration;
+        test_mod_init!();
+
+        test_case!(async access_stats => {
+            let (db, store_name) = open_any_db().await;
+            let stats = AccessStats::new(&store_name, &store_name);
+
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).unwrap();
+            let store = tx.object_store(&store_name).unwrap();
+            for key in &["a", "b", "c"] {
+                store.put_key_val_owned(*key, &JsValue::from(*key)).unwrap();
+            }
+            tx.await.into_result().unwrap();
+
+            let tx = db.transaction_on_one(&store_name).unwrap();
+            let store = tx.object_store(&store_name).unwrap().typed::<String>().with_middleware(stats.clone());
+            store.get(&"b".to_string()).unwrap().await.expect("get b");
+            stats.record_read(&"c".to_string());
+            drop(store);
+            drop(tx);
+            assert_eq!(stats.pending(), 2, "pending");
+            assert_eq!(stats.last_read(&db, &"b".to_string()).await.expect("unflushed"), None, "lazy");
+
+            stats.flush(&db).await.expect("flush");
+            assert_eq!(stats.pending(), 0, "flushed");
+            assert!(stats.last_read(&db, &"b".to_string()).await.expect("last read").is_some(), "b");
+            let lru = stats.least_recently_read(&db, 10).await.expect("lru");
+            assert_eq!(lru, vec![JsValue::from("b"), JsValue::from("c")], "never read excluded");
+
+            let later = js_sys::Promise::new(&mut |resolve, _| {
+                web_sys::window().unwrap().set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, 5).unwrap();
+            });
+            wasm_bindgen_futures::JsFuture::from(later).await.unwrap();
+            let evicted = stats.evict_idle(&db, Duration::from_millis(1)).await.expect("evict");
+            assert_eq!(evicted, 2, "evicted");
+            let tx = db.transaction_on_one(&store_name).unwrap();
+            let keys = tx.object_store(&store_name).unwrap().get_all_keys().unwrap().await.unwrap();
+            let keys = keys.iter().filter(|k| k.is_string()).collect::<Vec<_>>();
+            assert_eq!(keys, vec![JsValue::from("a")], "unread kept");
+        });
+    }
+
+    #[cfg(feature = "cursors")]
+    pub mod soft_delete {
+        use crate::prelude::*;
+        use crate::test_utils::open_any_db;
+        test_mod_init!();
+
+        test_case!(async soft_delete => {
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).unwrap();
+            let store = tx.object_store(&store_name).unwrap();
+            for key in &["a", "b", "c"] {
+                let record = js_sys::Object::new();
+                js_sys::Reflect::set(&record, &"name".into(), &JsValue::from(*key)).unwrap();
+                store.put_key_val_owned(*key, &record).unwrap();
+            }
+
+            let records = store.soft_delete("deletedAt");
+            assert!(records.delete(&"b".to_string()).await.expect("delete"), "deleted");
+            assert!(!records.delete(&"b".to_string()).await.expect("delete again"), "already deleted");
+            assert!(!records.delete(&"x".to_string()).await.expect("delete missing"), "missing");
+
+            assert_eq!(records.count(Visibility::All).await.expect("all"), 3, "all");
+            assert_eq!(records.count(Visibility::Live).await.expect("live"), 2, "live");
+            assert_eq!(records.count(Visibility::Deleted).await.expect("deleted"), 1, "deleted");
+
+            let names = |records: Vec<JsValue>| -> Vec<String> {
+                records.iter().map(|r| js_sys::Reflect::get(r, &"name".into()).unwrap().as_string().unwrap()).collect()
+            };
+            assert_eq!(names(records.get_all(Visibility::Live).await.unwrap()), vec!["a", "c"], "live records");
+            let deleted = records.get_all(Visibility::Deleted).await.unwrap();
+            assert_eq!(names(deleted.clone()), vec!["b"], "tombstones");
+            assert!(records.is_deleted(&deleted[0]), "is_deleted");
+            assert!(records.get(&"b".to_string(), Visibility::Live).await.unwrap().is_none(), "hidden");
+
+            assert!(records.restore(&"b".to_string()).await.expect("restore"), "restored");
+            assert_eq!(records.count(Visibility::Live).await.unwrap(), 3, "live after restore");
+            assert!(records.get(&"b".to_string(), Visibility::Live).await.unwrap().is_some(), "visible");
+        });
+    }
+
+    pub mod audit_log {
+        use crate::clock::FakeClock;
+        use crate::prelude::*;
+        use crate::test_utils::open_db_with;
+        test_mod_init!();
+
+        fn field(value: &JsValue, path: &[&str]) -> JsValue {
+            path.iter().fold(value.clone(), |v, p| {
+                js_sys::Reflect::get(&v, &(*p).into()).unwrap()
+            })
+        }
+
+        fn names(value: JsValue) -> Vec<String> {
+            js_sys::Array::from(&value)
+                .iter()
+                .filter_map(|v| v.as_string())
+                .collect()
+        }
+
+        test_case!(async audit_log => {
+            let db = open_db_with(|evt: &IdbVersionChangeEvent| {
+                evt.db().create_object_store("docs")?;
+                let mut params = IdbObjectStoreParameters::new();
+                params.auto_increment(true);
+                evt.db().create_object_store_with_params("audit", &params)?;
+                Ok(())
+            }).await;
+            let audit = AuditLog::new("audit").track("docs").actor("alice").with_clock(FakeClock::new(5.0));
+            let key = String::from("k");
+            let doc = |json: &str| js_sys::JSON::parse(json).unwrap();
+
+            let tx = db.transaction_on_multi_with_mode(&["docs", "audit"], IdbTransactionMode::Readwrite).unwrap();
+            let store = tx.object_store("docs").unwrap().typed::<String>().with_middleware(audit.clone());
+            store.add_key_val(&key, &doc(r#"{"a":1,"b":2}"#)).expect("add");
+            store.put_key_val(&key, &doc(r#"{"a":1,"b":3,"c":4}"#)).expect("put");
+            store.delete(&key).expect("delete");
+            drop(store);
+            tx.await.into_result().expect("tx");
+
+            let tx = db.transaction_on_one("audit").unwrap();
+            let records = tx.object_store("audit").unwrap().get_all().unwrap().await.unwrap();
+            assert_eq!(records.length(), 3, "one record per write");
+            let ops = records.iter().map(|r| field(&r, &["op"]).as_string().unwrap()).collect::<Vec<_>>();
+            assert_eq!(ops, vec!["add", "put", "delete"], "ops");
+            let put = records.get(1);
+            assert_eq!(field(&put, &["actor"]), JsValue::from("alice"), "actor");
+            assert_eq!(field(&put, &["at"]), JsValue::from(5.0), "at");
+            assert_eq!(field(&put, &["key"]), JsValue::from("k"), "key");
+            assert_eq!(names(field(&put, &["diff", "added"])), vec!["c"], "added");
+            assert_eq!(names(field(&put, &["diff", "changed"])), vec!["b"], "changed");
+            assert_eq!(names(field(&records.get(0), &["diff", "added"])), vec!["a", "b"], "created");
+            assert_eq!(names(field(&records.get(2), &["diff", "removed"])), vec!["a", "b", "c"], "deleted");
+            drop(tx);
+
+            let tx = db.transaction_on_one_with_mode("docs", IdbTransactionMode::Readwrite).unwrap();
+            let store = tx.object_store("docs").unwrap().typed::<String>().with_middleware(audit);
+            let err = store.put_key_val(&key, &JsValue::from(1)).expect_err("audit store not in scope");
+            assert_eq!(err.name(), "InvalidStateError", "unaudited write");
+        });
+
+        test_case!(async failed_writes_leave_no_record => {
+            let db = open_db_with(|evt: &IdbVersionChangeEvent| {
+                evt.db().create_object_store("docs")?;
+                let mut params = IdbObjectStoreParameters::new();
+                params.auto_increment(true);
+                evt.db().create_object_store_with_params("audit", &params)?;
+                Ok(())
+            }).await;
+            let key = String::from("k");
+
+            let tx = db.transaction_on_multi_with_mode(&["docs", "audit"], IdbTransactionMode::Readwrite).unwrap();
+            tx.set_ignore_request_errors(true);
+            let store = tx.object_store("docs").unwrap().typed::<String>().with_middleware(AuditLog::new("audit").track("docs"));
+            store.add_key_val(&key, &JsValue::from(1)).expect("add");
+            store.add_key_val(&key, &JsValue::from(2)).expect("conflicting add");
+            drop(store);
+            tx.await.into_result().expect("tx");
+
+            let tx = db.transaction_on_one("audit").unwrap();
+            let records = tx.object_store("audit").unwrap().get_all().unwrap().await.unwrap();
+            assert_eq!(records.length(), 1, "only the successful write");
+        });
+    }
+
+    pub mod bloom_filter {
+        use crate::prelude::*;
+        use crate::test_utils::open_any_db;
+        test_mod_init!();
+
+        test_case!(async bloom_filter => {
+            let (db, store_name) = open_any_db().await;
+            let filter = BloomFilter::new(&store_name, &store_name, 100, 0.01);
+            assert!(!filter.might_contain(&"a".to_string()), "empty");
+
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).unwrap();
+            let store = tx.object_store(&store_name).unwrap().typed::<String>().with_middleware(filter.clone());
+            for i in 0..50 {
+                store.put_key_val(&format!("event-{}", i), &JsValue::from(i)).expect("put");
+            }
+            tx.await.into_result().expect("tx");
+
+            assert!((0..50).all(|i| filter.might_contain(&format!("event-{}", i))), "no false negatives");
+            let false_positives = (50..1050).filter(|i| filter.might_contain(&format!("event-{}", i))).count();
+            assert!(false_positives < 50, "false positives: {}", false_positives);
+            assert!(!filter.is_dirty(), "persisted with the writes");
+            assert_eq!(filter.len(), 50, "len");
+
+            let loaded = BloomFilter::new(&store_name, &store_name, 100, 0.01);
+            assert!(loaded.load(&db).await.expect("load"), "loaded");
+            assert!(loaded.might_contain(&"event-7".to_string()), "loaded bits");
+            let resized = BloomFilter::new(&store_name, &store_name, 1000, 0.01);
+            assert!(!resized.load(&db).await.expect("load resized"), "size mismatch");
+
+            let binary = js_sys::Uint8Array::from(&[1u8, 2, 3][..]);
+            filter.insert(&JsValue::from(binary.clone()));
+            assert!(filter.might_contain(&JsValue::from(binary.buffer())), "binary key");
+        });
+    }
+
+    #[cfg(feature = "cursors")]
+    pub mod encryption {
+        use crate::idb_object_store::rotate_key;
+        use crate::prelude::*;
+        use crate::test_utils::open_db_with;
+        use web_sys::IdbTransactionMode as TxMode;
+        test_mod_init!();
+
+        struct Xor(u8);
+
+        impl Cipher for Xor {
+            fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, DomException> {
+                Ok(plaintext.iter().map(|b| b ^ self.0).collect())
+            }
+
+            fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, DomException> {
+                self.encrypt(ciphertext)
+            }
+        }
+
+        async fn open_encrypted_db() -> IdbDatabase {
+            open_db_with(|evt: &IdbVersionChangeEvent| {
+                evt.db().create_object_store("secrets")?;
+                evt.db().create_object_store("meta")?;
+                evt.db().create_object_store_with_params(
+                    "keyed",
+                    IdbObjectStoreParameters::new().key_path(Some(&IdbKeyPath::str("id"))),
+                )?;
+                Ok(())
+            })
+            .await
+        }
+
+        fn secret(i: u8) -> JsValue {
+            js_sys::JSON::parse(&format!(r#"{{"n":{}}}"#, i)).unwrap()
+        }
+
+        fn n_of(value: &JsValue) -> u8 {
+            js_sys::Reflect::get(value, &"n".into())
+                .unwrap()
+                .as_f64()
+                .unwrap() as u8
+        }
+
+        test_case!(async round_trip => {
+            let db = open_encrypted_db().await;
+            let tx = db.transaction_on_one_with_mode("secrets", TxMode::Readwrite).expect("tx");
+            let store = tx.object_store("secrets").unwrap().typed::<u32>().with_middleware(Encryption::new(1, Xor(7)));
+            store.put_key_val(&1, &secret(5)).expect("put").into_future().await.expect("put await");
+            store.untyped().put_key_val_owned(2u32, &secret(6)).expect("put plain");
+
+            let raw = store.untyped().get_owned(1u32).unwrap().await.unwrap().unwrap();
+            assert_eq!(Encryption::key_version(&raw), Some(1), "envelope");
+            let read = store.get(&1).unwrap().await.unwrap().unwrap();
+            assert_eq!(n_of(&read), 5, "decrypted");
+            let plain = store.get(&2).unwrap().await.unwrap().unwrap();
+            assert_eq!(n_of(&plain), 6, "passed through");
+        });
+
+        test_case!(strict_envelopes => {
+            let encryption = Encryption::new(1, Xor(7));
+            let err = encryption.encrypt_value(&JsValue::UNDEFINED).expect_err("undefined");
+            assert_eq!(err.name(), "DataError", "undefined");
+
+            let envelope = encryption.encrypt_value(&secret(1)).unwrap();
+            assert_eq!(Encryption::key_version(&envelope), Some(1), "envelope");
+            for lookalike in [
+                r#"{"__enc":1,"data":"x"}"#,
+                r#"{"__indexed_db_futures_envelope":"encrypted/v1","key":1,"data":[1]}"#,
+                r#"{"__indexed_db_futures_envelope":"encrypted/v2","key":1}"#,
+            ] {
+                let value = js_sys::JSON::parse(lookalike).unwrap();
+                assert_eq!(Encryption::key_version(&value), None, "{}", lookalike);
+                let read = encryption.decrypt_value(&value).expect("passed through");
+                assert_eq!(js_sys::JSON::stringify(&read).unwrap(), js_sys::JSON::stringify(&value).unwrap(), "{}", lookalike);
+            }
+            let data = js_sys::Reflect::get(&envelope, &"data".into()).unwrap();
+            let lookalike = js_sys::JSON::parse(r#"{"__indexed_db_futures_envelope":"encrypted/v1","key":1.5}"#).unwrap();
+            js_sys::Reflect::set(&lookalike, &"data".into(), &data).unwrap();
+            assert_eq!(Encryption::key_version(&lookalike), None, "fractional key version");
+        });
+
+        test_case!(async in_line_keys => {
+            let db = open_encrypted_db().await;
+            let tx = db.transaction_on_one_with_mode("keyed", TxMode::Readwrite).expect("tx");
+            let store = tx.object_store("keyed").unwrap().typed::<u32>().with_middleware(Encryption::new(1, Xor(7)));
+            let record = js_sys::JSON::parse(r#"{"id":1,"n":1}"#).unwrap();
+            let err = store.put_key_val(&1, &record).expect_err("whole record");
+            assert_eq!(err.name(), "DataError", "whole record");
+            assert!(err.message().contains("in-line keys"), "rejected by the middleware");
+        });
+
+        test_case!(async fields => {
+            let db = open_encrypted_db().await;
+            let encryption = Encryption::new(1, Xor(7)).fields(&["card.number", "missing"]);
+            let tx = db.transaction_on_one_with_mode("secrets", TxMode::Readwrite).expect("tx");
+            let store = tx.object_store("secrets").unwrap().typed::<u32>().with_middleware(encryption.clone());
+            let record = js_sys::JSON::parse(r#"{"name":"a","card":{"number":"1234","kind":"visa"}}"#).unwrap();
+            store.put_key_val(&1, &record).expect("put").into_future().await.expect("put await");
+            let card = js_sys::Reflect::get(&record, &"card".into()).unwrap();
+            assert_eq!(js_sys::Reflect::get(&card, &"number".into()).unwrap(), JsValue::from("1234"), "input untouched");
+
+            let raw = store.untyped().get_owned(1u32).unwrap().await.unwrap().unwrap();
+            let raw_card = js_sys::Reflect::get(&raw, &"card".into()).unwrap();
+            assert_eq!(js_sys::Reflect::get(&raw, &"name".into()).unwrap(), JsValue::from("a"), "plaintext field");
+            assert_eq!(js_sys::Reflect::get(&raw_card, &"kind".into()).unwrap(), JsValue::from("visa"), "plaintext sibling");
+            assert_eq!(Encryption::key_version(&js_sys::Reflect::get(&raw_card, &"number".into()).unwrap()), Some(1), "encrypted field");
+            assert!(!js_sys::Reflect::has(&raw, &"missing".into()).unwrap(), "missing left out");
+
+            let read = store.get(&1).unwrap().await.unwrap().unwrap();
+            assert_eq!(js_sys::JSON::stringify(&read).unwrap(), js_sys::JSON::stringify(&record).unwrap(), "decrypted");
+            assert!(!encryption.needs_rotation(&raw), "current");
+            let rotated = Encryption::new(2, Xor(9)).with_previous_key(1, Xor(7)).fields(&["card.number"]);
+            assert!(rotated.needs_rotation(&raw), "outdated");
+        });
+
+        test_case!(async rotate => {
+            let db = open_encrypted_db().await;
+            let old = Encryption::new(1, Xor(7));
+            let tx = db.transaction_on_one_with_mode("secrets", TxMode::Readwrite).expect("tx");
+            let store = tx.object_store("secrets").unwrap();
+            for i in 0..5u8 {
+                store.put_key_val_owned(u32::from(i), &old.encrypt_value(&secret(i)).unwrap()).expect("put");
+            }
+            tx.await.into_result().expect("tx await");
+
+            let new = Encryption::new(2, Xor(9)).with_previous_key(1, Xor(7));
+            let rotation = KeyRotation::new("meta", "secrets", new.clone());
+            let progress = rotation.run_batch(&db, 2).await.expect("batch");
+            assert_eq!(progress, RotationProgress { processed: 2, rewritten: 2, total: 5, done: false }, "batch");
+
+            // Reads mid-rotation see both key versions
+            let tx = db.transaction_on_one("secrets").unwrap();
+            let store = tx.object_store("secrets").unwrap().typed::<u32>().with_middleware(new.clone());
+            let versions = store.untyped().get_all().unwrap().await.unwrap()
+                .iter()
+                .map(|v| Encryption::key_version(&v).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(versions, vec![2, 2, 1, 1, 1], "versions");
+            assert_eq!(n_of(&store.get(&1).unwrap().await.unwrap().unwrap()), 1, "rotated read");
+            assert_eq!(n_of(&store.get(&4).unwrap().await.unwrap().unwrap()), 4, "old read");
+            drop(store);
+            tx.await.into_result().expect("read tx");
+
+            let mut reports = Vec::new();
+            rotate_key(&db, "meta", "secrets", (1, Xor(7)), (2, Xor(9)), 2, |p| reports.push(p))
+                .await
+                .expect("rotate_key");
+            let last = *reports.last().unwrap();
+            assert!(last.done, "done");
+            assert_eq!((last.processed, last.rewritten), (5, 5), "counts");
+            assert_eq!(rotation.progress(&db).await.expect("progress"), last, "stored progress");
+
+            let tx = db.transaction_on_one("secrets").unwrap();
+            let store = tx.object_store("secrets").unwrap().typed::<u32>().with_middleware(Encryption::new(2, Xor(9)));
+            for i in 0..5u32 {
+                assert_eq!(u32::from(n_of(&store.get(&i).unwrap().await.unwrap().unwrap())), i, "value {}", i);
+            }
+        });
+    }
+
+    #[cfg(feature = "blob-streams")]
+    test_case!(async get_blob_stream => {
+        let (db, store_name) = open_any_db().await;
+        let tx = db.transaction_on_one_with_mode(&store_name, TxMode::Readwrite).expect("tx");
+        let store = tx.object_store(&store_name).expect("store");
+        let blob = web_sys::Blob::new_with_str_sequence(&js_sys::Array::of2(&"hello ".into(), &"world".into()))
+            .unwrap();
+        store.put_key_val_owned("blob", &blob).expect("put blob");
+        store.put_key_val_owned("text", &JsValue::from("hello")).expect("put text");
+
+        let stream = store.get_blob_stream(&JsValue::from("blob")).await.expect("stream").expect("some");
+        let missing = store.get_blob_stream(&JsValue::from("missing")).await.expect("missing");
+        let not_blob = store.get_blob_stream(&JsValue::from("text")).await;
+        tx.await.into_result().expect("tx await");
+        assert!(missing.is_none(), "missing");
+        assert!(not_blob.is_err(), "not a blob");
+
+        let reader = stream.get_reader();
+        let read: js_sys::Function = js_sys::Reflect::get(&reader, &"read".into()).unwrap().unchecked_into();
+        let mut bytes = Vec::new();
+        loop {
+            let promise: js_sys::Promise = read.call0(&reader).unwrap().unchecked_into();
+            let chunk = wasm_bindgen_futures::JsFuture::from(promise).await.expect("read");
+            if js_sys::Reflect::get(&chunk, &"done".into()).unwrap().is_truthy() {
+                break;
+            }
+            let value = js_sys::Reflect::get(&chunk, &"value".into()).unwrap();
+            bytes.extend(js_sys::Uint8Array::new(&value).to_vec());
+        }
+        assert_eq!(bytes, b"hello world", "contents");
+    });
+
     #[cfg(feature = "indices")]
     pub mod indices {
         use crate::prelude::*;
-        use uuid::Uuid;
+        use crate::test_utils::open_db_with;
         test_mod_init!();
 
         test_case!(async index_names => {
-            let db_name = Uuid::new_v4().to_string();
-            let store_name = Uuid::new_v4().to_string();
-            let mut req = crate::IdbDatabase::open(&db_name).expect("db open");
-            {
-                let store_cloned = store_name.clone();
-                req.set_on_upgrade_needed(Some(move |evt: &IdbVersionChangeEvent| {
-                    let store = evt.db().create_object_store(&store_cloned)?;
-                    store.create_index("idx1", &IdbKeyPath::str("foo"))?;
-                    store.create_index("idx2", &IdbKeyPath::str("foo"))?;
-                    Ok(())
-                }));
-            }
-            let db = req.into_future().await.expect("db await");
-            let tx = db.transaction_on_one(&store_name).expect("tx");
-            let store = tx.object_store(&store_name).expect("store");
+            let db = open_db_with(|evt: &IdbVersionChangeEvent| {
+                let store = evt.db().create_object_store("s")?;
+                store.create_index("idx1", &IdbKeyPath::str("foo"))?;
+                store.create_index("idx2", &IdbKeyPath::str("foo"))?;
+                Ok(())
+            })
+            .await;
+            let tx = db.transaction_on_one("s").expect("tx");
+            let store = tx.object_store("s").expect("store");
             let mut idx_names: Vec<String> = store.index_names().collect();
             idx_names.sort();
 
             assert_eq!(idx_names, vec!["idx1", "idx2"]);
         });
+
+        test_case!(async upsert_by_index => {
+            let db = open_db_with(move |evt: &IdbVersionChangeEvent| {
+                let mut params = IdbObjectStoreParameters::new();
+                params.auto_increment(true).key_path(Some(&IdbKeyPath::str("id")));
+                let store = evt.db().create_object_store_with_params("users", &params)?;
+                store.create_index_with_params(
+                    "email",
+                    &IdbKeyPath::str("email"),
+                    IdbIndexParameters::new().unique(true),
+                )?;
+                Ok(())
+            }).await;
+
+            let user = |email: &str, name: &str| -> JsValue {
+                let obj = js_sys::Object::new();
+                js_sys::Reflect::set(&obj, &"email".into(), &email.into()).unwrap();
+                js_sys::Reflect::set(&obj, &"name".into(), &name.into()).unwrap();
+                obj.into()
+            };
+
+            let tx = db.transaction_on_one_with_mode("users", IdbTransactionMode::Readwrite).expect("tx");
+            let store = tx.object_store("users").expect("store");
+            let email = JsValue::from("a@b.c");
+            let k1 = store.upsert_by_index("email", &email, &user("a@b.c", "foo")).await.expect("upsert 1");
+            let k2 = store.upsert_by_index("email", &email, &user("a@b.c", "bar")).await.expect("upsert 2");
+            tx.await.into_result().expect("tx await");
+
+            let tx = db.transaction_on_one("users").expect("tx2");
+            let store = tx.object_store("users").expect("store2");
+            let all = store.get_all().expect("get_all").await.expect("get_all await");
+
+            assert_eq!(k1, k2, "keys");
+            assert_eq!(all.length(), 1, "length");
+            assert_eq!(js_sys::Reflect::get(&all.get(0), &"name".into()).unwrap(), JsValue::from("bar"), "name");
+        });
+
+        #[cfg(feature = "cursors")]
+        test_case!(async index_backfill => {
+            let db = crate::test_utils::open_db_with_stores(&["users", "meta"]).await;
+            let name = db.name();
+            let tx = db.transaction_on_one_with_mode("users", TxMode::Readwrite).unwrap();
+            let store = tx.object_store("users").unwrap();
+            for i in 0..5u8 {
+                let user = js_sys::JSON::parse(&format!(r#"{{"email":"u{}@x"}}"#, i)).unwrap();
+                store.put_key_val_owned(i, &user).expect("put");
+            }
+            store.put_key_val_owned(9u8, &JsValue::from(1u8)).expect("put primitive");
+            tx.await.into_result().expect("tx");
+            db.close();
+
+            let backfill = IndexBackfill::new("meta", "users", "by_email", "email");
+            let backfill_cb = backfill.clone();
+            let mut req = IdbDatabase::open_u32(&name, 2).expect("open2");
+            req.set_on_upgrade_needed(Some(move |evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+                let tx = evt.transaction().expect("upgrade tx");
+                let store = tx.object_store("users")?;
+                backfill_cb.create_index(&store, None)?;
+                Ok(())
+            }));
+            let db = req.into_future().await.expect("db2");
+            assert!(!backfill.index_ready(&db).await.expect("ready 1"), "not ready");
+
+            assert!(!backfill.run_batch(&db, 2).await.expect("batch"), "partial batch");
+            backfill.run(&db, 2).await.expect("run");
+            assert!(backfill.index_ready(&db).await.expect("ready 2"), "ready");
+
+            let tx = db.transaction_on_one("users").unwrap();
+            let store = tx.object_store("users").unwrap();
+            let index = store.index("by_email").unwrap();
+            assert_eq!(index.count().unwrap().await.unwrap(), 5, "index count");
+            let key = index.get_key_owned(JsValue::from("u3@x")).unwrap().await.unwrap();
+            assert_eq!(key, Some(JsValue::from(3u8)), "lookup");
+        });
+
+        test_case!(async blind_index => {
+            // A toy keyed hash; apps would use HMAC
+            struct Sum(u8);
+            impl KeyedHash for Sum {
+                fn mac(&self, data: &[u8]) -> Result<Vec<u8>, DomException> {
+                    Ok(data.chunks(4).map(|c| c.iter().fold(self.0, |a, b| a.wrapping_add(*b))).collect())
+                }
+            }
+            struct Xor;
+            impl Cipher for Xor {
+                fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, DomException> {
+                    Ok(plaintext.iter().map(|b| b ^ 7).collect())
+                }
+                fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, DomException> {
+                    self.encrypt(ciphertext)
+                }
+            }
+
+            let blind = BlindIndex::new("by_ssn", "ssn", Sum(3));
+            let blind_cb = blind.clone();
+            let db = open_db_with(move |evt: &IdbVersionChangeEvent| {
+                let store = evt.db().create_object_store("people")?;
+                blind_cb.create_index(&store, None)?;
+                Ok(())
+            }).await;
+
+            let person = |ssn: &str| js_sys::JSON::parse(&format!(r#"{{"ssn":"{}"}}"#, ssn)).unwrap();
+            let tx = db.transaction_on_one_with_mode("people", IdbTransactionMode::Readwrite).expect("tx");
+            let store = tx.object_store("people").unwrap().typed::<u32>()
+                .with_middleware(blind.clone())
+                .with_middleware(Encryption::new(1, Xor).fields(&["ssn"]));
+            for (key, ssn) in [(1, "123-45"), (2, "999-00"), (3, "123-45")].iter() {
+                store.put_key_val(key, &person(ssn)).unwrap().into_future().await.expect("put");
+            }
+            store.put_key_val(&4, &js_sys::Object::new()).unwrap().into_future().await.expect("put without ssn");
+
+            let keys = blind.find_keys(store.untyped(), &"123-45".into()).await.expect("find_keys");
+            assert_eq!(keys.to_vec(), vec![JsValue::from(1), JsValue::from(3)], "keys");
+            let found = blind.find(store.untyped(), &"999-00".into()).await.expect("find");
+            assert_eq!(found.length(), 1, "found");
+            let ssn = js_sys::Reflect::get(&found.get(0), &"ssn".into()).unwrap();
+            assert_eq!(Encryption::key_version(&ssn), Some(1), "stored encrypted");
+            assert_eq!(store.untyped().index("by_ssn").unwrap().count().unwrap().await.unwrap(), 3, "count");
+        });
+
+        test_case!(async unique_checks => {
+            let db = open_db_with(move |evt: &IdbVersionChangeEvent| {
+                let store = evt.db().create_object_store("users")?;
+                store.create_index_with_params(
+                    "email",
+                    &IdbKeyPath::str("contact.email"),
+                    IdbIndexParameters::new().unique(true),
+                )?;
+                Ok(())
+            }).await;
+
+            let user = |email: &str| -> JsValue {
+                let contact = js_sys::Object::new();
+                js_sys::Reflect::set(&contact, &"email".into(), &email.into()).unwrap();
+                let obj = js_sys::Object::new();
+                js_sys::Reflect::set(&obj, &"contact".into(), &contact).unwrap();
+                obj.into()
+            };
+
+            let tx = db.transaction_on_one_with_mode("users", IdbTransactionMode::Readwrite).expect("tx");
+            let store = tx.object_store("users").expect("store");
+            store.put_key_val_checked(&JsValue::from(1), &user("a@b.c")).await.expect("put 1");
+
+            let index = store.index("email").expect("index");
+            let taken = index.would_violate_unique(&JsValue::from("a@b.c")).await.expect("taken");
+            let free = index.would_violate_unique(&JsValue::from("x@y.z")).await.expect("free");
+            let own = index.would_violate_unique_for(&JsValue::from("a@b.c"), &JsValue::from(1))
+                .await
+                .expect("own");
+
+            let update = store.put_key_val_checked(&JsValue::from(1), &user("a@b.c")).await;
+            let violation = store.put_key_val_checked(&JsValue::from(2), &user("a@b.c")).await;
+            tx.await.into_result().expect("tx await");
+
+            assert!(taken, "taken");
+            assert!(!free, "free");
+            assert!(!own, "own");
+            assert!(update.is_ok(), "update");
+            assert_eq!(violation, Err(CheckedPutError::UniqueViolation("email".into())), "violation");
+        });
     }
 }
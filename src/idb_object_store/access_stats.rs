@@ -0,0 +1,222 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbKeyRange, IdbTransactionMode};
+
+use crate::clock::{Clock, SharedClock};
+use crate::idb_database::IdbDatabase;
+use crate::idb_key::IdbKey;
+use crate::idb_query_source::IdbQuerySource;
+use crate::maintenance::{JobFuture, JobStep, MaintenanceJob};
+
+use super::{Middleware, MiddlewareContext};
+
+const LAST_READ: &str = "lastRead";
+
+/// Last-read times of a store's records, kept in a sidecar store with out-of-line keys, so that
+/// eviction can go by how records are actually used rather than when they were written.
+///
+/// Reads get recorded in memory, through [record_read][AccessStats::record_read] or by
+/// registering the stats as [Middleware] on a [TypedObjectStore][super::TypedObjectStore], and
+/// written to the sidecar store in one batch by [flush][AccessStats::flush], so that reads don't
+/// turn into writes. Registering the stats as a [MaintenanceJob] flushes them whenever the page
+/// is idle. Records that were never read have no last-read time. Several stores can share a
+/// sidecar store; clones share the same pending reads.
+#[derive(Debug, Clone)]
+pub struct AccessStats {
+    stats_store: String,
+    store_name: String,
+    pending: Rc<RefCell<Vec<(JsValue, f64)>>>,
+    clock: SharedClock,
+}
+
+impl AccessStats {
+    /// Track reads of the given store in the given sidecar store
+    pub fn new(stats_store: &str, store_name: &str) -> Self {
+        Self {
+            stats_store: stats_store.into(),
+            store_name: store_name.into(),
+            pending: Rc::new(RefCell::new(Vec::new())),
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Read the time of reads and the idle cutoff from the given clock instead of the system time
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// Record a read of the key now
+    pub fn record_read<K: IdbKey>(&self, key: &K) {
+        self.pending
+            .borrow_mut()
+            .push((key.to_js_key(), self.clock.now()));
+    }
+
+    /// The number of reads recorded since the last flush
+    pub fn pending(&self) -> usize {
+        self.pending.borrow().len()
+    }
+
+    /// Write the recorded reads to the sidecar store in a single transaction. Reads recorded
+    /// while the flush is in progress are kept for the next one.
+    pub async fn flush(&self, db: &IdbDatabase) -> Result<(), DomException> {
+        let pending = std::mem::take(&mut *self.pending.borrow_mut());
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let tx =
+            db.transaction_on_one_with_mode(&self.stats_store, IdbTransactionMode::Readwrite)?;
+        let stats = tx.object_store(&self.stats_store)?;
+        for (key, time) in pending.iter() {
+            let record = js_sys::Object::new();
+            js_sys::Reflect::set(&record, &LAST_READ.into(), &(*time).into())?;
+            stats.put_key_val(&self.stats_key(key), &record)?;
+        }
+        match tx.await.into_result() {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let mut current = self.pending.borrow_mut();
+                let newer = std::mem::replace(&mut *current, pending);
+                current.extend(newer);
+                Err(e)
+            }
+        }
+    }
+
+    /// When the key was last read, in milliseconds since the epoch, as of the last flush
+    pub async fn last_read<K: IdbKey>(
+        &self,
+        db: &IdbDatabase,
+        key: &K,
+    ) -> Result<Option<f64>, DomException> {
+        let tx = db.transaction_on_one(&self.stats_store)?;
+        let record = tx
+            .object_store(&self.stats_store)?
+            .get(&self.stats_key(&key.to_js_key()))?
+            .await?;
+        Ok(record.and_then(|r| read_time(&r)))
+    }
+
+    /// The keys of up to `n` records, least recently read first, as of the last flush. Records
+    /// that were never read aren't included.
+    pub async fn least_recently_read(
+        &self,
+        db: &IdbDatabase,
+        n: u32,
+    ) -> Result<Vec<JsValue>, DomException> {
+        let mut stats = self.all(db).await?;
+        stats.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(stats
+            .into_iter()
+            .take(n as usize)
+            .map(|(key, _)| key)
+            .collect())
+    }
+
+    /// Flush, then delete the records that were last read longer ago than `max_idle` from the
+    /// store along with their stats, in one transaction. Records that were never read are kept.
+    /// Resolves to the number of records deleted.
+    pub async fn evict_idle(
+        &self,
+        db: &IdbDatabase,
+        max_idle: Duration,
+    ) -> Result<u32, DomException> {
+        self.flush(db).await?;
+        let cutoff = self.clock.now() - max_idle.as_millis() as f64;
+        let idle = self
+            .all(db)
+            .await?
+            .into_iter()
+            .filter(|(_, time)| *time < cutoff)
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+        if idle.is_empty() {
+            return Ok(0);
+        }
+
+        let tx = db.transaction_on_multi_with_mode(
+            &[&self.store_name, &self.stats_store],
+            IdbTransactionMode::Readwrite,
+        )?;
+        let store = tx.object_store(&self.store_name)?;
+        let stats = tx.object_store(&self.stats_store)?;
+        for key in idle.iter() {
+            store.delete(key)?;
+            stats.delete(&self.stats_key(key))?;
+        }
+        tx.await.into_result()?;
+        Ok(idle.len() as u32)
+    }
+
+    /// Every flushed `(key, last read)` pair of the store, in key order
+    async fn all(&self, db: &IdbDatabase) -> Result<Vec<(JsValue, f64)>, DomException> {
+        let tx = db.transaction_on_one(&self.stats_store)?;
+        let stats = tx.object_store(&self.stats_store)?;
+        let range = self.range()?;
+        let keys = stats.get_all_keys_with_key(&range)?.await?;
+        let records = stats.get_all_with_key(&range)?.await?;
+        Ok(keys
+            .iter()
+            .zip(records.iter())
+            .filter_map(|(key, record)| {
+                let key = js_sys::Reflect::get(&key, &1.into()).ok()?;
+                Some((key, read_time(&record)?))
+            })
+            .collect())
+    }
+
+    /// Stats are keyed `[store name, key]`
+    fn stats_key(&self, key: &JsValue) -> js_sys::Array {
+        js_sys::Array::of2(&JsValue::from_str(&self.store_name), key)
+    }
+
+    /// Every stats key of the store: from `[store name]` up to, but excluding, the next possible
+    /// store name
+    fn range(&self) -> Result<IdbKeyRange, DomException> {
+        let lower = js_sys::Array::of1(&JsValue::from_str(&self.store_name));
+        let upper = js_sys::Array::of1(&JsValue::from_str(&format!("{}\0", self.store_name)));
+        Ok(IdbKeyRange::bound_with_lower_open_and_upper_open(
+            &lower, &upper, false, true,
+        )?)
+    }
+}
+
+impl Middleware for AccessStats {
+    fn on_read(&self, ctx: &MiddlewareContext, value: JsValue) -> Result<JsValue, DomException> {
+        if ctx.store().name() == self.store_name {
+            if let Some(key) = ctx.key() {
+                self.record_read(key);
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// Flushes the recorded reads in a single chunk
+impl MaintenanceJob for AccessStats {
+    fn name(&self) -> String {
+        format!("access-stats/{}", self.store_name)
+    }
+
+    fn run_chunk<'a>(
+        &'a self,
+        db: &'a IdbDatabase,
+        _checkpoint: Option<JsValue>,
+        _chunk_size: u32,
+    ) -> JobFuture<'a> {
+        Box::pin(async move {
+            self.flush(db).await?;
+            Ok(JobStep::Done)
+        })
+    }
+}
+
+fn read_time(record: &JsValue) -> Option<f64> {
+    js_sys::Reflect::get(record, &LAST_READ.into())
+        .ok()?
+        .as_f64()
+}
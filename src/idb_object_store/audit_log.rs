@@ -0,0 +1,280 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::clock::{Clock, SharedClock};
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::{dom_exception, ClosureKind, ClosureToken};
+
+use super::{Middleware, MiddlewareContext, WriteOp};
+
+/// [Middleware] that appends an audit record to a dedicated store for every write on the
+/// configured stores of a [TypedObjectStore][super::TypedObjectStore].
+///
+/// The audit store must use out-of-line, auto-incremented keys, so that records sort in the order
+/// the writes were made. Each record holds:
+///
+/// - `store` and `key`: the record that was written
+/// - `op`: `"add"`, `"put"` or `"delete"`
+/// - `actor`: who made the write, as set by [actor][AuditLog::actor], or `null`
+/// - `at`: when the write was made, in milliseconds since the epoch
+/// - `diff`: the `added`, `removed` and `changed` top-level field names between the previous and
+///   the new value. Values that aren't plain objects are compared as a whole, under the empty
+///   field name, and a missing record has no fields.
+///
+/// The previous value gets read in the same transaction as the write and the audit record gets
+/// written once the write has succeeded, so a failed write leaves no record. If the record can't
+/// be written, the transaction gets aborted, so either both make it to disk or neither does.
+/// Writes on a configured store fail with an `InvalidStateError` if the transaction isn't a
+/// readwrite one covering the audit store.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    audit_store: String,
+    stores: Vec<String>,
+    actor: Option<String>,
+    clock: SharedClock,
+    /// The record of the write being made, between its write and after_write hooks
+    pending: RefCell<Option<Pending>>,
+}
+
+#[derive(Debug, Clone)]
+struct Pending {
+    audit: web_sys::IdbObjectStore,
+    record: js_sys::Object,
+    /// The read of the value from before the write, when the key is known
+    before: Option<web_sys::IdbRequest>,
+    after: JsValue,
+}
+
+impl AuditLog {
+    /// Write audit records to the given store. No stores are audited until
+    /// [track][AuditLog::track]ed.
+    pub fn new(audit_store: &str) -> Self {
+        Self {
+            audit_store: audit_store.into(),
+            stores: Vec::new(),
+            actor: None,
+            clock: SharedClock::default(),
+            pending: RefCell::new(None),
+        }
+    }
+
+    /// Audit writes on the given store
+    pub fn track(mut self, store: &str) -> Self {
+        self.stores.push(store.into());
+        self
+    }
+
+    /// Record writes as made by the given actor, e.g. the signed in user's ID
+    pub fn actor(mut self, actor: &str) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    /// Read the time of writes from the given clock instead of the system time
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// Whether writes on the store get audited
+    #[inline]
+    pub fn is_tracked(&self, store: &str) -> bool {
+        self.stores.iter().any(|s| s == store)
+    }
+
+    /// Build the record and read the previous value; the record gets written in
+    /// [after_write][Middleware::after_write]
+    fn prepare(&self, ctx: &MiddlewareContext, after: JsValue) -> Result<(), DomException> {
+        self.pending.replace(None);
+        let store = ctx.store();
+        let store_name = store.name();
+        if !self.is_tracked(&store_name) {
+            return Ok(());
+        }
+        let audit = match store.transaction() {
+            Some(tx) => {
+                let tx = tx.as_web_sys();
+                let in_scope = tx.object_store_names().contains(&self.audit_store);
+                if in_scope && tx.mode()? == IdbTransactionMode::Readwrite {
+                    Some(tx.object_store(&self.audit_store)?)
+                } else {
+                    None
+                }
+            }
+            None => None,
+        };
+        let audit = audit.ok_or_else(|| {
+            dom_exception(
+                &format!(
+                    "Writes on {} must be made in a readwrite transaction on the audit store {}",
+                    store_name, self.audit_store
+                ),
+                "InvalidStateError",
+            )
+        })?;
+
+        let key = ctx.key().cloned().unwrap_or(JsValue::NULL);
+        let record = js_sys::Object::new();
+        js_sys::Reflect::set(&record, &"store".into(), &store_name.into())?;
+        js_sys::Reflect::set(&record, &"key".into(), &key)?;
+        let op = ctx.op().unwrap_or(WriteOp::Put);
+        js_sys::Reflect::set(&record, &"op".into(), &op.as_str().into())?;
+        let actor = self.actor.as_deref().map_or(JsValue::NULL, JsValue::from);
+        js_sys::Reflect::set(&record, &"actor".into(), &actor)?;
+        js_sys::Reflect::set(&record, &"at".into(), &self.clock.now().into())?;
+
+        // Requests execute in order, so this sees the value from before the write that's about
+        // to be made
+        let before = match ctx.key() {
+            Some(key) => Some(store.as_web_sys().get(key)?),
+            None => None,
+        };
+        self.pending.replace(Some(Pending {
+            audit,
+            record,
+            before,
+            after,
+        }));
+        Ok(())
+    }
+}
+
+impl Pending {
+    /// Write the record once the write has succeeded, aborting the transaction if it can't be
+    fn listen(self, write: &web_sys::IdbRequest) -> Result<(), DomException> {
+        let tx = self.audit.transaction();
+        let req = write.clone();
+        let token = ClosureToken::new(ClosureKind::Request);
+        let listener = Closure::once_into_js(move |evt: web_sys::Event| {
+            token.hold();
+            if evt.type_() == "success" && self.write(&req).is_err() {
+                let _ = tx.abort();
+            }
+        });
+        for event in &["success", "error"] {
+            write.add_event_listener_with_callback(event, listener.unchecked_ref())?;
+        }
+        Ok(())
+    }
+
+    fn write(self, write: &web_sys::IdbRequest) -> Result<(), JsValue> {
+        let before = match self.before.as_ref() {
+            Some(req) if req.error()?.is_some() => {
+                return Err(
+                    dom_exception("Failed to read the previous value", "UnknownError").into(),
+                );
+            }
+            Some(req) => req.result()?,
+            None => {
+                // The key got generated or read from the value, and is the write's result
+                js_sys::Reflect::set(&self.record, &"key".into(), &write.result()?)?;
+                JsValue::UNDEFINED
+            }
+        };
+        js_sys::Reflect::set(&self.record, &"diff".into(), &diff(&before, &self.after))?;
+        let add = self.audit.add(&self.record)?;
+
+        // Where request errors are ignored, a failed record wouldn't abort the transaction
+        let tx = self.audit.transaction();
+        let token = ClosureToken::new(ClosureKind::Request);
+        let listener = Closure::once_into_js(move |evt: web_sys::Event| {
+            token.hold();
+            if evt.type_() == "error" {
+                let _ = tx.abort();
+            }
+        });
+        for event in &["success", "error"] {
+            add.add_event_listener_with_callback(event, listener.unchecked_ref())?;
+        }
+        Ok(())
+    }
+}
+
+impl Middleware for AuditLog {
+    fn on_write(&self, ctx: &MiddlewareContext, value: JsValue) -> Result<JsValue, DomException> {
+        self.prepare(ctx, value.clone())?;
+        Ok(value)
+    }
+
+    fn on_delete(&self, ctx: &MiddlewareContext) -> Result<(), DomException> {
+        self.prepare(ctx, JsValue::UNDEFINED)
+    }
+
+    fn after_write(
+        &self,
+        _: &MiddlewareContext,
+        request: &web_sys::IdbRequest,
+    ) -> Result<(), DomException> {
+        match self.pending.take() {
+            Some(pending) => pending.listen(request),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The `{added, removed, changed}` field names between two values
+fn diff(before: &JsValue, after: &JsValue) -> JsValue {
+    let before = fields(before);
+    let after = fields(after);
+    let added = js_sys::Array::new();
+    let removed = js_sys::Array::new();
+    let changed = js_sys::Array::new();
+    for (name, value) in after.iter() {
+        match before.get(name) {
+            None => {
+                added.push(&name.into());
+            }
+            Some(old) if old != value => {
+                changed.push(&name.into());
+            }
+            Some(_) => {}
+        }
+    }
+    for name in before.keys().filter(|name| !after.contains_key(*name)) {
+        removed.push(&name.into());
+    }
+
+    let diff = js_sys::Object::new();
+    let _ = js_sys::Reflect::set(&diff, &"added".into(), &added);
+    let _ = js_sys::Reflect::set(&diff, &"removed".into(), &removed);
+    let _ = js_sys::Reflect::set(&diff, &"changed".into(), &changed);
+    diff.into()
+}
+
+/// A value's top-level fields, JSON-encoded for comparison
+fn fields(value: &JsValue) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+    if value.is_undefined() {
+        return fields;
+    }
+    if is_plain_object(value) {
+        for entry in js_sys::Object::entries(value.unchecked_ref()).iter() {
+            let entry: js_sys::Array = entry.unchecked_into();
+            if let Some(name) = entry.get(0).as_string() {
+                fields.insert(name, encode(&entry.get(1)));
+            }
+        }
+    } else {
+        fields.insert(String::new(), encode(value));
+    }
+    fields
+}
+
+fn is_plain_object(value: &JsValue) -> bool {
+    if !value.is_object() || js_sys::Array::is_array(value) {
+        return false;
+    }
+    let proto = JsValue::from(js_sys::Object::get_prototype_of(value));
+    let object_proto = JsValue::from(js_sys::Object::get_prototype_of(&js_sys::Object::new()));
+    proto.is_null() || proto == object_proto
+}
+
+fn encode(value: &JsValue) -> String {
+    js_sys::JSON::stringify(value)
+        .ok()
+        .and_then(|s| s.as_string())
+        .unwrap_or_else(|| format!("{:?}", value))
+}
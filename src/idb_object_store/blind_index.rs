@@ -0,0 +1,152 @@
+use std::fmt;
+use std::rc::Rc;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{DomException, IdbIndexParameters};
+
+use crate::idb_index::IdbIndex;
+use crate::idb_key_path::IdbKeyPath;
+use crate::idb_query_source::IdbQuerySource;
+
+use super::{IdbObjectStore, Middleware, MiddlewareContext};
+
+const SHADOW_FIELD: &str = "__blind";
+
+/// A keyed hash supplied by the app, typically HMAC-SHA256 with a key kept apart from the
+/// encryption keys. This crate doesn't ship any cryptography of its own.
+pub trait KeyedHash {
+    /// The MAC of the data. Must be deterministic for a given key.
+    fn mac(&self, data: &[u8]) -> Result<Vec<u8>, DomException>;
+}
+
+/// An index of keyed hashes of a sensitive field, allowing equality lookups on a field that's
+/// stored encrypted.
+///
+/// The field's value is serialised to JSON and hashed with the [KeyedHash] into a hex digest in a
+/// shadow field, `__blind.<index name>`, which the index is created on. Lookups hash the value
+/// being searched for the same way, so equal values find each other without the index revealing
+/// them. Like any blind index it does reveal which records share a value.
+///
+/// Writes need to populate the shadow field, either through [prepare][BlindIndex::prepare] or by
+/// registering the index as [Middleware] on a [TypedObjectStore][super::TypedObjectStore]
+/// *before* the [Encryption][super::Encryption] middleware, so that it sees the plaintext. The
+/// shadow field must stay unencrypted, so whole-record encryption can't be combined with it; use
+/// [field-level encryption][super::Encryption::fields] instead.
+///
+/// Index names must not contain dots.
+///
+/// Features required: `indices`
+#[derive(Clone)]
+pub struct BlindIndex {
+    index_name: String,
+    source: IdbKeyPath,
+    hash: Rc<dyn KeyedHash>,
+}
+
+impl fmt::Debug for BlindIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlindIndex")
+            .field("index_name", &self.index_name)
+            .field("source", &self.source)
+            .finish()
+    }
+}
+
+impl BlindIndex {
+    /// Describe a blind index over the field at the given dotted key path
+    pub fn new<H: KeyedHash + 'static>(index_name: &str, source_path: &str, hash: H) -> Self {
+        Self {
+            index_name: index_name.into(),
+            source: IdbKeyPath::str(source_path),
+            hash: Rc::new(hash),
+        }
+    }
+
+    /// The key path the index is actually created on
+    pub fn shadow_path(&self) -> String {
+        format!("{}.{}", SHADOW_FIELD, self.index_name)
+    }
+
+    /// Create the index on the shadow field. Must be called during an upgrade.
+    pub fn create_index<'a>(
+        &self,
+        store: &'a IdbObjectStore<'a>,
+        params: Option<&IdbIndexParameters>,
+    ) -> Result<IdbIndex<'a>, DomException> {
+        let key_path = IdbKeyPath::str(&self.shadow_path());
+        match params {
+            Some(params) => store.create_index_with_params(&self.index_name, &key_path, params),
+            None => store.create_index(&self.index_name, &key_path),
+        }
+    }
+
+    /// The hex digest the value gets indexed under
+    pub fn digest(&self, value: &JsValue) -> Result<String, DomException> {
+        let json = String::from(js_sys::JSON::stringify(value)?);
+        let mac = self.hash.mac(json.as_bytes())?;
+        Ok(mac.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    /// Write the digest of the source field into the shadow field of a value that's about to be
+    /// written, or remove it if the source field is missing. Non-object values are left alone.
+    pub fn prepare(&self, value: &JsValue) -> Result<(), DomException> {
+        if !value.is_object() {
+            return Ok(());
+        }
+        let shadow = js_sys::Reflect::get(value, &JsValue::from_str(SHADOW_FIELD))?;
+        match self.source.evaluate(value) {
+            Some(field) => {
+                let shadow = if shadow.is_object() {
+                    shadow
+                } else {
+                    let obj: JsValue = js_sys::Object::new().into();
+                    js_sys::Reflect::set(value, &JsValue::from_str(SHADOW_FIELD), &obj)?;
+                    obj
+                };
+                let digest = JsValue::from(self.digest(&field)?);
+                js_sys::Reflect::set(&shadow, &JsValue::from_str(&self.index_name), &digest)?;
+            }
+            None if shadow.is_object() => {
+                js_sys::Reflect::delete_property(
+                    shadow.unchecked_ref(),
+                    &JsValue::from_str(&self.index_name),
+                )?;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// The records of the store whose source field equals the value
+    pub async fn find(
+        &self,
+        store: &IdbObjectStore<'_>,
+        value: &JsValue,
+    ) -> Result<js_sys::Array, DomException> {
+        let digest = JsValue::from(self.digest(value)?);
+        store
+            .index(&self.index_name)?
+            .get_all_with_key(&digest)?
+            .await
+    }
+
+    /// The primary keys of the records of the store whose source field equals the value
+    pub async fn find_keys(
+        &self,
+        store: &IdbObjectStore<'_>,
+        value: &JsValue,
+    ) -> Result<js_sys::Array, DomException> {
+        let digest = JsValue::from(self.digest(value)?);
+        store
+            .index(&self.index_name)?
+            .get_all_keys_with_key(&digest)?
+            .await
+    }
+}
+
+impl Middleware for BlindIndex {
+    fn on_write(&self, _: &MiddlewareContext, value: JsValue) -> Result<JsValue, DomException> {
+        self.prepare(&value)?;
+        Ok(value)
+    }
+}
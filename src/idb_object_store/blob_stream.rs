@@ -0,0 +1,37 @@
+use wasm_bindgen::JsCast;
+use web_sys::{Blob, DomException, ReadableStream};
+
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::dom_exception;
+
+use super::IdbObjectStore;
+
+impl IdbObjectStore<'_> {
+    /// Get the [Blob] stored at the given key. Fails with a `DataError` if the value there isn't
+    /// a blob.
+    ///
+    /// Features required: `blob-streams`
+    pub async fn get_blob<K: JsCast>(&self, key: &K) -> Result<Option<Blob>, DomException> {
+        match self.get(key)?.await? {
+            Some(value) => value
+                .dyn_into::<Blob>()
+                .map(Some)
+                .map_err(|_| dom_exception("The stored value is not a Blob", "DataError")),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the [Blob] stored at the given key as a [ReadableStream] of its bytes, e.g. to feed
+    /// large media to a `MediaSource` or a download without copying all of it into wasm memory.
+    /// Blobs read from IndexedDB stay readable after their transaction has finished, so the
+    /// stream can be consumed at any pace. Fails with a `DataError` if the value there isn't a
+    /// blob.
+    ///
+    /// Features required: `blob-streams`
+    pub async fn get_blob_stream<K: JsCast>(
+        &self,
+        key: &K,
+    ) -> Result<Option<ReadableStream>, DomException> {
+        Ok(self.get_blob(key).await?.map(|blob| blob.stream()))
+    }
+}
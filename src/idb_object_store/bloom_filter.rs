@@ -0,0 +1,240 @@
+use std::cell::RefCell;
+use std::f64::consts::LN_2;
+use std::rc::Rc;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::idb_database::IdbDatabase;
+use crate::idb_key::IdbKey;
+use crate::idb_query_source::IdbQuerySource;
+
+use super::{Middleware, MiddlewareContext};
+
+#[derive(Debug)]
+struct Bits {
+    bytes: Vec<u8>,
+    items: u32,
+    dirty: bool,
+}
+
+/// A bloom filter over a store's keys, held in memory and persisted in a metadata store with
+/// out-of-line keys, for cheap negative membership checks: if
+/// [might_contain][BloomFilter::might_contain] returns false the key is definitely absent,
+/// e.g. so that a sync can drop events it has already seen without a database lookup.
+///
+/// Keys get added as they're written through [insert][BloomFilter::insert] or by registering the
+/// filter as [Middleware] on a [TypedObjectStore][super::TypedObjectStore]. When the write
+/// transaction also covers the metadata store the middleware persists the filter with every
+/// write; otherwise call [save][BloomFilter::save] periodically. Bloom filters can't forget keys,
+/// so deletions only make false positives likelier until the next
+/// [rebuild][BloomFilter::rebuild]. Clones share the same filter.
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    meta_store: String,
+    store_name: String,
+    hashes: u32,
+    bits: Rc<RefCell<Bits>>,
+}
+
+impl BloomFilter {
+    /// An empty filter for the given store, sized for `expected_items` keys with the given false
+    /// positive rate, e.g. `0.01`
+    pub fn new(
+        meta_store: &str,
+        store_name: &str,
+        expected_items: u32,
+        false_positive_rate: f64,
+    ) -> Self {
+        let n = f64::from(expected_items.max(1));
+        let p = false_positive_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let bits = (-n * p.ln() / (LN_2 * LN_2)).ceil().max(8.0);
+        let hashes = (bits / n * LN_2).round().max(1.0) as u32;
+        Self {
+            meta_store: meta_store.into(),
+            store_name: store_name.into(),
+            hashes,
+            bits: Rc::new(RefCell::new(Bits {
+                bytes: vec![0; (bits / 8.0).ceil() as usize],
+                items: 0,
+                dirty: false,
+            })),
+        }
+    }
+
+    /// Whether the key might be in the store. False positives are possible, false negatives
+    /// aren't.
+    pub fn might_contain<K: IdbKey>(&self, key: &K) -> bool {
+        let bits = self.bits.borrow();
+        self.positions(&key.to_js_key(), bits.bytes.len())
+            .all(|bit| bits.bytes[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Add a key to the in-memory filter
+    pub fn insert<K: IdbKey>(&self, key: &K) {
+        let key = key.to_js_key();
+        let mut bits = self.bits.borrow_mut();
+        let len = bits.bytes.len();
+        for bit in self.positions(&key, len) {
+            bits.bytes[bit / 8] |= 1 << (bit % 8);
+        }
+        bits.items += 1;
+        bits.dirty = true;
+    }
+
+    /// The number of keys inserted since the filter was created or rebuilt
+    pub fn len(&self) -> u32 {
+        self.bits.borrow().items
+    }
+
+    /// Whether no keys have been inserted
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the filter holds inserts that haven't been persisted yet
+    pub fn is_dirty(&self) -> bool {
+        self.bits.borrow().dirty
+    }
+
+    /// Load the persisted filter. Resolves to false, leaving the in-memory filter as it is, if
+    /// none was persisted or it was persisted with a different size; [rebuild][BloomFilter::rebuild]
+    /// it in that case.
+    pub async fn load(&self, db: &IdbDatabase) -> Result<bool, DomException> {
+        let tx = db.transaction_on_one(&self.meta_store)?;
+        let record = tx
+            .object_store(&self.meta_store)?
+            .get_owned(self.meta_key())?
+            .await?;
+        let record = match record {
+            Some(record) => record,
+            None => return Ok(false),
+        };
+        let hashes = js_sys::Reflect::get(&record, &"hashes".into())?.as_f64();
+        let bytes = js_sys::Reflect::get(&record, &"bits".into())?
+            .dyn_into::<js_sys::Uint8Array>()
+            .map(|b| b.to_vec())
+            .unwrap_or_default();
+        let mut bits = self.bits.borrow_mut();
+        if hashes != Some(f64::from(self.hashes)) || bytes.len() != bits.bytes.len() {
+            return Ok(false);
+        }
+        bits.bytes = bytes;
+        bits.items = js_sys::Reflect::get(&record, &"items".into())?
+            .as_f64()
+            .unwrap_or(0.0) as u32;
+        bits.dirty = false;
+        Ok(true)
+    }
+
+    /// Persist the filter
+    pub async fn save(&self, db: &IdbDatabase) -> Result<(), DomException> {
+        let tx =
+            db.transaction_on_one_with_mode(&self.meta_store, IdbTransactionMode::Readwrite)?;
+        self.persist(tx.as_web_sys())?;
+        tx.await.into_result()?;
+        self.bits.borrow_mut().dirty = false;
+        Ok(())
+    }
+
+    /// Rebuild the filter from the store's current keys and persist it
+    ///
+    /// Features required: `cursors`
+    #[cfg(feature = "cursors")]
+    pub async fn rebuild(&self, db: &IdbDatabase) -> Result<(), DomException> {
+        {
+            let mut bits = self.bits.borrow_mut();
+            bits.bytes.iter_mut().for_each(|b| *b = 0);
+            bits.items = 0;
+        }
+        let tx = db.transaction_on_one(&self.store_name)?;
+        let store = tx.object_store(&self.store_name)?;
+        if let Some(cursor) = store.open_key_cursor()?.await? {
+            while let Some(key) = cursor.primary_key() {
+                self.insert(&key);
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+        self.save(db).await
+    }
+
+    fn persist(&self, tx: &web_sys::IdbTransaction) -> Result<(), DomException> {
+        let bits = self.bits.borrow();
+        let record = js_sys::Object::new();
+        let bytes = js_sys::Uint8Array::from(bits.bytes.as_slice());
+        js_sys::Reflect::set(&record, &"bits".into(), &bytes)?;
+        js_sys::Reflect::set(&record, &"hashes".into(), &self.hashes.into())?;
+        js_sys::Reflect::set(&record, &"items".into(), &bits.items.into())?;
+        tx.object_store(&self.meta_store)?
+            .put_with_key(&record, &JsValue::from_str(&self.meta_key()))?;
+        Ok(())
+    }
+
+    /// The filter's bit positions for the key, by double hashing
+    fn positions(&self, key: &JsValue, len: usize) -> impl Iterator<Item = usize> {
+        let bytes = key_bytes(key);
+        let h1 = fnv1a(&bytes, 0x811c_9dc5);
+        let h2 = fnv1a(&bytes, 0x0100_0193) | 1;
+        let bits = (len * 8) as u64;
+        (0..u64::from(self.hashes))
+            .map(move |i| ((u64::from(h1) + i * u64::from(h2)) % bits) as usize)
+    }
+
+    fn meta_key(&self) -> String {
+        format!("bloom-filter/{}", self.store_name)
+    }
+}
+
+impl Middleware for BloomFilter {
+    fn on_write(&self, ctx: &MiddlewareContext, value: JsValue) -> Result<JsValue, DomException> {
+        if ctx.store().name() != self.store_name {
+            return Ok(value);
+        }
+        if let Some(key) = ctx.key() {
+            self.insert(key);
+            if let Some(tx) = ctx.store().transaction() {
+                let tx = tx.as_web_sys();
+                let in_scope = tx.object_store_names().contains(&self.meta_store);
+                if in_scope && tx.mode()? == IdbTransactionMode::Readwrite {
+                    self.persist(tx)?;
+                    self.bits.borrow_mut().dirty = false;
+                }
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// The key's bytes to hash: binary keys as they are, anything else as JSON
+fn key_bytes(key: &JsValue) -> Vec<u8> {
+    let binary = if key.is_instance_of::<js_sys::ArrayBuffer>() {
+        Some(js_sys::Uint8Array::new(key))
+    } else if js_sys::ArrayBuffer::is_view(key) {
+        let view: &js_sys::Uint8Array = key.unchecked_ref();
+        Some(js_sys::Uint8Array::new_with_byte_offset_and_length(
+            &view.buffer(),
+            view.byte_offset(),
+            view.byte_length(),
+        ))
+    } else {
+        None
+    };
+    if let Some(binary) = binary {
+        let mut bytes = vec![b'#'];
+        bytes.extend(binary.to_vec());
+        return bytes;
+    }
+    js_sys::JSON::stringify(key)
+        .ok()
+        .and_then(|s| s.as_string())
+        .unwrap_or_default()
+        .into_bytes()
+}
+
+fn fnv1a(bytes: &[u8], seed: u32) -> u32 {
+    bytes.iter().fold(seed, |hash, byte| {
+        (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+    })
+}
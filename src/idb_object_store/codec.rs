@@ -0,0 +1,269 @@
+use std::future::Future;
+use std::ops::Deref;
+
+use serde::{de::DeserializeOwned, Serialize};
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+use crate::idb_key::IdbKey;
+use crate::request::VoidRequest;
+
+use super::serde_store::to_js;
+use super::{SerdeStoreError, TypedObjectStore};
+
+/// How records get turned into the JS values that are actually stored, selected per store with
+/// [TypedObjectStore::with_codec].
+///
+/// [SerdeWasmBindgen] stores records as plain JS objects, which key paths and indices can see
+/// into. Binary codecs store a `Uint8Array` instead: opaque to indices, but usually much smaller
+/// and far cheaper to structured-clone for large records.
+///
+/// Features required: `serde`
+pub trait Codec {
+    /// Encode the record into the value to store
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<JsValue, SerdeStoreError>;
+
+    /// Decode a stored value back into a record
+    fn decode<T: DeserializeOwned>(&self, value: JsValue) -> Result<T, SerdeStoreError>;
+
+    /// Decode a batch of stored values, e.g. from a `get_all`. Codecs can override this to share
+    /// work between records.
+    fn decode_all<T: DeserializeOwned>(
+        &self,
+        values: Vec<JsValue>,
+    ) -> Result<Vec<T>, SerdeStoreError> {
+        values.into_iter().map(|value| self.decode(value)).collect()
+    }
+}
+
+/// Stores records as plain JS objects via `serde_wasm_bindgen`, with the
+/// [serde encoding][crate::values#serde-encoding]. This is what
+/// [put_ser][TypedObjectStore::put_ser] and [get_de][TypedObjectStore::get_de] use.
+///
+/// Features required: `serde`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SerdeWasmBindgen;
+
+impl Codec for SerdeWasmBindgen {
+    #[inline]
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<JsValue, SerdeStoreError> {
+        Ok(to_js(value)?)
+    }
+
+    #[inline]
+    fn decode<T: DeserializeOwned>(&self, value: JsValue) -> Result<T, SerdeStoreError> {
+        Ok(crate::values::from_value(value)?)
+    }
+}
+
+/// Stores records as JSON strings
+///
+/// Features required: `serde_json`
+#[cfg(feature = "serde_json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonString;
+
+#[cfg(feature = "serde_json")]
+impl Codec for JsonString {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<JsValue, SerdeStoreError> {
+        let json =
+            serde_json::to_string(value).map_err(|e| SerdeStoreError::Codec(e.to_string()))?;
+        Ok(json.into())
+    }
+
+    fn decode<T: DeserializeOwned>(&self, value: JsValue) -> Result<T, SerdeStoreError> {
+        let json = value
+            .as_string()
+            .ok_or_else(|| SerdeStoreError::Codec("The stored value is not a string".into()))?;
+        serde_json::from_str(&json).map_err(|e| SerdeStoreError::Codec(e.to_string()))
+    }
+}
+
+/// A serde data format producing bytes, e.g. CBOR, MessagePack or bincode, for use with
+/// [Binary]. Wrapping a format crate takes a couple of lines:
+///
+/// ```rust
+/// use indexed_db_futures::idb_object_store::BinaryFormat;
+/// use serde::{de::DeserializeOwned, Serialize};
+///
+/// struct Json;
+///
+/// impl BinaryFormat for Json {
+///     fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, String> {
+///         serde_json::to_vec(value).map_err(|e| e.to_string())
+///     }
+///
+///     fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, String> {
+///         serde_json::from_slice(bytes).map_err(|e| e.to_string())
+///     }
+/// }
+/// ```
+///
+/// Features required: `serde`
+pub trait BinaryFormat {
+    /// Serialise the record
+    fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, String>;
+
+    /// Deserialise a record
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, String>;
+}
+
+/// Stores records as `Uint8Array`s in the given [BinaryFormat]
+///
+/// Reads copy each stored array's bytes straight into Rust memory in one go and deserialise them
+/// natively, without walking a JS object graph; [decode_all][Codec::decode_all] reuses a single
+/// buffer for the whole batch. Stored `ArrayBuffer`s are read as well.
+///
+/// Features required: `serde`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Binary<F>(pub F);
+
+impl<F: BinaryFormat> Codec for Binary<F> {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<JsValue, SerdeStoreError> {
+        let bytes = self.0.serialize(value).map_err(SerdeStoreError::Codec)?;
+        Ok(js_sys::Uint8Array::from(bytes.as_slice()).into())
+    }
+
+    fn decode<T: DeserializeOwned>(&self, value: JsValue) -> Result<T, SerdeStoreError> {
+        let mut bytes = Vec::new();
+        copy_bytes(&value, &mut bytes)?;
+        self.0.deserialize(&bytes).map_err(SerdeStoreError::Codec)
+    }
+
+    fn decode_all<T: DeserializeOwned>(
+        &self,
+        values: Vec<JsValue>,
+    ) -> Result<Vec<T>, SerdeStoreError> {
+        let mut buf = Vec::new();
+        values
+            .iter()
+            .map(|value| {
+                copy_bytes(value, &mut buf)?;
+                self.0.deserialize(&buf).map_err(SerdeStoreError::Codec)
+            })
+            .collect()
+    }
+}
+
+/// Copy a stored `Uint8Array` or `ArrayBuffer` into the buffer, replacing its contents
+fn copy_bytes(value: &JsValue, buf: &mut Vec<u8>) -> Result<(), SerdeStoreError> {
+    let array = if let Some(array) = value.dyn_ref::<js_sys::Uint8Array>() {
+        array.clone()
+    } else if value.is_instance_of::<js_sys::ArrayBuffer>() {
+        js_sys::Uint8Array::new(value)
+    } else {
+        return Err(SerdeStoreError::Codec(
+            "The stored value is not a Uint8Array".into(),
+        ));
+    };
+    buf.clear();
+    buf.resize(array.length() as usize, 0);
+    array.copy_to(buf);
+    Ok(())
+}
+
+/// A [TypedObjectStore] that encodes and decodes records with a [Codec], created by
+/// [TypedObjectStore::with_codec]. Derefs to the typed store for everything else.
+///
+/// Features required: `serde`
+#[derive(Debug)]
+pub struct CodecStore<'a, K: IdbKey, C> {
+    inner: TypedObjectStore<'a, K>,
+    codec: C,
+}
+
+impl<'a, K: IdbKey> TypedObjectStore<'a, K> {
+    /// Encode and decode records with the given codec
+    ///
+    /// Features required: `serde`
+    #[inline]
+    pub fn with_codec<C: Codec>(self, codec: C) -> CodecStore<'a, K, C> {
+        CodecStore { inner: self, codec }
+    }
+}
+
+impl<'a, K: IdbKey, C: Codec> CodecStore<'a, K, C> {
+    /// The store's codec
+    #[inline]
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    /// Unwrap the typed store
+    #[inline]
+    pub fn into_inner(self) -> TypedObjectStore<'a, K> {
+        self.inner
+    }
+
+    /// Encode the record and put it at the given key, overwriting any existing value
+    pub fn put<T: Serialize + ?Sized>(
+        &self,
+        key: &K,
+        value: &T,
+    ) -> Result<VoidRequest, SerdeStoreError> {
+        Ok(self.inner.put_key_val(key, &self.codec.encode(value)?)?)
+    }
+
+    /// Encode the record and add it at the given key. Throws if the key already exists.
+    pub fn add<T: Serialize + ?Sized>(
+        &self,
+        key: &K,
+        value: &T,
+    ) -> Result<VoidRequest, SerdeStoreError> {
+        Ok(self.inner.add_key_val(key, &self.codec.encode(value)?)?)
+    }
+
+    /// Get and decode the record at the given key
+    pub fn get<T: DeserializeOwned>(
+        &self,
+        key: &K,
+    ) -> Result<impl Future<Output = Result<Option<T>, SerdeStoreError>> + '_, DomException> {
+        let fut = self.inner.get(key)?;
+        Ok(async move {
+            match fut.await? {
+                Some(value) => Ok(Some(self.codec.decode(value)?)),
+                None => Ok(None),
+            }
+        })
+    }
+
+    /// Get and decode every record in the store
+    pub fn get_all<T: DeserializeOwned>(
+        &self,
+    ) -> Result<impl Future<Output = Result<Vec<T>, SerdeStoreError>> + '_, DomException> {
+        let fut = self.inner.get_all()?;
+        Ok(async move { self.codec.decode_all(fut.await?) })
+    }
+}
+
+impl<K: IdbKey, F: BinaryFormat> CodecStore<'_, K, Binary<F>> {
+    /// Get the encoded bytes of the record at the given key without decoding them
+    ///
+    /// Features required: `serde`
+    pub fn get_bytes(
+        &self,
+        key: &K,
+    ) -> Result<impl Future<Output = Result<Option<Vec<u8>>, SerdeStoreError>> + '_, DomException>
+    {
+        let fut = self.inner.get(key)?;
+        Ok(async move {
+            match fut.await? {
+                Some(value) => {
+                    let mut bytes = Vec::new();
+                    copy_bytes(&value, &mut bytes)?;
+                    Ok(Some(bytes))
+                }
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+impl<'a, K: IdbKey, C> Deref for CodecStore<'a, K, C> {
+    type Target = TypedObjectStore<'a, K>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
@@ -0,0 +1,472 @@
+use std::rc::Rc;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+#[cfg(feature = "cursors")]
+use web_sys::IdbTransactionMode;
+
+#[cfg(feature = "cursors")]
+use crate::idb_database::IdbDatabase;
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::dom_exception;
+#[cfg(feature = "cursors")]
+use crate::maintenance::{JobFuture, JobStep, MaintenanceJob, OpPriority};
+
+use super::projection::get_path;
+use super::{Middleware, MiddlewareContext};
+
+/// The field marking a value as an envelope, set to [ENVELOPE_FORMAT]
+const ENVELOPE_FIELD: &str = "__indexed_db_futures_envelope";
+const ENVELOPE_FORMAT: &str = "encrypted/v1";
+
+/// A symmetric cipher supplied by the app, e.g. a wrapper around an AES-GCM implementation. This
+/// crate doesn't ship any cryptography of its own.
+pub trait Cipher {
+    /// Encrypt the plaintext
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, DomException>;
+
+    /// Decrypt a ciphertext produced by [encrypt][Cipher::encrypt]
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, DomException>;
+}
+
+/// [Middleware] that encrypts values on write and decrypts them on read.
+///
+/// Values get serialised to JSON, so they must be JSON-representable, and encrypted with the
+/// current key into an envelope,
+/// `{__indexed_db_futures_envelope: "encrypted/v1", key: <key version>, data: <ciphertext bytes>}`.
+/// Every key is identified by a version number written into the envelope, so records encrypted
+/// with an older key stay readable as long as that key is still registered via
+/// [with_previous_key][Encryption::with_previous_key], e.g. while a [KeyRotation] is running.
+/// Values that aren't envelopes of exactly that shape are passed through on read.
+///
+/// With [fields][Encryption::fields] set, only the values at the given key paths get encrypted
+/// and the rest of the record stays in plaintext, so it can still be indexed and queried by its
+/// non-sensitive fields. Encrypting whole records would hide the key of stores with an in-line
+/// key path, so writes to such stores fail with a `DataError` unless fields are set.
+#[derive(Clone)]
+pub struct Encryption {
+    current: u32,
+    keys: Vec<(u32, Rc<dyn Cipher>)>,
+    fields: Option<Vec<Vec<String>>>,
+}
+
+impl std::fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Encryption")
+            .field("current", &self.current)
+            .field(
+                "versions",
+                &self.keys.iter().map(|(v, _)| *v).collect::<Vec<_>>(),
+            )
+            .field("fields", &self.fields)
+            .finish()
+    }
+}
+
+impl Encryption {
+    /// Encrypt with the given key, identified by the given version
+    pub fn new<C: Cipher + 'static>(version: u32, cipher: C) -> Self {
+        Self {
+            current: version,
+            keys: vec![(version, Rc::new(cipher))],
+            fields: None,
+        }
+    }
+
+    /// Only encrypt the values at the given dotted key paths, e.g. `["ssn", "card.number"]`,
+    /// instead of whole records. Paths missing from a record are left out, and paths used in
+    /// indices or the store's key path should not be listed.
+    pub fn fields(mut self, paths: &[&str]) -> Self {
+        self.fields = Some(
+            paths
+                .iter()
+                .map(|p| p.split('.').map(String::from).collect())
+                .collect(),
+        );
+        self
+    }
+
+    /// Keep decrypting values encrypted with an older key
+    pub fn with_previous_key<C: Cipher + 'static>(mut self, version: u32, cipher: C) -> Self {
+        if version != self.current {
+            self.keys.retain(|(v, _)| *v != version);
+            self.keys.push((version, Rc::new(cipher)));
+        }
+        self
+    }
+
+    /// The version of the key new values get encrypted with
+    #[inline]
+    pub fn current_version(&self) -> u32 {
+        self.current
+    }
+
+    /// The key version the value was encrypted with; `None` if it isn't an envelope
+    pub fn key_version(value: &JsValue) -> Option<u32> {
+        if !value.is_object() {
+            return None;
+        }
+        let get = |field: &str| js_sys::Reflect::get(value, &JsValue::from_str(field)).ok();
+        if get(ENVELOPE_FIELD)?.as_string()? != ENVELOPE_FORMAT
+            || !get("data")?.is_instance_of::<js_sys::Uint8Array>()
+        {
+            return None;
+        }
+        let version = get("key")?.as_f64()?;
+        if version.fract() != 0.0 || !(0.0..=f64::from(u32::MAX)).contains(&version) {
+            return None;
+        }
+        Some(version as u32)
+    }
+
+    /// Encrypt the value into an envelope with the current key
+    pub fn encrypt_value(&self, value: &JsValue) -> Result<JsValue, DomException> {
+        let json = js_sys::JSON::stringify(value)?
+            .as_string()
+            .ok_or_else(|| dom_exception("Value can't be serialised to JSON", "DataError"))?;
+        let ciphertext = self.cipher(self.current)?.encrypt(json.as_bytes())?;
+
+        let envelope = js_sys::Object::new();
+        js_sys::Reflect::set(
+            &envelope,
+            &JsValue::from_str(ENVELOPE_FIELD),
+            &JsValue::from_str(ENVELOPE_FORMAT),
+        )?;
+        js_sys::Reflect::set(&envelope, &"key".into(), &JsValue::from(self.current))?;
+        js_sys::Reflect::set(
+            &envelope,
+            &"data".into(),
+            &js_sys::Uint8Array::from(ciphertext.as_slice()),
+        )?;
+        Ok(envelope.into())
+    }
+
+    /// Decrypt an envelope with whichever registered key it was encrypted with. Values that aren't
+    /// envelopes are returned as they are.
+    pub fn decrypt_value(&self, value: &JsValue) -> Result<JsValue, DomException> {
+        let version = match Self::key_version(value) {
+            Some(version) => version,
+            None => return Ok(value.clone()),
+        };
+        let data = js_sys::Reflect::get(value, &"data".into())?
+            .dyn_into::<js_sys::Uint8Array>()
+            .map_err(|_| dom_exception("Malformed encryption envelope", "DataError"))?;
+        let plaintext = self.cipher(version)?.decrypt(&data.to_vec())?;
+        let json = std::str::from_utf8(&plaintext)
+            .map_err(|_| dom_exception("Decrypted value is not valid UTF-8", "DataError"))?;
+        Ok(js_sys::JSON::parse(json)?)
+    }
+
+    /// Encrypt a record about to be written: the whole record, or just the configured
+    /// [fields][Encryption::fields]. The given record is left unchanged.
+    pub fn encrypt_record(&self, record: &JsValue) -> Result<JsValue, DomException> {
+        match self.fields {
+            Some(ref fields) => map_fields(record, fields, |v| self.encrypt_value(v)),
+            None => self.encrypt_value(record),
+        }
+    }
+
+    /// Decrypt a record that has just been read, the inverse of
+    /// [encrypt_record][Encryption::encrypt_record]
+    pub fn decrypt_record(&self, record: &JsValue) -> Result<JsValue, DomException> {
+        match self.fields {
+            Some(ref fields) => map_fields(record, fields, |v| self.decrypt_value(v)),
+            None => self.decrypt_value(record),
+        }
+    }
+
+    /// Whether any part of the record is encrypted with a key other than the current one
+    pub fn needs_rotation(&self, record: &JsValue) -> bool {
+        let outdated = |v: &JsValue| matches!(Self::key_version(v), Some(v) if v != self.current);
+        match self.fields {
+            Some(ref fields) => fields
+                .iter()
+                .filter_map(|path| get_path(record, path))
+                .any(|v| outdated(&v)),
+            None => outdated(record),
+        }
+    }
+
+    fn cipher(&self, version: u32) -> Result<&dyn Cipher, DomException> {
+        self.keys
+            .iter()
+            .find(|(v, _)| *v == version)
+            .map(|(_, cipher)| cipher.as_ref())
+            .ok_or_else(|| {
+                dom_exception(
+                    &format!("No key registered for key version {}", version),
+                    "NotFoundError",
+                )
+            })
+    }
+}
+
+impl Middleware for Encryption {
+    fn on_write(&self, ctx: &MiddlewareContext, value: JsValue) -> Result<JsValue, DomException> {
+        if self.fields.is_none() && ctx.store().key_path().is_some() {
+            return Err(dom_exception(
+                "Encrypting whole records would hide the store's in-line keys; encrypt fields instead",
+                "DataError",
+            ));
+        }
+        self.encrypt_record(&value)
+    }
+
+    fn on_read(&self, _: &MiddlewareContext, value: JsValue) -> Result<JsValue, DomException> {
+        self.decrypt_record(&value)
+    }
+}
+
+/// How far a [KeyRotation] has got
+///
+/// Features required: `cursors`
+#[cfg(feature = "cursors")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RotationProgress {
+    /// Records visited so far
+    pub processed: u32,
+    /// Records re-encrypted with the current key so far
+    pub rewritten: u32,
+    /// The number of records in the store when the last batch ran
+    pub total: u32,
+    /// Whether every record is encrypted with the current key
+    pub done: bool,
+}
+
+/// Re-encrypts every record of a store that isn't encrypted with the current key yet, in
+/// resumable batches, checkpointing its progress in a metadata store with out-of-line keys.
+///
+/// The [Encryption] must still have the old key registered so that records can be read, both by
+/// the rotation and by the app while the rotation is running. Once it's [done][RotationProgress]
+/// the old key can be dropped. Records written by the app meanwhile get encrypted with the
+/// current key and are skipped.
+///
+/// Features required: `cursors`
+#[cfg(feature = "cursors")]
+#[derive(Debug, Clone)]
+pub struct KeyRotation {
+    meta_store: String,
+    store_name: String,
+    encryption: Encryption,
+    priority: OpPriority,
+}
+
+#[cfg(feature = "cursors")]
+impl KeyRotation {
+    /// Rotate the records of `store_name` to the encryption's current key
+    pub fn new(meta_store: &str, store_name: &str, encryption: Encryption) -> Self {
+        Self {
+            meta_store: meta_store.into(),
+            store_name: store_name.into(),
+            encryption,
+            priority: OpPriority::Interactive,
+        }
+    }
+
+    /// Set whether [run][KeyRotation::run]'s batches run right away or wait for the page to be
+    /// idle. Defaults to [OpPriority::Interactive].
+    pub fn priority(mut self, priority: OpPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// The progress of the rotation so far
+    pub async fn progress(&self, db: &IdbDatabase) -> Result<RotationProgress, DomException> {
+        let tx = db.transaction_on_multi(&[&self.store_name, &self.meta_store])?;
+        let meta = tx.object_store(&self.meta_store)?;
+        let record = meta.get_owned(self.meta_key())?.await?;
+        let (mut progress, _) = read_meta(record.as_ref());
+        progress.total = tx.object_store(&self.store_name)?.count()?.await?;
+        Ok(progress)
+    }
+
+    /// Visit up to `batch_size` records in one transaction, continuing from the last checkpoint
+    pub async fn run_batch(
+        &self,
+        db: &IdbDatabase,
+        batch_size: u32,
+    ) -> Result<RotationProgress, DomException> {
+        let tx = db.transaction_on_multi_with_mode(
+            &[&self.store_name, &self.meta_store],
+            IdbTransactionMode::Readwrite,
+        )?;
+        let meta = tx.object_store(&self.meta_store)?;
+        let record = meta.get_owned(self.meta_key())?.await?;
+        let (mut progress, checkpoint) = read_meta(record.as_ref());
+        let store = tx.object_store(&self.store_name)?;
+        progress.total = store.count()?.await?;
+        if progress.done {
+            return Ok(progress);
+        }
+
+        let cursor = match checkpoint {
+            Some(checkpoint) => {
+                let range = web_sys::IdbKeyRange::lower_bound_with_open(&checkpoint, true)?;
+                store.open_cursor_with_range(&range)?.await?
+            }
+            None => store.open_cursor()?.await?,
+        };
+
+        let mut done = true;
+        let mut last = None;
+        if let Some(cursor) = cursor {
+            let mut count = 0;
+            loop {
+                let value = cursor.value();
+                if self.encryption.needs_rotation(&value) {
+                    let plaintext = self.encryption.decrypt_record(&value)?;
+                    cursor
+                        .update(&self.encryption.encrypt_record(&plaintext)?)?
+                        .await?;
+                    progress.rewritten += 1;
+                }
+                progress.processed += 1;
+                last = cursor.primary_key();
+                count += 1;
+                if count >= batch_size {
+                    done = false;
+                    break;
+                }
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+        progress.done = done;
+
+        let record = js_sys::Object::new();
+        js_sys::Reflect::set(&record, &"processed".into(), &progress.processed.into())?;
+        js_sys::Reflect::set(&record, &"rewritten".into(), &progress.rewritten.into())?;
+        js_sys::Reflect::set(&record, &"done".into(), &JsValue::from(done))?;
+        if let Some(last) = last {
+            js_sys::Reflect::set(&record, &"checkpoint".into(), &last)?;
+        }
+        meta.put_key_val_owned(self.meta_key(), &record)?;
+        tx.await.into_result()?;
+
+        Ok(progress)
+    }
+
+    /// Run batches until every record is encrypted with the current key, reporting the progress
+    /// after each one
+    pub async fn run<F: FnMut(RotationProgress)>(
+        &self,
+        db: &IdbDatabase,
+        batch_size: u32,
+        mut on_progress: F,
+    ) -> Result<(), DomException> {
+        loop {
+            self.priority.ready().await?;
+            let progress = self.run_batch(db, batch_size.max(1)).await?;
+            on_progress(progress);
+            if progress.done {
+                return Ok(());
+            }
+        }
+    }
+
+    fn meta_key(&self) -> String {
+        format!(
+            "key-rotation/{}/{}",
+            self.store_name, self.encryption.current
+        )
+    }
+}
+
+/// Copy the record, replacing the values at the given paths with the results of `f`. Objects
+/// along each path get copied rather than modified.
+fn map_fields<F>(record: &JsValue, fields: &[Vec<String>], f: F) -> Result<JsValue, DomException>
+where
+    F: Fn(&JsValue) -> Result<JsValue, DomException>,
+{
+    let mut out = record.clone();
+    for path in fields {
+        if let Some(value) = get_path(&out, path) {
+            out = replace_at(&out, path, &f(&value)?)?;
+        }
+    }
+    Ok(out)
+}
+
+fn replace_at(value: &JsValue, path: &[String], new: &JsValue) -> Result<JsValue, DomException> {
+    let (first, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return Ok(new.clone()),
+    };
+    let key = JsValue::from_str(first);
+    let child = replace_at(&js_sys::Reflect::get(value, &key)?, rest, new)?;
+    let copy: JsValue = if js_sys::Array::is_array(value) {
+        js_sys::Array::from(value).into()
+    } else {
+        js_sys::Object::assign(&js_sys::Object::new(), value.unchecked_ref()).into()
+    };
+    js_sys::Reflect::set(&copy, &key, &child)?;
+    Ok(copy)
+}
+
+#[cfg(feature = "cursors")]
+fn read_meta(record: Option<&JsValue>) -> (RotationProgress, Option<JsValue>) {
+    let record = match record {
+        Some(record) => record,
+        None => return (RotationProgress::default(), None),
+    };
+    let get = |field: &str| js_sys::Reflect::get(record, &field.into()).ok();
+    let number = |field: &str| get(field).and_then(|v| v.as_f64()).unwrap_or(0.0) as u32;
+    let progress = RotationProgress {
+        processed: number("processed"),
+        rewritten: number("rewritten"),
+        total: 0,
+        done: get("done").and_then(|v| v.as_bool()).unwrap_or(false),
+    };
+    let checkpoint = get("checkpoint").filter(|c| !c.is_undefined());
+    (progress, checkpoint)
+}
+
+/// Runs one [batch][KeyRotation::run_batch] per chunk. The rotation keeps its own checkpoint,
+/// so once it's finished later passes are no-ops.
+#[cfg(feature = "cursors")]
+impl MaintenanceJob for KeyRotation {
+    fn name(&self) -> String {
+        self.meta_key()
+    }
+
+    fn run_chunk<'a>(
+        &'a self,
+        db: &'a IdbDatabase,
+        _checkpoint: Option<JsValue>,
+        chunk_size: u32,
+    ) -> JobFuture<'a> {
+        Box::pin(async move {
+            Ok(match self.run_batch(db, chunk_size.max(1)).await?.done {
+                true => JobStep::Done,
+                false => JobStep::Continue(None),
+            })
+        })
+    }
+}
+
+/// Rotate the records of a store from the old key to the new one, in resumable batches of
+/// `batch_size` records with progress reported after each batch. Keys are given as
+/// `(version, cipher)` pairs. See [KeyRotation].
+///
+/// Features required: `cursors`
+#[cfg(feature = "cursors")]
+pub async fn rotate_key<O, N, F>(
+    db: &IdbDatabase,
+    meta_store: &str,
+    store_name: &str,
+    old_key: (u32, O),
+    new_key: (u32, N),
+    batch_size: u32,
+    on_progress: F,
+) -> Result<(), DomException>
+where
+    O: Cipher + 'static,
+    N: Cipher + 'static,
+    F: FnMut(RotationProgress),
+{
+    let encryption = Encryption::new(new_key.0, new_key.1).with_previous_key(old_key.0, old_key.1);
+    KeyRotation::new(meta_store, store_name, encryption)
+        .run(db, batch_size, on_progress)
+        .await
+}
@@ -0,0 +1,224 @@
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{DomException, IdbIndexParameters, IdbTransactionMode};
+
+use crate::idb_database::IdbDatabase;
+use crate::idb_index::IdbIndex;
+use crate::idb_key_path::IdbKeyPath;
+use crate::idb_query_source::IdbQuerySource;
+use crate::maintenance::{JobFuture, JobStep, MaintenanceJob, OpPriority};
+
+use super::{IdbObjectStore, Middleware, MiddlewareContext};
+
+const SHADOW_FIELD: &str = "__backfill";
+
+/// An index that gets populated by a background task instead of during the upgrade that
+/// creates it.
+///
+/// The index is created on a shadow field, `__backfill.<index name>`, which existing records
+/// don't have yet, so creating it doesn't write any index entries. [run][IndexBackfill::run]
+/// then copies the source field into the shadow field in batches, checkpointing its progress in
+/// a metadata store with out-of-line keys, and [index_ready][IndexBackfill::index_ready] reports
+/// whether it has finished. New writes should copy the field themselves, either through
+/// [prepare][IndexBackfill::prepare] or by registering the backfill as [Middleware] on a
+/// [TypedObjectStore][super::TypedObjectStore].
+///
+/// Index names must not contain dots.
+///
+/// Features required: `indices`, `cursors`
+#[derive(Debug, Clone)]
+pub struct IndexBackfill {
+    meta_store: String,
+    store_name: String,
+    index_name: String,
+    source: IdbKeyPath,
+    priority: OpPriority,
+}
+
+impl IndexBackfill {
+    /// Describe a backfilled index on the given store, populated from the given dotted key path
+    pub fn new(meta_store: &str, store_name: &str, index_name: &str, source_path: &str) -> Self {
+        Self {
+            meta_store: meta_store.into(),
+            store_name: store_name.into(),
+            index_name: index_name.into(),
+            source: IdbKeyPath::str(source_path),
+            priority: OpPriority::Interactive,
+        }
+    }
+
+    /// Set whether [run][IndexBackfill::run]'s batches run right away or wait for the page to be
+    /// idle. Defaults to [OpPriority::Interactive].
+    pub fn priority(mut self, priority: OpPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// The key path the index is actually created on
+    pub fn shadow_path(&self) -> String {
+        format!("{}.{}", SHADOW_FIELD, self.index_name)
+    }
+
+    /// Create the index on the shadow field. Must be called during an upgrade, on the store given
+    /// to [new][IndexBackfill::new].
+    pub fn create_index<'a>(
+        &self,
+        store: &'a IdbObjectStore<'a>,
+        params: Option<&IdbIndexParameters>,
+    ) -> Result<IdbIndex<'a>, DomException> {
+        let key_path = IdbKeyPath::str(&self.shadow_path());
+        match params {
+            Some(params) => store.create_index_with_params(&self.index_name, &key_path, params),
+            None => store.create_index(&self.index_name, &key_path),
+        }
+    }
+
+    /// Copy the source field into the shadow field of a value that's about to be written.
+    /// Non-object values are left alone.
+    pub fn prepare(&self, value: &JsValue) -> Result<(), DomException> {
+        if !value.is_object() {
+            return Ok(());
+        }
+        let shadow = js_sys::Reflect::get(value, &JsValue::from_str(SHADOW_FIELD))?;
+        match self.source.evaluate(value) {
+            Some(key) => {
+                let shadow = if shadow.is_object() {
+                    shadow
+                } else {
+                    let obj: JsValue = js_sys::Object::new().into();
+                    js_sys::Reflect::set(value, &JsValue::from_str(SHADOW_FIELD), &obj)?;
+                    obj
+                };
+                js_sys::Reflect::set(&shadow, &JsValue::from_str(&self.index_name), &key)?;
+            }
+            None if shadow.is_object() => {
+                js_sys::Reflect::delete_property(
+                    shadow.unchecked_ref(),
+                    &JsValue::from_str(&self.index_name),
+                )?;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Whether the backfill has finished
+    pub async fn index_ready(&self, db: &IdbDatabase) -> Result<bool, DomException> {
+        let tx = db.transaction_on_one(&self.meta_store)?;
+        let meta = tx.object_store(&self.meta_store)?;
+        let record = meta.get_owned(self.meta_key())?.await?;
+        Ok(record.map(|r| read_meta(&r).0).unwrap_or(false))
+    }
+
+    /// Backfill up to `batch_size` records in one transaction, continuing from the last
+    /// checkpoint. Resolves to whether the backfill has finished.
+    pub async fn run_batch(&self, db: &IdbDatabase, batch_size: u32) -> Result<bool, DomException> {
+        let tx = db.transaction_on_multi_with_mode(
+            &[&self.store_name, &self.meta_store],
+            IdbTransactionMode::Readwrite,
+        )?;
+        let meta = tx.object_store(&self.meta_store)?;
+        let (ready, checkpoint) = match meta.get_owned(self.meta_key())?.await? {
+            Some(record) => read_meta(&record),
+            None => (false, None),
+        };
+        if ready {
+            return Ok(true);
+        }
+
+        let store = tx.object_store(&self.store_name)?;
+        let cursor = match checkpoint {
+            Some(checkpoint) => {
+                let range = web_sys::IdbKeyRange::lower_bound_with_open(&checkpoint, true)?;
+                store.open_cursor_with_range(&range)?.await?
+            }
+            None => store.open_cursor()?.await?,
+        };
+
+        let mut done = true;
+        let mut last = None;
+        if let Some(cursor) = cursor {
+            let mut count = 0;
+            loop {
+                let value = cursor.value();
+                self.prepare(&value)?;
+                cursor.update(&value)?.await?;
+                last = cursor.primary_key();
+                count += 1;
+                if count >= batch_size {
+                    done = false;
+                    break;
+                }
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+
+        let record = js_sys::Object::new();
+        js_sys::Reflect::set(&record, &"ready".into(), &JsValue::from(done))?;
+        if let Some(last) = last {
+            js_sys::Reflect::set(&record, &"checkpoint".into(), &last)?;
+        }
+        meta.put_key_val_owned(self.meta_key(), &record)?;
+        tx.await.into_result()?;
+
+        Ok(done)
+    }
+
+    /// Run batches until the backfill has finished, e.g. from
+    /// [spawn_local](https://docs.rs/wasm-bindgen-futures/latest/wasm_bindgen_futures/fn.spawn_local.html)
+    pub async fn run(&self, db: &IdbDatabase, batch_size: u32) -> Result<(), DomException> {
+        loop {
+            self.priority.ready().await?;
+            if self.run_batch(db, batch_size.max(1)).await? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn meta_key(&self) -> String {
+        format!("index-backfill/{}/{}", self.store_name, self.index_name)
+    }
+}
+
+impl Middleware for IndexBackfill {
+    fn on_write(&self, ctx: &MiddlewareContext, value: JsValue) -> Result<JsValue, DomException> {
+        if ctx.store().name() == self.store_name {
+            self.prepare(&value)?;
+        }
+        Ok(value)
+    }
+}
+
+/// Runs one [batch][IndexBackfill::run_batch] per chunk. The backfill keeps its own checkpoint,
+/// so once it's finished later passes are no-ops.
+impl MaintenanceJob for IndexBackfill {
+    fn name(&self) -> String {
+        self.meta_key()
+    }
+
+    fn run_chunk<'a>(
+        &'a self,
+        db: &'a IdbDatabase,
+        _checkpoint: Option<JsValue>,
+        chunk_size: u32,
+    ) -> JobFuture<'a> {
+        Box::pin(async move {
+            Ok(match self.run_batch(db, chunk_size.max(1)).await? {
+                true => JobStep::Done,
+                false => JobStep::Continue(None),
+            })
+        })
+    }
+}
+
+fn read_meta(record: &JsValue) -> (bool, Option<JsValue>) {
+    let ready = js_sys::Reflect::get(record, &"ready".into())
+        .ok()
+        .and_then(|r| r.as_bool())
+        .unwrap_or(false);
+    let checkpoint = js_sys::Reflect::get(record, &"checkpoint".into())
+        .ok()
+        .filter(|c| !c.is_undefined());
+    (ready, checkpoint)
+}
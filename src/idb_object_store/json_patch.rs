@@ -0,0 +1,273 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::dom_exception;
+
+use super::{to_js, IdbObjectStore};
+
+/// A [JSON Patch](https://www.rfc-editor.org/rfc/rfc6902) operation. Paths are JSON pointers.
+/// Serialises to and from the RFC's JSON representation, e.g. `{"op":"add","path":"/a","value":1}`.
+///
+/// Features required: `serde_json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    /// Add the value at the path, inserting into arrays; `-` appends
+    Add {
+        /// Target location
+        path: String,
+        /// Value to add
+        value: Value,
+    },
+    /// Remove the value at the path
+    Remove {
+        /// Target location
+        path: String,
+    },
+    /// Replace the existing value at the path
+    Replace {
+        /// Target location
+        path: String,
+        /// Replacement value
+        value: Value,
+    },
+    /// Remove the value at `from` and add it at `path`
+    Move {
+        /// Source location
+        from: String,
+        /// Target location
+        path: String,
+    },
+    /// Copy the value at `from` to `path`
+    Copy {
+        /// Source location
+        from: String,
+        /// Target location
+        path: String,
+    },
+    /// Check that the value at the path equals the given value
+    Test {
+        /// Target location
+        path: String,
+        /// Expected value
+        value: Value,
+    },
+}
+
+/// Error returned when applying a [JSON Patch][PatchOp]
+///
+/// Features required: `serde_json`
+#[derive(Debug)]
+pub enum PatchError {
+    /// The operation at the given index failed, e.g. because a path didn't exist or a test
+    /// didn't match. Nothing was changed.
+    Failed {
+        /// Index of the operation in the patch
+        index: usize,
+        /// What went wrong
+        message: String,
+    },
+    /// The record couldn't be converted to or from JSON
+    Serde(serde_wasm_bindgen::Error),
+    /// The underlying operation failed
+    Dom(DomException),
+}
+
+impl From<DomException> for PatchError {
+    #[inline]
+    fn from(e: DomException) -> Self {
+        Self::Dom(e)
+    }
+}
+
+impl From<serde_wasm_bindgen::Error> for PatchError {
+    #[inline]
+    fn from(e: serde_wasm_bindgen::Error) -> Self {
+        Self::Serde(e)
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Failed { index, message } => {
+                write!(f, "Patch operation {} failed: {}", index, message)
+            }
+            Self::Serde(e) => fmt::Display::fmt(e, f),
+            Self::Dom(e) => write!(f, "{}: {}", e.name(), e.message()),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+impl IdbObjectStore<'_> {
+    /// Fetch the record at the given key, apply the [JSON Patch][PatchOp] to it and write it back.
+    /// If any operation fails, nothing gets written. Fails with a `NotFoundError` if there's no
+    /// record at the key. Resolves to the patched record.
+    ///
+    /// Requires a [readwrite][web_sys::IdbTransactionMode::Readwrite] transaction; the read and
+    /// the write happen in it back to back.
+    ///
+    /// Features required: `serde_json`
+    pub async fn apply_patch<K: JsCast>(
+        &self,
+        key: &K,
+        patch: &[PatchOp],
+    ) -> Result<JsValue, PatchError> {
+        let key: &JsValue = key.unchecked_ref();
+        let record = match self.get(key)?.await? {
+            Some(record) => record,
+            None => {
+                return Err(dom_exception(
+                    "There is no record to patch at the given key",
+                    "NotFoundError",
+                )
+                .into())
+            }
+        };
+
+        let mut doc: Value = serde_wasm_bindgen::from_value(record)?;
+        apply_json_patch(&mut doc, patch)?;
+        let patched = to_js(&doc)?;
+
+        let req = match self.key_path() {
+            Some(_) => self.put_val(&patched)?,
+            None => self.put_key_val(key, &patched)?,
+        };
+        req.into_future().await?;
+
+        Ok(patched)
+    }
+}
+
+/// Apply a [JSON Patch][PatchOp] to a JSON document. The document is left unchanged if any
+/// operation fails.
+///
+/// Features required: `serde_json`
+pub fn apply_json_patch(doc: &mut Value, patch: &[PatchOp]) -> Result<(), PatchError> {
+    let mut patched = doc.clone();
+    for (index, op) in patch.iter().enumerate() {
+        apply_op(&mut patched, op).map_err(|message| PatchError::Failed { index, message })?;
+    }
+    *doc = patched;
+    Ok(())
+}
+
+fn apply_op(doc: &mut Value, op: &PatchOp) -> Result<(), String> {
+    match op {
+        PatchOp::Add { path, value } => add(doc, &parse_pointer(path)?, value.clone()),
+        PatchOp::Remove { path } => remove(doc, &parse_pointer(path)?).map(drop),
+        PatchOp::Replace { path, value } => {
+            let tokens = parse_pointer(path)?;
+            remove(doc, &tokens)?;
+            add(doc, &tokens, value.clone())
+        }
+        PatchOp::Move { from, path } => {
+            if path.starts_with(&format!("{}/", from)) {
+                return Err(format!("Cannot move {} into its own child {}", from, path));
+            }
+            let value = remove(doc, &parse_pointer(from)?)?;
+            add(doc, &parse_pointer(path)?, value)
+        }
+        PatchOp::Copy { from, path } => {
+            let value = navigate(doc, &parse_pointer(from)?)?.clone();
+            add(doc, &parse_pointer(path)?, value)
+        }
+        PatchOp::Test { path, value } => {
+            if navigate(doc, &parse_pointer(path)?)? == value {
+                Ok(())
+            } else {
+                Err(format!("Test failed at {}", path))
+            }
+        }
+    }
+}
+
+/// Split a JSON pointer into unescaped reference tokens
+fn parse_pointer(pointer: &str) -> Result<Vec<String>, String> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !pointer.starts_with('/') {
+        return Err(format!("Invalid JSON pointer {}", pointer));
+    }
+    Ok(pointer[1..]
+        .split('/')
+        .map(|t| t.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+fn array_index(token: &str, len: usize) -> Result<usize, String> {
+    match token.parse::<usize>() {
+        Ok(idx) if idx <= len && (token == "0" || !token.starts_with('0')) => Ok(idx),
+        _ => Err(format!("Invalid array index {}", token)),
+    }
+}
+
+fn navigate<'v>(doc: &'v mut Value, tokens: &[String]) -> Result<&'v mut Value, String> {
+    let mut current = doc;
+    for token in tokens {
+        current = match current {
+            Value::Object(map) => map.get_mut(token),
+            Value::Array(arr) => {
+                let idx = array_index(token, arr.len())?;
+                arr.get_mut(idx)
+            }
+            _ => None,
+        }
+        .ok_or_else(|| format!("Path segment {} does not exist", token))?;
+    }
+    Ok(current)
+}
+
+fn add(doc: &mut Value, tokens: &[String], value: Value) -> Result<(), String> {
+    let (last, parent) = match tokens.split_last() {
+        Some(split) => split,
+        None => {
+            *doc = value;
+            return Ok(());
+        }
+    };
+    match navigate(doc, parent)? {
+        Value::Object(map) => {
+            map.insert(last.clone(), value);
+            Ok(())
+        }
+        Value::Array(arr) => {
+            let idx = if last == "-" {
+                arr.len()
+            } else {
+                array_index(last, arr.len())?
+            };
+            arr.insert(idx, value);
+            Ok(())
+        }
+        _ => Err(format!("Cannot add {} to a non-container", last)),
+    }
+}
+
+fn remove(doc: &mut Value, tokens: &[String]) -> Result<Value, String> {
+    let (last, parent) = match tokens.split_last() {
+        Some(split) => split,
+        None => return Ok(std::mem::take(doc)),
+    };
+    let missing = || format!("Path segment {} does not exist", last);
+    match navigate(doc, parent)? {
+        Value::Object(map) => map.remove(last).ok_or_else(missing),
+        Value::Array(arr) => {
+            let idx = array_index(last, arr.len())?;
+            if idx < arr.len() {
+                Ok(arr.remove(idx))
+            } else {
+                Err(missing())
+            }
+        }
+        _ => Err(missing()),
+    }
+}
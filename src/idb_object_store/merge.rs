@@ -0,0 +1,112 @@
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::dom_exception;
+
+use super::IdbObjectStore;
+
+/// How [IdbObjectStore::merge_with] combines the partial object with the stored record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeMode {
+    /// Copy the partial object's top-level properties over the record's, like `Object.assign`
+    Shallow,
+    /// Merge plain objects recursively following
+    /// [JSON Merge Patch](https://www.rfc-editor.org/rfc/rfc7386) semantics: `null` properties
+    /// remove the property from the record and any other non-object value, including arrays,
+    /// replaces it.
+    Deep,
+}
+
+impl IdbObjectStore<'_> {
+    /// [Shallow][MergeMode::Shallow]-merge the partial object into the record at the given key
+    #[inline]
+    pub async fn merge<K: JsCast, V: JsCast>(
+        &self,
+        key: &K,
+        partial: &V,
+    ) -> Result<JsValue, DomException> {
+        self.merge_with(key, partial, MergeMode::Shallow).await
+    }
+
+    /// Fetch the record at the given key, merge the partial object into it and write it back.
+    /// Fails with a `NotFoundError` if there's no record at the key. Resolves to the merged
+    /// record.
+    ///
+    /// Requires a [readwrite][web_sys::IdbTransactionMode::Readwrite] transaction; the read and
+    /// the write happen in it back to back.
+    pub async fn merge_with<K: JsCast, V: JsCast>(
+        &self,
+        key: &K,
+        partial: &V,
+        mode: MergeMode,
+    ) -> Result<JsValue, DomException> {
+        let key: &JsValue = key.unchecked_ref();
+        let record = match self.get(key)?.await? {
+            Some(record) => record,
+            None => {
+                return Err(dom_exception(
+                    "There is no record to merge into at the given key",
+                    "NotFoundError",
+                ))
+            }
+        };
+
+        let merged = merge_values(record, partial.unchecked_ref(), mode)?;
+        let req = match self.key_path() {
+            Some(_) => self.put_val(&merged)?,
+            None => self.put_key_val(key, &merged)?,
+        };
+        req.into_future().await?;
+
+        Ok(merged)
+    }
+}
+
+/// Merge `partial` into `target`, returning the result. Non-object targets get replaced.
+pub(crate) fn merge_values(
+    target: JsValue,
+    partial: &JsValue,
+    mode: MergeMode,
+) -> Result<JsValue, DomException> {
+    if !is_plain_object(partial) {
+        return Ok(partial.clone());
+    }
+    let target = if is_plain_object(&target) {
+        target
+    } else {
+        js_sys::Object::new().into()
+    };
+
+    match mode {
+        MergeMode::Shallow => {
+            js_sys::Object::assign(target.unchecked_ref(), partial.unchecked_ref());
+        }
+        MergeMode::Deep => {
+            for entry in js_sys::Object::entries(partial.unchecked_ref()).iter() {
+                let entry: js_sys::Array = entry.unchecked_into();
+                let (prop, value) = (entry.get(0), entry.get(1));
+                if value.is_null() {
+                    js_sys::Reflect::delete_property(target.unchecked_ref(), &prop)?;
+                } else {
+                    let current = js_sys::Reflect::get(&target, &prop)?;
+                    let merged = merge_values(current, &value, mode)?;
+                    js_sys::Reflect::set(&target, &prop, &merged)?;
+                }
+            }
+        }
+    }
+
+    Ok(target)
+}
+
+/// Whether the value is an object literal rather than an array, date, binary data, etc.
+fn is_plain_object(value: &JsValue) -> bool {
+    match value.dyn_ref::<js_sys::Object>() {
+        Some(obj) if !js_sys::Array::is_array(value) => {
+            let proto = js_sys::Object::get_prototype_of(obj);
+            proto.is_null() || proto == js_sys::Object::get_prototype_of(&js_sys::Object::new())
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,193 @@
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use web_sys::DomException;
+
+use super::IdbObjectStore;
+
+/// A hook that can observe and modify values as they're written to and read from a
+/// [TypedObjectStore][super::TypedObjectStore], used to implement concerns such as timestamps,
+/// compression or validation uniformly across stores. Registered via
+/// [TypedObjectStore::with_middleware][super::TypedObjectStore::with_middleware].
+///
+/// Write hooks run in registration order and read hooks run in reverse registration order, so
+/// each middleware sees values the way it produced them, e.g. registering compression and then
+/// encryption encrypts compressed values on write and decrypts before decompressing on read.
+pub trait Middleware {
+    /// Called with the value about to be written. Returning an error prevents the write.
+    #[inline]
+    fn on_write(&self, _ctx: &MiddlewareContext, value: JsValue) -> Result<JsValue, DomException> {
+        Ok(value)
+    }
+
+    /// Called with a value that has just been read
+    #[inline]
+    fn on_read(&self, _ctx: &MiddlewareContext, value: JsValue) -> Result<JsValue, DomException> {
+        Ok(value)
+    }
+
+    /// Called before the record at the context's key gets deleted. Returning an error prevents
+    /// the delete.
+    #[inline]
+    fn on_delete(&self, _ctx: &MiddlewareContext) -> Result<(), DomException> {
+        Ok(())
+    }
+
+    /// Called with the request of a write or delete right after it's been made, once every
+    /// middleware's [on_write][Middleware::on_write] or [on_delete][Middleware::on_delete] hook
+    /// has let it through. Returning an error fails the call that made the write, but doesn't stop
+    /// the write itself; abort the transaction for that.
+    #[inline]
+    fn after_write(
+        &self,
+        _ctx: &MiddlewareContext,
+        _request: &web_sys::IdbRequest,
+    ) -> Result<(), DomException> {
+        Ok(())
+    }
+}
+
+impl<M: Middleware + ?Sized> Middleware for Rc<M> {
+    #[inline]
+    fn on_write(&self, ctx: &MiddlewareContext, value: JsValue) -> Result<JsValue, DomException> {
+        (**self).on_write(ctx, value)
+    }
+
+    #[inline]
+    fn on_read(&self, ctx: &MiddlewareContext, value: JsValue) -> Result<JsValue, DomException> {
+        (**self).on_read(ctx, value)
+    }
+
+    #[inline]
+    fn on_delete(&self, ctx: &MiddlewareContext) -> Result<(), DomException> {
+        (**self).on_delete(ctx)
+    }
+
+    #[inline]
+    fn after_write(
+        &self,
+        ctx: &MiddlewareContext,
+        request: &web_sys::IdbRequest,
+    ) -> Result<(), DomException> {
+        (**self).after_write(ctx, request)
+    }
+}
+
+/// The kind of write a [Middleware] hook is being called for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WriteOp {
+    /// Adding a record that mustn't exist yet
+    Add,
+    /// Putting a record, overwriting any existing one
+    Put,
+    /// Deleting a record
+    Delete,
+}
+
+impl WriteOp {
+    /// The operation's name, e.g. `"put"`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Add => "add",
+            Self::Put => "put",
+            Self::Delete => "delete",
+        }
+    }
+}
+
+/// What a [Middleware] hook is being called for
+#[derive(Debug)]
+pub struct MiddlewareContext<'c> {
+    store: &'c IdbObjectStore<'c>,
+    key: Option<&'c JsValue>,
+    op: Option<WriteOp>,
+}
+
+impl<'c> MiddlewareContext<'c> {
+    #[inline]
+    pub(crate) fn new(store: &'c IdbObjectStore<'c>, key: Option<&'c JsValue>) -> Self {
+        Self {
+            store,
+            key,
+            op: None,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn with_op(mut self, op: WriteOp) -> Self {
+        self.op = Some(op);
+        self
+    }
+
+    /// The store the value is being written to or read from. Writes made to it happen in the
+    /// same transaction as the operation that triggered the hook.
+    #[inline]
+    pub fn store(&self) -> &'c IdbObjectStore<'c> {
+        self.store
+    }
+
+    /// The record's primary key. `None` when it isn't known ahead of time, e.g. for in-line or
+    /// generated keys, or for bulk reads.
+    #[inline]
+    pub fn key(&self) -> Option<&'c JsValue> {
+        self.key
+    }
+
+    /// The kind of write being made. `None` for reads.
+    #[inline]
+    pub fn op(&self) -> Option<WriteOp> {
+        self.op
+    }
+}
+
+/// An ordered list of middleware
+#[derive(Clone, Default)]
+pub(crate) struct MiddlewarePipeline(Vec<Rc<dyn Middleware>>);
+
+impl MiddlewarePipeline {
+    #[inline]
+    pub fn push(&mut self, middleware: Rc<dyn Middleware>) {
+        self.0.push(middleware);
+    }
+
+    pub fn on_write(
+        &self,
+        ctx: &MiddlewareContext,
+        value: JsValue,
+    ) -> Result<JsValue, DomException> {
+        self.0
+            .iter()
+            .try_fold(value, move |value, m| m.on_write(ctx, value))
+    }
+
+    pub fn on_read(
+        &self,
+        ctx: &MiddlewareContext,
+        value: JsValue,
+    ) -> Result<JsValue, DomException> {
+        self.0
+            .iter()
+            .rev()
+            .try_fold(value, move |value, m| m.on_read(ctx, value))
+    }
+
+    pub fn on_delete(&self, ctx: &MiddlewareContext) -> Result<(), DomException> {
+        self.0.iter().try_for_each(move |m| m.on_delete(ctx))
+    }
+
+    pub fn after_write(
+        &self,
+        ctx: &MiddlewareContext,
+        request: &web_sys::IdbRequest,
+    ) -> Result<(), DomException> {
+        self.0
+            .iter()
+            .try_for_each(move |m| m.after_write(ctx, request))
+    }
+}
+
+impl std::fmt::Debug for MiddlewarePipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MiddlewarePipeline({} layers)", self.0.len())
+    }
+}
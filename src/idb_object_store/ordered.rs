@@ -0,0 +1,103 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+use crate::internal_utils::{dom_exception, ClosureKind, ClosureToken};
+use crate::request::OptionalJsValueFuture;
+
+use super::IdbObjectStore;
+
+/// An operation for [IdbObjectStore::apply_ordered]
+#[derive(Debug, Clone)]
+pub enum OrderedOp {
+    /// Add the value, at the key if the store uses out-of-line keys. Results in the record's key.
+    Add {
+        /// The key for stores with out-of-line keys
+        key: Option<JsValue>,
+        /// The value to add
+        value: JsValue,
+    },
+    /// Put the value, at the key if the store uses out-of-line keys. Results in the record's key.
+    Put {
+        /// The key for stores with out-of-line keys
+        key: Option<JsValue>,
+        /// The value to put
+        value: JsValue,
+    },
+    /// Delete the record at the key or key range. Results in `undefined`.
+    Delete(JsValue),
+    /// Get the record at the key or the first one in the key range. Results in the value, or
+    /// `undefined` if there's none.
+    Get(JsValue),
+}
+
+impl IdbObjectStore<'_> {
+    /// Issue the operations in order on the store's transaction and resolve to their results in
+    /// the same positions.
+    ///
+    /// IndexedDB executes the requests made on a transaction in the order they were made, so each
+    /// operation sees the effects of the ones before it, e.g. a parent gets written before its
+    /// children and a `Get` after a `Put` sees the new value. The completion order gets checked
+    /// regardless, failing with an `InvalidStateError` should a browser ever violate it.
+    ///
+    /// A failed operation's error gets reported at its position. Unless
+    /// [request errors are ignored][crate::idb_transaction::IdbTransaction::set_ignore_request_errors],
+    /// it also aborts the transaction, failing every operation after it.
+    pub async fn apply_ordered(
+        &self,
+        ops: &[OrderedOp],
+    ) -> Result<Vec<Result<JsValue, DomException>>, DomException> {
+        let completed = Rc::new(RefCell::new(Vec::with_capacity(ops.len())));
+        let mut listeners = Vec::with_capacity(ops.len());
+        let mut futures = Vec::with_capacity(ops.len());
+
+        for (idx, op) in ops.iter().enumerate() {
+            let req = match op {
+                OrderedOp::Add {
+                    key: Some(key),
+                    value,
+                } => self.write(|s| s.add_with_key(value, key))?,
+                OrderedOp::Add { key: None, value } => self.write(|s| s.add(value))?,
+                OrderedOp::Put {
+                    key: Some(key),
+                    value,
+                } => self.write(|s| s.put_with_key(value, key))?,
+                OrderedOp::Put { key: None, value } => self.write(|s| s.put(value))?,
+                OrderedOp::Delete(key) => self.write(|s| s.delete(key))?,
+                OrderedOp::Get(key) => {
+                    self.record_request();
+                    self.inner.get(key)?
+                }
+            };
+
+            let completed = completed.clone();
+            let token = ClosureToken::new(ClosureKind::Request);
+            let listener = Closure::wrap(Box::new(move || {
+                token.hold();
+                completed.borrow_mut().push(idx);
+            }) as Box<dyn Fn()>);
+            for event in &["success", "error"] {
+                req.add_event_listener_with_callback(event, listener.as_ref().unchecked_ref())?;
+            }
+            listeners.push(listener);
+            futures.push(OptionalJsValueFuture::new(Ok(req))?);
+        }
+
+        let mut results = Vec::with_capacity(futures.len());
+        for fut in futures {
+            results.push(fut.await.map(|v| v.unwrap_or(JsValue::UNDEFINED)));
+        }
+        drop(listeners);
+
+        if completed.borrow().iter().copied().ne(0..ops.len()) {
+            return Err(dom_exception(
+                "Requests on the transaction completed out of order",
+                "InvalidStateError",
+            ));
+        }
+
+        Ok(results)
+    }
+}
@@ -0,0 +1,103 @@
+use std::future::Future;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+use crate::idb_query_source::IdbQuerySource;
+
+use super::IdbObjectStore;
+
+/// A set of dotted key paths, e.g. `["a", "b.c"]`, to extract from records so that only the
+/// listed fields of wide records get deserialised. Projected values are plain objects containing
+/// just the listed paths, nested as in the original; paths missing from a record are left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Projection {
+    paths: Vec<Vec<String>>,
+}
+
+impl Projection {
+    /// Project the given dotted key paths
+    pub fn new(paths: &[&str]) -> Self {
+        Self {
+            paths: paths
+                .iter()
+                .map(|p| p.split('.').map(String::from).collect())
+                .collect(),
+        }
+    }
+
+    /// Extract the projected fields from the value. Non-object values get projected to an empty
+    /// object.
+    pub fn apply(&self, value: &JsValue) -> JsValue {
+        let out = js_sys::Object::new();
+        for path in self.paths.iter() {
+            if let Some(field) = get_path(value, path) {
+                set_path(&out, path, &field);
+            }
+        }
+        out.into()
+    }
+}
+
+impl IdbObjectStore<'_> {
+    /// Get only the given dotted key paths of the record at the given key. See [Projection].
+    pub fn get_fields<K: JsCast>(
+        &self,
+        key: &K,
+        fields: &[&str],
+    ) -> Result<impl Future<Output = Result<Option<JsValue>, DomException>>, DomException> {
+        let fut = self.get(key)?;
+        let projection = Projection::new(fields);
+        Ok(async move { Ok(fut.await?.map(|value| projection.apply(&value))) })
+    }
+
+    /// Get all the records in the store, projected to the given fields
+    pub fn get_all_projected(
+        &self,
+        projection: &Projection,
+    ) -> Result<impl Future<Output = Result<Vec<JsValue>, DomException>>, DomException> {
+        let fut = self.get_all()?;
+        let projection = projection.clone();
+        Ok(async move {
+            let values = fut.await?;
+            Ok(values
+                .iter()
+                .map(|value| projection.apply(&value))
+                .collect())
+        })
+    }
+}
+
+pub(super) fn get_path(value: &JsValue, path: &[String]) -> Option<JsValue> {
+    let mut current = value.clone();
+    for segment in path {
+        if !current.is_object() {
+            return None;
+        }
+        current = js_sys::Reflect::get(&current, &JsValue::from_str(segment)).ok()?;
+        if current.is_undefined() {
+            return None;
+        }
+    }
+    Some(current)
+}
+
+fn set_path(target: &js_sys::Object, path: &[String], value: &JsValue) {
+    let (last, parents) = match path.split_last() {
+        Some(split) => split,
+        None => return,
+    };
+    let mut current: JsValue = target.clone().into();
+    for segment in parents {
+        let segment = JsValue::from_str(segment);
+        let next = js_sys::Reflect::get(&current, &segment).unwrap_or(JsValue::UNDEFINED);
+        current = if next.is_object() {
+            next
+        } else {
+            let obj: JsValue = js_sys::Object::new().into();
+            let _ = js_sys::Reflect::set(&current, &segment, &obj);
+            obj
+        };
+    }
+    let _ = js_sys::Reflect::set(&current, &JsValue::from_str(last), value);
+}
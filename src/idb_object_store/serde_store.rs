@@ -0,0 +1,213 @@
+use std::fmt;
+use std::future::Future;
+
+use serde::{de::DeserializeOwned, Serialize};
+use wasm_bindgen::prelude::*;
+#[cfg(all(feature = "indices", feature = "cursors"))]
+use wasm_bindgen::JsCast;
+use web_sys::DomException;
+
+#[cfg(all(feature = "indices", feature = "cursors"))]
+use crate::idb_cursor::IndexPageToken;
+#[cfg(all(feature = "indices", feature = "cursors"))]
+use crate::idb_key::js_key_into;
+use crate::idb_key::IdbKey;
+use crate::request::VoidRequest;
+use crate::validation::{Validate, ValidationError};
+
+use super::TypedObjectStore;
+
+/// Error returned by the serde-based [TypedObjectStore] methods
+///
+/// Features required: `serde`
+#[derive(Debug)]
+pub enum SerdeStoreError {
+    /// The record failed [validation][Validate]. Nothing was written.
+    Validation(ValidationError),
+    /// The record couldn't be converted to or from a JS value
+    Serde(serde_wasm_bindgen::Error),
+    /// The record couldn't be encoded or decoded by a [Codec][super::Codec]
+    Codec(String),
+    /// The underlying operation failed
+    Dom(DomException),
+}
+
+impl From<DomException> for SerdeStoreError {
+    #[inline]
+    fn from(e: DomException) -> Self {
+        Self::Dom(e)
+    }
+}
+
+impl From<ValidationError> for SerdeStoreError {
+    #[inline]
+    fn from(e: ValidationError) -> Self {
+        Self::Validation(e)
+    }
+}
+
+impl From<serde_wasm_bindgen::Error> for SerdeStoreError {
+    #[inline]
+    fn from(e: serde_wasm_bindgen::Error) -> Self {
+        Self::Serde(e)
+    }
+}
+
+impl fmt::Display for SerdeStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Validation(e) => fmt::Display::fmt(e, f),
+            Self::Serde(e) => fmt::Display::fmt(e, f),
+            Self::Codec(e) => f.write_str(e),
+            Self::Dom(e) => write!(f, "{}: {}", e.name(), e.message()),
+        }
+    }
+}
+
+impl std::error::Error for SerdeStoreError {}
+
+/// Serialise maps as plain objects so that key paths can see into them
+#[inline]
+pub(crate) fn to_js<T: Serialize + ?Sized>(
+    value: &T,
+) -> Result<JsValue, serde_wasm_bindgen::Error> {
+    crate::values::to_value(value)
+}
+
+/// A deserialised record read via an index, along with the index key it matched and its primary
+/// key, e.g. for grouping records by index key in a UI
+///
+/// Features required: `serde`, `indices`, `cursors`
+#[cfg(all(feature = "indices", feature = "cursors"))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedRecord<K, T> {
+    /// The index key the record matched
+    pub key: JsValue,
+    /// The record's primary key
+    pub primary_key: K,
+    /// The deserialised record
+    pub value: T,
+}
+
+#[cfg(all(feature = "indices", feature = "cursors"))]
+impl<K: IdbKey, T> IndexedRecord<K, T> {
+    /// A token for continuing an [index page][TypedObjectStore::index_page_de] after this record
+    pub fn token(&self) -> IndexPageToken {
+        IndexPageToken {
+            key: self.key.clone(),
+            primary_key: self.primary_key.to_js_key(),
+        }
+    }
+}
+
+/// A page of records returned by [TypedObjectStore::index_page_de]
+///
+/// Features required: `serde`, `indices`, `cursors`
+#[cfg(all(feature = "indices", feature = "cursors"))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedPage<K, T> {
+    /// The records on the page, in index order
+    pub records: Vec<IndexedRecord<K, T>>,
+    /// Token for the next page; `None` if this is the last one
+    pub next: Option<IndexPageToken>,
+}
+
+impl<K: IdbKey> TypedObjectStore<'_, K> {
+    /// Serialise the value and put it at the given key, overwriting any existing value
+    ///
+    /// Features required: `serde`
+    pub fn put_ser<T: Serialize>(
+        &self,
+        key: &K,
+        value: &T,
+    ) -> Result<VoidRequest, SerdeStoreError> {
+        Ok(self.put_key_val(key, &to_js(value)?)?)
+    }
+
+    /// Serialise the value and add it at the given key. Throws if the key already exists.
+    ///
+    /// Features required: `serde`
+    pub fn add_ser<T: Serialize>(
+        &self,
+        key: &K,
+        value: &T,
+    ) -> Result<VoidRequest, SerdeStoreError> {
+        Ok(self.add_key_val(key, &to_js(value)?)?)
+    }
+
+    /// [Validate] the value, then [put][TypedObjectStore::put_ser] it
+    ///
+    /// Features required: `serde`
+    pub fn put_validated<T: Serialize + Validate>(
+        &self,
+        key: &K,
+        value: &T,
+    ) -> Result<VoidRequest, SerdeStoreError> {
+        value.validate()?;
+        self.put_ser(key, value)
+    }
+
+    /// [Validate] the value, then [add][TypedObjectStore::add_ser] it
+    ///
+    /// Features required: `serde`
+    pub fn add_validated<T: Serialize + Validate>(
+        &self,
+        key: &K,
+        value: &T,
+    ) -> Result<VoidRequest, SerdeStoreError> {
+        value.validate()?;
+        self.add_ser(key, value)
+    }
+
+    /// Get and deserialise the value at the given key. See the
+    /// [serde encoding][crate::values#serde-encoding] for how `Option`s and enums map to stored
+    /// values.
+    ///
+    /// Features required: `serde`
+    pub fn get_de<T: DeserializeOwned>(
+        &self,
+        key: &K,
+    ) -> Result<impl Future<Output = Result<Option<T>, SerdeStoreError>> + '_, DomException> {
+        let fut = self.get(key)?;
+        Ok(async move {
+            match fut.await? {
+                Some(value) => Ok(Some(crate::values::from_value(value)?)),
+                None => Ok(None),
+            }
+        })
+    }
+
+    /// Read a [page][crate::idb_index::IdbIndex::page] of records from the given key range of
+    /// the named index, or all of it if the range is `undefined`, and deserialise them. Each
+    /// record comes with the index key it matched and its primary key. Fails with a `DataError`
+    /// if a primary key isn't a `K`.
+    ///
+    /// Features required: `serde`, `indices`, `cursors`
+    #[cfg(all(feature = "indices", feature = "cursors"))]
+    pub async fn index_page_de<R: JsCast, T: DeserializeOwned>(
+        &self,
+        index: &str,
+        range: &R,
+        after: Option<&IndexPageToken>,
+        limit: u32,
+    ) -> Result<IndexedPage<K, T>, SerdeStoreError> {
+        let index = self.untyped().index(index)?;
+        let page = index.page(range, after, limit).await?;
+        let records = page
+            .records
+            .into_iter()
+            .map(|record| {
+                let value = self.after_read(&record.primary_key, record.value)?;
+                Ok(IndexedRecord {
+                    key: record.key,
+                    primary_key: js_key_into(record.primary_key)?,
+                    value: crate::values::from_value(value)?,
+                })
+            })
+            .collect::<Result<_, SerdeStoreError>>()?;
+        Ok(IndexedPage {
+            records,
+            next: page.next,
+        })
+    }
+}
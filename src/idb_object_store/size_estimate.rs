@@ -0,0 +1,212 @@
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+use crate::idb_query_source::IdbQuerySource;
+use crate::idb_transaction::IdbTransaction;
+use crate::internal_utils::dom_exception;
+
+use super::IdbObjectStore;
+
+/// Result of [IdbObjectStore::estimate_size]
+///
+/// Features required: `cursors`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SizeEstimate {
+    /// The number of records in the store
+    pub records: u32,
+    /// The number of records whose size was actually measured
+    pub sampled: u32,
+    /// The estimated size of all the store's keys and values, in bytes
+    pub bytes: f64,
+}
+
+impl IdbObjectStore<'_> {
+    /// Estimate the size of the store's records by measuring every one through a cursor. See
+    /// [approximate_size] for how sizes are measured.
+    ///
+    /// Features required: `cursors`
+    #[inline]
+    pub async fn estimate_size(&self) -> Result<SizeEstimate, DomException> {
+        self.estimate_size_sampled(u32::MAX).await
+    }
+
+    /// Estimate the size of the store's records by measuring at most `max_samples` records spread
+    /// evenly across the store and extrapolating, so that huge stores can be estimated cheaply.
+    ///
+    /// Features required: `cursors`
+    pub async fn estimate_size_sampled(
+        &self,
+        max_samples: u32,
+    ) -> Result<SizeEstimate, DomException> {
+        let records = self.count()?.await?;
+        if records == 0 || max_samples == 0 {
+            return Ok(SizeEstimate {
+                records,
+                ..SizeEstimate::default()
+            });
+        }
+
+        let step = ((records as f64) / (max_samples as f64)).ceil().max(1.0) as u32;
+        let mut sampled = 0u32;
+        let mut measured = 0.0;
+
+        if let Some(cursor) = self.open_cursor()?.await? {
+            loop {
+                measured += approximate_size(&cursor.key().unwrap_or(JsValue::UNDEFINED))
+                    + approximate_size(&cursor.value());
+                sampled += 1;
+                if !cursor.advance(step)?.await? {
+                    break;
+                }
+            }
+        }
+
+        let bytes = if sampled == 0 {
+            0.0
+        } else {
+            measured / (sampled as f64) * (records as f64)
+        };
+
+        Ok(SizeEstimate {
+            records,
+            sampled,
+            bytes,
+        })
+    }
+}
+
+/// Approximate the serialised size of a JS value in bytes. Strings count 2 bytes per UTF-16 code
+/// unit, numbers and dates 8, booleans 4, binary data and blobs their byte length, and objects
+/// and arrays the sum of their keys and values.
+///
+/// Features required: `cursors`
+pub fn approximate_size(value: &JsValue) -> f64 {
+    if let Some(s) = value.dyn_ref::<js_sys::JsString>() {
+        (s.length() as f64) * 2.0
+    } else if value.as_f64().is_some() || value.is_instance_of::<js_sys::Date>() {
+        8.0
+    } else if value.as_bool().is_some() {
+        4.0
+    } else if value.is_null() || value.is_undefined() {
+        0.0
+    } else if let Some(buf) = value.dyn_ref::<js_sys::ArrayBuffer>() {
+        buf.byte_length() as f64
+    } else if js_sys::ArrayBuffer::is_view(value) {
+        let len = js_sys::Reflect::get(value, &JsValue::from_str("byteLength")).ok();
+        len.and_then(|l| l.as_f64()).unwrap_or(0.0)
+    } else if let Some(blob) = value.dyn_ref::<web_sys::Blob>() {
+        blob.size()
+    } else if let Some(arr) = value.dyn_ref::<js_sys::Array>() {
+        arr.iter().map(|v| approximate_size(&v)).sum()
+    } else if let Some(obj) = value.dyn_ref::<js_sys::Object>() {
+        js_sys::Object::entries(obj)
+            .iter()
+            .map(|entry| {
+                let entry: js_sys::Array = entry.unchecked_into();
+                approximate_size(&entry.get(0)) + approximate_size(&entry.get(1))
+            })
+            .sum()
+    } else {
+        0.0
+    }
+}
+
+/// Maintains a running size total per object store in a metadata store, so that storage breakdowns
+/// can be shown without scanning. The metadata store must use out-of-line keys; totals are stored
+/// at the tracked store's name. Writes and deletes must go through the counter and the
+/// transaction must include the metadata store.
+///
+/// Features required: `cursors`
+#[derive(Debug, Clone)]
+pub struct SizeCounter {
+    meta_store: String,
+}
+
+impl SizeCounter {
+    /// Track sizes in the given metadata store
+    pub fn new(meta_store: &str) -> Self {
+        Self {
+            meta_store: meta_store.into(),
+        }
+    }
+
+    /// Put the value at the given key, adjusting the store's total by the size difference
+    pub async fn put_key_val<K: JsCast, V: JsCast>(
+        &self,
+        store: &IdbObjectStore<'_>,
+        key: &K,
+        val: &V,
+    ) -> Result<(), DomException> {
+        let key: &JsValue = key.unchecked_ref();
+        let val: &JsValue = val.unchecked_ref();
+        let old = self.record_size(store, key).await?;
+        store.put_key_val(key, val)?;
+        let new = approximate_size(key) + approximate_size(val);
+        self.adjust(store, new - old).await
+    }
+
+    /// Delete the record at the given key, subtracting its size from the store's total
+    pub async fn delete<K: JsCast>(
+        &self,
+        store: &IdbObjectStore<'_>,
+        key: &K,
+    ) -> Result<(), DomException> {
+        let key: &JsValue = key.unchecked_ref();
+        let old = self.record_size(store, key).await?;
+        store.delete(key)?;
+        self.adjust(store, -old).await
+    }
+
+    /// Get the tracked total for the given store, in bytes
+    pub async fn get(
+        &self,
+        tx: &IdbTransaction<'_>,
+        store_name: &str,
+    ) -> Result<f64, DomException> {
+        let meta = tx.object_store(&self.meta_store)?;
+        let total = meta.get_owned(store_name)?.await?;
+        Ok(total.and_then(|t| t.as_f64()).unwrap_or(0.0))
+    }
+
+    /// Reset the store's total to a full [estimate][IdbObjectStore::estimate_size], e.g. after
+    /// writes that bypassed the counter. Resolves to the new total.
+    pub async fn recalculate(&self, store: &IdbObjectStore<'_>) -> Result<f64, DomException> {
+        let total = store.estimate_size().await?.bytes;
+        self.meta(store)?
+            .put_key_val_owned(store.name(), &JsValue::from(total))?
+            .into_future()
+            .await?;
+        Ok(total)
+    }
+
+    async fn record_size(
+        &self,
+        store: &IdbObjectStore<'_>,
+        key: &JsValue,
+    ) -> Result<f64, DomException> {
+        Ok(match store.get(key)?.await? {
+            Some(old) => approximate_size(key) + approximate_size(&old),
+            None => 0.0,
+        })
+    }
+
+    async fn adjust(&self, store: &IdbObjectStore<'_>, delta: f64) -> Result<(), DomException> {
+        let meta = self.meta(store)?;
+        let name = store.name();
+        let current = meta.get_owned(name.as_str())?.await?;
+        let total = current.and_then(|t| t.as_f64()).unwrap_or(0.0) + delta;
+        meta.put_key_val_owned(name, &JsValue::from(total.max(0.0)))?
+            .into_future()
+            .await
+    }
+
+    fn meta<'s>(&self, store: &'s IdbObjectStore<'s>) -> Result<IdbObjectStore<'s>, DomException> {
+        match store.transaction() {
+            Some(tx) => Ok(tx.object_store(&self.meta_store)?),
+            None => Err(dom_exception(
+                "Size counters need a store obtained from a transaction",
+                "InvalidStateError",
+            )),
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+use crate::clock::{Clock, SharedClock};
+#[cfg(feature = "streams")]
+use crate::idb_cursor::ScanStream;
+use crate::idb_key::IdbKey;
+use crate::idb_query_source::IdbQuerySource;
+
+use super::IdbObjectStore;
+
+/// Which records of a [SoftDeleteStore] to include
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    /// Records that haven't been soft-deleted
+    Live,
+    /// Tombstones only
+    Deleted,
+    /// Both
+    All,
+}
+
+impl Visibility {
+    /// Whether a record with the given tombstone field value is included
+    #[inline]
+    fn includes(self, tombstone: &JsValue) -> bool {
+        let deleted = is_tombstone(tombstone);
+        match self {
+            Self::Live => !deleted,
+            Self::Deleted => deleted,
+            Self::All => true,
+        }
+    }
+}
+
+/// A view of an object store whose records get soft-deleted: [delete][SoftDeleteStore::delete]
+/// stamps the record's tombstone field with the deletion time instead of removing it, so that
+/// the deletion can sync or be undone. Every read takes a [Visibility], so tombstones only ever
+/// come back when they're asked for. Records must be objects.
+///
+/// An index on the tombstone field, if the store has one, lets [count][SoftDeleteStore::count]
+/// skip reading the records: records without the field aren't in the index. Otherwise counts
+/// other than [All][Visibility::All] walk a cursor over the whole store.
+///
+/// Features required: `cursors`
+#[derive(Debug)]
+pub struct SoftDeleteStore<'a> {
+    store: &'a IdbObjectStore<'a>,
+    field: String,
+    clock: SharedClock,
+}
+
+impl<'a> IdbObjectStore<'a> {
+    /// Soft-delete records by stamping the given top-level field, e.g. `deletedAt`
+    ///
+    /// Features required: `cursors`
+    pub fn soft_delete(&'a self, field: &str) -> SoftDeleteStore<'a> {
+        SoftDeleteStore {
+            store: self,
+            field: field.into(),
+            clock: SharedClock::default(),
+        }
+    }
+}
+
+impl<'a> SoftDeleteStore<'a> {
+    /// Read deletion times from the given clock instead of the system time
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// The underlying store
+    #[inline]
+    pub fn store(&self) -> &'a IdbObjectStore<'a> {
+        self.store
+    }
+
+    /// The tombstone field
+    #[inline]
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    /// Whether the record is a tombstone
+    pub fn is_deleted(&self, record: &JsValue) -> bool {
+        is_tombstone(&self.tombstone(record))
+    }
+
+    /// Mark the record at the key as deleted now. Resolves to false if there's no live record at
+    /// the key.
+    pub async fn delete<K: IdbKey>(&self, key: &K) -> Result<bool, DomException> {
+        self.set_tombstone(key, &self.clock.now().into()).await
+    }
+
+    /// Bring a soft-deleted record back. Resolves to false if there's no tombstone at the key.
+    pub async fn restore<K: IdbKey>(&self, key: &K) -> Result<bool, DomException> {
+        self.set_tombstone(key, &JsValue::UNDEFINED).await
+    }
+
+    /// Get the record at the key if it's included in the visibility
+    pub async fn get<K: IdbKey>(
+        &self,
+        key: &K,
+        visibility: Visibility,
+    ) -> Result<Option<JsValue>, DomException> {
+        let record = self.store.get(&key.to_js_key())?.await?;
+        Ok(record.filter(|record| visibility.includes(&self.tombstone(record))))
+    }
+
+    /// Count the records included in the visibility
+    pub async fn count(&self, visibility: Visibility) -> Result<u32, DomException> {
+        match visibility {
+            Visibility::All => return self.store.count()?.await,
+            #[cfg(feature = "indices")]
+            _ if self.index_name().is_some() => {
+                let index = self.store.index(&self.index_name().unwrap_or_default())?;
+                let deleted = index.count()?.await?;
+                return Ok(match visibility {
+                    Visibility::Deleted => deleted,
+                    _ => self.store.count()?.await?.saturating_sub(deleted),
+                });
+            }
+            _ => {}
+        }
+        let mut count = 0;
+        if let Some(cursor) = self.store.open_cursor()?.await? {
+            loop {
+                if visibility.includes(&self.tombstone(&cursor.value())) {
+                    count += 1;
+                }
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// Get every record included in the visibility, in key order
+    pub async fn get_all(&self, visibility: Visibility) -> Result<Vec<JsValue>, DomException> {
+        let all = self.store.get_all()?.await?;
+        Ok(all
+            .iter()
+            .filter(|record| visibility.includes(&self.tombstone(record)))
+            .collect())
+    }
+
+    /// Stream the records included in the visibility, in key order
+    ///
+    /// Features required: `streams`
+    #[cfg(feature = "streams")]
+    pub async fn stream(
+        &self,
+        visibility: Visibility,
+    ) -> Result<ScanStream<'a, IdbObjectStore<'a>, impl FnMut(&JsValue) -> bool + 'a>, DomException>
+    {
+        let field = JsValue::from_str(&self.field);
+        self.store
+            .scan_where(&JsValue::UNDEFINED, move |record| {
+                let tombstone = js_sys::Reflect::get(record, &field).unwrap_or(JsValue::UNDEFINED);
+                visibility.includes(&tombstone)
+            })
+            .await
+    }
+
+    fn tombstone(&self, record: &JsValue) -> JsValue {
+        js_sys::Reflect::get(record, &JsValue::from_str(&self.field)).unwrap_or(JsValue::UNDEFINED)
+    }
+
+    /// The store's index on the tombstone field, if it has one
+    #[cfg(feature = "indices")]
+    fn index_name(&self) -> Option<String> {
+        let key_path = crate::idb_key_path::IdbKeyPath::str(&self.field);
+        self.store.index_names().find(|name| {
+            self.store
+                .index(name)
+                .map(|index| index.key_path() == Some(key_path.clone()))
+                .unwrap_or(false)
+        })
+    }
+
+    /// Set the tombstone of a record that doesn't match it yet; `undefined` removes it
+    async fn set_tombstone<K: IdbKey>(
+        &self,
+        key: &K,
+        tombstone: &JsValue,
+    ) -> Result<bool, DomException> {
+        let key = key.to_js_key();
+        let record = match self.store.get(&key)?.await? {
+            Some(record) if record.is_object() => record,
+            _ => return Ok(false),
+        };
+        if is_tombstone(&self.tombstone(&record)) == is_tombstone(tombstone) {
+            return Ok(false);
+        }
+        let field = JsValue::from_str(&self.field);
+        if tombstone.is_undefined() {
+            js_sys::Reflect::delete_property(record.unchecked_ref(), &field)?;
+        } else {
+            js_sys::Reflect::set(&record, &field, tombstone)?;
+        }
+        match self.store.key_path() {
+            Some(_) => self.store.put_val(&record)?,
+            None => self.store.put_key_val(&key, &record)?,
+        };
+        Ok(true)
+    }
+}
+
+fn is_tombstone(value: &JsValue) -> bool {
+    !(value.is_undefined() || value.is_null() || *value == JsValue::FALSE)
+}
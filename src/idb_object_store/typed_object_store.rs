@@ -0,0 +1,443 @@
+use std::future::Future;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+use crate::idb_key::{js_key_into, js_keys_into, IdbKey};
+use crate::idb_query_source::IdbQuerySource;
+use crate::request::{CountFuture, VoidRequest};
+
+use super::{IdbObjectStore, Middleware, MiddlewareContext, MiddlewarePipeline, WriteOp};
+
+/// An [IdbObjectStore] whose keys are all of type `K`, so that e.g. passing a number to a
+/// string-keyed store is a compile error rather than a silent miss or a `DataError`. Created via
+/// [IdbObjectStore::typed].
+#[derive(Debug)]
+pub struct TypedObjectStore<'a, K: IdbKey> {
+    inner: IdbObjectStore<'a>,
+    middleware: MiddlewarePipeline,
+    _key: PhantomData<K>,
+}
+
+impl<'a, K: IdbKey> TypedObjectStore<'a, K> {
+    #[inline]
+    pub(crate) fn new(inner: IdbObjectStore<'a>) -> Self {
+        Self {
+            inner,
+            middleware: MiddlewarePipeline::default(),
+            _key: PhantomData,
+        }
+    }
+
+    /// Add a [Middleware] to the end of the store's pipeline. Pass an [Rc] to share the same
+    /// middleware between stores.
+    pub fn with_middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Rc::new(middleware));
+        self
+    }
+
+    /// The underlying, untyped object store. Operations on it bypass the middleware.
+    #[inline]
+    pub fn untyped(&self) -> &IdbObjectStore<'a> {
+        &self.inner
+    }
+
+    /// Unwrap the underlying, untyped object store
+    #[inline]
+    pub fn into_untyped(self) -> IdbObjectStore<'a> {
+        self.inner
+    }
+
+    /// Get the value at the given key
+    pub fn get(
+        &self,
+        key: &K,
+    ) -> Result<impl Future<Output = Result<Option<JsValue>, DomException>> + '_, DomException>
+    {
+        let key = key.to_js_key();
+        let fut = self.inner.get(&key)?;
+        Ok(async move {
+            match fut.await? {
+                Some(value) => {
+                    let ctx = MiddlewareContext::new(&self.inner, Some(&key));
+                    Ok(Some(self.middleware.on_read(&ctx, value)?))
+                }
+                None => Ok(None),
+            }
+        })
+    }
+
+    /// Get all the values in the object store
+    pub fn get_all(
+        &self,
+    ) -> Result<impl Future<Output = Result<Vec<JsValue>, DomException>> + '_, DomException> {
+        let fut = self.inner.get_all()?;
+        Ok(async move {
+            let ctx = MiddlewareContext::new(&self.inner, None);
+            fut.await?
+                .iter()
+                .map(|value| self.middleware.on_read(&ctx, value))
+                .collect()
+        })
+    }
+
+    /// Get all the keys in the object store. Fails with a `DataError` if a key isn't a `K`.
+    pub fn get_all_keys(
+        &self,
+    ) -> Result<impl Future<Output = Result<Vec<K>, DomException>>, DomException> {
+        let fut = self.inner.get_all_keys()?;
+        Ok(async move { js_keys_into(fut.await?) })
+    }
+
+    /// Get the key of the first record matching the given key, or `None` if there isn't one.
+    pub fn get_key(
+        &self,
+        key: &K,
+    ) -> Result<impl Future<Output = Result<Option<K>, DomException>>, DomException> {
+        let fut = self.inner.get_key(&key.to_js_key())?;
+        Ok(async move { fut.await?.map(js_key_into).transpose() })
+    }
+
+    /// Count the number of records in the object store
+    #[inline]
+    pub fn count(&self) -> Result<CountFuture, DomException> {
+        self.inner.count()
+    }
+
+    /// Clone and store the value in the object store at the given key. Throws if the key already
+    /// exists.
+    pub fn add_key_val<V: JsCast>(&self, key: &K, val: &V) -> Result<VoidRequest, DomException> {
+        let key = key.to_js_key();
+        let val = self.before_write(WriteOp::Add, &key, val)?;
+        let req = self.inner.add_key_val(&key, &val)?;
+        self.after_write(WriteOp::Add, &key, req)
+    }
+
+    /// Clone and store the value in the object store at the given key, overwriting any existing
+    /// value.
+    pub fn put_key_val<V: JsCast>(&self, key: &K, val: &V) -> Result<VoidRequest, DomException> {
+        let key = key.to_js_key();
+        let val = self.before_write(WriteOp::Put, &key, val)?;
+        let req = self.inner.put_key_val(&key, &val)?;
+        self.after_write(WriteOp::Put, &key, req)
+    }
+
+    /// Delete the record with the given key
+    pub fn delete(&self, key: &K) -> Result<VoidRequest, DomException> {
+        let key = key.to_js_key();
+        let ctx = MiddlewareContext::new(&self.inner, Some(&key)).with_op(WriteOp::Delete);
+        self.middleware.on_delete(&ctx)?;
+        let req = self.inner.delete(&key)?;
+        self.after_write(WriteOp::Delete, &key, req)
+    }
+
+    fn before_write<V: JsCast>(
+        &self,
+        op: WriteOp,
+        key: &JsValue,
+        val: &V,
+    ) -> Result<JsValue, DomException> {
+        let ctx = MiddlewareContext::new(&self.inner, Some(key)).with_op(op);
+        let val: &JsValue = val.unchecked_ref();
+        self.middleware.on_write(&ctx, val.clone())
+    }
+
+    fn after_write(
+        &self,
+        op: WriteOp,
+        key: &JsValue,
+        req: VoidRequest,
+    ) -> Result<VoidRequest, DomException> {
+        let ctx = MiddlewareContext::new(&self.inner, Some(key)).with_op(op);
+        self.middleware.after_write(&ctx, req.as_web_sys())?;
+        Ok(req)
+    }
+
+    #[cfg(all(feature = "serde", feature = "indices", feature = "cursors"))]
+    pub(crate) fn after_read(&self, key: &JsValue, val: JsValue) -> Result<JsValue, DomException> {
+        let ctx = MiddlewareContext::new(&self.inner, Some(key));
+        self.middleware.on_read(&ctx, val)
+    }
+}
+
+impl<'a, K: IdbKey> AsRef<IdbObjectStore<'a>> for TypedObjectStore<'a, K> {
+    #[inline]
+    fn as_ref(&self) -> &IdbObjectStore<'a> {
+        self.untyped()
+    }
+}
+
+impl<'a, K: IdbKey> From<IdbObjectStore<'a>> for TypedObjectStore<'a, K> {
+    #[inline]
+    fn from(inner: IdbObjectStore<'a>) -> Self {
+        Self::new(inner)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::open_any_db;
+
+    test_mod_init!();
+
+    test_case!(async typed_keys => {
+        let (db, store_name) = open_any_db().await;
+
+        let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite)
+            .expect("tx1");
+        let store = tx.object_store(&store_name).expect("store1").typed::<String>();
+        store.put_key_val(&String::from("b"), &JsValue::from(2u8)).expect("put b");
+        store.add_key_val(&String::from("a"), &JsValue::from(1u8)).expect("add a");
+        tx.await.into_result().expect("tx1 await");
+
+        let tx = db.transaction_on_one(&store_name).expect("tx2");
+        let store = tx.object_store(&store_name).expect("store2").typed::<String>();
+        let keys = store.get_all_keys().expect("get_all_keys").await.expect("keys await");
+        let value = store.get(&String::from("a")).expect("get").await.expect("get await");
+        let missing = store.get_key(&String::from("c")).expect("get_key").await.expect("get_key await");
+
+        assert_eq!(keys, vec![String::from("a"), String::from("b")], "keys");
+        assert_eq!(value, Some(JsValue::from(1u8)), "value");
+        assert_eq!(missing, None, "missing");
+    });
+
+    test_case!(async mismatched_key_type => {
+        let (db, store_name) = open_any_db().await;
+
+        let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite)
+            .expect("tx1");
+        let store = tx.object_store(&store_name).expect("store1");
+        store.put_key_val_owned(1u8, &JsValue::from(1u8)).expect("put");
+        tx.await.into_result().expect("tx1 await");
+
+        let tx = db.transaction_on_one(&store_name).expect("tx2");
+        let store = tx.object_store(&store_name).expect("store2").typed::<String>();
+        let err = store.get_all_keys().expect("get_all_keys").await.expect_err("keys await");
+
+        assert_eq!(err.name(), "DataError");
+    });
+
+    struct Suffix(&'static str);
+
+    impl Middleware for Suffix {
+        fn on_write(&self, _: &MiddlewareContext, value: JsValue) -> Result<JsValue, DomException> {
+            Ok(format!("{}{}", value.as_string().unwrap(), self.0).into())
+        }
+
+        fn on_read(&self, _: &MiddlewareContext, value: JsValue) -> Result<JsValue, DomException> {
+            let value = value.as_string().unwrap();
+            let stripped = value.strip_suffix(self.0).expect("middleware order");
+            Ok(stripped.into())
+        }
+    }
+
+    test_case!(async middleware => {
+        let (db, store_name) = open_any_db().await;
+
+        let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite)
+            .expect("tx1");
+        let store = tx.object_store(&store_name).expect("store1")
+            .typed::<String>()
+            .with_middleware(Suffix("a"))
+            .with_middleware(Suffix("b"));
+        store.put_key_val(&String::from("k"), &JsValue::from("x")).expect("put");
+        tx.await.into_result().expect("tx1 await");
+
+        let tx = db.transaction_on_one(&store_name).expect("tx2");
+        let store = tx.object_store(&store_name).expect("store2")
+            .typed::<String>()
+            .with_middleware(Suffix("a"))
+            .with_middleware(Suffix("b"));
+        let key = String::from("k");
+        let raw = store.untyped().get_owned("k").expect("raw").await.expect("raw await");
+        let value = store.get(&key).expect("get").await.expect("get await");
+        let all = store.get_all().expect("get_all").await.expect("get_all await");
+
+        assert_eq!(raw, Some(JsValue::from("xab")), "raw");
+        assert_eq!(value, Some(JsValue::from("x")), "value");
+        assert_eq!(all, vec![JsValue::from("x")], "all");
+    });
+
+    #[cfg(feature = "serde")]
+    pub mod serde_values {
+        use crate::idb_object_store::SerdeStoreError;
+        use crate::prelude::*;
+        use crate::test_utils::{open_any_db, open_db_with};
+        use crate::validation::{Validate, ValidationError};
+
+        test_mod_init!();
+
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct User {
+            name: String,
+            age: u8,
+        }
+
+        impl Validate for User {
+            fn validate(&self) -> Result<(), ValidationError> {
+                let mut err = ValidationError::new();
+                if self.name.is_empty() {
+                    err = err.problem("name", "must not be empty");
+                }
+                if self.age > 150 {
+                    err = err.problem("age", "must be at most 150");
+                }
+                err.into_result()
+            }
+        }
+
+        test_case!(async validated_writes => {
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite)
+                .expect("tx");
+            let store = tx.object_store(&store_name).expect("store").typed::<u32>();
+
+            let valid = User { name: "foo".into(), age: 30 };
+            let invalid = User { name: "".into(), age: 200 };
+            store.put_validated(&1, &valid).expect("put valid");
+            let err = store.put_validated(&2, &invalid).expect_err("put invalid");
+
+            let read: Option<User> = store.get_de(&1).expect("get").await.expect("get await");
+            let count = store.count().expect("count").await.expect("count await");
+
+            match err {
+                SerdeStoreError::Validation(e) => {
+                    let fields: Vec<&str> = e.problems.iter().map(|p| p.field.as_str()).collect();
+                    assert_eq!(fields, vec!["name", "age"], "fields");
+                }
+                other => panic!("Unexpected error: {}", other),
+            }
+            assert_eq!(read, Some(valid), "read");
+            assert_eq!(count, 1, "count");
+        });
+
+        #[cfg(feature = "serde_json")]
+        test_case!(async codecs => {
+            use crate::idb_object_store::{Binary, BinaryFormat, Codec, JsonString, SerdeWasmBindgen};
+            use serde::{de::DeserializeOwned, Serialize};
+
+            struct JsonBytes;
+
+            impl BinaryFormat for JsonBytes {
+                fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, String> {
+                    serde_json::to_vec(value).map_err(|e| e.to_string())
+                }
+
+                fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, String> {
+                    serde_json::from_slice(bytes).map_err(|e| e.to_string())
+                }
+            }
+
+            async fn round_trip<C: Codec>(codec: C) -> JsValue {
+                let (db, store_name) = open_any_db().await;
+                let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).unwrap();
+                let store = tx.object_store(&store_name).unwrap().typed::<u32>().with_codec(codec);
+                let user = User { name: "a".into(), age: 30 };
+                store.put(&1, &user).expect("put");
+                let read: Option<User> = store.get(&1).expect("get").await.expect("get await");
+                assert_eq!(read, Some(user), "read");
+                let all: Vec<User> = store.get_all().expect("get_all").await.expect("get_all await");
+                assert_eq!(all.len(), 1, "get_all");
+                store.untyped().get_owned(1u32).unwrap().await.unwrap().expect("raw")
+            }
+
+            assert!(round_trip(SerdeWasmBindgen).await.is_object(), "object");
+            assert_eq!(round_trip(JsonString).await, JsValue::from(r#"{"name":"a","age":30}"#), "json");
+            let raw = round_trip(Binary(JsonBytes)).await;
+            assert!(raw.is_instance_of::<js_sys::Uint8Array>(), "binary");
+
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).unwrap();
+            let store = tx.object_store(&store_name).unwrap().typed::<u32>().with_codec(Binary(JsonBytes));
+            store.put(&1, &User { name: "a".into(), age: 30 }).expect("put a");
+            store.put(&2, &User { name: "bb".into(), age: 2 }).expect("put b");
+            let buffer = js_sys::Uint8Array::from(&br#"{"name":"c","age":3}"#[..]).buffer();
+            store.untyped().put_key_val_owned(3u32, &buffer).expect("put buffer");
+            let bytes = store.get_bytes(&1).expect("get_bytes").await.expect("get_bytes await");
+            assert_eq!(bytes.as_deref(), Some(&br#"{"name":"a","age":30}"#[..]), "bytes");
+            let all: Vec<User> = store.get_all().expect("get_all").await.expect("get_all await");
+            let names: Vec<&str> = all.iter().map(|u| u.name.as_str()).collect();
+            assert_eq!(names, vec!["a", "bb", "c"], "decode_all");
+
+            let err = Binary(JsonBytes).decode::<User>(JsValue::from("nope")).expect_err("not binary");
+            assert!(matches!(err, SerdeStoreError::Codec(_)), "codec error");
+        });
+
+        #[cfg(all(feature = "indices", feature = "cursors"))]
+        test_case!(async index_page_de => {
+            let db = open_db_with(|evt: &IdbVersionChangeEvent| {
+                let store = evt.db().create_object_store("users")?;
+                store.create_index("by_age", &IdbKeyPath::str("age"))?;
+                Ok(())
+            }).await;
+
+            let tx = db.transaction_on_one_with_mode("users", IdbTransactionMode::Readwrite)
+                .expect("tx");
+            let store = tx.object_store("users").expect("store").typed::<u32>();
+            for (id, name, age) in [(1u32, "a", 30u8), (2, "b", 20), (3, "c", 30)].iter() {
+                store.put_ser(id, &User { name: (*name).into(), age: *age }).expect("put");
+            }
+
+            let page = store
+                .index_page_de::<_, User>("by_age", &JsValue::UNDEFINED, None, 2)
+                .await
+                .expect("page");
+            let ids: Vec<u32> = page.records.iter().map(|r| r.primary_key).collect();
+            assert_eq!(ids, vec![2, 1], "ids");
+            assert_eq!(page.records[1].key, JsValue::from(30u8), "index key");
+            assert_eq!(page.records[1].value, User { name: "a".into(), age: 30 }, "value");
+            assert_eq!(page.next.as_ref(), Some(&page.records[1].token()), "token");
+
+            let rest = store
+                .index_page_de::<_, User>("by_age", &JsValue::UNDEFINED, page.next.as_ref(), 2)
+                .await
+                .expect("rest");
+            let ids: Vec<u32> = rest.records.iter().map(|r| r.primary_key).collect();
+            assert_eq!(ids, vec![3], "rest");
+            assert!(rest.next.is_none(), "last page");
+        });
+    }
+
+    pub mod round_trip {
+        test_mod_init!();
+
+        fn round_trip<K: IdbKey + PartialEq + std::fmt::Debug>(key: K) {
+            assert_eq!(K::from_js_key(key.to_js_key()), Some(key));
+        }
+
+        test_case!(string => {
+            round_trip(String::from("foo"));
+        });
+
+        test_case!(numbers => {
+            round_trip(1.5f64);
+            round_trip(255u8);
+            round_trip(-5i32);
+        });
+
+        test_case!(compound => {
+            round_trip((String::from("series"), 42u32));
+            round_trip(vec![1u8, 2, 3]);
+        });
+    }
+
+    pub mod mismatch {
+        test_mod_init!();
+
+        test_case!(wrong_type => {
+            assert_eq!(String::from_js_key(JsValue::from(1u8)), None);
+        });
+
+        test_case!(out_of_range => {
+            assert_eq!(u8::from_js_key(JsValue::from(256u32)), None, "too large");
+            assert_eq!(u8::from_js_key(JsValue::from(1.5f64)), None, "fraction");
+        });
+
+        test_case!(wrong_tuple_length => {
+            assert_eq!(<(u8, u8)>::from_js_key(vec![1u8].to_js_key()), None);
+        });
+    }
+}
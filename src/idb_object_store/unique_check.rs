@@ -0,0 +1,105 @@
+use std::fmt;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+use crate::idb_query_source::IdbQuerySource;
+use crate::request::JsCastRequestFuture;
+
+use super::IdbObjectStore;
+
+/// Error returned by [IdbObjectStore::put_val_checked] and
+/// [IdbObjectStore::put_key_val_checked]
+///
+/// Features required: `indices`
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckedPutError {
+    /// Putting the value would violate the named unique index. Nothing was written.
+    UniqueViolation(String),
+    /// Checking or writing the value failed
+    Dom(DomException),
+}
+
+impl From<DomException> for CheckedPutError {
+    #[inline]
+    fn from(e: DomException) -> Self {
+        Self::Dom(e)
+    }
+}
+
+impl fmt::Display for CheckedPutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UniqueViolation(index) => write!(f, "Value violates unique index `{}`", index),
+            Self::Dom(e) => write!(f, "{}: {}", e.name(), e.message()),
+        }
+    }
+}
+
+impl std::error::Error for CheckedPutError {}
+
+impl IdbObjectStore<'_> {
+    /// Put the value after checking that it doesn't violate any of the store's unique indices, so
+    /// that a violation doesn't abort the transaction. For stores with in-line keys. Resolves to
+    /// the record's primary key.
+    ///
+    /// Features required: `indices`
+    pub async fn put_val_checked<V: JsCast>(&self, val: &V) -> Result<JsValue, CheckedPutError> {
+        let val: &JsValue = val.unchecked_ref();
+        let key = self.key_path().and_then(|path| path.evaluate(val));
+        self.check_unique(val, key.as_ref()).await?;
+        let req = self.write(|s| s.put(val))?;
+        Ok(JsCastRequestFuture::new(Ok(req))?.await?)
+    }
+
+    /// Put the value at the given key after checking that it doesn't violate any of the store's
+    /// unique indices, so that a violation doesn't abort the transaction. For stores with
+    /// out-of-line keys. Resolves to the record's primary key.
+    ///
+    /// Features required: `indices`
+    pub async fn put_key_val_checked<K: JsCast, V: JsCast>(
+        &self,
+        key: &K,
+        val: &V,
+    ) -> Result<JsValue, CheckedPutError> {
+        let key: &JsValue = key.unchecked_ref();
+        let val: &JsValue = val.unchecked_ref();
+        self.check_unique(val, Some(key)).await?;
+        let req = self.write(|s| s.put_with_key(val, key))?;
+        Ok(JsCastRequestFuture::new(Ok(req))?.await?)
+    }
+
+    async fn check_unique(
+        &self,
+        val: &JsValue,
+        primary_key: Option<&JsValue>,
+    ) -> Result<(), CheckedPutError> {
+        for name in self.index_names() {
+            let index = self.index(&name)?;
+            if !index.unique() {
+                continue;
+            }
+            let index_key = match index.key_path().and_then(|path| path.evaluate(val)) {
+                Some(k) => k,
+                None => continue,
+            };
+            let index_keys: Vec<JsValue> =
+                if index.multi_entry() && js_sys::Array::is_array(&index_key) {
+                    index_key.unchecked_into::<js_sys::Array>().iter().collect()
+                } else {
+                    vec![index_key]
+                };
+
+            for index_key in index_keys.iter() {
+                let violates = match primary_key {
+                    Some(pk) => index.would_violate_unique_for(index_key, pk).await?,
+                    None => index.would_violate_unique(index_key).await?,
+                };
+                if violates {
+                    return Err(CheckedPutError::UniqueViolation(name));
+                }
+            }
+        }
+        Ok(())
+    }
+}
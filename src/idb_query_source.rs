@@ -2,7 +2,7 @@ use wasm_bindgen::{prelude::*, JsCast};
 use web_sys::DomException;
 
 use crate::idb_key_path::IdbKeyPath;
-use crate::request::{CountFuture, JsCastRequestFuture, OptionalJsValueFuture};
+use crate::request::{CountFuture, GetEntryFuture, JsCastRequestFuture, OptionalJsValueFuture};
 #[cfg(feature = "cursors")]
 use crate::request::{IdbCursorFuture, IdbCursorWithValueFuture};
 
@@ -29,6 +29,21 @@ pub trait IdbQuerySource: Sized {
         self.get(&key.into())
     }
 
+    /// Like [get][IdbQuerySource::get], but tell a missing record apart from one whose value is
+    /// `undefined`, e.g. for caches where `undefined` is a legal value. Issues a `getKey` next to
+    /// the `get`.
+    #[inline]
+    fn get_entry<K: JsCast>(&self, key: &K) -> Result<GetEntryFuture, DomException> {
+        Ok(GetEntryFuture::new(self.get(key)?, self.get_key(key)?))
+    }
+
+    /// Like [get][IdbQuerySource::get], but tell a missing record apart from one whose value is
+    /// `undefined`
+    #[inline]
+    fn get_entry_owned<K: Into<JsValue>>(&self, key: K) -> Result<GetEntryFuture, DomException> {
+        self.get_entry(&key.into())
+    }
+
     /// Get all values in the index/object store
     fn get_all(&self) -> Result<JsCastRequestFuture<js_sys::Array>, DomException>;
 
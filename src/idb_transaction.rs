@@ -1,5 +1,6 @@
 //! Transaction-related code
 
+use std::convert::TryInto;
 use std::fmt::Debug;
 use std::future::Future;
 use std::pin::Pin;
@@ -9,26 +10,37 @@ use web_sys::{DomException, IdbTransactionMode};
 
 pub(crate) use idb_transaction_listeners::*;
 pub use idb_transaction_result::*;
+pub use store_not_found::*;
 
 use crate::dom_string_iterator::DomStringIterator;
 use crate::idb_database::IdbDatabase;
 use crate::idb_object_store::IdbObjectStore;
 
+#[cfg(feature = "tx-diagnostics")]
+mod diagnostics;
+mod get_multi;
 mod idb_transaction_listeners;
 mod idb_transaction_result;
+mod keep_alive;
+mod store_not_found;
 
 /// Wrapper around an IndexedDB transaction
+///
+/// Requests made on the same transaction execute in the order they were made, across all of its
+/// object stores; [apply_ordered][IdbObjectStore::apply_ordered] relies on this.
 #[derive(Debug)]
 pub struct IdbTransaction<'db> {
     inner: web_sys::IdbTransaction,
     db: &'db IdbDatabase,
     listeners: IdbTransactionListeners,
+    #[cfg(feature = "tx-diagnostics")]
+    diagnostics: diagnostics::TxDiagnostics,
 }
 
 impl IdbTransaction<'_> {
     /// Get a iterator of the names of [IdbObjectStore] objects associated with the transaction.
     #[inline]
-    pub fn object_store_names(&self) -> impl Iterator<Item = String> {
+    pub fn object_store_names(&self) -> DomStringIterator {
         DomStringIterator::from(self.inner.object_store_names())
     }
 
@@ -47,6 +59,35 @@ impl IdbTransaction<'_> {
         self.inner.error()
     }
 
+    /// Whether the transaction has finished, i.e. committed, failed or been aborted. Requests can
+    /// no longer be made on a finished transaction's object stores.
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.listeners.is_finished()
+    }
+
+    /// Call `preventDefault()` on request `error` events so that a failed request, e.g. an `add`
+    /// with a duplicate key during a bulk insert, doesn't abort the whole transaction. Errors are
+    /// still reported by the failed requests' own futures, but no longer by the transaction's.
+    /// Defaults to `false`.
+    #[inline]
+    pub fn set_ignore_request_errors(&self, ignore: bool) {
+        self.listeners.set_ignore_request_errors(ignore);
+    }
+
+    /// The underlying web_sys transaction
+    #[inline]
+    pub fn as_web_sys(&self) -> &web_sys::IdbTransaction {
+        &self.inner
+    }
+
+    /// Unwrap the underlying web_sys transaction. The wrapper's event listeners get removed, i.e.
+    /// the transaction can no longer be awaited through this crate.
+    #[inline]
+    pub fn into_web_sys(self) -> web_sys::IdbTransaction {
+        self.inner.clone()
+    }
+
     /// Rolls back all the changes to objects in the database associated with this transaction.
     /// If this transaction has been aborted or completed, this method fires an error event.
     #[inline]
@@ -63,9 +104,38 @@ impl<'db> IdbTransaction<'db> {
             inner,
             db,
             listeners,
+            #[cfg(feature = "tx-diagnostics")]
+            diagnostics: Default::default(),
         }
     }
 
+    /// Count a request made on the given object store towards the transaction's
+    /// [TransactionStats]
+    #[inline]
+    pub(crate) fn record_request(&self, store: &web_sys::IdbObjectStore) {
+        self.listeners.record_request(store);
+    }
+
+    /// What the transaction did, once it has committed successfully. Await the transaction by
+    /// reference, e.g. `(&mut tx).await`, to read them afterwards.
+    #[inline]
+    pub fn stats(&self) -> Option<TransactionStats> {
+        self.listeners.stats()
+    }
+
+    #[cfg(feature = "tx-diagnostics")]
+    #[inline]
+    pub(crate) fn diagnostics(&self) -> &diagnostics::TxDiagnostics {
+        &self.diagnostics
+    }
+
+    /// Wrap a web_sys transaction created outside of this crate. The transaction must belong to
+    /// the given database.
+    #[inline]
+    pub fn from_raw(inner: web_sys::IdbTransaction, db: &'db IdbDatabase) -> Self {
+        Self::new(inner, db)
+    }
+
     /// The database connection with which this transaction is associated.
     #[inline]
     pub fn db(&self) -> &'db IdbDatabase {
@@ -73,11 +143,37 @@ impl<'db> IdbTransaction<'db> {
     }
 
     /// Returns an [IdbObjectStore] object representing an object store that is part of the scope
-    /// of this transaction.
-    pub fn object_store(&'db self, name: &str) -> Result<IdbObjectStore<'db>, DomException> {
-        let tx = self.inner.object_store(name)?;
+    /// of this transaction. Fails with [StoreNotFound][ObjectStoreError::StoreNotFound] if the
+    /// store isn't part of the scope.
+    pub fn object_store(&'db self, name: &str) -> Result<IdbObjectStore<'db>, ObjectStoreError> {
+        let names = self.inner.object_store_names();
+        if !names.contains(name) {
+            return Err(StoreNotFound {
+                name: name.into(),
+                available: DomStringIterator::from(names).collect(),
+            }
+            .into());
+        }
+        let tx = self.inner.object_store(name).map_err(DomException::from)?;
         Ok(IdbObjectStore::from_tx(tx, self))
     }
+
+    /// Get handles to several of the transaction's object stores at once, e.g.
+    /// `let [users, posts] = tx.stores(["users", "posts"])?;`. Fails if any of them isn't part of
+    /// the transaction's scope.
+    pub fn stores<const N: usize>(
+        &'db self,
+        names: [&str; N],
+    ) -> Result<[IdbObjectStore<'db>; N], ObjectStoreError> {
+        let mut stores = Vec::with_capacity(N);
+        for name in names.iter() {
+            stores.push(self.object_store(name)?);
+        }
+        match stores.try_into() {
+            Ok(stores) => Ok(stores),
+            Err(_) => unreachable!("one store per name"),
+        }
+    }
 }
 
 impl Drop for IdbTransaction<'_> {
@@ -100,8 +196,8 @@ impl Future for IdbTransaction<'_> {
 #[cfg(test)]
 pub mod test {
     pub mod future {
-        use crate::internal_utils::open_any_db;
-        use crate::prelude::{IdbTransactionMode, IdbTransactionResult};
+        use crate::prelude::{IdbQuerySource, IdbTransactionMode, IdbTransactionResult};
+        use crate::test_utils::{open_any_db, open_db_with_stores};
 
         test_mod_init!();
 
@@ -122,6 +218,18 @@ pub mod test {
             assert!(tx.await.into_result().is_ok(), "result");
         });
 
+        test_case!(async should_round_trip_through_web_sys => {
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).expect("tx");
+            let tx = crate::idb_transaction::IdbTransaction::from_raw(tx.into_web_sys(), &db);
+            let store = tx.object_store(&store_name).expect("store");
+            let store = crate::idb_object_store::IdbObjectStore::from_raw(store.into_web_sys(), &tx);
+
+            store.put_key_val_owned("foo", &JsValue::from("bar")).expect("put");
+            assert_eq!(tx.as_web_sys().mode().unwrap(), IdbTransactionMode::Readwrite, "mode");
+            assert!(tx.await.into_result().is_ok(), "result");
+        });
+
         test_case!(async should_propagate_errors => {
             let (db, store_name) = open_any_db().await;
             let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).expect("tx");
@@ -137,5 +245,178 @@ pub mod test {
                 }
             };
         });
+
+        test_case!(async result_combinators => {
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).expect("tx");
+            tx.object_store(&store_name).expect("store").put_key_val_owned("foo", &JsValue::from(1)).expect("put");
+            let result = tx.await;
+            assert!(result.is_success() && !result.is_abort(), "success");
+            assert_eq!(result.clone().map(|| 5).expect("map"), 5, "map");
+            assert!(result.clone().err().is_none(), "no err");
+            let converted: Result<(), web_sys::DomException> = result.into();
+            assert!(converted.is_ok(), "into");
+
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).expect("tx2");
+            let store = tx.object_store(&store_name).expect("store2");
+            store.add_key_val_owned("foo", &JsValue::from(2)).expect("conflicting add");
+            let result = tx.await;
+            assert!(!result.is_success(), "failure");
+            assert!(result.clone().and_then(|| Ok(())).is_err(), "and_then");
+            assert!(result.err().is_some(), "err");
+
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).expect("tx3");
+            let raw = tx.as_web_sys().clone();
+            raw.abort().expect("abort");
+            let result = tx.await;
+            assert!(result.is_abort(), "abort");
+            assert_eq!(result.err().expect("abort err").name(), "AbortError", "abort err");
+        });
+
+        test_case!(async stats => {
+            let db = crate::test_utils::open_db_with_stores(&["a", "b", "c"]).await;
+
+            let mut tx = db.transaction_on_multi_with_mode(&["a", "b", "c"], IdbTransactionMode::Readwrite).expect("tx");
+            let [a, b] = tx.stores(["a", "b"]).expect("stores");
+            b.put_key_val_owned("k", &JsValue::from(1u8)).expect("put b");
+            a.put_key_val_owned("k", &JsValue::from(2u8)).expect("put a");
+            b.get_owned("k").expect("get b").await.expect("get b await");
+            assert!(tx.stats().is_none(), "no stats before commit");
+            assert!((&mut tx).await.is_success(), "success");
+            let stats = tx.stats().expect("stats");
+            assert_eq!(stats.requests, 3, "requests");
+            assert_eq!(stats.stores, vec!["b".to_string(), "a".to_string()], "stores touched");
+
+            let mut tx = db.transaction_on_one_with_mode("a", IdbTransactionMode::Readwrite).expect("tx2");
+            tx.as_web_sys().abort().expect("abort");
+            assert!((&mut tx).await.is_abort(), "aborted");
+            assert!(tx.stats().is_none(), "no stats when aborted");
+        });
+
+        test_case!(async should_ignore_request_errors => {
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).expect("tx");
+            tx.set_ignore_request_errors(true);
+            let store = tx.object_store(&store_name).expect("store");
+
+            store.add_key_val_owned("foo", &JsValue::from("bar")).expect("add 1");
+            store.add_key_val_owned("foo", &JsValue::from("qux")).expect("add 2");
+            store.add_key_val_owned("baz", &JsValue::from("qux")).expect("add 3");
+            assert!(tx.await.into_result().is_ok(), "result");
+
+            let tx = db.transaction_on_one(&store_name).expect("tx2");
+            let store = tx.object_store(&store_name).expect("store2");
+            let count = store.count().expect("count").await.expect("count await");
+            let foo = store.get_owned("foo").expect("get").await.expect("get await");
+
+            assert_eq!(count, 2, "count");
+            assert_eq!(foo, Some(JsValue::from("bar")), "foo");
+        });
+
+        test_case!(async keep_alive_while => {
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).expect("tx");
+            let store = tx.object_store(&store_name).expect("store");
+            store.put_key_val_owned("a", &JsValue::from(1u8)).expect("put a");
+
+            let timer = js_sys::Promise::new(&mut |resolve, _| {
+                web_sys::window()
+                    .unwrap()
+                    .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, 50)
+                    .unwrap();
+            });
+            let out = tx
+                .keep_alive_while(&store_name, async { wasm_bindgen_futures::JsFuture::from(timer).await.map(|_| 42u8) })
+                .await
+                .expect("keep alive");
+            assert_eq!(out, Ok(42), "output");
+
+            store.put_key_val_owned("b", &JsValue::from(2u8)).expect("put b");
+            assert!(tx.await.into_result().is_ok(), "result");
+
+            let tx = db.transaction_on_one(&store_name).expect("tx2");
+            let store = tx.object_store(&store_name).expect("store2");
+            assert_eq!(store.count().expect("count").await.expect("count await"), 2, "count");
+        });
+
+        test_case!(async keep_alive_resolves_while_active => {
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).expect("tx");
+            let store = tx.object_store(&store_name).expect("store");
+
+            let timer = js_sys::Promise::new(&mut |resolve, _| {
+                web_sys::window()
+                    .unwrap()
+                    .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, 0)
+                    .unwrap();
+            });
+            tx.keep_alive_while(&store_name, wasm_bindgen_futures::JsFuture::from(timer))
+                .await
+                .expect("keep alive")
+                .expect("timer");
+
+            store.put_key_val_owned("a", &JsValue::from(1u8)).expect("put").into_future().await.expect("put await");
+            assert!(tx.await.into_result().is_ok(), "result");
+        });
+
+        test_case!(async stores => {
+            let db = open_db_with_stores(&["a", "b"]).await;
+
+            let tx = db.transaction_on_multi_with_mode(&["a", "b"], IdbTransactionMode::Readwrite).expect("tx");
+            let [a, b] = tx.stores(["a", "b"]).expect("stores");
+            a.put_key_val_owned("k", &JsValue::from(1u8)).expect("put a");
+            b.put_key_val_owned("k", &JsValue::from(2u8)).expect("put b");
+            assert_eq!((a.name(), b.name()), ("a".into(), "b".into()), "names");
+            assert!(tx.stores(["a", "nope"]).is_err(), "unknown store");
+            assert!(tx.await.into_result().is_ok(), "result");
+        });
+
+        test_case!(async store_not_found => {
+            use crate::idb_transaction::ObjectStoreError;
+            use web_sys::DomException;
+
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one(&store_name).expect("tx");
+            let not_found = match tx.object_store("nope").expect_err("unknown store") {
+                ObjectStoreError::StoreNotFound(not_found) => not_found,
+                e => panic!("unexpected error: {}", e),
+            };
+            assert_eq!(not_found.name, "nope", "asked for");
+            assert_eq!(not_found.available, vec![store_name.clone()], "scope");
+            let err = DomException::from(not_found);
+            assert_eq!(err.name(), "NotFoundError", "name");
+            assert!(err.message().contains(&store_name), "message lists the scope");
+        });
+
+        test_case!(async get_multi => {
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).expect("tx");
+            let store = tx.object_store(&store_name).expect("store");
+            store.put_key_val_owned("a", &JsValue::from(1u8)).expect("put a");
+            store.put_key_val_owned("b", &JsValue::from(2u8)).expect("put b");
+
+            let (a, missing, b) = (JsValue::from("a"), JsValue::from("c"), JsValue::from("b"));
+            let gets = [(store_name.as_str(), &a), (store_name.as_str(), &missing), (store_name.as_str(), &b)];
+            let values = tx.get_multi(&gets).await.expect("get_multi");
+            assert_eq!(values, vec![Some(JsValue::from(1u8)), None, Some(JsValue::from(2u8))], "values");
+
+            let no_store = [("nope", &a)];
+            assert!(tx.get_multi(&no_store).await.is_err(), "unknown store");
+        });
+
+        test_case!(async join => {
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).expect("tx");
+            let store = tx.object_store(&store_name).expect("store");
+            store.put_key_val_owned("a", &JsValue::from(1u8)).expect("put a");
+
+            let (count, value) = crate::request::join((
+                store.count().expect("count"),
+                store.get_owned("a").expect("get"),
+            ))
+            .await;
+            assert_eq!(count.expect("count await"), 1, "count");
+            assert_eq!(value.expect("get await"), Some(JsValue::from(1u8)), "value");
+        });
     }
 }
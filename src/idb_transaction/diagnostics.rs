@@ -0,0 +1,41 @@
+use std::cell::RefCell;
+use std::panic::Location;
+
+/// Records where the requests on a transaction were made from, so that a
+/// `TransactionInactiveError` can point at the `.await` that most likely let the transaction
+/// auto-commit
+#[derive(Debug, Default)]
+pub(crate) struct TxDiagnostics {
+    requests: RefCell<Vec<RequestRecord>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RequestRecord {
+    location: &'static Location<'static>,
+    at: f64,
+}
+
+impl TxDiagnostics {
+    pub fn record_request(&self, location: &'static Location<'static>) {
+        self.requests.borrow_mut().push(RequestRecord {
+            location,
+            at: js_sys::Date::now(),
+        });
+    }
+
+    /// Explain a failed request made at the given location. `None` if no request has been made on
+    /// the transaction yet.
+    pub fn report(&self, location: &'static Location<'static>) -> Option<String> {
+        let requests = self.requests.borrow();
+        let last = requests.last()?;
+        Some(format!(
+            "{} request(s) were made on it, the last one at {} {}ms before this one at {}; an \
+             `.await` on something other than this transaction's requests in between most likely \
+             let it auto-commit",
+            requests.len(),
+            last.location,
+            (js_sys::Date::now() - last.at).round(),
+            location
+        ))
+    }
+}
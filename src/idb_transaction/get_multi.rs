@@ -0,0 +1,30 @@
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+use crate::request::OptionalJsValueFuture;
+
+use super::IdbTransaction;
+
+impl IdbTransaction<'_> {
+    /// Get the records at the given `(store, key)` pairs, which may span any of the transaction's
+    /// object stores. All the requests are made up front and the result for each pair ends up at
+    /// its position; `None` where there's no record.
+    pub async fn get_multi<K: JsCast>(
+        &self,
+        gets: &[(&str, &K)],
+    ) -> Result<Vec<Option<JsValue>>, DomException> {
+        let futures = gets
+            .iter()
+            .map(|(store, key)| {
+                let store = self.as_web_sys().object_store(store)?;
+                OptionalJsValueFuture::new(store.get(key.unchecked_ref()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut results = Vec::with_capacity(futures.len());
+        for fut in futures {
+            results.push(fut.await?);
+        }
+        Ok(results)
+    }
+}
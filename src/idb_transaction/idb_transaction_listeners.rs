@@ -1,4 +1,4 @@
-use std::cell::RefMut;
+use std::cell::{Cell, RefMut};
 use std::ops::Deref;
 use std::task::Poll;
 use std::{
@@ -9,20 +9,63 @@ use std::{
 
 use wasm_bindgen::{prelude::*, JsCast};
 
-use crate::internal_utils::{create_lazy_ref_cell, wake};
+use crate::internal_utils::{create_lazy_ref_cell, dom_exception, wake, ClosureKind, ClosureToken};
 
-use super::IdbTransactionResult;
+use super::{IdbTransactionResult, TransactionStats};
 
 type Cb = dyn Fn() + 'static;
 type ErrCb = dyn Fn(web_sys::Event) + 'static;
 type WakerRef = Rc<RefCell<Option<Waker>>>;
 type ResultRef = Rc<RefCell<Option<IdbTransactionResult>>>;
+type FlagRef = Rc<Cell<bool>>;
+type StatsRef = Rc<StatsRecorder>;
+
+/// Collects a transaction's [TransactionStats] until it commits
+#[derive(Debug)]
+struct StatsRecorder {
+    started_at: f64,
+    requests: Cell<u32>,
+    /// A transaction hands out the same object store instance for the same name, so stores can be
+    /// told apart by reference and their names only read once the transaction commits
+    stores: RefCell<Vec<web_sys::IdbObjectStore>>,
+    committed: RefCell<Option<TransactionStats>>,
+}
+
+impl StatsRecorder {
+    fn new() -> Self {
+        Self {
+            started_at: js_sys::Date::now(),
+            requests: Cell::new(0),
+            stores: RefCell::new(Vec::new()),
+            committed: RefCell::new(None),
+        }
+    }
+
+    fn record_request(&self, store: &web_sys::IdbObjectStore) {
+        self.requests.set(self.requests.get().saturating_add(1));
+        let mut stores = self.stores.borrow_mut();
+        if !stores.iter().rev().any(|s| s == store) {
+            stores.push(store.clone());
+        }
+    }
+
+    fn commit(&self) {
+        let millis = (js_sys::Date::now() - self.started_at).max(0.0);
+        self.committed.replace(Some(TransactionStats {
+            requests: self.requests.get(),
+            duration: std::time::Duration::from_millis(millis as u64),
+            stores: self.stores.borrow().iter().map(|s| s.name()).collect(),
+        }));
+    }
+}
 
 /// IdbTransaction event listeners
 #[derive(Debug)]
 pub(crate) struct IdbTransactionListeners {
     waker: WakerRef,
     result: ResultRef,
+    ignore_request_errors: FlagRef,
+    stats: StatsRef,
     on_success: Closure<Cb>,
     on_abort: Closure<Cb>,
     on_error: Closure<ErrCb>,
@@ -33,10 +76,26 @@ impl IdbTransactionListeners {
         let waker = create_lazy_ref_cell();
         let result = create_lazy_ref_cell();
 
-        let on_success =
-            base_callback(waker.clone(), result.clone(), IdbTransactionResult::Success);
-        let on_error = error_callback(waker.clone(), result.clone());
-        let on_abort = base_callback(waker.clone(), result.clone(), IdbTransactionResult::Abort);
+        let ignore_request_errors = Rc::new(Cell::new(false));
+        let stats = Rc::new(StatsRecorder::new());
+
+        let on_success = {
+            let stats = stats.clone();
+            base_callback(waker.clone(), result.clone(), move || {
+                stats.commit();
+                IdbTransactionResult::Success
+            })
+        };
+        let on_error = error_callback(waker.clone(), result.clone(), ignore_request_errors.clone());
+        let on_abort = {
+            // The browser aborts transactions that fail to commit, e.g. with a
+            // QuotaExceededError, and records why on the transaction
+            let inner = inner.clone();
+            base_callback(waker.clone(), result.clone(), move || match inner.error() {
+                Some(err) => IdbTransactionResult::Error(err),
+                None => IdbTransactionResult::Abort,
+            })
+        };
 
         inner.set_oncomplete(Some(on_success.as_ref().unchecked_ref()));
         inner.set_onerror(Some(on_error.as_ref().unchecked_ref()));
@@ -45,12 +104,38 @@ impl IdbTransactionListeners {
         Self {
             waker,
             result,
+            ignore_request_errors,
+            stats,
             on_error,
             on_success,
             on_abort,
         }
     }
 
+    #[inline]
+    pub fn set_ignore_request_errors(&self, val: bool) {
+        self.ignore_request_errors.set(val);
+    }
+
+    /// Count a request made on the given object store towards the transaction's stats
+    #[inline]
+    pub fn record_request(&self, store: &web_sys::IdbObjectStore) {
+        self.stats.record_request(store);
+    }
+
+    /// The transaction's stats, once it has committed
+    pub fn stats(&self) -> Option<TransactionStats> {
+        self.stats.committed.borrow().clone()
+    }
+
+    /// Whether the transaction has completed, errored or been aborted
+    pub fn is_finished(&self) -> bool {
+        self.result
+            .try_borrow()
+            .map(|result| result.is_some())
+            .unwrap_or(false)
+    }
+
     pub fn do_poll(&self, ctx: &Context<'_>) -> Poll<IdbTransactionResult> {
         if let Some(v) = self.result.borrow().deref() {
             Poll::Ready(v.clone())
@@ -70,12 +155,14 @@ fn try_get_result_ref(result: &ResultRef) -> Option<RefMut<Option<IdbTransaction
     None
 }
 
-fn error_callback(waker: WakerRef, result: ResultRef) -> Closure<ErrCb> {
+fn error_callback(waker: WakerRef, result: ResultRef, ignore: FlagRef) -> Closure<ErrCb> {
     fn extract_error(evt: web_sys::Event) -> Option<web_sys::DomException> {
         if let Some(tgt) = evt.target() {
             let req: web_sys::IdbRequest = tgt.unchecked_into();
-            req.error()
-                .expect("Error unreachable on an errored transaction")
+            match req.error() {
+                Ok(err) => err,
+                Err(_) => Some(dom_exception("Transaction failed", "UnknownError")),
+            }
         } else {
             None
         }
@@ -92,7 +179,14 @@ fn error_callback(waker: WakerRef, result: ResultRef) -> Closure<ErrCb> {
         }
         false
     }
+    let token = ClosureToken::new(ClosureKind::Transaction);
     let b = Box::new(move |e: web_sys::Event| {
+        token.hold();
+        if ignore.get() {
+            // Keeps the transaction from aborting
+            e.prevent_default();
+            return;
+        }
         if process(e, &result) {
             wake(&waker);
         }
@@ -100,20 +194,24 @@ fn error_callback(waker: WakerRef, result: ResultRef) -> Closure<ErrCb> {
     Closure::wrap(b)
 }
 
-fn base_callback(waker: WakerRef, result: ResultRef, kind: IdbTransactionResult) -> Closure<Cb> {
+fn base_callback<F>(waker: WakerRef, result: ResultRef, kind: F) -> Closure<Cb>
+where
+    F: Fn() -> IdbTransactionResult + 'static,
+{
     /// Returns true if the waker should be called
     fn process(result: &ResultRef, kind: IdbTransactionResult) -> bool {
         if let Some(mut v) = try_get_result_ref(result) {
-            v.replace(kind.clone());
+            v.replace(kind);
             true
         } else {
             false
         }
     }
 
+    let token = ClosureToken::new(ClosureKind::Transaction);
     let b = Box::new(move || {
-        if process(&result, kind.clone()) {
-            // Clone so this can be Fn and not FnOnce
+        token.hold();
+        if process(&result, kind()) {
             wake(&waker);
         }
     });
@@ -1,5 +1,23 @@
+use std::time::Duration;
+
 use web_sys::DomException;
 
+use crate::internal_utils::dom_exception;
+
+/// What a [transaction][crate::idb_transaction::IdbTransaction] did, for lightweight performance
+/// logging, as read through [stats][crate::idb_transaction::IdbTransaction::stats]. Only requests
+/// made through this crate's wrappers are counted; a cursor counts as the one request that opened
+/// it, however many times it's advanced.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransactionStats {
+    /// The number of requests made on the transaction
+    pub requests: u32,
+    /// The time from the transaction being created to it committing
+    pub duration: Duration,
+    /// The object stores requests were made on, in the order they were first used
+    pub stores: Vec<String>,
+}
+
 /// The [transaction's][crate::idb_transaction::IdbTransaction] result
 #[derive(Debug, Clone)]
 pub enum IdbTransactionResult {
@@ -7,7 +25,8 @@ pub enum IdbTransactionResult {
     Success,
     /// Transaction errored
     Error(DomException),
-    /// Transaction aborted
+    /// Transaction aborted without an error of its own, e.g. through
+    /// [abort][crate::idb_transaction::IdbTransaction::abort]
     Abort,
 }
 
@@ -17,11 +36,48 @@ impl IdbTransactionResult {
         match self {
             IdbTransactionResult::Success => Ok(()),
             IdbTransactionResult::Error(xc) => Err(xc),
-            IdbTransactionResult::Abort => {
-                let xc = DomException::new_with_message("Transaction aborted")
-                    .expect("Failed to construct abort event dom exception");
-                Err(xc)
-            }
+            IdbTransactionResult::Abort => Err(dom_exception("Transaction aborted", "AbortError")),
         }
     }
+
+    /// Whether the transaction committed successfully
+    #[inline]
+    pub fn is_success(&self) -> bool {
+        matches!(self, IdbTransactionResult::Success)
+    }
+
+    /// Whether the transaction was aborted
+    #[inline]
+    pub fn is_abort(&self) -> bool {
+        matches!(self, IdbTransactionResult::Abort)
+    }
+
+    /// The error the transaction failed with, if it errored or was aborted. Aborts are reported
+    /// the same way as by [into_result][IdbTransactionResult::into_result].
+    #[inline]
+    pub fn err(self) -> Option<DomException> {
+        self.into_result().err()
+    }
+
+    /// Map a successful result to a value, e.g. `tx.await.map(|| count)`
+    #[inline]
+    pub fn map<T, F: FnOnce() -> T>(self, f: F) -> Result<T, DomException> {
+        self.into_result().map(|_| f())
+    }
+
+    /// Chain a fallible operation onto a successful result
+    #[inline]
+    pub fn and_then<T, F>(self, f: F) -> Result<T, DomException>
+    where
+        F: FnOnce() -> Result<T, DomException>,
+    {
+        self.into_result().and_then(|_| f())
+    }
+}
+
+impl From<IdbTransactionResult> for Result<(), DomException> {
+    #[inline]
+    fn from(result: IdbTransactionResult) -> Self {
+        result.into_result()
+    }
 }
@@ -0,0 +1,92 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use wasm_bindgen::prelude::*;
+use web_sys::DomException;
+
+use crate::request::OptionalJsValueFuture;
+
+use super::IdbTransaction;
+
+const SENTINEL_KEY: &str = "__idb_keep_alive";
+
+impl IdbTransaction<'_> {
+    /// Keep the transaction active while the future runs by continuously issuing no-op `get`
+    /// requests for a sentinel key on the given object store, then resolve to the future's
+    /// output from the next no-op request's success callback, while the transaction is active
+    /// again and new requests can be made on it. Without this, awaiting anything other than one of the transaction's own requests,
+    /// e.g. a `fetch` or a timer, lets the transaction auto-commit.
+    ///
+    /// This is an escape hatch with real costs, so it's opt-in per call:
+    ///
+    /// - The transaction keeps its stores locked for as long as the future runs, blocking every
+    ///   other transaction that needs them, including ones in other tabs
+    /// - A request is always in flight, which keeps the browser busy for the whole duration
+    /// - It only helps futures that yield to the event loop. Synchronous Rust computation blocks
+    ///   the requests from completing just the same; split it up with timers or idle callbacks.
+    /// - The transaction still ends if it's aborted, e.g. by the browser on shutdown or quota
+    ///   errors
+    ///
+    /// Prefer finishing the work before starting the transaction, or splitting it across several
+    /// transactions, wherever possible.
+    ///
+    /// Fails if the store isn't in the transaction's scope or a no-op request fails.
+    pub async fn keep_alive_while<F: Future>(
+        &self,
+        store: &str,
+        future: F,
+    ) -> Result<F::Output, DomException> {
+        let store = self.as_web_sys().object_store(store)?;
+        KeepAlive {
+            future: Box::pin(future),
+            output: None,
+            store,
+            pending: None,
+        }
+        .await
+    }
+}
+
+/// Resolves from the success callback of a sentinel request rather than whenever the wrapped
+/// future completes, as that's usually outside of an IDB task, e.g. in a timer callback, where the
+/// transaction is inactive and the caller's next request would throw a `TransactionInactiveError`
+struct KeepAlive<F: Future> {
+    future: Pin<Box<F>>,
+    output: Option<F::Output>,
+    store: web_sys::IdbObjectStore,
+    pending: Option<OptionalJsValueFuture>,
+}
+
+impl<F: Future> Unpin for KeepAlive<F> {}
+
+impl<F: Future> Future for KeepAlive<F> {
+    type Output = Result<F::Output, DomException>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if this.output.is_none() {
+            if let Poll::Ready(out) = this.future.as_mut().poll(ctx) {
+                this.output = Some(out);
+            }
+        }
+        loop {
+            match this.pending.as_mut() {
+                Some(pending) => match Pin::new(pending).poll(ctx) {
+                    Poll::Ready(Ok(_)) => {
+                        this.pending = None;
+                        if let Some(out) = this.output.take() {
+                            return Poll::Ready(Ok(out));
+                        }
+                    }
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => return Poll::Pending,
+                },
+                None => {
+                    let req = this.store.get(&JsValue::from_str(SENTINEL_KEY));
+                    this.pending = Some(OptionalJsValueFuture::new(req)?);
+                }
+            }
+        }
+    }
+}
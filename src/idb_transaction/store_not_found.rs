@@ -0,0 +1,106 @@
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+use web_sys::DomException;
+
+use crate::internal_utils::dom_exception;
+
+const PREFIX: &str = "No object store ";
+const SCOPE: &str = " in the transaction's scope ";
+
+/// [IdbTransaction::object_store][super::IdbTransaction::object_store] was called with a name
+/// that isn't part of the transaction's scope, usually because of a typo or because the store
+/// wasn't passed to `transaction_on_multi`.
+///
+/// Returned as [ObjectStoreError::StoreNotFound]. Converts to a `NotFoundError` [DomException]
+/// whose message lists the transaction's scope, so that it can be propagated with `?` like the
+/// transaction's other errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreNotFound {
+    /// The name that was asked for
+    pub name: String,
+    /// The names of the object stores in the transaction's scope
+    pub available: Vec<String>,
+}
+
+impl fmt::Display for StoreNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = |value: &JsValue| {
+            js_sys::JSON::stringify(value)
+                .ok()
+                .and_then(|s| s.as_string())
+                .unwrap_or_default()
+        };
+        let available = self
+            .available
+            .iter()
+            .map(|name| JsValue::from_str(name))
+            .collect::<js_sys::Array>();
+        write!(
+            f,
+            "{}{}{}{}",
+            PREFIX,
+            json(&JsValue::from_str(&self.name)),
+            SCOPE,
+            json(&available)
+        )
+    }
+}
+
+impl std::error::Error for StoreNotFound {}
+
+impl From<StoreNotFound> for DomException {
+    fn from(e: StoreNotFound) -> Self {
+        dom_exception(&e.to_string(), "NotFoundError")
+    }
+}
+
+/// Why [object_store][super::IdbTransaction::object_store] failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectStoreError {
+    /// The store isn't part of the transaction's scope
+    StoreNotFound(StoreNotFound),
+    /// Getting the store failed for another reason, e.g. because the transaction has finished
+    Dom(DomException),
+}
+
+impl From<StoreNotFound> for ObjectStoreError {
+    #[inline]
+    fn from(e: StoreNotFound) -> Self {
+        Self::StoreNotFound(e)
+    }
+}
+
+impl From<DomException> for ObjectStoreError {
+    #[inline]
+    fn from(e: DomException) -> Self {
+        Self::Dom(e)
+    }
+}
+
+impl From<ObjectStoreError> for DomException {
+    fn from(e: ObjectStoreError) -> Self {
+        match e {
+            ObjectStoreError::StoreNotFound(e) => e.into(),
+            ObjectStoreError::Dom(e) => e,
+        }
+    }
+}
+
+impl From<ObjectStoreError> for JsValue {
+    #[inline]
+    fn from(e: ObjectStoreError) -> Self {
+        DomException::from(e).into()
+    }
+}
+
+impl fmt::Display for ObjectStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StoreNotFound(e) => fmt::Display::fmt(e, f),
+            Self::Dom(e) => write!(f, "{}: {}", e.name(), e.message()),
+        }
+    }
+}
+
+impl std::error::Error for ObjectStoreError {}
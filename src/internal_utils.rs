@@ -1,28 +1,16 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::future::Future;
 use std::ops::Deref;
+use std::pin::Pin;
 use std::rc::Rc;
-use std::task::Waker;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
 
 use cfg_if::cfg_if;
 use wasm_bindgen::prelude::*;
 
-#[cfg(test)]
-pub(crate) async fn open_any_db() -> (crate::IdbDatabase, String) {
-    use crate::prelude::*;
-
-    let db = uuid::Uuid::new_v4().to_string();
-    let store = uuid::Uuid::new_v4().to_string();
-    let mut req = crate::IdbDatabase::open(&db).expect("db open");
-    let store_cloned = store.clone();
-    req.set_on_upgrade_needed(Some(move |evt: &IdbVersionChangeEvent| {
-        evt.db().create_object_store(&store_cloned)?;
-        Ok(())
-    }));
-
-    (req.into_future().await.expect("fut"), store)
-}
-
 /// unwrap_unchecked if running in nightly, else just unwrap
+#[cfg(not(feature = "no-panic"))]
 #[inline]
 pub(crate) fn safe_unwrap_option<T>(option: Option<T>) -> T {
     cfg_if! {
@@ -34,18 +22,38 @@ pub(crate) fn safe_unwrap_option<T>(option: Option<T>) -> T {
     }
 }
 
-/// unwrap_unchecked if running in nightly, else just unwrap
+/// Unwrap an option that is only ever `None` if the browser misbehaves. With the `no-panic`
+/// feature that becomes an `UnknownError` instead of a panic.
 #[inline]
-pub(crate) fn safe_unwrap_result<O, E: std::fmt::Debug>(result: Result<O, E>) -> O {
+pub(crate) fn require<T>(option: Option<T>) -> Result<T, web_sys::DomException> {
     cfg_if! {
-        if #[cfg(feature = "nightly")] {
-            unsafe { result.unwrap_unchecked() }
+        if #[cfg(feature = "no-panic")] {
+            option.ok_or_else(unexpected_state)
         } else {
-            result.unwrap()
+            Ok(safe_unwrap_option(option))
         }
     }
 }
 
+#[cfg(feature = "no-panic")]
+#[cold]
+fn unexpected_state() -> web_sys::DomException {
+    dom_exception("Unexpected request state", "UnknownError")
+}
+
+/// Construct a [DomException][web_sys::DomException] without panicking, falling back to a plain
+/// JS `Error`, which has the same `name` and `message` properties, if the constructor throws
+#[cold]
+pub(crate) fn dom_exception(message: &str, name: &str) -> web_sys::DomException {
+    use wasm_bindgen::JsCast;
+
+    web_sys::DomException::new_with_message_and_name(message, name).unwrap_or_else(|_| {
+        let err = js_sys::Error::new(message);
+        err.set_name(name);
+        err.unchecked_into()
+    })
+}
+
 /// Wake the given option ref cell
 pub(crate) fn wake(waker: &RefCell<Option<Waker>>) {
     if let Some(w) = waker.borrow().deref() {
@@ -53,6 +61,55 @@ pub(crate) fn wake(waker: &RefCell<Option<Waker>>) {
     }
 }
 
+/// What a JS closure handed to the browser was created for, as counted in
+/// [leak reports](crate::leak_audit)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClosureKind {
+    /// Open, upgrade, blocked and version change callbacks on a database
+    Database,
+    /// Transaction completion and error listeners
+    Transaction,
+    /// Request success and error listeners
+    Request,
+    /// Anything else, e.g. page lifecycle listeners that live as long as the page does
+    Other,
+}
+
+/// Moved into a closure when it's created so that, with the `leak-audit` feature, the closure
+/// counts as outstanding until the browser side lets go of it and its Rust half gets dropped.
+/// Zero-sized and free otherwise.
+#[must_use]
+pub(crate) struct ClosureToken {
+    #[cfg(feature = "leak-audit")]
+    kind: ClosureKind,
+}
+
+impl ClosureToken {
+    #[inline]
+    pub(crate) fn new(kind: ClosureKind) -> Self {
+        cfg_if! {
+            if #[cfg(feature = "leak-audit")] {
+                crate::leak_audit::allocated(kind);
+                Self { kind }
+            } else {
+                let _ = kind;
+                Self {}
+            }
+        }
+    }
+
+    /// Reference the token from inside a `move` closure, making the closure own it
+    #[inline]
+    pub(crate) fn hold(&self) {}
+}
+
+#[cfg(feature = "leak-audit")]
+impl Drop for ClosureToken {
+    fn drop(&mut self) {
+        crate::leak_audit::released(self.kind);
+    }
+}
+
 /// Return `None` if `val` is undefined, else `Some(val)`
 #[inline]
 pub(crate) fn optional_jsvalue_undefined(val: JsValue) -> Option<JsValue> {
@@ -63,6 +120,159 @@ pub(crate) fn optional_jsvalue_undefined(val: JsValue) -> Option<JsValue> {
     }
 }
 
+/// Convert an arbitrary JS error, e.g. a rejected non-IDB promise, into a [DomException]
+pub(crate) fn js_error_into_dom_exception(err: JsValue) -> web_sys::DomException {
+    use wasm_bindgen::JsCast;
+
+    match err.dyn_into::<web_sys::DomException>() {
+        Ok(xc) => xc,
+        Err(err) => {
+            let (message, name) = match err.dyn_ref::<js_sys::Error>() {
+                Some(err) => (String::from(err.message()), String::from(err.name())),
+                None => (
+                    err.as_string()
+                        .unwrap_or_else(|| String::from("Unknown error")),
+                    String::from("UnknownError"),
+                ),
+            };
+            dom_exception(&message, &name)
+        }
+    }
+}
+
+/// Await a JS promise, converting rejections into a [DomException][web_sys::DomException]
+pub(crate) async fn await_promise(
+    promise: js_sys::Promise,
+) -> Result<JsValue, web_sys::DomException> {
+    wasm_bindgen_futures::JsFuture::from(promise)
+        .await
+        .map_err(js_error_into_dom_exception)
+}
+
+/// A `setTimeout` on the global scope, so that it works in windows and workers alike. The
+/// timeout gets cleared when the timer is dropped before it fires.
+pub(crate) struct Timer {
+    handle: JsValue,
+    fired: Rc<Cell<bool>>,
+    waker: Rc<RefCell<Option<Waker>>>,
+    _callback: Closure<dyn FnMut()>,
+}
+
+impl Timer {
+    pub(crate) fn new(duration: Duration) -> Result<Self, web_sys::DomException> {
+        let fired = Rc::new(Cell::new(false));
+        let waker = create_lazy_ref_cell();
+        let callback = {
+            let fired = fired.clone();
+            let waker = waker.clone();
+            let token = ClosureToken::new(ClosureKind::Other);
+            Closure::wrap(Box::new(move || {
+                token.hold();
+                fired.set(true);
+                wake(&waker);
+            }) as Box<dyn FnMut()>)
+        };
+        let ms = duration.as_millis().min(i32::MAX as u128) as i32;
+        let handle = global_function("setTimeout")?
+            .call2(&js_sys::global(), callback.as_ref(), &ms.into())
+            .map_err(js_error_into_dom_exception)?;
+
+        Ok(Self {
+            handle,
+            fired,
+            waker,
+            _callback: callback,
+        })
+    }
+}
+
+impl Future for Timer {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.fired.get() {
+            Poll::Ready(())
+        } else {
+            self.waker.borrow_mut().replace(ctx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if !self.fired.get() {
+            if let Ok(clear) = global_function("clearTimeout") {
+                let _ = clear.call1(&js_sys::global(), &self.handle);
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for Timer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Timer")
+            .field("fired", &self.fired.get())
+            .finish()
+    }
+}
+
+fn global_function(name: &str) -> Result<js_sys::Function, web_sys::DomException> {
+    use wasm_bindgen::JsCast;
+
+    js_sys::Reflect::get(&js_sys::global(), &name.into())
+        .map_err(js_error_into_dom_exception)?
+        .dyn_into()
+        .map_err(|_| dom_exception(&format!("{} is not available", name), "NotSupportedError"))
+}
+
+/// Resolves to `None` if the timer fires before the future completes. The timer gets cleared
+/// once the future completes or the whole thing is dropped.
+#[derive(Debug)]
+pub(crate) struct WithTimeout<F> {
+    fut: Pin<Box<F>>,
+    timer: Option<Timer>,
+}
+
+impl<F: Future> WithTimeout<F> {
+    pub(crate) fn new(fut: F, timeout: Option<Duration>) -> Result<Self, web_sys::DomException> {
+        Ok(Self {
+            fut: Box::pin(fut),
+            timer: timeout.map(Timer::new).transpose()?,
+        })
+    }
+}
+
+impl<F: Future> Future for WithTimeout<F> {
+    type Output = Option<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Poll::Ready(out) = self.fut.as_mut().poll(ctx) {
+            self.timer = None;
+            return Poll::Ready(Some(out));
+        }
+        match self.timer.as_mut() {
+            Some(timer) => match Pin::new(timer).poll(ctx) {
+                Poll::Ready(()) => {
+                    self.timer = None;
+                    Poll::Ready(None)
+                }
+                Poll::Pending => Poll::Pending,
+            },
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Check whether two IndexedDB keys are equal
+#[cfg(any(feature = "indices", feature = "staged"))]
+pub(crate) fn keys_eq(a: &JsValue, b: &JsValue) -> bool {
+    matches!(
+        crate::idb_database::factory().map(|f| f.cmp(a, b)),
+        Ok(Ok(0))
+    )
+}
+
 #[inline]
 pub(crate) fn create_lazy_ref_cell<T>() -> Rc<RefCell<Option<T>>> {
     Rc::new(RefCell::new(None))
@@ -108,6 +318,42 @@ pub mod test {
         });
     }
 
+    pub mod dom_exception {
+        test_mod_init!();
+
+        test_case!(name_and_message => {
+            let err = dom_exception("msg", "DataError");
+            assert_eq!(err.name(), "DataError", "name");
+            assert_eq!(err.message(), "msg", "message");
+        });
+
+        #[cfg(feature = "no-panic")]
+        test_case!(require_none => {
+            let err = require::<u8>(None).expect_err("require");
+            assert_eq!(err.name(), "UnknownError");
+        });
+    }
+
+    pub mod with_timeout {
+        use std::future::pending;
+
+        test_mod_init!();
+
+        test_case!(async completes => {
+            let out = WithTimeout::new(async { 1 }, Some(Duration::from_secs(60)))
+                .expect("timer")
+                .await;
+            assert_eq!(out, Some(1));
+        });
+
+        test_case!(async times_out => {
+            let out = WithTimeout::new(pending::<()>(), Some(Duration::from_millis(5)))
+                .expect("timer")
+                .await;
+            assert_eq!(out, None);
+        });
+    }
+
     pub mod optional_jsvalue_undefined {
         test_mod_init!();
 
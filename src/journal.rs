@@ -0,0 +1,318 @@
+//! A write-ahead journal for multi-step operations, such as imports or data migrations, that
+//! need to survive the tab getting killed midway.
+//!
+//! Before an operation starts, its intention gets recorded with [Journal::begin]. As it
+//! progresses, it [checkpoints][JournalEntry::checkpoint] how far it got, and once it's done it
+//! [completes][JournalEntry::complete], removing the entry. Anything still in the journal on the
+//! next startup was interrupted and can be [replayed][Journal::replay]: resumed from its last
+//! checkpoint or rolled back, depending on what the operation calls for.
+//!
+//! The journal store must use out-of-line keys.
+//!
+//! [checkpoint][JournalEntry::checkpoint] and [complete][JournalEntry::complete] commit in
+//! transactions of their own, so a step can commit and the tab get killed before the checkpoint
+//! after it does. Replayed operations then redo that step, which therefore has to be idempotent,
+//! e.g. by putting rather than adding records. Steps that can't be written that way should
+//! checkpoint in their own transaction instead, with [checkpoint_in][JournalEntry::checkpoint_in].
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::journal::{Journal, JournalEntry};
+//! use wasm_bindgen::prelude::*;
+//! use web_sys::DomException;
+//!
+//! async fn import(db: &IdbDatabase, mut entry: JournalEntry) -> Result<(), DomException> {
+//!     let mut next = entry.progress().and_then(|p| p.as_f64()).unwrap_or(0.0) as u32;
+//!     while next < 1000 {
+//!         // ...import a chunk of records...
+//!         next += 100;
+//!         entry.checkpoint(db, &next.into()).await?;
+//!     }
+//!     entry.complete(db).await
+//! }
+//!
+//! async fn example(db: &IdbDatabase) -> Result<(), DomException> {
+//!     let journal = Journal::new("journal");
+//!
+//!     // On startup, resume any import that got interrupted
+//!     journal.replay(db, |entry| import(db, entry)).await?;
+//!
+//!     let entry = journal.begin(db, "import", &JsValue::from_str("users.json")).await?;
+//!     import(db, entry).await
+//! }
+//! ```
+
+use std::future::Future;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::idb_database::IdbDatabase;
+use crate::idb_query_source::IdbQuerySource;
+use crate::idb_transaction::IdbTransaction;
+use crate::internal_utils::{dom_exception, optional_jsvalue_undefined};
+use crate::keygen::Ulid;
+
+/// A write-ahead journal kept in an object store. Steps between separately committed checkpoints
+/// may be replayed and must be idempotent; see the [module docs][self].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Journal {
+    store: String,
+}
+
+/// An operation recorded in a [Journal]
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    store: String,
+    id: String,
+    kind: String,
+    payload: JsValue,
+    progress: Option<JsValue>,
+}
+
+impl Journal {
+    /// Journal in the given store
+    pub fn new(store: &str) -> Self {
+        Self {
+            store: store.into(),
+        }
+    }
+
+    /// Record the intention to run an operation of the given kind, described by the payload
+    pub async fn begin(
+        &self,
+        db: &IdbDatabase,
+        kind: &str,
+        payload: &JsValue,
+    ) -> Result<JournalEntry, DomException> {
+        let entry = JournalEntry {
+            store: self.store.clone(),
+            id: next_id().to_string(),
+            kind: kind.into(),
+            payload: payload.clone(),
+            progress: None,
+        };
+        entry.write(db).await?;
+        Ok(entry)
+    }
+
+    /// Every operation that was begun but not completed, oldest first
+    pub async fn pending(&self, db: &IdbDatabase) -> Result<Vec<JournalEntry>, DomException> {
+        let tx = db.transaction_on_one(&self.store)?;
+        let store = tx.object_store(&self.store)?;
+        let keys = store.get_all_keys()?.await?;
+        let values = store.get_all()?.await?;
+
+        keys.iter()
+            .zip(values.iter())
+            .map(|(id, record)| JournalEntry::from_js(&self.store, &id, &record))
+            .collect()
+    }
+
+    /// Hand every pending operation to the handler, oldest first, e.g. to resume or roll it back.
+    /// The handler is responsible for [completing][JournalEntry::complete] the entry; entries it
+    /// doesn't complete get replayed again next time. Resolves to the number of entries replayed.
+    pub async fn replay<F, Fut>(
+        &self,
+        db: &IdbDatabase,
+        mut handler: F,
+    ) -> Result<usize, DomException>
+    where
+        F: FnMut(JournalEntry) -> Fut,
+        Fut: Future<Output = Result<(), DomException>>,
+    {
+        let pending = self.pending(db).await?;
+        let count = pending.len();
+        for entry in pending {
+            handler(entry).await?;
+        }
+        Ok(count)
+    }
+}
+
+impl JournalEntry {
+    /// Unique ID of the entry. IDs sort by the time the operation was begun.
+    #[inline]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Kind of operation, as given to [Journal::begin]
+    #[inline]
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// Description of the operation, as given to [Journal::begin]
+    #[inline]
+    pub fn payload(&self) -> &JsValue {
+        &self.payload
+    }
+
+    /// The last checkpoint, if any
+    #[inline]
+    pub fn progress(&self) -> Option<&JsValue> {
+        self.progress.as_ref()
+    }
+
+    /// Persist how far the operation has got, in a transaction of its own
+    pub async fn checkpoint(
+        &mut self,
+        db: &IdbDatabase,
+        progress: &JsValue,
+    ) -> Result<(), DomException> {
+        self.progress = Some(progress.clone());
+        self.write(db).await
+    }
+
+    /// Persist how far the operation has got as part of the given readwrite transaction, which
+    /// must include the journal store. The checkpoint commits or aborts together with the step's
+    /// own writes, so the step doesn't need to be idempotent.
+    pub fn checkpoint_in(
+        &mut self,
+        tx: &IdbTransaction,
+        progress: &JsValue,
+    ) -> Result<(), DomException> {
+        self.progress = Some(progress.clone());
+        tx.object_store(&self.store)?
+            .put_key_val_owned(self.id.as_str(), &self.to_js()?)?;
+        Ok(())
+    }
+
+    /// Mark the operation as done, removing it from the journal
+    pub async fn complete(self, db: &IdbDatabase) -> Result<(), DomException> {
+        let tx = db.transaction_on_one_with_mode(&self.store, IdbTransactionMode::Readwrite)?;
+        tx.object_store(&self.store)?
+            .delete_owned(self.id.as_str())?;
+        tx.await.into_result()
+    }
+
+    async fn write(&self, db: &IdbDatabase) -> Result<(), DomException> {
+        let tx = db.transaction_on_one_with_mode(&self.store, IdbTransactionMode::Readwrite)?;
+        tx.object_store(&self.store)?
+            .put_key_val_owned(self.id.as_str(), &self.to_js()?)?;
+        tx.await.into_result()
+    }
+
+    fn to_js(&self) -> Result<js_sys::Object, DomException> {
+        let record = js_sys::Object::new();
+        js_sys::Reflect::set(&record, &"kind".into(), &JsValue::from_str(&self.kind))?;
+        js_sys::Reflect::set(&record, &"payload".into(), &self.payload)?;
+        if let Some(progress) = self.progress.as_ref() {
+            js_sys::Reflect::set(&record, &"progress".into(), progress)?;
+        }
+        Ok(record)
+    }
+
+    fn from_js(store: &str, id: &JsValue, record: &JsValue) -> Result<Self, DomException> {
+        let malformed = || dom_exception("Malformed journal entry", "DataError");
+        let field = |name: &str| {
+            js_sys::Reflect::get(record, &name.into())
+                .map(optional_jsvalue_undefined)
+                .map_err(|_| malformed())
+        };
+        Ok(Self {
+            store: store.into(),
+            id: id.as_string().ok_or_else(malformed)?,
+            kind: field("kind")?
+                .and_then(|kind| kind.as_string())
+                .ok_or_else(malformed)?,
+            payload: field("payload")?.unwrap_or(JsValue::UNDEFINED),
+            progress: field("progress")?,
+        })
+    }
+}
+
+thread_local! {
+    static LAST_ID: std::cell::Cell<u128> = const { std::cell::Cell::new(0) };
+}
+
+/// A new ULID, bumped if needed so that entries begun within the same millisecond still sort in
+/// order
+fn next_id() -> Ulid {
+    let id = Ulid::new().as_u128();
+    LAST_ID.with(|last| {
+        let id = id.max(last.get() + 1);
+        last.set(id);
+        Ulid::from_parts((id >> 80) as u64, id)
+    })
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::test_utils::open_db_with_stores;
+
+    use super::*;
+
+    test_mod_init!();
+
+    async fn open_db() -> IdbDatabase {
+        open_db_with_stores(&["journal", "data"]).await
+    }
+
+    async fn progress(db: &IdbDatabase, journal: &Journal) -> Option<JsValue> {
+        let pending = journal.pending(db).await.expect("pending");
+        pending[0].progress().cloned()
+    }
+
+    test_case!(async checkpoint_in_step_transaction => {
+        let db = open_db().await;
+        let journal = Journal::new("journal");
+        let mut entry = journal.begin(&db, "import", &JsValue::NULL).await.expect("begin");
+
+        let tx = db
+            .transaction_on_multi_with_mode(&["journal", "data"], IdbTransactionMode::Readwrite)
+            .expect("tx");
+        tx.object_store("data").unwrap().put_key_val_owned(1u8, &JsValue::from(1u8)).expect("put");
+        entry.checkpoint_in(&tx, &JsValue::from(1u8)).expect("checkpoint");
+        tx.abort().expect("abort");
+        assert_eq!(progress(&db, &journal).await, None, "aborted with the step");
+
+        let tx = db
+            .transaction_on_multi_with_mode(&["journal", "data"], IdbTransactionMode::Readwrite)
+            .expect("tx 2");
+        tx.object_store("data").unwrap().put_key_val_owned(1u8, &JsValue::from(1u8)).expect("put 2");
+        entry.checkpoint_in(&tx, &JsValue::from(1u8)).expect("checkpoint 2");
+        tx.await.into_result().expect("commit");
+        assert_eq!(progress(&db, &journal).await, Some(JsValue::from(1u8)), "committed with the step");
+    });
+
+    test_case!(async resume_interrupted => {
+        let db = open_db().await;
+        let journal = Journal::new("journal");
+
+        let mut entry = journal.begin(&db, "import", &"users.json".into()).await.expect("begin");
+        entry.checkpoint(&db, &JsValue::from(3u8)).await.expect("checkpoint");
+        // Simulate the tab getting killed
+        drop(entry);
+        journal.begin(&db, "migrate", &JsValue::NULL).await.expect("begin 2");
+
+        let pending = journal.pending(&db).await.expect("pending");
+        assert_eq!(pending.len(), 2, "pending");
+        assert_eq!(pending[0].kind(), "import", "kind");
+        assert_eq!(pending[0].payload(), &JsValue::from_str("users.json"), "payload");
+        assert_eq!(pending[0].progress(), Some(&JsValue::from(3u8)), "progress");
+        assert_eq!(pending[1].progress(), None, "no progress");
+
+        let mut seen = Vec::new();
+        let replayed = journal
+            .replay(&db, |entry| {
+                seen.push(entry.kind().to_string());
+                let db = &db;
+                async move {
+                    if entry.kind() == "import" {
+                        entry.complete(db).await?;
+                    }
+                    Ok(())
+                }
+            })
+            .await
+            .expect("replay");
+        assert_eq!(replayed, 2, "replayed");
+        assert_eq!(seen, vec!["import", "migrate"], "order");
+
+        let pending = journal.pending(&db).await.expect("pending 2");
+        assert_eq!(pending.len(), 1, "left");
+        assert_eq!(pending[0].kind(), "migrate", "left kind");
+    });
+}
@@ -0,0 +1,191 @@
+//! Key ordering utilities
+//!
+//! IndexedDB orders keys by type first - numbers, then dates, strings, binary keys and finally
+//! arrays - and then by value. [compare_keys] asks the browser's own comparator. For the Rust-side
+//! [key][crate::IdbKey] types, [Ord]/[PartialOrd] match that comparator with one exception:
+//! strings are compared by UTF-16 code unit in IndexedDB but by UTF-8 byte in Rust, which only
+//! differs for characters outside the Basic Multilingual Plane. Use [cmp_str] to order strings
+//! exactly like IndexedDB does.
+
+use std::cmp::Ordering;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+use crate::idb_key::IdbKey;
+
+/// Compare two keys using [IDBFactory.cmp](https://developer.mozilla.org/en-US/docs/Web/API/IDBFactory/cmp).
+/// Fails with a `DataError` if either isn't a valid key.
+pub fn compare_keys<A: IdbKey, B: IdbKey>(a: &A, b: &B) -> Result<Ordering, DomException> {
+    let res = crate::idb_database::factory()?.cmp(&a.to_js_key(), &b.to_js_key())?;
+    Ok(res.cmp(&0))
+}
+
+/// Compare two strings the way IndexedDB does: by UTF-16 code unit
+pub fn cmp_str(a: &str, b: &str) -> Ordering {
+    a.encode_utf16().cmp(b.encode_utf16())
+}
+
+/// A binary key, stored as an `ArrayBuffer`. Binary keys sort after strings and before arrays and
+/// are compared byte by byte, which matches the [Ord] of the wrapped bytes.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct BinaryKey(pub Vec<u8>);
+
+impl IdbKey for BinaryKey {
+    fn to_js_key(&self) -> JsValue {
+        js_sys::Uint8Array::from(self.0.as_slice()).buffer().into()
+    }
+
+    fn from_js_key(key: JsValue) -> Option<Self> {
+        let buf: js_sys::ArrayBuffer = key.dyn_into().ok()?;
+        Some(Self(js_sys::Uint8Array::new(&buf).to_vec()))
+    }
+}
+
+impl From<Vec<u8>> for BinaryKey {
+    #[inline]
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    test_mod_init!();
+
+    /// Deterministic xorshift generator so that failures can be reproduced
+    struct Gen(u64);
+
+    impl Gen {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, max: u64) -> u64 {
+            self.next() % max
+        }
+
+        fn num(&mut self) -> f64 {
+            match self.below(4) {
+                0 => self.below(10) as f64,
+                1 => -(self.below(1000) as f64),
+                2 => (self.next() as i64 as f64) / 3.0,
+                _ => f64::from_bits(self.next() & !(0x7ff << 52) | (0x3ff << 52)),
+            }
+        }
+
+        fn string(&mut self) -> String {
+            let len = self.below(6);
+            (0..len)
+                .filter_map(|_| {
+                    let code = match self.below(3) {
+                        0 => 0x61 + self.below(4) as u32,
+                        1 => 0xe000 + self.below(0x2000) as u32,
+                        _ => 0x10000 + self.below(0x1000) as u32,
+                    };
+                    std::char::from_u32(code)
+                })
+                .collect()
+        }
+
+        fn bytes(&mut self) -> Vec<u8> {
+            let len = self.below(5);
+            (0..len).map(|_| self.below(4) as u8).collect()
+        }
+    }
+
+    const ITERATIONS: usize = 200;
+
+    fn check<K: IdbKey + std::fmt::Debug>(a: &K, b: &K, rust: Ordering) {
+        let idb = compare_keys(a, b).expect("compare_keys");
+        assert_eq!(idb, rust, "{:?} vs {:?}", a, b);
+    }
+
+    test_case!(numbers => {
+        let mut gen = Gen(1);
+        for _ in 0..ITERATIONS {
+            let (a, b) = (gen.num(), gen.num());
+            check(&a, &b, a.partial_cmp(&b).unwrap());
+        }
+        for _ in 0..ITERATIONS {
+            let (a, b) = (gen.next() as i32, gen.next() as i32);
+            check(&a, &b, a.cmp(&b));
+        }
+    });
+
+    test_case!(strings => {
+        let mut gen = Gen(2);
+        for _ in 0..ITERATIONS {
+            let (a, b) = (gen.string(), gen.string());
+            check(&a, &b, cmp_str(&a, &b));
+        }
+    });
+
+    test_case!(str_differs_from_rust_outside_bmp => {
+        let (bmp, astral) = ("\u{ffff}".to_string(), "\u{10000}".to_string());
+        assert_eq!(bmp.cmp(&astral), Ordering::Less, "rust");
+        assert_eq!(cmp_str(&bmp, &astral), Ordering::Greater, "cmp_str");
+        check(&bmp, &astral, Ordering::Greater);
+    });
+
+    test_case!(dates => {
+        let mut gen = Gen(3);
+        for _ in 0..ITERATIONS {
+            let (a, b) = (gen.below(1 << 40) as f64, gen.below(1 << 40) as f64);
+            let (da, db) = (js_sys::Date::new(&a.into()), js_sys::Date::new(&b.into()));
+            check(&da, &db, a.partial_cmp(&b).unwrap());
+        }
+    });
+
+    test_case!(binary => {
+        let mut gen = Gen(4);
+        for _ in 0..ITERATIONS {
+            let (a, b) = (BinaryKey(gen.bytes()), BinaryKey(gen.bytes()));
+            check(&a, &b, a.cmp(&b));
+        }
+    });
+
+    test_case!(binary_round_trip => {
+        let key = BinaryKey(vec![0, 1, 255]);
+        assert_eq!(BinaryKey::from_js_key(key.to_js_key()), Some(key));
+    });
+
+    test_case!(arrays => {
+        let mut gen = Gen(5);
+        for _ in 0..ITERATIONS {
+            let len_a = gen.below(4) as usize;
+            let len_b = gen.below(4) as usize;
+            let a: Vec<u32> = (0..len_a).map(|_| gen.below(3) as u32).collect();
+            let b: Vec<u32> = (0..len_b).map(|_| gen.below(3) as u32).collect();
+            check(&a, &b, a.cmp(&b));
+        }
+        for _ in 0..ITERATIONS {
+            let a = (gen.below(3) as u32, gen.num());
+            let b = (gen.below(3) as u32, gen.num());
+            check(&a, &b, a.partial_cmp(&b).unwrap());
+        }
+    });
+
+    test_case!(types => {
+        let keys: Vec<JsValue> = vec![
+            JsValue::from_f64(1e9),
+            js_sys::Date::new(&JsValue::from_f64(0.0)).into(),
+            JsValue::from_str(""),
+            BinaryKey(vec![]).to_js_key(),
+            js_sys::Array::new().into(),
+        ];
+        for (i, a) in keys.iter().enumerate() {
+            for (j, b) in keys.iter().enumerate() {
+                check(a, b, i.cmp(&j));
+            }
+        }
+    });
+
+    test_case!(invalid_key => {
+        let err = compare_keys(&JsValue::NULL, &JsValue::from_f64(1.0)).expect_err("null key");
+        assert_eq!(err.name(), "DataError");
+    });
+}
@@ -0,0 +1,206 @@
+//! Unique key generators
+//!
+//! Keys that sort in creation order are the best default for IndexedDB: new records get appended
+//! to the end of the store's B-tree and range queries over creation time come for free.
+//!
+//! - [Ulid] - 26 character, lexicographically sortable string keys
+//! - [UuidKey] - UUIDv4 (random) or UUIDv7 (time-ordered) string keys. Features required: `uuid`
+
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+const CROCKFORD_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const ULID_LEN: usize = 26;
+const TIMESTAMP_BITS: u32 = 48;
+const RANDOM_BITS: u32 = 80;
+
+/// A [ULID](https://github.com/ulid/spec): a 48-bit millisecond timestamp followed by 80 random
+/// bits, encoded as 26 Crockford base32 characters. Lexicographic ordering of the encoded string
+/// matches creation order to the millisecond; ULIDs created within the same millisecond are
+/// ordered randomly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ulid(u128);
+
+impl Ulid {
+    /// Generate a new ULID using the current time and `Math.random()`
+    pub fn new() -> Self {
+        let mut random: u128 = 0;
+        for _ in 0..(RANDOM_BITS / 8) {
+            random = (random << 8) | (js_sys::Math::random() * 256.0) as u128;
+        }
+        Self::from_parts(js_sys::Date::now() as u64, random)
+    }
+
+    /// Construct a ULID from a millisecond timestamp and random component. Bits beyond the
+    /// 48 timestamp bits and 80 random bits are discarded.
+    pub fn from_parts(timestamp_ms: u64, random: u128) -> Self {
+        let timestamp = (timestamp_ms as u128) & ((1 << TIMESTAMP_BITS) - 1);
+        let random = random & ((1 << RANDOM_BITS) - 1);
+        Self((timestamp << RANDOM_BITS) | random)
+    }
+
+    /// The millisecond timestamp the ULID was created with
+    #[inline]
+    pub fn timestamp_ms(&self) -> u64 {
+        (self.0 >> RANDOM_BITS) as u64
+    }
+
+    /// The raw 128-bit value
+    #[inline]
+    pub fn as_u128(&self) -> u128 {
+        self.0
+    }
+}
+
+impl Default for Ulid {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Ulid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = [0u8; ULID_LEN];
+        let mut value = self.0;
+        for c in out.iter_mut().rev() {
+            *c = CROCKFORD_ALPHABET[(value & 0x1f) as usize];
+            value >>= 5;
+        }
+
+        // The alphabet is pure ASCII
+        f.write_str(std::str::from_utf8(&out).map_err(|_| fmt::Error)?)
+    }
+}
+
+impl From<Ulid> for String {
+    #[inline]
+    fn from(ulid: Ulid) -> Self {
+        ulid.to_string()
+    }
+}
+
+impl From<Ulid> for JsValue {
+    #[inline]
+    fn from(ulid: Ulid) -> Self {
+        JsValue::from_str(&ulid.to_string())
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "uuid")] {
+        /// A UUID usable directly as a store key. It is stored as its lowercase hyphenated string
+        /// form, which preserves the ordering of [UUIDv7][UuidKey::new_v7] keys.
+        ///
+        /// Features required: `uuid`
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct UuidKey(uuid::Uuid);
+
+        impl UuidKey {
+            /// Generate a random UUIDv4 key
+            #[inline]
+            pub fn new_v4() -> Self {
+                Self(uuid::Uuid::new_v4())
+            }
+
+            /// Generate a time-ordered UUIDv7 key: a 48-bit millisecond timestamp followed by
+            /// random bits
+            pub fn new_v7() -> Self {
+                Self::v7_from_parts(js_sys::Date::now() as u64, *uuid::Uuid::new_v4().as_bytes())
+            }
+
+            /// Construct a UUIDv7 from a millisecond timestamp and 16 random bytes, of which the
+            /// first 6 and the version & variant bits get overwritten.
+            pub fn v7_from_parts(timestamp_ms: u64, random: [u8; 16]) -> Self {
+                let mut bytes = random;
+                bytes[..6].copy_from_slice(&timestamp_ms.to_be_bytes()[2..]);
+                bytes[6] = 0x70 | (bytes[6] & 0x0f);
+                bytes[8] = 0x80 | (bytes[8] & 0x3f);
+                Self(uuid::Uuid::from_bytes(bytes))
+            }
+
+            /// The wrapped UUID
+            #[inline]
+            pub fn as_uuid(&self) -> &uuid::Uuid {
+                &self.0
+            }
+        }
+
+        impl From<uuid::Uuid> for UuidKey {
+            #[inline]
+            fn from(uuid: uuid::Uuid) -> Self {
+                Self(uuid)
+            }
+        }
+
+        impl From<UuidKey> for uuid::Uuid {
+            #[inline]
+            fn from(key: UuidKey) -> Self {
+                key.0
+            }
+        }
+
+        impl fmt::Display for UuidKey {
+            #[inline]
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Display::fmt(&self.0.to_hyphenated_ref(), f)
+            }
+        }
+
+        impl From<UuidKey> for JsValue {
+            #[inline]
+            fn from(key: UuidKey) -> Self {
+                JsValue::from_str(&key.to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    pub mod ulid {
+        test_mod_init!();
+
+        test_case!(format => {
+            let ulid = Ulid::from_parts(1469918176385, 0);
+            assert_eq!(ulid.to_string(), "01ARYZ6S410000000000000000");
+        });
+
+        test_case!(timestamp_round_trip => {
+            let ulid = Ulid::new();
+            let now = js_sys::Date::now() as u64;
+            assert!(now - ulid.timestamp_ms() < 1000, "timestamp");
+            assert_eq!(ulid.to_string().len(), 26, "length");
+        });
+
+        test_case!(sorts_by_time => {
+            let earlier = Ulid::from_parts(1000, u128::MAX).to_string();
+            let later = Ulid::from_parts(1001, 0).to_string();
+            assert!(earlier < later);
+        });
+
+        test_case!(into_js_value => {
+            let ulid = Ulid::from_parts(0, 1);
+            assert_eq!(JsValue::from(ulid).as_string(), Some(String::from("00000000000000000000000001")));
+        });
+    }
+
+    #[cfg(feature = "uuid")]
+    pub mod uuid_key {
+        test_mod_init!();
+
+        test_case!(v7_layout => {
+            let key = UuidKey::v7_from_parts(0x0102_0304_0506, [0xff; 16]);
+            assert_eq!(key.to_string(), "01020304-0506-7fff-bfff-ffffffffffff");
+        });
+
+        test_case!(v7_sorts_by_time => {
+            let earlier = UuidKey::v7_from_parts(1000, [0xff; 16]).to_string();
+            let later = UuidKey::v7_from_parts(1001, [0; 16]).to_string();
+            assert!(earlier < later);
+        });
+    }
+}
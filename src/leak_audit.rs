@@ -0,0 +1,155 @@
+//! Counting the JS closures the crate hands to the browser
+//!
+//! Features required: `leak-audit`
+//!
+//! Every request, transaction and open request gets Rust closures attached as JS event listeners.
+//! Each of them is freed once the future or handle that owns it is dropped, unless something
+//! keeps that owner alive, e.g. a transaction stashed in a long-lived struct or a future that
+//! never completes. With this feature the crate counts every closure it creates and every one
+//! that gets dropped, so an app can check at quiet points of a long session that the number
+//! still outstanding isn't growing.
+//!
+//! ```rust
+//! use indexed_db_futures::leak_audit::leak_report;
+//!
+//! fn leak_warning() -> Option<String> {
+//!     let report = leak_report();
+//!     if report.outstanding() > 0 {
+//!         Some(report.to_string())
+//!     } else {
+//!         None
+//!     }
+//! }
+//! ```
+//!
+//! Listeners meant to last as long as the page does, such as the
+//! [page lifecycle](crate::page_lifecycle) hooks, show up as [Other][ClosureKind::Other].
+
+use std::cell::RefCell;
+use std::fmt;
+
+pub use crate::internal_utils::ClosureKind;
+
+thread_local! {
+    static COUNTS: RefCell<LeakReport> = RefCell::new(LeakReport::default());
+}
+
+/// How many closures of one [kind][ClosureKind] have been created and dropped
+///
+/// Features required: `leak-audit`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClosureCounts {
+    /// Closures created so far
+    pub created: u64,
+    /// Closures dropped so far
+    pub dropped: u64,
+}
+
+impl ClosureCounts {
+    /// Closures created but not dropped yet
+    #[inline]
+    pub fn outstanding(&self) -> u64 {
+        self.created.saturating_sub(self.dropped)
+    }
+}
+
+/// A snapshot of the closure counts, from [leak_report]
+///
+/// Features required: `leak-audit`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LeakReport {
+    /// Open, upgrade, blocked and version change callbacks
+    pub database: ClosureCounts,
+    /// Transaction completion and error listeners
+    pub transaction: ClosureCounts,
+    /// Request success and error listeners
+    pub request: ClosureCounts,
+    /// Everything else
+    pub other: ClosureCounts,
+}
+
+impl LeakReport {
+    /// The counts for the given kind
+    pub fn get(&self, kind: ClosureKind) -> ClosureCounts {
+        match kind {
+            ClosureKind::Database => self.database,
+            ClosureKind::Transaction => self.transaction,
+            ClosureKind::Request => self.request,
+            ClosureKind::Other => self.other,
+        }
+    }
+
+    fn get_mut(&mut self, kind: ClosureKind) -> &mut ClosureCounts {
+        match kind {
+            ClosureKind::Database => &mut self.database,
+            ClosureKind::Transaction => &mut self.transaction,
+            ClosureKind::Request => &mut self.request,
+            ClosureKind::Other => &mut self.other,
+        }
+    }
+
+    /// Closures of any kind created but not dropped yet
+    pub fn outstanding(&self) -> u64 {
+        self.database.outstanding()
+            + self.transaction.outstanding()
+            + self.request.outstanding()
+            + self.other.outstanding()
+    }
+}
+
+impl fmt::Display for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} outstanding closures (database: {}, transaction: {}, request: {}, other: {})",
+            self.outstanding(),
+            self.database.outstanding(),
+            self.transaction.outstanding(),
+            self.request.outstanding(),
+            self.other.outstanding()
+        )
+    }
+}
+
+/// The closure counts so far
+///
+/// Features required: `leak-audit`
+pub fn leak_report() -> LeakReport {
+    COUNTS.with(|counts| *counts.borrow())
+}
+
+pub(crate) fn allocated(kind: ClosureKind) {
+    COUNTS.with(|counts| counts.borrow_mut().get_mut(kind).created += 1);
+}
+
+pub(crate) fn released(kind: ClosureKind) {
+    COUNTS.with(|counts| counts.borrow_mut().get_mut(kind).dropped += 1);
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::open_any_db;
+
+    use super::*;
+
+    test_mod_init!();
+
+    test_case!(async request_closures_are_dropped => {
+        let (db, store_name) = open_any_db().await;
+        let before = leak_report();
+        {
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).unwrap();
+            let store = tx.object_store(&store_name).unwrap();
+            store.put_key_val_owned("a", &JsValue::from(1)).unwrap().into_future().await.expect("put");
+            store.get_owned("a").unwrap().await.expect("get");
+            tx.await.into_result().expect("tx");
+        }
+        let after = leak_report();
+
+        assert!(after.request.created >= before.request.created + 4, "request closures counted");
+        assert!(after.transaction.created > before.transaction.created, "transaction closures counted");
+        assert_eq!(after.request.outstanding(), before.request.outstanding(), "request closures dropped");
+        assert_eq!(after.transaction.outstanding(), before.transaction.outstanding(), "transaction closures dropped");
+    });
+}
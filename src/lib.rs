@@ -22,7 +22,55 @@
 //!
 //! - `cursors` - Enable cursor support
 //! - `indices` - Enable index support
+//! - `cache-storage` - Enable [Cache Storage interop][crate::asset_cache]
+//! - `blob-streams` - Enable reading stored [Blob][web_sys::Blob]s as
+//!   [ReadableStream][web_sys::ReadableStream]s
+//! - `serde` - Enable serde-based reads and writes on typed stores, including
+//!   [validation][crate::validation]
+//! - `serde_json` - Enable converting between `serde_json` values and [store values][crate::values],
+//!   applying JSON Patches to records and loading [fixtures][crate::fixtures]. Implies `serde`.
+//! - `geo` - Enable [geohash indices][crate::geo] for location queries. Implies `indices`.
+//! - `uuid` - Enable UUID [key generation][crate::keygen::UuidKey]
+//! - `leak-audit` - Count the JS closures the crate creates and drops for a
+//!   [leak report][crate::leak_audit::leak_report]. Meant for debug builds.
+//! - `no-panic` - Return errors instead of panicking when the browser hands back something
+//!   unexpected in request, cursor and listener plumbing, keeping panic paths out of release
+//!   builds. Takes precedence over the unchecked unwraps of `nightly`.
 //! - `nightly` - Use `unsafe` nightly features where appropriate, such as [unwrap_unchecked][Option::unwrap_unchecked].
+//! - `streams` - Enable turning cursors into [Stream][futures_core::Stream]s. Implies `cursors`.
+//! - `tx-diagnostics` - Record where each write request on a transaction was made from, so that a
+//!   `TransactionInactiveError` names the `.await` that most likely let the transaction
+//!   auto-commit. Meant for debug builds.
+//! - `bench` - Enable [throughput measurements][crate::bench] of common access patterns, for
+//!   tracking performance regressions from `wasm-bindgen-test`. Not meant for release builds.
+//!
+//! Higher-level building blocks are opt-in, one feature each, so that they don't add to the binary
+//! of apps that don't use them:
+//!
+//! - `attachments` - [Blob attachments][crate::attachments] linked to parent records
+//! - `cas` - [Content-addressed blob storage][crate::cas]
+//! - `chunked` - [Large values stored in chunks][crate::chunked]
+//! - `counters` - [Counters][crate::counters] kept up to date with the writes to a store. Implies
+//!   `indices`.
+//! - `cross-db` - [Best-effort atomic writes][crate::cross_db] across several databases
+//! - `graph` - A [graph layer][crate::graph] over two object stores. Implies `indices`.
+//! - `history` - [Undo and redo][crate::history] for writes on selected object stores
+//! - `journal` - A [write-ahead journal][crate::journal] for multi-step operations
+//! - `local-storage` - One-time [migration of `localStorage`][crate::local_storage] entries
+//! - `page-lifecycle` - [Hooks][crate::page_lifecycle] that run when the page gets hidden. Implied
+//!   by `leak-audit`.
+//! - `rate-limit` - [Client-side rate limiting][crate::rate_limit] that survives reloads
+//! - `relation` - [Many-to-many relations][crate::relation] over a join store. Implies `indices`.
+//! - `retry` - [Retrying transactions][crate::retry] that fail for transient reasons
+//! - `spillover` - Large values [spilled over][crate::spillover] into the Origin Private File
+//!   System
+//! - `staged` - [Staging writes][crate::staged] in memory
+//! - `time-series` - [Time-series storage][crate::time_series]
+//! - `wipe` - [Erasing all of the origin's local data][crate::wipe]
+//! - `test-utils` - Export the [scaffolding][crate::test_utils] this crate's own browser tests
+//!   use, for downstream crates to test against a real IndexedDB. Implies `uuid`.
+//! - `schema` - Enable declarative [schemas][crate::schema], including Dexie schema string parsing.
+//!   Implies `indices`.
 //! - `default`:
 //!    - `cursors`
 //!    - `indices`
@@ -91,6 +139,7 @@ pub use js_sys;
 pub use web_sys;
 
 pub use idb_database::*;
+pub use idb_key::*;
 pub use idb_key_path::*;
 pub use idb_query_source::*;
 
@@ -103,28 +152,13 @@ macro_rules! test_mod_init {
         #[allow(unused_imports)]
         use {
             super::*,
+            crate::test_case,
             wasm_bindgen::{prelude::*, JsCast},
             wasm_bindgen_test::*,
         };
     };
 }
 
-#[cfg(test)]
-macro_rules! test_case {
-    ($name: ident => $body: block) => {
-        #[wasm_bindgen_test::wasm_bindgen_test]
-        fn $name() {
-            $body
-        }
-    };
-    (async $name: ident => $body: block) => {
-        #[wasm_bindgen_test::wasm_bindgen_test]
-        async fn $name() {
-            $body
-        }
-    };
-}
-
 macro_rules! impl_display_for_named {
     ($for: ty) => {
         impl std::fmt::Display for $for {
@@ -149,6 +183,7 @@ macro_rules! impl_query_source {
                         &self,
                     ) -> Result<$crate::request::IdbCursorWithValueFuture<Self>, web_sys::DomException>
                     {
+                        self.record_request();
                         let base = $crate::request::IdbCursorFuture::new(self.inner.open_cursor(), self)?;
                         Ok($crate::request::IdbCursorWithValueFuture::new(base))
                     }
@@ -156,6 +191,7 @@ macro_rules! impl_query_source {
                     fn open_cursor_with_range_and_direction<K: wasm_bindgen::JsCast>(&self, range: &K, direction: web_sys::IdbCursorDirection) -> Result<$crate::request::IdbCursorWithValueFuture<Self>, web_sys::DomException> {
                         #[allow(unused_imports)]
                         use wasm_bindgen::JsCast;
+                        self.record_request();
                         let base = self.inner.open_cursor_with_range_and_direction(range.unchecked_ref(), direction);
                         let base = $crate::request::IdbCursorFuture::new(base, self)?;
                         Ok($crate::request::IdbCursorWithValueFuture::new(base))
@@ -164,6 +200,7 @@ macro_rules! impl_query_source {
                     fn open_cursor_with_range<K: wasm_bindgen::JsCast>(&self, range: &K) -> Result<$crate::request::IdbCursorWithValueFuture<Self>, web_sys::DomException> {
                         #[allow(unused_imports)]
                         use wasm_bindgen::JsCast;
+                        self.record_request();
                         let base = self.inner.open_cursor_with_range(range.unchecked_ref());
                         let base = $crate::request::IdbCursorFuture::new(base, self)?;
                         Ok($crate::request::IdbCursorWithValueFuture::new(base))
@@ -172,15 +209,18 @@ macro_rules! impl_query_source {
                     fn open_key_cursor(
                         &self,
                     ) -> Result<$crate::request::IdbCursorFuture<Self>, web_sys::DomException> {
+                        self.record_request();
                         $crate::request::IdbCursorFuture::new(self.inner.open_key_cursor(), self)
                     }
 
                     fn open_key_cursor_with_range<K: wasm_bindgen::JsCast>(&self, range: &K) -> Result<$crate::request::IdbCursorFuture<Self>, web_sys::DomException> {
+                        self.record_request();
                         let base = self.inner.open_key_cursor_with_range(range.unchecked_ref());
                         $crate::request::IdbCursorFuture::new(base, self)
                     }
 
                     fn open_key_cursor_with_range_and_direction<K: wasm_bindgen::JsCast>(&self, range: &K, direction: web_sys::IdbCursorDirection) -> Result<$crate::request::IdbCursorFuture<Self>, web_sys::DomException> {
+                        self.record_request();
                         let base = self.inner.open_key_cursor_with_range_and_direction(range.unchecked_ref(), direction);
                         $crate::request::IdbCursorFuture::new(base, self)
                     }
@@ -194,6 +234,7 @@ macro_rules! impl_query_source {
             ) -> Result<$crate::request::OptionalJsValueFuture, web_sys::DomException> {
                 #[allow(unused_imports)]
                 use wasm_bindgen::JsCast;
+                self.record_request();
                 $crate::request::OptionalJsValueFuture::new(self.inner.get(key.unchecked_ref()))
             }
 
@@ -202,6 +243,7 @@ macro_rules! impl_query_source {
                 &self,
             ) -> Result<$crate::request::JsCastRequestFuture<js_sys::Array>, web_sys::DomException>
             {
+                self.record_request();
                 $crate::request::JsCastRequestFuture::new(self.inner.get_all())
             }
 
@@ -213,11 +255,13 @@ macro_rules! impl_query_source {
             {
                 #[allow(unused_imports)]
                 use wasm_bindgen::JsCast;
+                self.record_request();
                 $crate::request::JsCastRequestFuture::new(self.inner.get_all_with_key(key.unchecked_ref()))
             }
 
             #[inline]
             fn count(&self) -> Result<$crate::request::CountFuture, web_sys::DomException> {
+                self.record_request();
                 $crate::request::CountFuture::new(self.inner.count())
             }
 
@@ -233,6 +277,7 @@ macro_rules! impl_query_source {
             ) -> Result<$crate::request::CountFuture, web_sys::DomException> {
                 #[allow(unused_imports)]
                 use wasm_bindgen::JsCast;
+                self.record_request();
                 $crate::request::CountFuture::new(self.inner.count_with_key(key.unchecked_ref()))
             }
 
@@ -253,6 +298,7 @@ macro_rules! impl_query_source {
             ) -> Result<$crate::request::OptionalJsValueFuture, web_sys::DomException> {
                 #[allow(unused_imports)]
                 use wasm_bindgen::JsCast;
+                self.record_request();
                 $crate::request::OptionalJsValueFuture::new(self.inner.get_key(key.unchecked_ref()))
             }
 
@@ -261,6 +307,7 @@ macro_rules! impl_query_source {
                 &self,
             ) -> Result<$crate::request::JsCastRequestFuture<js_sys::Array>, web_sys::DomException>
             {
+                self.record_request();
                 $crate::request::JsCastRequestFuture::new(self.inner.get_all_keys())
             }
 
@@ -272,6 +319,7 @@ macro_rules! impl_query_source {
             {
                 #[allow(unused_imports)]
                 use wasm_bindgen::JsCast;
+                self.record_request();
                 $crate::request::JsCastRequestFuture::new(self.inner.get_all_keys_with_key(key.unchecked_ref()))
             }
 
@@ -284,6 +332,7 @@ macro_rules! impl_query_source {
             {
                 #[allow(unused_imports)]
                 use wasm_bindgen::JsCast;
+                self.record_request();
                 $crate::request::JsCastRequestFuture::new(
                     self.inner.get_all_keys_with_key_and_limit(key.unchecked_ref(), limit),
                 )
@@ -292,15 +341,21 @@ macro_rules! impl_query_source {
     };
 }
 
+pub mod clock;
+pub mod dom_string_iterator;
 mod idb_database;
+mod idb_key;
+mod idb_key_path;
 pub mod idb_object_store;
 mod idb_query_source;
 pub mod idb_transaction;
 mod internal_utils;
+pub mod key_order;
+pub mod keygen;
+pub mod maintenance;
 pub mod prelude;
 pub mod request;
-
-pub(crate) mod dom_string_iterator;
+pub mod values;
 
 cfg_if! {
     if #[cfg(feature = "indices")] {
@@ -309,6 +364,59 @@ cfg_if! {
     }
 }
 
+#[cfg(feature = "cache-storage")]
+pub mod asset_cache;
+#[cfg(feature = "attachments")]
+pub mod attachments;
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(feature = "cas")]
+pub mod cas;
+#[cfg(feature = "chunked")]
+pub mod chunked;
+#[cfg(feature = "counters")]
+pub mod counters;
+#[cfg(feature = "cross-db")]
+pub mod cross_db;
+#[cfg(feature = "serde_json")]
+pub mod fixtures;
+#[cfg(feature = "geo")]
+pub mod geo;
+#[cfg(feature = "graph")]
+pub mod graph;
+#[cfg(feature = "history")]
+pub mod history;
 #[cfg(feature = "cursors")]
 pub mod idb_cursor;
-mod idb_key_path;
+#[cfg(feature = "journal")]
+pub mod journal;
+#[cfg(feature = "leak-audit")]
+pub mod leak_audit;
+#[cfg(feature = "local-storage")]
+pub mod local_storage;
+#[cfg(feature = "page-lifecycle")]
+pub mod page_lifecycle;
+#[cfg(feature = "cursors")]
+pub mod query;
+#[cfg(feature = "rate-limit")]
+pub mod rate_limit;
+#[cfg(feature = "relation")]
+pub mod relation;
+#[cfg(feature = "retry")]
+pub mod retry;
+#[cfg(feature = "schema")]
+pub mod schema;
+#[cfg(feature = "cursors")]
+pub mod shard;
+#[cfg(feature = "spillover")]
+pub mod spillover;
+#[cfg(feature = "staged")]
+pub mod staged;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+#[cfg(feature = "time-series")]
+pub mod time_series;
+#[cfg(feature = "serde")]
+pub mod validation;
+#[cfg(feature = "wipe")]
+pub mod wipe;
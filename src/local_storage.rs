@@ -0,0 +1,163 @@
+//! One-time migration of `localStorage` entries into an object store
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbTransactionMode, Storage};
+
+use crate::idb_database::IdbDatabase;
+use crate::internal_utils::dom_exception;
+
+/// Options for copying `localStorage` entries into an object store. The store must use
+/// out-of-line keys: each entry gets stored at its `localStorage` key.
+#[derive(Debug, Clone, Default)]
+pub struct LocalStorageMigration {
+    strip_prefix: bool,
+    parse_json: bool,
+    remove_migrated: bool,
+}
+
+impl LocalStorageMigration {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove the prefix from the keys records get stored at. Defaults to `false`.
+    #[inline]
+    pub fn strip_prefix(&mut self, val: bool) -> &mut Self {
+        self.strip_prefix = val;
+        self
+    }
+
+    /// Try to parse each value as JSON, falling back to storing the raw string if it isn't valid
+    /// JSON. Defaults to `false`.
+    #[inline]
+    pub fn parse_json(&mut self, val: bool) -> &mut Self {
+        self.parse_json = val;
+        self
+    }
+
+    /// Remove the migrated entries from `localStorage` once the transaction has committed.
+    /// Defaults to `false`.
+    #[inline]
+    pub fn remove_migrated(&mut self, val: bool) -> &mut Self {
+        self.remove_migrated = val;
+        self
+    }
+
+    /// Copy every `localStorage` entry whose key starts with `prefix` into the given object store
+    /// in a single readwrite transaction. Returns the number of migrated entries.
+    pub async fn run(
+        &self,
+        db: &IdbDatabase,
+        store_name: &str,
+        prefix: &str,
+    ) -> Result<u32, DomException> {
+        let storage = local_storage()?;
+        let entries = matching_entries(&storage, prefix)?;
+
+        let tx = db.transaction_on_one_with_mode(store_name, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(store_name)?;
+        for (key, value) in entries.iter() {
+            let store_key = if self.strip_prefix {
+                &key[prefix.len()..]
+            } else {
+                key.as_str()
+            };
+            store.put_key_val(&JsValue::from_str(store_key), &self.convert_value(value))?;
+        }
+        tx.await.into_result()?;
+
+        if self.remove_migrated {
+            for (key, _) in entries.iter() {
+                storage.remove_item(key)?;
+            }
+        }
+
+        Ok(entries.len() as u32)
+    }
+
+    fn convert_value(&self, value: &str) -> JsValue {
+        if self.parse_json {
+            if let Ok(parsed) = js_sys::JSON::parse(value) {
+                return parsed;
+            }
+        }
+        JsValue::from_str(value)
+    }
+}
+
+/// Copy every `localStorage` entry whose key starts with `prefix` into the given object store
+/// using the default [options][LocalStorageMigration]. Returns the number of migrated entries.
+#[inline]
+pub async fn migrate_from_local_storage(
+    db: &IdbDatabase,
+    store_name: &str,
+    prefix: &str,
+) -> Result<u32, DomException> {
+    LocalStorageMigration::new()
+        .run(db, store_name, prefix)
+        .await
+}
+
+fn local_storage() -> Result<Storage, DomException> {
+    let unavailable = || dom_exception("localStorage is unavailable", "NotSupportedError");
+    let window = web_sys::window().ok_or_else(unavailable)?;
+    window.local_storage()?.ok_or_else(unavailable)
+}
+
+fn matching_entries(
+    storage: &Storage,
+    prefix: &str,
+) -> Result<Vec<(String, String)>, DomException> {
+    let len = storage.length()?;
+    let mut out = Vec::new();
+    for i in 0..len {
+        if let Some(key) = storage.key(i)? {
+            if key.starts_with(prefix) {
+                if let Some(value) = storage.get_item(&key)? {
+                    out.push((key, value));
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::{open_any_db, unique_name};
+
+    use super::*;
+
+    test_mod_init!();
+
+    test_case!(async migrate => {
+        let (db, store_name) = open_any_db().await;
+        let prefix = format!("{}:", unique_name());
+        let storage = local_storage().expect("local storage");
+        storage.set_item(&format!("{}a", prefix), "{\"foo\":1}").expect("set a");
+        storage.set_item(&format!("{}b", prefix), "not json").expect("set b");
+        storage.set_item("unrelated", "x").expect("set unrelated");
+
+        let migrated = LocalStorageMigration::new()
+            .strip_prefix(true)
+            .parse_json(true)
+            .remove_migrated(true)
+            .run(&db, &store_name, &prefix)
+            .await
+            .expect("migrate");
+
+        let tx = db.transaction_on_one(&store_name).expect("tx");
+        let store = tx.object_store(&store_name).expect("store");
+        let a = store.get_owned("a").expect("get a").await.expect("get a await").expect("a");
+        let b = store.get_owned("b").expect("get b").await.expect("get b await");
+
+        assert_eq!(migrated, 2, "migrated");
+        assert_eq!(js_sys::Reflect::get(&a, &"foo".into()).unwrap(), JsValue::from(1), "a");
+        assert_eq!(b, Some(JsValue::from("not json")), "b");
+        assert_eq!(storage.get_item(&format!("{}a", prefix)).unwrap(), None, "a removed");
+        assert_eq!(storage.get_item("unrelated").unwrap(), Some("x".into()), "unrelated kept");
+        storage.remove_item("unrelated").unwrap();
+    });
+}
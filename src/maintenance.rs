@@ -0,0 +1,362 @@
+//! Background maintenance, such as expiry purges, LRU eviction, index backfills or compaction,
+//! run in small chunks while the page is idle.
+//!
+//! A [MaintenanceScheduler] runs each registered [MaintenanceJob] one chunk at a time, waiting
+//! for an idle period (`requestIdleCallback`, falling back to a timer where it's unavailable)
+//! before every chunk so that maintenance doesn't compete with user interactions. After every
+//! chunk the job's checkpoint gets persisted in a metadata store with out-of-line keys, so a pass
+//! that gets interrupted, e.g. by a page reload, resumes where it left off.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::idb_database::IdbDatabase;
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::{await_promise, js_error_into_dom_exception};
+
+/// Future returned by [MaintenanceJob::run_chunk]
+pub type JobFuture<'a> = Pin<Box<dyn Future<Output = Result<JobStep, DomException>> + 'a>>;
+
+/// Outcome of running one chunk of a [MaintenanceJob]
+#[derive(Debug, Clone)]
+pub enum JobStep {
+    /// There's more work to do in this pass. The checkpoint, if any, gets persisted and handed to
+    /// the next chunk.
+    Continue(Option<JsValue>),
+    /// The pass is complete; the next pass starts without a checkpoint
+    Done,
+}
+
+/// Whether a bulk operation's batches run right away or wait for the page to be idle, so that a
+/// background sync doesn't contend with user-facing reads and writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OpPriority {
+    /// Run every batch immediately
+    #[default]
+    Interactive,
+    /// Wait for an idle period before every batch, or at most the given time
+    Background(Duration),
+}
+
+impl OpPriority {
+    /// Background priority, running a batch after at most a second if the page never goes idle
+    pub const BACKGROUND: Self = Self::Background(Duration::from_secs(1));
+
+    /// Resolve immediately for [Interactive][OpPriority::Interactive] work and on the next idle
+    /// period for [Background][OpPriority::Background] work. Bulk operations call this before
+    /// every batch.
+    pub async fn ready(self) -> Result<(), DomException> {
+        match self {
+            Self::Interactive => Ok(()),
+            Self::Background(timeout) => wait_for_idle(timeout).await,
+        }
+    }
+}
+
+/// A unit of maintenance work that can be split into chunks
+pub trait MaintenanceJob {
+    /// Unique name of the job, used as its checkpoint key
+    fn name(&self) -> String;
+
+    /// Run one chunk of at most `chunk_size` records, ideally in a single transaction, starting
+    /// from the checkpoint returned by the previous chunk.
+    fn run_chunk<'a>(
+        &'a self,
+        db: &'a IdbDatabase,
+        checkpoint: Option<JsValue>,
+        chunk_size: u32,
+    ) -> JobFuture<'a>;
+}
+
+/// Runs [MaintenanceJob]s in small chunks when the page is idle.
+///
+/// # Example
+///
+/// ```rust
+/// use indexed_db_futures::prelude::*;
+/// use indexed_db_futures::maintenance::MaintenanceScheduler;
+/// use std::time::Duration;
+/// use web_sys::DomException;
+///
+/// async fn example(db: IdbDatabase) -> Result<(), DomException> {
+///     let scheduler = MaintenanceScheduler::new("meta")
+///         .job(IndexBackfill::new("meta", "users", "by_email", "email"))
+///         .chunk_size(50);
+///
+///     // Typically run from wasm_bindgen_futures::spawn_local
+///     scheduler.run_every(&db, Duration::from_secs(60)).await
+/// }
+/// ```
+pub struct MaintenanceScheduler {
+    meta_store: String,
+    jobs: Vec<Box<dyn MaintenanceJob>>,
+    chunk_size: u32,
+    idle_timeout: Duration,
+}
+
+impl MaintenanceScheduler {
+    /// Create a scheduler persisting checkpoints in the given store, which must use out-of-line
+    /// keys
+    pub fn new(meta_store: &str) -> Self {
+        Self {
+            meta_store: meta_store.into(),
+            jobs: Vec::new(),
+            chunk_size: 100,
+            idle_timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// Register a job. Jobs run in registration order.
+    pub fn job<J: MaintenanceJob + 'static>(mut self, job: J) -> Self {
+        self.jobs.push(Box::new(job));
+        self
+    }
+
+    /// Maximum number of records a job should process per chunk. Defaults to 100.
+    pub fn chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Longest time to wait for an idle period before running a chunk anyway. Defaults to one
+    /// second.
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Run every job to completion once, one chunk per idle period
+    pub async fn run_pass(&self, db: &IdbDatabase) -> Result<(), DomException> {
+        for job in self.jobs.iter() {
+            let key = checkpoint_key(job.as_ref());
+            let mut checkpoint = self.load_checkpoint(db, &key).await?;
+            loop {
+                wait_for_idle(self.idle_timeout).await?;
+                match job.run_chunk(db, checkpoint, self.chunk_size).await? {
+                    JobStep::Continue(next) => {
+                        self.save_checkpoint(db, &key, next.as_ref()).await?;
+                        checkpoint = next;
+                    }
+                    JobStep::Done => {
+                        self.save_checkpoint(db, &key, None).await?;
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a pass, then wait for the given interval, indefinitely. Only returns if a job fails.
+    pub async fn run_every(
+        &self,
+        db: &IdbDatabase,
+        interval: Duration,
+    ) -> Result<(), DomException> {
+        loop {
+            self.run_pass(db).await?;
+            sleep(interval).await?;
+        }
+    }
+
+    async fn load_checkpoint(
+        &self,
+        db: &IdbDatabase,
+        key: &str,
+    ) -> Result<Option<JsValue>, DomException> {
+        let tx = db.transaction_on_one(&self.meta_store)?;
+        let meta = tx.object_store(&self.meta_store)?;
+        Ok(meta
+            .get_owned(key)?
+            .await?
+            .and_then(|record| js_sys::Reflect::get(&record, &"checkpoint".into()).ok())
+            .filter(|checkpoint| !checkpoint.is_undefined()))
+    }
+
+    async fn save_checkpoint(
+        &self,
+        db: &IdbDatabase,
+        key: &str,
+        checkpoint: Option<&JsValue>,
+    ) -> Result<(), DomException> {
+        let tx =
+            db.transaction_on_one_with_mode(&self.meta_store, IdbTransactionMode::Readwrite)?;
+        let meta = tx.object_store(&self.meta_store)?;
+        match checkpoint {
+            Some(checkpoint) => {
+                let record = js_sys::Object::new();
+                js_sys::Reflect::set(&record, &"checkpoint".into(), checkpoint)?;
+                meta.put_key_val_owned(key, &record)?;
+            }
+            None => {
+                meta.delete_owned(key)?;
+            }
+        }
+        tx.await.into_result()
+    }
+}
+
+impl std::fmt::Debug for MaintenanceScheduler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MaintenanceScheduler")
+            .field("meta_store", &self.meta_store)
+            .field(
+                "jobs",
+                &self.jobs.iter().map(|j| j.name()).collect::<Vec<_>>(),
+            )
+            .field("chunk_size", &self.chunk_size)
+            .field("idle_timeout", &self.idle_timeout)
+            .finish()
+    }
+}
+
+fn checkpoint_key(job: &dyn MaintenanceJob) -> String {
+    format!("maintenance/{}", job.name())
+}
+
+fn millis(duration: Duration) -> i32 {
+    duration.as_millis().min(i32::MAX as u128) as i32
+}
+
+/// Resolve on the next idle period, or after the timeout if none comes along
+async fn wait_for_idle(timeout: Duration) -> Result<(), DomException> {
+    let window: JsValue = web_sys::window()
+        .ok_or_else(|| crate::internal_utils::dom_exception("No window", "NotSupportedError"))?
+        .into();
+    let request_idle = js_sys::Reflect::get(&window, &"requestIdleCallback".into())
+        .map_err(js_error_into_dom_exception)?;
+    match request_idle.dyn_into::<js_sys::Function>() {
+        Ok(request_idle) => {
+            let options = js_sys::Object::new();
+            js_sys::Reflect::set(&options, &"timeout".into(), &millis(timeout).into())?;
+            let mut err = None;
+            let promise = js_sys::Promise::new(&mut |resolve, _| {
+                if let Err(e) = request_idle.call2(&window, &resolve, &options) {
+                    err = Some(e);
+                }
+            });
+            if let Some(e) = err {
+                return Err(js_error_into_dom_exception(e));
+            }
+            await_promise(promise).await.map(drop)
+        }
+        Err(_) => sleep(Duration::from_millis(0)).await,
+    }
+}
+
+pub(crate) async fn sleep(duration: Duration) -> Result<(), DomException> {
+    let window = web_sys::window()
+        .ok_or_else(|| crate::internal_utils::dom_exception("No window", "NotSupportedError"))?;
+    let mut err = None;
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        if let Err(e) =
+            window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis(duration))
+        {
+            err = Some(e);
+        }
+    });
+    if let Some(e) = err {
+        return Err(js_error_into_dom_exception(e));
+    }
+    await_promise(promise).await.map(drop)
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::test_utils::open_db_with_stores;
+
+    use super::*;
+
+    test_mod_init!();
+
+    /// Counts from the checkpoint up to `total`, recording the chunks it ran
+    struct CountingJob {
+        total: u32,
+        chunks: Rc<RefCell<Vec<(u32, u32)>>>,
+    }
+
+    impl MaintenanceJob for CountingJob {
+        fn name(&self) -> String {
+            "counter".into()
+        }
+
+        fn run_chunk<'a>(
+            &'a self,
+            _db: &'a IdbDatabase,
+            checkpoint: Option<JsValue>,
+            chunk_size: u32,
+        ) -> JobFuture<'a> {
+            Box::pin(async move {
+                let from = checkpoint.and_then(|c| c.as_f64()).unwrap_or(0.0) as u32;
+                let to = (from + chunk_size).min(self.total);
+                self.chunks.borrow_mut().push((from, to));
+                Ok(if to == self.total {
+                    JobStep::Done
+                } else {
+                    JobStep::Continue(Some(JsValue::from(to)))
+                })
+            })
+        }
+    }
+
+    async fn open_db() -> IdbDatabase {
+        open_db_with_stores(&["meta"]).await
+    }
+
+    test_case!(async chunked_pass => {
+        let db = open_db().await;
+        let chunks = Rc::new(RefCell::new(Vec::new()));
+        let scheduler = MaintenanceScheduler::new("meta")
+            .job(CountingJob { total: 10, chunks: chunks.clone() })
+            .chunk_size(4);
+
+        scheduler.run_pass(&db).await.expect("pass 1");
+        assert_eq!(*chunks.borrow(), vec![(0, 4), (4, 8), (8, 10)], "pass 1");
+
+        chunks.borrow_mut().clear();
+        scheduler.run_pass(&db).await.expect("pass 2");
+        assert_eq!(*chunks.borrow(), vec![(0, 4), (4, 8), (8, 10)], "pass 2 starts over");
+    });
+
+    test_case!(async resumes_from_checkpoint => {
+        let db = open_db().await;
+        let tx = db.transaction_on_one_with_mode("meta", IdbTransactionMode::Readwrite).unwrap();
+        let record = js_sys::JSON::parse(r#"{"checkpoint":6}"#).unwrap();
+        tx.object_store("meta")
+            .unwrap()
+            .put_key_val_owned("maintenance/counter", &record)
+            .unwrap();
+        tx.await.into_result().expect("tx");
+
+        let chunks = Rc::new(RefCell::new(Vec::new()));
+        let scheduler = MaintenanceScheduler::new("meta")
+            .job(CountingJob { total: 10, chunks: chunks.clone() })
+            .chunk_size(3);
+
+        scheduler.run_pass(&db).await.expect("pass");
+        assert_eq!(*chunks.borrow(), vec![(6, 9), (9, 10)]);
+    });
+
+    test_case!(async background_priority => {
+        assert_eq!(OpPriority::default(), OpPriority::Interactive, "default");
+        OpPriority::Interactive.ready().await.expect("interactive");
+        OpPriority::Background(Duration::from_millis(50)).ready().await.expect("background");
+
+        let (db, store_name) = crate::test_utils::open_any_db().await;
+        let records = (0..5).map(|i| (JsValue::from(i), JsValue::from(i)));
+        let last = db.put_all_chunked(&store_name, records, 2)
+            .priority(OpPriority::BACKGROUND)
+            .run(|_| {})
+            .await
+            .expect("run")
+            .expect("last");
+        assert_eq!(last.written, 5, "written");
+    });
+}
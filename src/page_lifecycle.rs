@@ -0,0 +1,193 @@
+//! Hooks that run when the page gets hidden, so that buffered writes can be flushed before the
+//! browser freezes or discards it.
+//!
+//! A hook fires when the document's visibility changes to `hidden` or on `pagehide`, whichever
+//! comes first, and fires again only after the page has been shown again. Pages often get
+//! discarded without any further notice after being hidden, so this is the last reliable point
+//! at which to write anything out.
+//!
+//! Hooks are synchronous: there's no guarantee that the page keeps running long enough for a
+//! future to complete. IndexedDB writes issued from a hook, however, will still commit as long as
+//! the requests get made before the hook returns.
+//!
+//! ```rust
+//! use indexed_db_futures::page_lifecycle::on_page_hide;
+//!
+//! fn example() {
+//!     let hook = on_page_hide(|| {
+//!         // Start the writes that flush any buffered state here
+//!     });
+//!     // The hook stays registered until it's dropped
+//!     hook.forget();
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use wasm_bindgen::{prelude::*, JsCast};
+
+use crate::internal_utils::{ClosureKind, ClosureToken};
+
+type Hook = Rc<dyn Fn()>;
+
+struct Registry {
+    next_id: u64,
+    hooks: Vec<(u64, Hook)>,
+    hidden: bool,
+    installed: bool,
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = RefCell::new(Registry {
+        next_id: 0,
+        hooks: Vec::new(),
+        hidden: false,
+        installed: false,
+    });
+}
+
+/// Registration handle returned by [on_page_hide]. The hook gets unregistered when this is
+/// dropped.
+#[derive(Debug)]
+#[must_use = "the hook gets unregistered when the handle is dropped"]
+pub struct PageHideHook {
+    id: u64,
+}
+
+impl PageHideHook {
+    /// Keep the hook registered for the lifetime of the page
+    pub fn forget(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for PageHideHook {
+    fn drop(&mut self) {
+        let id = self.id;
+        REGISTRY.with(|r| r.borrow_mut().hooks.retain(|(hook_id, _)| *hook_id != id));
+    }
+}
+
+/// Run the callback whenever the page gets hidden. See the [module docs][self].
+pub fn on_page_hide<F: Fn() + 'static>(callback: F) -> PageHideHook {
+    install_listeners();
+    REGISTRY.with(|r| {
+        let mut registry = r.borrow_mut();
+        let id = registry.next_id;
+        registry.next_id += 1;
+        registry.hooks.push((id, Rc::new(callback)));
+        PageHideHook { id }
+    })
+}
+
+fn on_hidden() {
+    let hooks: Vec<Hook> = REGISTRY.with(|r| {
+        let mut registry = r.borrow_mut();
+        if registry.hidden {
+            return Vec::new();
+        }
+        registry.hidden = true;
+        registry
+            .hooks
+            .iter()
+            .map(|(_, hook)| hook.clone())
+            .collect()
+    });
+    // Called outside of the borrow so that hooks can register or drop hooks themselves
+    for hook in hooks {
+        hook();
+    }
+}
+
+fn on_shown() {
+    REGISTRY.with(|r| r.borrow_mut().hidden = false);
+}
+
+fn is_document_hidden(document: &JsValue) -> bool {
+    js_sys::Reflect::get(document, &"visibilityState".into())
+        .ok()
+        .and_then(|state| state.as_string())
+        .map(|state| state == "hidden")
+        .unwrap_or(false)
+}
+
+fn listen(target: &web_sys::EventTarget, event: &str, mut callback: impl FnMut() + 'static) {
+    let token = ClosureToken::new(ClosureKind::Other);
+    let closure = Closure::wrap(Box::new(move || {
+        token.hold();
+        callback();
+    }) as Box<dyn FnMut()>);
+    // The listeners live for as long as the page does
+    if target
+        .add_event_listener_with_callback(event, closure.as_ref().unchecked_ref())
+        .is_ok()
+    {
+        closure.forget();
+    }
+}
+
+fn install_listeners() {
+    let installed = REGISTRY.with(|r| std::mem::replace(&mut r.borrow_mut().installed, true));
+    if installed {
+        return;
+    }
+    let window = match web_sys::window() {
+        Some(window) => window,
+        None => return,
+    };
+
+    listen(&window, "pagehide", on_hidden);
+    listen(&window, "pageshow", on_shown);
+
+    let document = js_sys::Reflect::get(&window, &"document".into()).unwrap_or(JsValue::UNDEFINED);
+    if let Ok(target) = document.clone().dyn_into::<web_sys::EventTarget>() {
+        listen(&target, "visibilitychange", move || {
+            if is_document_hidden(&document) {
+                on_hidden();
+            } else {
+                on_shown();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::cell::Cell;
+
+    use super::*;
+
+    test_mod_init!();
+
+    fn dispatch(event: &str) {
+        let event = web_sys::Event::new(event).expect("event");
+        web_sys::window()
+            .expect("window")
+            .dispatch_event(&event)
+            .expect("dispatch");
+    }
+
+    test_case!(hooks => {
+        let calls = Rc::new(Cell::new(0));
+        let hook = {
+            let calls = calls.clone();
+            on_page_hide(move || calls.set(calls.get() + 1))
+        };
+
+        dispatch("pagehide");
+        assert_eq!(calls.get(), 1, "pagehide");
+        dispatch("pagehide");
+        assert_eq!(calls.get(), 1, "fires once per hide");
+
+        dispatch("pageshow");
+        dispatch("pagehide");
+        assert_eq!(calls.get(), 2, "fires again after being shown");
+
+        drop(hook);
+        dispatch("pageshow");
+        dispatch("pagehide");
+        assert_eq!(calls.get(), 2, "dropped");
+        dispatch("pageshow");
+    });
+}
@@ -1,17 +1,61 @@
 //! The file to `use` everything from in most cases
+//!
+//! Items are only exported when the feature they belong to is enabled.
 
+#[cfg(feature = "cache-storage")]
+pub use crate::asset_cache::AssetCache;
+#[cfg(feature = "geo")]
+pub use crate::geo::GeoIndex;
+#[cfg(all(feature = "indices", feature = "cursors"))]
+pub use crate::idb_object_store::IndexBackfill;
+#[cfg(all(feature = "serde", feature = "indices", feature = "cursors"))]
+pub use crate::idb_object_store::{IndexedPage, IndexedRecord};
+#[cfg(feature = "serde_json")]
+pub use crate::idb_object_store::{PatchError, PatchOp};
+#[cfg(feature = "uuid")]
+pub use crate::keygen::UuidKey;
+#[cfg(feature = "schema")]
+pub use crate::schema::{DbManager, DbSchema, IndexSchema, StoreSchema};
+#[cfg(feature = "time-series")]
+pub use crate::time_series::TimeSeriesStore;
+#[cfg(feature = "serde")]
+pub use crate::{
+    idb_object_store::{Codec, SerdeStoreError},
+    validation::{Validate, ValidationError},
+};
+#[cfg(feature = "streams")]
+pub use futures_core::Stream;
 #[cfg(feature = "cursors")]
-pub use {crate::idb_cursor::*, web_sys::IdbCursorDirection};
-#[cfg(feature = "indices")]
-pub use {crate::idb_index::*, web_sys::IdbIndexParameters};
+pub use {
+    crate::{
+        idb_cursor::*,
+        idb_object_store::{KeyRotation, RotationProgress, SizeCounter, SizeEstimate},
+    },
+    web_sys::IdbCursorDirection,
+};
 pub use {
     crate::{
         idb_database::*,
+        idb_key::*,
         idb_key_path::*,
-        idb_object_store::{IdbObjectStore, IdbObjectStoreParameters},
+        idb_object_store::{
+            AccessStats, BloomFilter, Cipher, Encryption, IdbObjectStore, IdbObjectStoreParameters,
+            MergeMode, Middleware, MiddlewareContext, OrderedOp, Projection, TypedObjectStore,
+        },
         idb_query_source::IdbQuerySource,
-        idb_transaction::{IdbTransaction, IdbTransactionResult},
+        idb_transaction::{IdbTransaction, IdbTransactionResult, ObjectStoreError},
+        key_order::{compare_keys, BinaryKey},
+        keygen::Ulid,
+        maintenance::OpPriority,
         request::*,
     },
-    web_sys::IdbTransactionMode,
+    web_sys::{IdbKeyRange, IdbTransactionMode},
+};
+#[cfg(feature = "indices")]
+pub use {
+    crate::{
+        idb_index::*,
+        idb_object_store::{BlindIndex, CheckedPutError, KeyedHash},
+    },
+    web_sys::IdbIndexParameters,
 };
@@ -0,0 +1,314 @@
+//! Composable queries with an explainable execution plan
+//!
+//! Features required: `cursors`
+//!
+//! A [Query] describes what to read from an object store: which index to go through, if any,
+//! the key range, the direction and an optional per-record filter. Running it picks the cheapest
+//! way to execute that description, a single `getAll` where the browser can do all the work and
+//! a cursor otherwise. [explain][Query::explain] reports that plan along with how many records
+//! it's going to visit, so that a test can check a query isn't doing a full scan.
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::query::Query;
+//! use wasm_bindgen::prelude::*;
+//! use web_sys::DomException;
+//!
+//! async fn example(store: &IdbObjectStore<'_>) -> Result<Vec<JsValue>, DomException> {
+//!     let range = web_sys::IdbKeyRange::lower_bound(&"2024-01-01".into())?;
+//!     let query = Query::new(store)
+//!         .index("by_date")
+//!         .range(range)
+//!         .direction(IdbCursorDirection::Prev);
+//!
+//!     let plan = query.explain().await?;
+//!     assert!(!plan.is_full_scan(), "{}", plan);
+//!     query.get_all().await
+//! }
+//! ```
+
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbCursorDirection};
+
+use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+
+type Filter<'a> = Box<dyn Fn(&JsValue) -> bool + 'a>;
+
+/// How a [Query] gets executed
+///
+/// Features required: `cursors`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessMethod {
+    /// A single `getAll` request
+    GetAll,
+    /// A cursor visiting every record in the range, needed for filters and for directions other
+    /// than `next`
+    Cursor,
+}
+
+/// The execution plan of a [Query], from [Query::explain]
+///
+/// Features required: `cursors`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explain {
+    /// The object store being queried
+    pub store: String,
+    /// The index being queried, if any
+    pub index: Option<String>,
+    /// The key range; `None` if the query covers the whole store or index
+    pub range: Option<JsValue>,
+    /// The direction records are visited in
+    pub direction: IdbCursorDirection,
+    /// How the query gets executed
+    pub method: AccessMethod,
+    /// Whether a filter discards some of the visited records
+    pub filtered: bool,
+    /// How many records the query is going to visit, by counting the range
+    pub estimated_records: u32,
+}
+
+impl Explain {
+    /// Whether the query visits every record of the store or index
+    #[inline]
+    pub fn is_full_scan(&self) -> bool {
+        self.range.is_none()
+    }
+}
+
+impl fmt::Display for Explain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let method = match self.method {
+            AccessMethod::GetAll => "getAll",
+            AccessMethod::Cursor => "cursor",
+        };
+        write!(f, "{} on {}", method, self.store)?;
+        if let Some(index) = self.index.as_ref() {
+            write!(f, " via index {}", index)?;
+        }
+        match self.range.as_ref() {
+            Some(range) => write!(f, ", range {}", describe_range(range))?,
+            None => f.write_str(", full scan")?,
+        }
+        write!(
+            f,
+            ", direction {:?}, ~{} records",
+            self.direction, self.estimated_records
+        )?;
+        if self.filtered {
+            f.write_str(", filtered")?;
+        }
+        Ok(())
+    }
+}
+
+/// A read query on an object store, run with [get_all][Query::get_all] and inspected with
+/// [explain][Query::explain]
+///
+/// Features required: `cursors`
+pub struct Query<'a> {
+    store: &'a IdbObjectStore<'a>,
+    index: Option<String>,
+    range: Option<JsValue>,
+    direction: IdbCursorDirection,
+    filter: Option<Filter<'a>>,
+}
+
+impl<'a> Query<'a> {
+    /// Query every record of the store in ascending key order
+    pub fn new(store: &'a IdbObjectStore<'a>) -> Self {
+        Self {
+            store,
+            index: None,
+            range: None,
+            direction: IdbCursorDirection::Next,
+            filter: None,
+        }
+    }
+
+    /// Go through the given index of the store; ranges then apply to the index's keys
+    ///
+    /// Features required: `cursors`, `indices`
+    #[cfg(feature = "indices")]
+    pub fn index(mut self, name: &str) -> Self {
+        self.index = Some(name.into());
+        self
+    }
+
+    /// Only visit keys in the given key range
+    pub fn range<K: Into<JsValue>>(mut self, range: K) -> Self {
+        let range = range.into();
+        self.range = if range.is_undefined() || range.is_null() {
+            None
+        } else {
+            Some(range)
+        };
+        self
+    }
+
+    /// Only visit the given key
+    #[inline]
+    pub fn only<K: Into<JsValue>>(self, key: K) -> Self {
+        self.range(key)
+    }
+
+    /// Visit records in the given direction. Defaults to `next`.
+    #[inline]
+    pub fn direction(mut self, direction: IdbCursorDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Only return records the predicate returns true for. The predicate runs on every visited
+    /// record, so it narrows the result without narrowing the scan.
+    pub fn filter<F: Fn(&JsValue) -> bool + 'a>(mut self, filter: F) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// How the query is going to be executed, counting the records it's going to visit
+    pub async fn explain(&self) -> Result<Explain, DomException> {
+        let estimated_records = match self.index.as_ref() {
+            #[cfg(feature = "indices")]
+            Some(index) => {
+                self.store
+                    .index(index)?
+                    .count_with_key(&self.js_range())?
+                    .await?
+            }
+            _ => self.store.count_with_key(&self.js_range())?.await?,
+        };
+        Ok(Explain {
+            store: self.store.name(),
+            index: self.index.clone(),
+            range: self.range.clone(),
+            direction: self.direction,
+            method: self.method(),
+            filtered: self.filter.is_some(),
+            estimated_records,
+        })
+    }
+
+    /// Run the query
+    pub async fn get_all(&self) -> Result<Vec<JsValue>, DomException> {
+        match self.index.as_ref() {
+            #[cfg(feature = "indices")]
+            Some(index) => self.run(&self.store.index(index)?).await,
+            _ => self.run(self.store).await,
+        }
+    }
+
+    fn method(&self) -> AccessMethod {
+        if self.filter.is_none() && self.direction == IdbCursorDirection::Next {
+            AccessMethod::GetAll
+        } else {
+            AccessMethod::Cursor
+        }
+    }
+
+    fn js_range(&self) -> JsValue {
+        self.range.clone().unwrap_or(JsValue::UNDEFINED)
+    }
+
+    async fn run<T: IdbQuerySource>(&self, source: &T) -> Result<Vec<JsValue>, DomException> {
+        let range = self.js_range();
+        if self.method() == AccessMethod::GetAll {
+            return Ok(source.get_all_with_key(&range)?.await?.iter().collect());
+        }
+
+        let mut out = Vec::new();
+        let cursor = source
+            .open_cursor_with_range_and_direction(&range, self.direction)?
+            .await?;
+        if let Some(cursor) = cursor {
+            loop {
+                let value = cursor.value();
+                if self.filter.as_ref().is_none_or(|filter| filter(&value)) {
+                    out.push(value);
+                }
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl fmt::Debug for Query<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Query")
+            .field("store", &self.store.name())
+            .field("index", &self.index)
+            .field("range", &self.range)
+            .field("direction", &self.direction)
+            .field("filter", &self.filter.is_some())
+            .finish()
+    }
+}
+
+/// `[lower, upper)` for key ranges, the key itself for single keys
+fn describe_range(range: &JsValue) -> String {
+    let get = |name: &str| js_sys::Reflect::get(range, &name.into()).unwrap_or(JsValue::UNDEFINED);
+    let json = |value: JsValue| {
+        if value.is_undefined() {
+            String::from("∞")
+        } else {
+            js_sys::JSON::stringify(&value)
+                .ok()
+                .and_then(|s| s.as_string())
+                .unwrap_or_else(|| format!("{:?}", value))
+        }
+    };
+    if range.is_instance_of::<web_sys::IdbKeyRange>() {
+        let open = |name: &str| get(name).as_bool().unwrap_or(false);
+        format!(
+            "{}{}, {}{}",
+            if open("lowerOpen") { '(' } else { '[' },
+            json(get("lower")),
+            json(get("upper")),
+            if open("upperOpen") { ')' } else { ']' },
+        )
+    } else {
+        json(range.clone())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::open_any_db;
+
+    use super::*;
+
+    test_mod_init!();
+
+    test_case!(async explain => {
+        let (db, store_name) = open_any_db().await;
+        let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).unwrap();
+        let store = tx.object_store(&store_name).unwrap();
+        for i in 0..10u32 {
+            store.put_key_val_owned(i, &JsValue::from(i)).unwrap();
+        }
+
+        let full = Query::new(&store).explain().await.expect("full");
+        assert!(full.is_full_scan(), "full scan");
+        assert_eq!((full.method, full.estimated_records), (AccessMethod::GetAll, 10), "full");
+
+        let range = web_sys::IdbKeyRange::bound_with_lower_open_and_upper_open(&3.into(), &7.into(), false, true).unwrap();
+        let query = Query::new(&store)
+            .range(range)
+            .direction(IdbCursorDirection::Prev)
+            .filter(|v| (v.as_f64().unwrap() as u32).is_multiple_of(2));
+        let plan = query.explain().await.expect("plan");
+        assert!(!plan.is_full_scan(), "ranged");
+        assert_eq!((plan.method, plan.estimated_records), (AccessMethod::Cursor, 4), "plan");
+        assert_eq!(plan.to_string(), format!("cursor on {}, range [3, 7), direction Prev, ~4 records, filtered", store_name), "display");
+        assert_eq!(query.get_all().await.expect("run"), vec![JsValue::from(6), JsValue::from(4)], "results");
+
+        let only = Query::new(&store).only(5).get_all().await.expect("only");
+        assert_eq!(only, vec![JsValue::from(5)], "only");
+    });
+}
@@ -0,0 +1,230 @@
+//! Client-side rate limiting that survives reloads
+//!
+//! A [RateLimiter] is a token bucket persisted in an object store with out-of-line keys: every
+//! bucket holds up to `capacity` tokens, refills continuously at `capacity` tokens per period and
+//! every throttled action takes tokens out of it. Taking tokens reads and updates the bucket in a
+//! single readwrite transaction, which IndexedDB runs one at a time for the same store, so tabs
+//! sharing a bucket can't both spend the same token.
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::rate_limit::{RateLimit, RateLimiter};
+//! use web_sys::DomException;
+//!
+//! async fn example(db: &IdbDatabase) -> Result<(), DomException> {
+//!     let limiter = RateLimiter::new("rate_limits", 10, Duration::from_secs(60));
+//!     match limiter.try_acquire(db, "search-api", 1).await? {
+//!         RateLimit::Allowed { .. } => { /* call the API */ }
+//!         RateLimit::Limited { retry_after } => { /* try again after retry_after */ }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::time::Duration;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::clock::{Clock, SharedClock};
+use crate::idb_database::IdbDatabase;
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::dom_exception;
+
+const TOKENS: &str = "tokens";
+const UPDATED: &str = "updated";
+
+/// The outcome of [RateLimiter::try_acquire]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateLimit {
+    /// The tokens were taken
+    Allowed {
+        /// The whole tokens left in the bucket
+        remaining: u32,
+    },
+    /// There weren't enough tokens; none were taken
+    Limited {
+        /// How long until the bucket has refilled enough
+        retry_after: Duration,
+    },
+}
+
+impl RateLimit {
+    /// Whether the tokens were taken
+    #[inline]
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, Self::Allowed { .. })
+    }
+}
+
+/// A persistent token bucket rate limiter. See the [module docs][self].
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    store: String,
+    capacity: f64,
+    /// Tokens per millisecond
+    rate: f64,
+    clock: SharedClock,
+}
+
+impl RateLimiter {
+    /// Allow up to `capacity` tokens per `period` for every bucket kept in the given store. New
+    /// buckets start out full.
+    pub fn new(store: &str, capacity: u32, period: Duration) -> Self {
+        let capacity = f64::from(capacity.max(1));
+        let period = (period.as_secs_f64() * 1000.0).max(f64::MIN_POSITIVE);
+        Self {
+            store: store.into(),
+            capacity,
+            rate: capacity / period,
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Read the time of refills from the given clock instead of the system time
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// The maximum number of tokens a bucket holds
+    #[inline]
+    pub fn capacity(&self) -> u32 {
+        self.capacity as u32
+    }
+
+    /// Take the given number of tokens from the bucket if it has that many, in a single
+    /// transaction. Fails with a `DataError` if more tokens than the capacity are requested,
+    /// as the bucket could never hold them.
+    pub async fn try_acquire(
+        &self,
+        db: &IdbDatabase,
+        bucket: &str,
+        tokens: u32,
+    ) -> Result<RateLimit, DomException> {
+        let tokens = f64::from(tokens);
+        if tokens > self.capacity {
+            return Err(dom_exception(
+                &format!(
+                    "Can't take {} tokens from a bucket that holds {}",
+                    tokens, self.capacity
+                ),
+                "DataError",
+            ));
+        }
+
+        let tx = db.transaction_on_one_with_mode(&self.store, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(&self.store)?;
+        let now = self.clock.now();
+        let available = self.available(store.get_owned(bucket)?.await?, now);
+        let outcome = if available >= tokens {
+            store.put_key_val_owned(bucket, &state(available - tokens, now)?)?;
+            RateLimit::Allowed {
+                remaining: (available - tokens).floor() as u32,
+            }
+        } else {
+            let wait = (tokens - available) / self.rate;
+            RateLimit::Limited {
+                retry_after: Duration::from_secs_f64(wait / 1000.0),
+            }
+        };
+        tx.await.into_result()?;
+        Ok(outcome)
+    }
+
+    /// Take the given number of tokens from the bucket, waiting for it to refill if it doesn't
+    /// have that many
+    pub async fn acquire(
+        &self,
+        db: &IdbDatabase,
+        bucket: &str,
+        tokens: u32,
+    ) -> Result<u32, DomException> {
+        loop {
+            match self.try_acquire(db, bucket, tokens).await? {
+                RateLimit::Allowed { remaining } => return Ok(remaining),
+                RateLimit::Limited { retry_after } => {
+                    crate::maintenance::sleep(retry_after).await?
+                }
+            }
+        }
+    }
+
+    /// The whole tokens currently in the bucket
+    pub async fn remaining(&self, db: &IdbDatabase, bucket: &str) -> Result<u32, DomException> {
+        let tx = db.transaction_on_one(&self.store)?;
+        let value = tx.object_store(&self.store)?.get_owned(bucket)?.await?;
+        Ok(self.available(value, self.clock.now()).floor() as u32)
+    }
+
+    /// Refill the bucket completely
+    pub async fn reset(&self, db: &IdbDatabase, bucket: &str) -> Result<(), DomException> {
+        let tx = db.transaction_on_one_with_mode(&self.store, IdbTransactionMode::Readwrite)?;
+        tx.object_store(&self.store)?.delete_owned(bucket)?;
+        tx.await.into_result()
+    }
+
+    /// The tokens in a bucket with the given stored state at the given time
+    fn available(&self, value: Option<JsValue>, now: f64) -> f64 {
+        let value = match value {
+            Some(value) => value,
+            None => return self.capacity,
+        };
+        let get = |field: &str| {
+            js_sys::Reflect::get(&value, &field.into())
+                .ok()
+                .and_then(|v| v.as_f64())
+        };
+        match (get(TOKENS), get(UPDATED)) {
+            (Some(tokens), Some(updated)) => {
+                let elapsed = (now - updated).max(0.0);
+                (tokens + elapsed * self.rate).min(self.capacity)
+            }
+            _ => self.capacity,
+        }
+    }
+}
+
+fn state(tokens: f64, now: f64) -> Result<JsValue, DomException> {
+    let state = js_sys::Object::new();
+    js_sys::Reflect::set(&state, &TOKENS.into(), &tokens.into())?;
+    js_sys::Reflect::set(&state, &UPDATED.into(), &now.into())?;
+    Ok(state.into())
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::clock::FakeClock;
+    use crate::test_utils::open_any_db;
+
+    use super::*;
+
+    test_mod_init!();
+
+    test_case!(async token_bucket => {
+        let (db, store) = open_any_db().await;
+        let clock = FakeClock::new(1_000.0);
+        let limiter = RateLimiter::new(&store, 2, Duration::from_secs(1)).with_clock(clock.clone());
+
+        assert_eq!(limiter.try_acquire(&db, "api", 1).await.unwrap(), RateLimit::Allowed { remaining: 1 }, "first");
+        assert_eq!(limiter.try_acquire(&db, "api", 1).await.unwrap(), RateLimit::Allowed { remaining: 0 }, "second");
+        assert_eq!(
+            limiter.try_acquire(&db, "api", 1).await.unwrap(),
+            RateLimit::Limited { retry_after: Duration::from_millis(500) },
+            "limited"
+        );
+        assert_eq!(limiter.remaining(&db, "other").await.unwrap(), 2, "buckets are separate");
+
+        clock.advance(Duration::from_millis(500));
+        let reloaded = RateLimiter::new(&store, 2, Duration::from_secs(1)).with_clock(clock.clone());
+        assert!(reloaded.try_acquire(&db, "api", 1).await.unwrap().is_allowed(), "refilled");
+        assert_eq!(reloaded.remaining(&db, "api").await.unwrap(), 0, "persisted");
+
+        let err = limiter.try_acquire(&db, "api", 3).await.expect_err("over capacity");
+        assert_eq!(err.name(), "DataError", "over capacity");
+
+        limiter.reset(&db, "api").await.unwrap();
+        assert_eq!(limiter.remaining(&db, "api").await.unwrap(), 2, "reset");
+    });
+}
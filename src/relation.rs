@@ -0,0 +1,233 @@
+//! Many-to-many relations over a join store
+//!
+//! Features required: `indices`
+//!
+//! A [Relation] links keys on its left side, e.g. post IDs, to keys on its right side, e.g. tag
+//! IDs. Each link is a `{left, right}` record keyed by `[left, right]` in a join store with an
+//! index per side, so both directions are a single index lookup.
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::relation::Relation;
+//! use wasm_bindgen::prelude::*;
+//! use web_sys::DomException;
+//!
+//! async fn example() -> Result<(), DomException> {
+//!     let mut req = IdbDatabase::open("blog")?;
+//!     req.set_on_upgrade_needed(Some(|evt: &IdbVersionChangeEvent| {
+//!         Ok(Relation::create_store(evt.db(), "post_tags")?)
+//!     }));
+//!     let db = req.into_future().await?;
+//!
+//!     let post_tags = Relation::new(&db, "post_tags");
+//!     post_tags.link(&1.into(), &"rust".into()).await?;
+//!     let _tags: Vec<JsValue> = post_tags.related_to(&1.into()).await?;
+//!     let _posts: Vec<JsValue> = post_tags.related_from(&"rust".into()).await?;
+//!     Ok(())
+//! }
+//! ```
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::idb_database::IdbDatabase;
+use crate::idb_key_path::IdbKeyPath;
+use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+
+/// The name of the join store's index on the left side
+pub const LEFT_INDEX: &str = "by_left";
+/// The name of the join store's index on the right side
+pub const RIGHT_INDEX: &str = "by_right";
+
+/// A many-to-many relation stored in a join store. See the [module docs][self].
+///
+/// Features required: `indices`
+#[derive(Debug)]
+pub struct Relation<'a> {
+    db: &'a IdbDatabase,
+    store_name: String,
+}
+
+impl<'a> Relation<'a> {
+    /// Use the given join store, which must have been set up by
+    /// [create_store][Relation::create_store]
+    pub fn new(db: &'a IdbDatabase, store_name: &str) -> Self {
+        Self {
+            db,
+            store_name: store_name.into(),
+        }
+    }
+
+    /// Create the join store with its [left][LEFT_INDEX] and [right][RIGHT_INDEX] indices. Must
+    /// be called during an upgrade.
+    pub fn create_store(db: &IdbDatabase, store_name: &str) -> Result<(), DomException> {
+        let store = db.create_object_store(store_name)?;
+        store.create_index(LEFT_INDEX, &IdbKeyPath::str("left"))?;
+        store.create_index(RIGHT_INDEX, &IdbKeyPath::str("right"))?;
+        Ok(())
+    }
+
+    /// Link the two keys. Linking keys that are already linked is a no-op.
+    pub async fn link(&self, left: &JsValue, right: &JsValue) -> Result<(), DomException> {
+        self.link_all(&[(left.clone(), right.clone())]).await
+    }
+
+    /// Create several links in one transaction
+    pub async fn link_all(&self, links: &[(JsValue, JsValue)]) -> Result<(), DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(&self.store_name)?;
+        for (left, right) in links {
+            let record = js_sys::Object::new();
+            js_sys::Reflect::set(&record, &"left".into(), left)?;
+            js_sys::Reflect::set(&record, &"right".into(), right)?;
+            store.put_key_val(&link_key(left, right), &record)?;
+        }
+        tx.await.into_result()
+    }
+
+    /// Remove the link between the two keys, if there is one
+    pub async fn unlink(&self, left: &JsValue, right: &JsValue) -> Result<(), DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        tx.object_store(&self.store_name)?
+            .delete(&link_key(left, right))?;
+        tx.await.into_result()
+    }
+
+    /// Remove every link of the left-side key, e.g. when deleting the record it refers to.
+    /// Resolves to the number of links removed.
+    pub async fn unlink_left(&self, left: &JsValue) -> Result<u32, DomException> {
+        self.unlink_side(LEFT_INDEX, left).await
+    }
+
+    /// Remove every link of the right-side key. Resolves to the number of links removed.
+    pub async fn unlink_right(&self, right: &JsValue) -> Result<u32, DomException> {
+        self.unlink_side(RIGHT_INDEX, right).await
+    }
+
+    /// Whether the two keys are linked
+    pub async fn is_linked(&self, left: &JsValue, right: &JsValue) -> Result<bool, DomException> {
+        let tx = self.db.transaction_on_one(&self.store_name)?;
+        let store = tx.object_store(&self.store_name)?;
+        let key = store.get_key(&link_key(left, right))?.await?;
+        Ok(key.is_some())
+    }
+
+    /// The right-side keys linked to the left-side key, in key order
+    pub async fn related_to(&self, left: &JsValue) -> Result<Vec<JsValue>, DomException> {
+        self.related(LEFT_INDEX, left, 1).await
+    }
+
+    /// The left-side keys linked to the right-side key, in key order
+    pub async fn related_from(&self, right: &JsValue) -> Result<Vec<JsValue>, DomException> {
+        self.related(RIGHT_INDEX, right, 0).await
+    }
+
+    async fn related(
+        &self,
+        index: &str,
+        key: &JsValue,
+        other: u32,
+    ) -> Result<Vec<JsValue>, DomException> {
+        let tx = self.db.transaction_on_one(&self.store_name)?;
+        let store = tx.object_store(&self.store_name)?;
+        let keys = link_keys(&store, index, key).await?;
+        Ok(keys
+            .iter()
+            .map(|k| k.unchecked_ref::<js_sys::Array>().get(other))
+            .collect())
+    }
+
+    async fn unlink_side(&self, index: &str, key: &JsValue) -> Result<u32, DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(&self.store_name)?;
+        let keys = link_keys(&store, index, key).await?;
+        for key in keys.iter() {
+            store.delete(&key)?;
+        }
+        tx.await.into_result()?;
+        Ok(keys.length())
+    }
+}
+
+fn link_key(left: &JsValue, right: &JsValue) -> JsValue {
+    js_sys::Array::of2(left, right).into()
+}
+
+/// The primary keys of the links with the given key on the index's side
+async fn link_keys(
+    store: &IdbObjectStore<'_>,
+    index: &str,
+    key: &JsValue,
+) -> Result<js_sys::Array, DomException> {
+    store.index(index)?.get_all_keys_with_key(key)?.await
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::open_db_with;
+
+    use super::*;
+
+    test_mod_init!();
+
+    async fn open_relation_db() -> IdbDatabase {
+        open_db_with(|evt: &IdbVersionChangeEvent| {
+            Ok(Relation::create_store(evt.db(), "post_tags")?)
+        })
+        .await
+    }
+
+    fn strings(values: Vec<JsValue>) -> Vec<String> {
+        values.into_iter().map(|v| v.as_string().unwrap()).collect()
+    }
+
+    fn numbers(values: Vec<JsValue>) -> Vec<u8> {
+        values
+            .into_iter()
+            .map(|v| v.as_f64().unwrap() as u8)
+            .collect()
+    }
+
+    test_case!(async link_and_query => {
+        let db = open_relation_db().await;
+        let rel = Relation::new(&db, "post_tags");
+        rel.link(&1.into(), &"rust".into()).await.expect("link 1");
+        rel.link(&1.into(), &"wasm".into()).await.expect("link 2");
+        rel.link(&1.into(), &"rust".into()).await.expect("link again");
+        rel.link_all(&[(2.into(), "rust".into()), (3.into(), "web".into())]).await.expect("link_all");
+
+        assert_eq!(strings(rel.related_to(&1.into()).await.expect("related_to")), vec!["rust", "wasm"], "tags of 1");
+        assert_eq!(numbers(rel.related_from(&"rust".into()).await.expect("related_from")), vec![1, 2], "posts of rust");
+        assert!(rel.is_linked(&3.into(), &"web".into()).await.expect("is_linked"), "linked");
+        assert!(!rel.is_linked(&3.into(), &"rust".into()).await.expect("is_linked 2"), "not linked");
+
+        rel.unlink(&1.into(), &"rust".into()).await.expect("unlink");
+        assert_eq!(numbers(rel.related_from(&"rust".into()).await.expect("after unlink")), vec![2], "unlinked");
+    });
+
+    test_case!(async unlink_sides => {
+        let db = open_relation_db().await;
+        let rel = Relation::new(&db, "post_tags");
+        rel.link_all(&[
+            (1.into(), "a".into()),
+            (1.into(), "b".into()),
+            (2.into(), "a".into()),
+            (3.into(), "b".into()),
+        ])
+        .await
+        .expect("link_all");
+
+        assert_eq!(rel.unlink_left(&1.into()).await.expect("unlink_left"), 2, "left");
+        assert!(rel.related_to(&1.into()).await.expect("related_to").is_empty(), "left gone");
+        assert_eq!(rel.unlink_right(&"a".into()).await.expect("unlink_right"), 1, "right");
+        assert_eq!(strings(rel.related_to(&3.into()).await.expect("untouched")), vec!["b"], "untouched");
+    });
+}
@@ -3,7 +3,7 @@ use std::pin::Pin;
 use wasm_bindgen::prelude::*;
 use web_sys::DomException;
 
-use crate::internal_utils::safe_unwrap_option;
+use crate::internal_utils::require;
 
 use super::{IdbRequestFuture, ResponseFormattingFuture};
 
@@ -18,7 +18,7 @@ impl CountFuture {
 
 impl ResponseFormattingFuture<u32> for CountFuture {
     fn format_response(v: Result<Option<JsValue>, DomException>) -> Result<u32, DomException> {
-        Ok(safe_unwrap_option(v?).as_f64().unwrap() as u32)
+        Ok(require(require(v?)?.as_f64())? as u32)
     }
 
     #[inline]
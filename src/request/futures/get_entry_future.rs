@@ -0,0 +1,94 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use wasm_bindgen::prelude::*;
+use web_sys::DomException;
+
+use super::OptionalJsValueFuture;
+
+/// The outcome of [get_entry][crate::idb_query_source::IdbQuerySource::get_entry], which, unlike
+/// [get][crate::idb_query_source::IdbQuerySource::get], tells a missing record apart from one
+/// whose value is `undefined`
+#[derive(Debug, Clone, PartialEq)]
+pub enum GetResult {
+    /// There's no record at the key
+    Missing,
+    /// The record's value, which may be `undefined`
+    Present(JsValue),
+}
+
+impl GetResult {
+    /// Whether there's a record at the key
+    #[inline]
+    pub fn is_present(&self) -> bool {
+        matches!(self, Self::Present(_))
+    }
+
+    /// Whether there's no record at the key
+    #[inline]
+    pub fn is_missing(&self) -> bool {
+        matches!(self, Self::Missing)
+    }
+
+    /// The record's value, if there is a record
+    #[inline]
+    pub fn into_option(self) -> Option<JsValue> {
+        self.into()
+    }
+}
+
+impl From<GetResult> for Option<JsValue> {
+    #[inline]
+    fn from(result: GetResult) -> Self {
+        match result {
+            GetResult::Missing => None,
+            GetResult::Present(value) => Some(value),
+        }
+    }
+}
+
+/// A [Future] for [get_entry][crate::idb_query_source::IdbQuerySource::get_entry]. Issues a `get`
+/// and a `getKey` for the same key, as keys can never be `undefined`.
+#[derive(Debug)]
+pub struct GetEntryFuture {
+    value: OptionalJsValueFuture,
+    key: OptionalJsValueFuture,
+    value_out: Option<Result<Option<JsValue>, DomException>>,
+}
+
+impl GetEntryFuture {
+    #[inline]
+    pub(crate) fn new(value: OptionalJsValueFuture, key: OptionalJsValueFuture) -> Self {
+        Self {
+            value,
+            key,
+            value_out: None,
+        }
+    }
+}
+
+impl Future for GetEntryFuture {
+    type Output = Result<GetResult, DomException>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.value_out.is_none() {
+            match Pin::new(&mut self.value).poll(ctx) {
+                Poll::Ready(out) => self.value_out = Some(out),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let key = match Pin::new(&mut self.key).poll(ctx) {
+            Poll::Ready(key) => key,
+            Poll::Pending => return Poll::Pending,
+        };
+        let value = self
+            .value_out
+            .take()
+            .expect("GetEntryFuture polled after completion");
+        Poll::Ready(match key? {
+            Some(_) => Ok(GetResult::Present(value?.unwrap_or(JsValue::UNDEFINED))),
+            None => value.map(|_| GetResult::Missing),
+        })
+    }
+}
@@ -4,7 +4,7 @@ use std::pin::Pin;
 use std::task::{Context, Poll};
 use web_sys::DomException;
 
-use crate::internal_utils::safe_unwrap_option;
+use crate::internal_utils::require;
 use wasm_bindgen::prelude::*;
 
 /// Future for cursors' advance() and continue()
@@ -20,7 +20,7 @@ impl IdbCursorAdvancementFuture {
     }
 
     fn on_done(res: Result<Option<JsValue>, DomException>) -> Result<bool, DomException> {
-        Ok(!safe_unwrap_option(res?).is_null())
+        Ok(!require(res?)?.is_null())
     }
 }
 
@@ -8,7 +8,7 @@ use web_sys::DomException;
 
 use crate::idb_cursor::IdbCursor;
 use crate::idb_query_source::IdbQuerySource;
-use crate::internal_utils::safe_unwrap_option;
+use crate::internal_utils::require;
 
 use super::{super::IdbRequestRef, IdbRequestFuture};
 
@@ -45,7 +45,7 @@ impl<'a, T: IdbQuerySource> IdbCursorFuture<'a, T> {
         &self,
         res: Result<Option<JsValue>, DomException>,
     ) -> Result<Option<IdbCursor<'a, T>>, DomException> {
-        let raw = safe_unwrap_option(res?);
+        let raw = require(res?)?;
         let opt = if raw.is_null() {
             None
         } else {
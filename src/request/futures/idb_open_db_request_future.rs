@@ -2,10 +2,9 @@ use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
-use wasm_bindgen::prelude::*;
-use web_sys::DomException;
+use wasm_bindgen::{prelude::*, JsCast};
 
-use super::{super::OpenDbRequestListeners, IdbRequestFuture};
+use super::{super::OpenDbError, super::OpenDbRequestListeners, IdbRequestFuture};
 
 /// Base IdbOpenDbRequest future implementation
 #[derive(Debug)]
@@ -22,10 +21,22 @@ impl IdbOpenDbRequestFuture {
 }
 
 impl Future for IdbOpenDbRequestFuture {
-    type Output = Result<Option<JsValue>, DomException>;
+    type Output = Result<Option<JsValue>, OpenDbError>;
 
-    #[inline]
     fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
-        self.base.do_poll(ctx)
+        let result = match self.base.do_poll(ctx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        // A panicking handler fails the open even if the browser managed to open the database
+        Poll::Ready(match self.listeners.take_panic() {
+            Some(panicked) => {
+                if let Ok(Some(db)) = result {
+                    db.unchecked_into::<web_sys::IdbDatabase>().close();
+                }
+                Err(panicked.into())
+            }
+            None => Ok(result?),
+        })
     }
 }
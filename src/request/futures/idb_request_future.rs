@@ -7,7 +7,7 @@ use std::task::{Context, Poll, Waker};
 use wasm_bindgen::{prelude::*, JsCast};
 use web_sys::{DomException, IdbRequestReadyState};
 
-use crate::internal_utils::{create_lazy_ref_cell, safe_unwrap_option, wake};
+use crate::internal_utils::{create_lazy_ref_cell, dom_exception, wake, ClosureKind, ClosureToken};
 
 use super::super::IdbRequestRef;
 
@@ -15,6 +15,7 @@ type WakerRef = Rc<RefCell<Option<Waker>>>;
 type ResultRef = Rc<RefCell<Option<OutputResult>>>;
 type OutputResult = Result<Option<JsValue>, DomException>;
 type Cb = Closure<dyn Fn() + 'static>;
+type ErrorCb = Closure<dyn Fn(web_sys::Event) + 'static>;
 
 /// Base IdbRequest future implementation
 #[derive(Debug)]
@@ -23,7 +24,7 @@ pub(crate) struct IdbRequestFuture {
     result: ResultRef,
     waker: WakerRef,
     on_success: Option<Cb>,
-    on_error: Option<Cb>,
+    on_error: Option<ErrorCb>,
 }
 
 impl IdbRequestFuture {
@@ -90,12 +91,13 @@ impl IdbRequestFuture {
 
     /// Actual [Future] polling function
     pub fn do_poll(&self, ctx: &Context<'_>) -> Poll<OutputResult> {
-        if self.result.borrow().is_some() {
-            let result = safe_unwrap_option(self.result.replace(None));
-            Poll::Ready(result)
-        } else {
-            RefCell::borrow_mut(&self.waker).replace(ctx.waker().clone());
-            Poll::Pending
+        let result = self.result.replace(None);
+        match result {
+            Some(result) => Poll::Ready(result),
+            None => {
+                RefCell::borrow_mut(&self.waker).replace(ctx.waker().clone());
+                Poll::Pending
+            }
         }
     }
 }
@@ -131,18 +133,36 @@ fn create_success_closure(
     request: Rc<IdbRequestRef>,
     read: bool,
 ) -> Cb {
+    let token = ClosureToken::new(ClosureKind::Request);
     let b = Box::new(move || {
+        token.hold();
         result.replace(Some(extract_success_result(&request, read)));
         wake(&waker);
     });
     Closure::wrap(b)
 }
 
-/// Create on_error callback
-fn create_error_closure(waker: WakerRef, result: ResultRef, request: Rc<IdbRequestRef>) -> Cb {
-    let b = Box::new(move || {
-        result.replace(Some(Err(request.error().expect("Failed to unwrap error"))));
+/// Create on_error callback. The error is captured when the event fires so that it's reported
+/// for this specific request rather than only through the transaction.
+fn create_error_closure(waker: WakerRef, result: ResultRef, request: Rc<IdbRequestRef>) -> ErrorCb {
+    let token = ClosureToken::new(ClosureKind::Request);
+    let b = Box::new(move |evt: web_sys::Event| {
+        token.hold();
+        let err = request
+            .error()
+            .or_else(move || event_error(&evt))
+            .unwrap_or_else(|| dom_exception("Request failed", "UnknownError"));
+        result.replace(Some(Err(err)));
         wake(&waker);
     });
     Closure::wrap(b)
 }
+
+/// Read the `error` of the event's target, which is the request that failed
+fn event_error(evt: &web_sys::Event) -> Option<DomException> {
+    let target = evt.target()?;
+    js_sys::Reflect::get(&target, &JsValue::from_str("error"))
+        .ok()?
+        .dyn_into()
+        .ok()
+}
@@ -7,7 +7,7 @@ use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::DomException;
 
-use crate::internal_utils::safe_unwrap_option;
+use crate::internal_utils::require;
 
 use super::{super::IdbRequestRef, IdbRequestFuture, ResponseFormattingFuture};
 
@@ -20,17 +20,21 @@ pub struct JsCastRequestFuture<T: JsCast> {
 
 impl<T: JsCast> JsCastRequestFuture<T> {
     pub(crate) fn new(req: Result<web_sys::IdbRequest, JsValue>) -> Result<Self, DomException> {
-        let out = Self {
-            inner: IdbRequestRef::new(req?).into_future(true),
+        Ok(Self::from_ref(IdbRequestRef::new(req?)))
+    }
+
+    #[inline]
+    pub(crate) fn from_ref(req: IdbRequestRef) -> Self {
+        Self {
+            inner: req.into_future(true),
             _cast: PhantomData::default(),
-        };
-        Ok(out)
+        }
     }
 }
 
 impl<T: JsCast> ResponseFormattingFuture<T> for JsCastRequestFuture<T> {
     fn format_response(v: Result<Option<JsValue>, DomException>) -> Result<T, DomException> {
-        Ok(safe_unwrap_option(v?).unchecked_into())
+        Ok(require(v?)?.unchecked_into())
     }
 
     #[inline]
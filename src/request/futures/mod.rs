@@ -4,6 +4,7 @@ use wasm_bindgen::prelude::*;
 use web_sys::DomException;
 
 pub use count_future::*;
+pub use get_entry_future::*;
 pub(crate) use idb_open_db_request_future::*;
 pub(crate) use idb_request_future::*;
 pub use jscast_request_future::*;
@@ -14,8 +15,12 @@ macro_rules! impl_result_formatting_struct_constructor {
         pub(crate) fn new(
             req: Result<web_sys::IdbRequest, wasm_bindgen::JsValue>,
         ) -> Result<Self, DomException> {
-            let base = $crate::request::IdbRequestRef::new(req?).into_future(true);
-            Ok(Self(base))
+            Ok(Self::from_ref($crate::request::IdbRequestRef::new(req?)))
+        }
+
+        #[inline]
+        pub(crate) fn from_ref(req: $crate::request::IdbRequestRef) -> Self {
+            Self(req.into_future(true))
         }
     };
 }
@@ -37,6 +42,7 @@ macro_rules! impl_result_formatting_future_future {
 }
 
 mod count_future;
+mod get_entry_future;
 mod idb_open_db_request_future;
 mod idb_request_future;
 mod jscast_request_future;
@@ -3,7 +3,7 @@ use std::pin::Pin;
 use wasm_bindgen::prelude::*;
 use web_sys::DomException;
 
-use crate::internal_utils::{optional_jsvalue_undefined, safe_unwrap_option};
+use crate::internal_utils::{optional_jsvalue_undefined, require};
 
 use super::{IdbRequestFuture, ResponseFormattingFuture};
 
@@ -19,7 +19,7 @@ impl ResponseFormattingFuture<Option<JsValue>> for OptionalJsValueFuture {
     fn format_response(
         v: Result<Option<JsValue>, DomException>,
     ) -> Result<Option<JsValue>, DomException> {
-        Ok(optional_jsvalue_undefined(safe_unwrap_option(v?)))
+        Ok(optional_jsvalue_undefined(require(v?)?))
     }
 
     #[inline]
@@ -0,0 +1,256 @@
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use web_sys::DomException;
+
+use crate::internal_utils::dom_exception;
+
+/// The [DomException] name [HandlerPanicked] errors are surfaced with
+pub const HANDLER_PANICKED: &str = "HandlerPanicked";
+
+/// An event handler passed to this crate, e.g. an `upgradeneeded` callback, panicked.
+///
+/// Open requests whose handler panicked fail with [OpenDbError::HandlerPanicked]. It converts to
+/// a [DomException] named [HANDLER_PANICKED], which is also what the browser sees. Where panics
+/// unwind the panic is caught, otherwise the payload message is only known if
+/// [set_handler_panic_hook] has been called. Either way the handler's transaction is aborted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandlerPanicked {
+    /// The event the handler was handling, e.g. `upgradeneeded`
+    pub handler: String,
+    /// The panic payload's message
+    pub message: String,
+}
+
+impl fmt::Display for HandlerPanicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} handler panicked: {}", self.handler, self.message)
+    }
+}
+
+impl std::error::Error for HandlerPanicked {}
+
+impl From<HandlerPanicked> for DomException {
+    fn from(panicked: HandlerPanicked) -> Self {
+        dom_exception(&panicked.to_string(), HANDLER_PANICKED)
+    }
+}
+
+/// Why opening or deleting a database failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpenDbError {
+    /// An event handler passed to the request panicked
+    HandlerPanicked(HandlerPanicked),
+    /// The request failed, e.g. with a `VersionError`
+    Dom(DomException),
+}
+
+impl From<HandlerPanicked> for OpenDbError {
+    #[inline]
+    fn from(e: HandlerPanicked) -> Self {
+        Self::HandlerPanicked(e)
+    }
+}
+
+impl From<DomException> for OpenDbError {
+    #[inline]
+    fn from(e: DomException) -> Self {
+        Self::Dom(e)
+    }
+}
+
+impl From<OpenDbError> for DomException {
+    fn from(e: OpenDbError) -> Self {
+        match e {
+            OpenDbError::HandlerPanicked(e) => e.into(),
+            OpenDbError::Dom(e) => e,
+        }
+    }
+}
+
+impl From<OpenDbError> for JsValue {
+    #[inline]
+    fn from(e: OpenDbError) -> Self {
+        DomException::from(e).into()
+    }
+}
+
+impl fmt::Display for OpenDbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HandlerPanicked(e) => fmt::Display::fmt(e, f),
+            Self::Dom(e) => write!(f, "{}: {}", e.name(), e.message()),
+        }
+    }
+}
+
+impl std::error::Error for OpenDbError {}
+
+pub(crate) type PanicSlot = Rc<RefCell<Option<HandlerPanicked>>>;
+
+struct Running {
+    handler: &'static str,
+    tx: Option<web_sys::IdbTransaction>,
+    slot: PanicSlot,
+}
+
+type Callback = Rc<dyn Fn(&HandlerPanicked)>;
+
+thread_local! {
+    static RUNNING: RefCell<Option<Running>> = const { RefCell::new(None) };
+    static CALLBACK: RefCell<Option<Callback>> = const { RefCell::new(None) };
+    static HOOK_INSTALLED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Install a panic hook, chained in front of the current one, that records panics raised inside
+/// event handlers passed to this crate and aborts their transactions before the panic
+/// continues. This is what gives [HandlerPanicked] errors their message on targets where panics
+/// abort instead of unwinding, such as `wasm32-unknown-unknown`; the original hook, e.g.
+/// `console_error_panic_hook`, still runs afterwards. The callback, if any, gets called with
+/// every recorded panic. Calling this again replaces the callback.
+pub fn set_handler_panic_hook<F: Fn(&HandlerPanicked) + 'static>(callback: Option<F>) {
+    let callback = callback.map(|f| Rc::new(f) as Callback);
+    CALLBACK.with(|current| *current.borrow_mut() = callback);
+    if HOOK_INSTALLED.with(|installed| installed.replace(true)) {
+        return;
+    }
+
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let message = payload_message(info.payload()).unwrap_or_else(|| info.to_string());
+        // Take the handler out rather than leave it for guard to restore: where panics abort,
+        // guard never returns, and a handler left behind would get blamed for later panics
+        let running = RUNNING
+            .try_with(|running| running.try_borrow_mut().ok().and_then(|mut r| r.take()))
+            .ok()
+            .flatten();
+        if let Some(ref running) = running {
+            record(running, message);
+        }
+        previous(info);
+    }));
+}
+
+/// Run an event handler, turning a panic into a recorded [HandlerPanicked] and an aborted
+/// transaction
+pub(crate) fn guard<F>(
+    handler: &'static str,
+    tx: Option<web_sys::IdbTransaction>,
+    slot: &PanicSlot,
+    f: F,
+) -> Result<(), JsValue>
+where
+    F: FnOnce() -> Result<(), JsValue>,
+{
+    let running = Running {
+        handler,
+        tx,
+        slot: slot.clone(),
+    };
+    let outer = RUNNING.with(|r| r.replace(Some(running)));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    let running = RUNNING.with(|r| r.replace(outer));
+
+    match result {
+        Ok(result) => result,
+        Err(payload) => {
+            if slot.borrow().is_none() {
+                let message =
+                    payload_message(&*payload).unwrap_or_else(|| String::from("Box<dyn Any>"));
+                if let Some(ref running) = running {
+                    record(running, message);
+                }
+            }
+            let panicked = slot.borrow().clone().unwrap_or_else(|| HandlerPanicked {
+                handler: handler.into(),
+                message: String::new(),
+            });
+            Err(DomException::from(panicked).into())
+        }
+    }
+}
+
+fn payload_message(payload: &(dyn Any + Send)) -> Option<String> {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => Some(String::from(*message)),
+        None => payload.downcast_ref::<String>().cloned(),
+    }
+}
+
+fn record(running: &Running, message: String) {
+    if let Some(ref tx) = running.tx {
+        let _ = tx.abort();
+    }
+    let panicked = HandlerPanicked {
+        handler: running.handler.into(),
+        message,
+    };
+    if let Ok(Some(callback)) = CALLBACK.try_with(|callback| callback.borrow().clone()) {
+        callback(&panicked);
+    }
+    if let Ok(mut slot) = running.slot.try_borrow_mut() {
+        slot.get_or_insert(panicked);
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    test_mod_init!();
+
+    test_case!(exception_conversion => {
+        let panicked = HandlerPanicked {
+            handler: "upgradeneeded".into(),
+            message: "index out of bounds: the len is 0".into(),
+        };
+        let exception = DomException::from(OpenDbError::from(panicked.clone()));
+        assert_eq!(exception.name(), HANDLER_PANICKED, "name");
+        assert_eq!(exception.message(), panicked.to_string(), "message");
+        let other = dom_exception("Transaction aborted", "AbortError");
+        assert_eq!(OpenDbError::from(other.clone()), OpenDbError::Dom(other), "other");
+    });
+
+    test_case!(records_panics => {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let seen_cb = seen.clone();
+        set_handler_panic_hook(Some(move |p: &HandlerPanicked| seen_cb.borrow_mut().push(p.clone())));
+
+        let slot = PanicSlot::default();
+        assert!(guard("upgradeneeded", None, &slot, || Ok(())).is_ok(), "ok");
+        assert!(slot.borrow().is_none(), "nothing recorded");
+
+        let running = Running { handler: "blocked", tx: None, slot: slot.clone() };
+        record(&running, "boom".into());
+        record(&running, "again".into());
+        let expected = HandlerPanicked { handler: "blocked".into(), message: "boom".into() };
+        assert_eq!(slot.borrow().clone(), Some(expected.clone()), "first panic kept");
+        assert_eq!(seen.borrow().first(), Some(&expected), "callback");
+        set_handler_panic_hook::<fn(&HandlerPanicked)>(None);
+    });
+
+    // A real panic kills the whole test run where panics abort
+    #[cfg(panic = "unwind")]
+    test_case!(async upgrade_panic_fails_open => {
+        use crate::prelude::*;
+        use crate::test_utils::unique_name;
+
+        let name = unique_name();
+        let mut req = IdbDatabase::open_u32(&name, 1).expect("open");
+        req.set_on_upgrade_needed(Some(|evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+            evt.db().create_object_store("s")?;
+            panic!("boom");
+        }));
+        let err = req.into_future().await.expect_err("open");
+        let expected = HandlerPanicked { handler: "upgradeneeded".into(), message: "boom".into() };
+        assert_eq!(err, OpenDbError::HandlerPanicked(expected));
+
+        let db = IdbDatabase::open(&name).expect("reopen").into_future().await.expect("reopen");
+        assert_eq!(db.object_store_names().count(), 0, "upgrade aborted");
+        db.close();
+    });
+}
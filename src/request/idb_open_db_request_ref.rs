@@ -1,18 +1,21 @@
 use std::ops::{Deref, DerefMut};
 use std::rc::{Rc, Weak};
 
+use cfg_if::cfg_if;
 use wasm_bindgen::{prelude::*, JsCast};
 
 use crate::idb_database::{IdbVersionChangeCallback, IdbVersionChangeEvent};
-use crate::internal_utils::{safe_unwrap_option, safe_unwrap_result};
+#[cfg(not(feature = "no-panic"))]
+use crate::internal_utils::safe_unwrap_option;
 
-use super::{IdbOpenDbRequestFuture, IdbRequestRef};
+use super::{HandlerPanicked, IdbOpenDbRequestFuture, IdbRequestRef, PanicSlot};
 
 #[derive(Debug)]
 pub(crate) struct OpenDbRequestListeners {
     request: Weak<IdbRequestRef>,
     on_blocked: Option<IdbVersionChangeCallback>,
     on_upgrade_needed: Option<IdbVersionChangeCallback>,
+    panicked: PanicSlot,
 }
 
 impl OpenDbRequestListeners {
@@ -22,18 +25,46 @@ impl OpenDbRequestListeners {
             request,
             on_upgrade_needed: None,
             on_blocked: None,
+            panicked: PanicSlot::default(),
         }
     }
 
-    pub fn set_on_upgrade_needed<F>(&mut self, callback: Option<F>)
+    /// The request these listeners belong to. It outlives them, so it's only ever gone if
+    /// something went very wrong: that panics, unless the `no-panic` feature is enabled, in
+    /// which case setting a callback does nothing.
+    fn base(&self) -> Option<Rc<IdbRequestRef>> {
+        cfg_if! {
+            if #[cfg(feature = "no-panic")] {
+                self.request.upgrade()
+            } else {
+                Some(safe_unwrap_option(self.request.upgrade()))
+            }
+        }
+    }
+
+    /// The panic raised by one of the callbacks, if any
+    #[inline]
+    pub fn take_panic(&self) -> Option<HandlerPanicked> {
+        self.panicked.borrow_mut().take()
+    }
+
+    pub fn set_on_upgrade_needed<F, E>(&mut self, callback: Option<F>)
     where
-        F: Fn(&IdbVersionChangeEvent) -> Result<(), JsValue> + 'static,
+        F: FnMut(&IdbVersionChangeEvent) -> Result<(), E> + 'static,
+        E: Into<JsValue>,
     {
-        let base = safe_unwrap_option(self.request.upgrade());
+        let base = match self.base() {
+            Some(base) => base,
+            None => return,
+        };
         let req = base.inner_as_idb_request();
         self.on_upgrade_needed = match callback {
             Some(callback) => {
-                let callback = IdbVersionChangeEvent::wrap_callback(callback);
+                let callback = IdbVersionChangeEvent::wrap_callback(
+                    callback,
+                    "upgradeneeded",
+                    self.panicked.clone(),
+                );
                 req.set_onupgradeneeded(Some(callback.as_ref().unchecked_ref()));
                 Some(callback)
             }
@@ -44,15 +75,23 @@ impl OpenDbRequestListeners {
         };
     }
 
-    pub fn set_on_blocked<F>(&mut self, callback: Option<F>)
+    pub fn set_on_blocked<F, E>(&mut self, callback: Option<F>)
     where
-        F: Fn(&IdbVersionChangeEvent) -> Result<(), JsValue> + 'static,
+        F: FnMut(&IdbVersionChangeEvent) -> Result<(), E> + 'static,
+        E: Into<JsValue>,
     {
-        let base = safe_unwrap_option(self.request.upgrade());
+        let base = match self.base() {
+            Some(base) => base,
+            None => return,
+        };
         let req = base.inner_as_idb_request();
         self.on_blocked = match callback {
             Some(callback) => {
-                let callback = IdbVersionChangeEvent::wrap_callback(callback);
+                let callback = IdbVersionChangeEvent::wrap_callback(
+                    callback,
+                    "blocked",
+                    self.panicked.clone(),
+                );
                 req.set_onblocked(Some(callback.as_ref().unchecked_ref()));
                 Some(callback)
             }
@@ -101,9 +140,16 @@ impl IdbOpenDbRequestRef {
         Self { base, listeners }
     }
 
+    #[inline]
+    pub fn inner(&self) -> &web_sys::IdbOpenDbRequest {
+        self.base.inner_as_idb_request()
+    }
+
     pub fn into_future(self, read_response: bool) -> IdbOpenDbRequestFuture {
         // We need to take the request out of the Rc to turn it into a future
-        let base = safe_unwrap_result(Rc::try_unwrap(self.base)).into_future(read_response);
+        let base = Rc::try_unwrap(self.base)
+            .unwrap_or_else(|rc| IdbRequestRef::new(rc.inner().clone()))
+            .into_future(read_response);
 
         // Then we need to re-set the new weak ref
         let mut listeners = self.listeners;
@@ -0,0 +1,155 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Poll a tuple of up to 8 futures, e.g. request futures with different output types, at the
+/// same time and resolve to a tuple of their outputs once they've all completed.
+///
+/// Requests get queued when they're created, not when they're first polled, so making them all
+/// up front and joining them keeps the transaction's lifetime to a minimum.
+///
+/// ```rust
+/// use indexed_db_futures::prelude::*;
+/// use indexed_db_futures::request::join;
+/// use wasm_bindgen::prelude::*;
+/// use web_sys::DomException;
+///
+/// async fn example(store: &IdbObjectStore<'_>) -> Result<(), DomException> {
+///     let (value, count) = join((store.get_owned("key")?, store.count()?)).await;
+///     let (_value, _count): (Option<JsValue>, u32) = (value?, count?);
+///     Ok(())
+/// }
+/// ```
+#[inline]
+pub fn join<T: Joinable>(futures: T) -> Join<T::Slots> {
+    Join(futures.into_slots())
+}
+
+/// A tuple of futures that can be [joined][join]
+pub trait Joinable {
+    /// The futures' join state
+    type Slots;
+
+    /// Wrap the futures up for joining
+    fn into_slots(self) -> Self::Slots;
+}
+
+/// Future returned by [join]
+#[derive(Debug)]
+pub struct Join<T>(T);
+
+// No pin projection into the outputs ever happens; the futures themselves are boxed.
+impl<T> Unpin for Join<T> {}
+
+/// A single future's state within a [Join]
+pub enum JoinSlot<F: Future> {
+    /// Still running
+    Pending(Pin<Box<F>>),
+    /// Completed; `None` once the output has been handed out
+    Done(Option<F::Output>),
+}
+
+impl<F: Future> JoinSlot<F> {
+    fn new(fut: F) -> Self {
+        Self::Pending(Box::pin(fut))
+    }
+
+    /// Poll the slot, returning whether it's done
+    fn poll_slot(&mut self, ctx: &mut Context<'_>) -> bool {
+        match self {
+            Self::Pending(fut) => match fut.as_mut().poll(ctx) {
+                Poll::Ready(out) => {
+                    *self = Self::Done(Some(out));
+                    true
+                }
+                Poll::Pending => false,
+            },
+            Self::Done(_) => true,
+        }
+    }
+
+    fn take(&mut self) -> F::Output {
+        match self {
+            Self::Done(out) => out.take().expect("Join polled after completion"),
+            Self::Pending(_) => unreachable!("Join output taken before completion"),
+        }
+    }
+}
+
+impl<F: Future> std::fmt::Debug for JoinSlot<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Pending(_) => "JoinSlot::Pending",
+            Self::Done(_) => "JoinSlot::Done",
+        })
+    }
+}
+
+/// Poll any number of futures of the same type at the same time and resolve to their outputs, in
+/// the same order, once they've all completed. See [join] for futures of different types.
+pub fn join_all<I>(futures: I) -> JoinAll<I::Item>
+where
+    I: IntoIterator,
+    I::Item: Future,
+{
+    JoinAll(futures.into_iter().map(JoinSlot::new).collect())
+}
+
+/// Future returned by [join_all]
+#[derive(Debug)]
+pub struct JoinAll<F: Future>(Vec<JoinSlot<F>>);
+
+impl<F: Future> Unpin for JoinAll<F> {}
+
+impl<F: Future> Future for JoinAll<F> {
+    type Output = Vec<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut done = true;
+        for slot in self.0.iter_mut() {
+            done &= slot.poll_slot(ctx);
+        }
+        if done {
+            Poll::Ready(self.0.iter_mut().map(JoinSlot::take).collect())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+macro_rules! impl_join {
+    ($($fut: ident $idx: tt),+) => {
+        impl<$($fut: Future),+> Joinable for ($($fut,)+) {
+            type Slots = ($(JoinSlot<$fut>,)+);
+
+            #[inline]
+            fn into_slots(self) -> Self::Slots {
+                ($(JoinSlot::new(self.$idx),)+)
+            }
+        }
+
+        impl<$($fut: Future),+> Future for Join<($(JoinSlot<$fut>,)+)> {
+            type Output = ($($fut::Output,)+);
+
+            fn poll(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+                let slots = &mut self.0;
+                let mut done = true;
+                $(done &= slots.$idx.poll_slot(ctx);)+
+                if done {
+                    Poll::Ready(($(slots.$idx.take(),)+))
+                } else {
+                    Poll::Pending
+                }
+            }
+        }
+    };
+}
+
+impl_join!(A 0);
+impl_join!(A 0, B 1);
+impl_join!(A 0, B 1, C 2);
+impl_join!(A 0, B 1, C 2, D 3);
+impl_join!(A 0, B 1, C 2, D 3, E 4);
+impl_join!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_join!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_join!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
@@ -3,15 +3,22 @@
 use std::future::Future;
 
 pub use futures::*;
+pub(crate) use handler_panic::{guard, PanicSlot};
+pub use handler_panic::{set_handler_panic_hook, HandlerPanicked, OpenDbError, HANDLER_PANICKED};
 use idb_open_db_request_ref::*;
 pub(crate) use idb_request_ref::*;
+pub use join::*;
 pub use open_db_request::*;
 pub use request_like::*;
+pub use typed_request::*;
 pub use void_open_db_request::*;
 pub use void_request::*;
 
 macro_rules! impl_void_request {
     ($for: ty, $raw_ty: ty, $ref_ty: ty) => {
+        impl_void_request!($for, $raw_ty, $ref_ty, web_sys::DomException);
+    };
+    ($for: ty, $raw_ty: ty, $ref_ty: ty, $err: ty) => {
         impl $for {
             #[inline]
             pub(crate) fn new(req: $raw_ty) -> Self {
@@ -20,11 +27,29 @@ macro_rules! impl_void_request {
 
             /// Turn the request into a future. This is when event listeners get set.
             #[inline]
-            pub fn into_future(
-                self,
-            ) -> impl std::future::Future<Output = Result<(), web_sys::DomException>> {
+            pub fn into_future(self) -> impl std::future::Future<Output = Result<(), $err>> {
                 $crate::request::await_void_future(self.0.into_future(false))
             }
+
+            /// The error the request failed with, if it has finished and failed. Useful for
+            /// finding out which request in a bulk operation failed.
+            #[inline]
+            pub fn error(&self) -> Option<web_sys::DomException> {
+                self.0.inner().error().ok()?
+            }
+
+            /// The underlying web_sys request
+            #[inline]
+            pub fn as_web_sys(&self) -> &$raw_ty {
+                self.0.inner()
+            }
+
+            /// Unwrap the underlying web_sys request. Any callbacks set through this wrapper get
+            /// removed.
+            #[inline]
+            pub fn into_web_sys(self) -> $raw_ty {
+                self.0.inner().clone()
+            }
         }
     };
 }
@@ -35,7 +60,7 @@ macro_rules! impl_idb_open_request_like {
             #[inline]
             fn set_on_upgrade_needed<F>(&mut self, callback: Option<F>)
             where
-                F: Fn(
+                F: FnMut(
                         &crate::idb_database::IdbVersionChangeEvent,
                     ) -> Result<(), wasm_bindgen::JsValue>
                     + 'static,
@@ -46,7 +71,7 @@ macro_rules! impl_idb_open_request_like {
             #[inline]
             fn set_on_blocked<F>(&mut self, callback: Option<F>)
             where
-                F: Fn(
+                F: FnMut(
                         &crate::idb_database::IdbVersionChangeEvent,
                     ) -> Result<(), wasm_bindgen::JsValue>
                     + 'static,
@@ -57,10 +82,17 @@ macro_rules! impl_idb_open_request_like {
     };
 }
 
+/// The callback type used for removing version change callbacks
+pub(crate) type NoVersionChangeCallback =
+    fn(&crate::idb_database::IdbVersionChangeEvent) -> Result<(), wasm_bindgen::JsValue>;
+
+mod handler_panic;
 mod idb_open_db_request_ref;
 mod idb_request_ref;
+mod join;
 mod open_db_request;
 mod request_like;
+mod typed_request;
 mod void_open_db_request;
 mod void_request;
 
@@ -1,12 +1,11 @@
 use std::future::Future;
 
 use wasm_bindgen::{JsCast, JsValue};
-use web_sys::DomException;
 
 use crate::idb_database::IdbDatabase;
-use crate::internal_utils::safe_unwrap_option;
+use crate::internal_utils::require;
 
-use super::IdbOpenDbRequestRef;
+use super::{IdbOpenDbRequestRef, OpenDbError};
 
 /// Request for opening an [IdbDatabase]
 #[derive(Debug)]
@@ -18,14 +17,24 @@ impl OpenDbRequest {
         Self(IdbOpenDbRequestRef::new(req))
     }
 
-    fn instantiate(
-        raw: Result<Option<JsValue>, DomException>,
-    ) -> Result<IdbDatabase, DomException> {
-        Ok(IdbDatabase::new(safe_unwrap_option(raw?).unchecked_into()))
+    fn instantiate(raw: Result<Option<JsValue>, OpenDbError>) -> Result<IdbDatabase, OpenDbError> {
+        Ok(IdbDatabase::new(require(raw?)?.unchecked_into()))
+    }
+
+    /// The underlying web_sys request
+    #[inline]
+    pub fn as_web_sys(&self) -> &web_sys::IdbOpenDbRequest {
+        self.0.inner()
+    }
+
+    /// Unwrap the underlying web_sys request. Any callbacks set through this wrapper get removed.
+    #[inline]
+    pub fn into_web_sys(self) -> web_sys::IdbOpenDbRequest {
+        self.0.inner().clone()
     }
 
     /// Turn the request into a future. This is when event listeners get set.
-    pub fn into_future(self) -> impl Future<Output = Result<IdbDatabase, DomException>> {
+    pub fn into_future(self) -> impl Future<Output = Result<IdbDatabase, OpenDbError>> {
         let fut = self.0.into_future(true);
         async move { Self::instantiate(fut.await) }
     }
@@ -3,6 +3,8 @@ use web_sys::{DomException, IdbOpenDbRequest, IdbRequest, IdbRequestReadyState};
 
 use crate::idb_database::IdbVersionChangeEvent;
 
+use super::NoVersionChangeCallback;
+
 pub(crate) trait IdbRequestLike {
     fn get_error(&self) -> Result<Option<DomException>, JsValue>;
     fn get_result(&self) -> Result<JsValue, JsValue>;
@@ -12,16 +14,80 @@ pub(crate) trait IdbRequestLike {
 }
 
 /// Common trait for IdbOpenDbRequests
+///
+/// Setting a callback replaces the previous one, so a request can be set up again, e.g. before
+/// a retry, without holding on to the old closure. Callbacks can be stateful [FnMut]s; types
+/// implementing this trait outside the crate need to relax their `Fn` bounds accordingly. Only
+/// the two setters are required, everything else is implemented in terms of them.
 pub trait IdbOpenDbRequestLike {
-    /// Set the callback for the `upgradeneeded` event
+    /// Set the callback for the `upgradeneeded` event. `None` removes it.
     fn set_on_upgrade_needed<F>(&mut self, callback: Option<F>)
     where
-        F: Fn(&IdbVersionChangeEvent) -> Result<(), JsValue> + 'static;
+        F: FnMut(&IdbVersionChangeEvent) -> Result<(), JsValue> + 'static;
 
-    /// Set the callback for the `blocked` event
+    /// Set the callback for the `blocked` event. `None` removes it.
     fn set_on_blocked<F>(&mut self, callback: Option<F>)
     where
-        F: Fn(&IdbVersionChangeEvent) -> Result<(), JsValue> + 'static;
+        F: FnMut(&IdbVersionChangeEvent) -> Result<(), JsValue> + 'static;
+
+    /// Like [set_on_upgrade_needed][IdbOpenDbRequestLike::set_on_upgrade_needed], but the
+    /// callback can fail with any error that converts into a [JsValue], such as a [DomException]
+    fn set_on_upgrade_needed_with_err<F, E>(&mut self, callback: Option<F>)
+    where
+        F: FnMut(&IdbVersionChangeEvent) -> Result<(), E> + 'static,
+        E: Into<JsValue> + 'static,
+    {
+        self.set_on_upgrade_needed(callback.map(map_err));
+    }
+
+    /// Like [set_on_blocked][IdbOpenDbRequestLike::set_on_blocked], but the callback can fail
+    /// with any error that converts into a [JsValue], such as a [DomException]
+    fn set_on_blocked_with_err<F, E>(&mut self, callback: Option<F>)
+    where
+        F: FnMut(&IdbVersionChangeEvent) -> Result<(), E> + 'static,
+        E: Into<JsValue> + 'static,
+    {
+        self.set_on_blocked(callback.map(map_err));
+    }
+
+    /// Remove the callback for the `upgradeneeded` event, if any
+    fn clear_on_upgrade_needed(&mut self) {
+        self.set_on_upgrade_needed(None::<NoVersionChangeCallback>);
+    }
+
+    /// Remove the callback for the `blocked` event, if any
+    fn clear_on_blocked(&mut self) {
+        self.set_on_blocked(None::<NoVersionChangeCallback>);
+    }
+
+    /// Set the callback for the `upgradeneeded` event, builder-style
+    fn with_on_upgrade_needed<F>(mut self, callback: F) -> Self
+    where
+        Self: Sized,
+        F: FnMut(&IdbVersionChangeEvent) -> Result<(), JsValue> + 'static,
+    {
+        self.set_on_upgrade_needed(Some(callback));
+        self
+    }
+
+    /// Set the callback for the `blocked` event, builder-style
+    fn with_on_blocked<F>(mut self, callback: F) -> Self
+    where
+        Self: Sized,
+        F: FnMut(&IdbVersionChangeEvent) -> Result<(), JsValue> + 'static,
+    {
+        self.set_on_blocked(Some(callback));
+        self
+    }
+}
+
+/// Convert a callback's error into a [JsValue]
+fn map_err<F, E>(mut callback: F) -> impl FnMut(&IdbVersionChangeEvent) -> Result<(), JsValue>
+where
+    F: FnMut(&IdbVersionChangeEvent) -> Result<(), E> + 'static,
+    E: Into<JsValue> + 'static,
+{
+    move |evt| callback(evt).map_err(Into::into)
 }
 
 macro_rules! impl_request_like {
@@ -0,0 +1,97 @@
+use super::{CountFuture, IdbRequestRef, JsCastRequestFuture, OptionalJsValueFuture};
+
+macro_rules! impl_typed_request {
+    ($for: ty, $fut: ty) => {
+        impl $for {
+            /// Wrap a raw web_sys request. No event listeners get set until the request is turned
+            /// into a future.
+            #[inline]
+            pub fn from_raw(req: web_sys::IdbRequest) -> Self {
+                Self(IdbRequestRef::new(req))
+            }
+
+            /// Turn the request into a future. This is when event listeners get set.
+            #[inline]
+            pub fn into_future(self) -> $fut {
+                <$fut>::from_ref(self.0)
+            }
+
+            /// The error the request failed with, if it has finished and failed
+            #[inline]
+            pub fn error(&self) -> Option<web_sys::DomException> {
+                self.0.error()
+            }
+
+            /// The underlying web_sys request
+            #[inline]
+            pub fn as_web_sys(&self) -> &web_sys::IdbRequest {
+                self.0.inner()
+            }
+
+            /// Unwrap the underlying web_sys request
+            #[inline]
+            pub fn into_web_sys(self) -> web_sys::IdbRequest {
+                self.0.inner().clone()
+            }
+        }
+
+        impl From<web_sys::IdbRequest> for $for {
+            #[inline]
+            fn from(req: web_sys::IdbRequest) -> Self {
+                Self::from_raw(req)
+            }
+        }
+    };
+}
+
+/// A request that resolves to a record count, e.g. from
+/// [IDBObjectStore.count](https://developer.mozilla.org/en-US/docs/Web/API/IDBObjectStore/count)
+#[derive(Debug)]
+pub struct CountRequest(IdbRequestRef);
+
+impl_typed_request!(CountRequest, CountFuture);
+
+/// A request that resolves to an optional key, e.g. from
+/// [IDBObjectStore.getKey](https://developer.mozilla.org/en-US/docs/Web/API/IDBObjectStore/getKey)
+/// or [IDBObjectStore.add](https://developer.mozilla.org/en-US/docs/Web/API/IDBObjectStore/add)
+#[derive(Debug)]
+pub struct KeyRequest(IdbRequestRef);
+
+impl_typed_request!(KeyRequest, OptionalJsValueFuture);
+
+/// A request that resolves to an array of keys, e.g. from
+/// [IDBObjectStore.getAllKeys](https://developer.mozilla.org/en-US/docs/Web/API/IDBObjectStore/getAllKeys)
+#[derive(Debug)]
+pub struct KeysRequest(IdbRequestRef);
+
+impl_typed_request!(KeysRequest, JsCastRequestFuture<js_sys::Array>);
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::open_any_db;
+
+    test_mod_init!();
+
+    test_case!(async typed_requests => {
+        let (db, store_name) = open_any_db().await;
+        let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite)
+            .expect("tx");
+        let store = tx.object_store(&store_name).expect("store");
+        store.put_key_val_owned("a", &JsValue::from(1u8)).expect("put a");
+        store.put_key_val_owned("b", &JsValue::from(2u8)).expect("put b");
+
+        let raw = store.as_web_sys();
+        let count: CountRequest = raw.count().expect("count").into();
+        let key: KeyRequest = raw.get_key(&"b".into()).expect("get_key").into();
+        let keys: KeysRequest = raw.get_all_keys().expect("get_all_keys").into();
+
+        let count: u32 = count.into_future().await.expect("count await");
+        let key: Option<JsValue> = key.into_future().await.expect("key await");
+        let keys: js_sys::Array = keys.into_future().await.expect("keys await");
+
+        assert_eq!(count, 2, "count");
+        assert_eq!(key, Some(JsValue::from("b")), "key");
+        assert_eq!(keys.length(), 2, "keys");
+    });
+}
@@ -7,6 +7,7 @@ pub struct VoidOpenDbRequest(IdbOpenDbRequestRef);
 impl_void_request!(
     VoidOpenDbRequest,
     web_sys::IdbOpenDbRequest,
-    IdbOpenDbRequestRef
+    IdbOpenDbRequestRef,
+    super::OpenDbError
 );
 impl_idb_open_request_like!(VoidOpenDbRequest);
@@ -0,0 +1,262 @@
+//! Retrying transactions that fail for transient reasons
+//!
+//! A transaction can fail without anything being wrong with it: the browser aborts it, e.g.
+//! because of a conflicting connection or memory pressure, storage runs out until something gets
+//! evicted, or the backing store hits an `UnknownError` it recovers from by itself. [retrying]
+//! runs a closure that builds and awaits a transaction again when it fails with such an error,
+//! waiting longer before every attempt.
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::retry::{retrying, RetryPolicy};
+//! use wasm_bindgen::prelude::*;
+//! use web_sys::DomException;
+//!
+//! async fn example(db: &IdbDatabase) -> Result<(), DomException> {
+//!     let policy = RetryPolicy::default().max_attempts(3);
+//!     retrying(&policy, || async {
+//!         let tx = db.transaction_on_one_with_mode("my_store", IdbTransactionMode::Readwrite)?;
+//!         tx.object_store("my_store")?
+//!             .put_key_val_owned("key", &JsValue::from(1))?;
+//!         tx.await.into_result()
+//!     })
+//!     .await
+//! }
+//! ```
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use web_sys::DomException;
+
+type OnRetry<'a> = Box<dyn Fn(u32, &DomException) + 'a>;
+
+/// How [retrying] classifies errors and spaces its attempts out. The delay before the `n`th retry
+/// is `initial_delay * multiplier^(n - 1)`, capped at `max_delay`, with up to `jitter` of it
+/// taken off at random so that tabs retrying the same conflict don't keep colliding.
+pub struct RetryPolicy<'a> {
+    max_attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+    multiplier: f64,
+    jitter: f64,
+    is_transient: fn(&DomException) -> bool,
+    on_retry: Option<OnRetry<'a>>,
+}
+
+impl<'a> RetryPolicy<'a> {
+    /// Make at most this many attempts, including the first one. Defaults to 5.
+    #[inline]
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// The delay before the first retry. Defaults to 10ms.
+    #[inline]
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// The longest delay between two attempts. Defaults to 1s.
+    #[inline]
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// What every delay gets multiplied by for the next one. Defaults to 2.
+    #[inline]
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// The fraction of every delay, between 0 and 1, that may be taken off at random. Defaults
+    /// to 0.5.
+    #[inline]
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Classify errors with the given function instead of [is_transient]
+    #[inline]
+    pub fn retry_if(mut self, is_transient: fn(&DomException) -> bool) -> Self {
+        self.is_transient = is_transient;
+        self
+    }
+
+    /// Call the given function with the number of the failed attempt, starting at 1, and its
+    /// error before every retry, e.g. to evict cached data after a `QuotaExceededError`
+    pub fn on_retry<F: Fn(u32, &DomException) + 'a>(mut self, on_retry: F) -> Self {
+        self.on_retry = Some(Box::new(on_retry));
+        self
+    }
+
+    /// Whether the policy retries after the given attempt, starting at 1, failed with the error
+    pub fn should_retry(&self, attempt: u32, error: &DomException) -> bool {
+        attempt < self.max_attempts && (self.is_transient)(error)
+    }
+
+    /// The delay before the retry that follows the given attempt, without jitter
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exp = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let millis = self.initial_delay.as_millis() as f64 * self.multiplier.powi(exp);
+        let max = self.max_delay.as_millis() as f64;
+        Duration::from_millis(millis.min(max) as u64)
+    }
+
+    fn jittered_delay(&self, attempt: u32) -> Duration {
+        let delay = self.delay(attempt).as_millis() as f64;
+        let jittered = delay * (1.0 - self.jitter * js_sys::Math::random());
+        Duration::from_millis(jittered.max(0.0) as u64)
+    }
+}
+
+impl Default for RetryPolicy<'_> {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: 0.5,
+            is_transient,
+            on_retry: None,
+        }
+    }
+}
+
+impl fmt::Debug for RetryPolicy<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_delay", &self.initial_delay)
+            .field("max_delay", &self.max_delay)
+            .field("multiplier", &self.multiplier)
+            .field("jitter", &self.jitter)
+            .field("on_retry", &self.on_retry.is_some())
+            .finish()
+    }
+}
+
+/// Whether the error is worth retrying the transaction for: an `AbortError`, a
+/// `QuotaExceededError`, which can go away once the browser or the app evicts something, or an
+/// `UnknownError`
+pub fn is_transient(error: &DomException) -> bool {
+    matches!(
+        error.name().as_str(),
+        "AbortError" | "QuotaExceededError" | "UnknownError"
+    )
+}
+
+/// Run the closure, and run it again after a delay whenever the future it returns fails with an
+/// error the policy considers transient, until it succeeds or runs out of attempts. The closure
+/// should create the transaction it awaits, as a failed transaction can't be reused. Resolves to
+/// the last attempt's result.
+pub async fn retrying<T, F, Fut>(policy: &RetryPolicy<'_>, mut f: F) -> Result<T, DomException>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, DomException>>,
+{
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Err(e) if policy.should_retry(attempt, &e) => {
+                if let Some(on_retry) = policy.on_retry.as_ref() {
+                    on_retry(attempt, &e);
+                }
+                crate::maintenance::sleep(policy.jittered_delay(attempt)).await?;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::cell::Cell;
+
+    use wasm_bindgen::prelude::*;
+    use web_sys::IdbTransactionMode;
+
+    use crate::idb_query_source::IdbQuerySource;
+    use crate::internal_utils::dom_exception;
+    use crate::test_utils::open_any_db;
+
+    use super::*;
+
+    test_mod_init!();
+
+    test_case!(async retrying => {
+        let attempts = Cell::new(0u32);
+        let retries = Cell::new(0u32);
+        let policy = RetryPolicy::default()
+            .initial_delay(Duration::from_millis(1))
+            .on_retry(|_, _| retries.set(retries.get() + 1));
+        let out = super::retrying(&policy, || async {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() < 3 {
+                Err(dom_exception("aborted", "AbortError"))
+            } else {
+                Ok(7)
+            }
+        })
+        .await;
+        assert_eq!(out.expect("eventually succeeds"), 7, "output");
+        assert_eq!((attempts.get(), retries.get()), (3, 2), "attempts");
+
+        attempts.set(0);
+        let out: Result<(), _> = super::retrying(&policy, || async {
+            attempts.set(attempts.get() + 1);
+            Err(dom_exception("duplicate key", "ConstraintError"))
+        })
+        .await;
+        assert_eq!(out.expect_err("permanent").name(), "ConstraintError", "error");
+        assert_eq!(attempts.get(), 1, "not retried");
+
+        attempts.set(0);
+        let policy = RetryPolicy::default().max_attempts(2).initial_delay(Duration::from_millis(1));
+        let out: Result<(), _> = super::retrying(&policy, || async {
+            attempts.set(attempts.get() + 1);
+            Err(dom_exception("full", "QuotaExceededError"))
+        })
+        .await;
+        assert!(out.is_err(), "gives up");
+        assert_eq!(attempts.get(), 2, "capped");
+    });
+
+    test_case!(async aborted_transaction => {
+        let (db, store) = open_any_db().await;
+        let attempts = Cell::new(0u32);
+        let policy = RetryPolicy::default().initial_delay(Duration::from_millis(1));
+        let out = super::retrying(&policy, || async {
+            attempts.set(attempts.get() + 1);
+            let tx = db.transaction_on_one_with_mode(&store, IdbTransactionMode::Readwrite)?;
+            tx.object_store(&store)?.put_key_val_owned("k", &JsValue::from(attempts.get()))?;
+            if attempts.get() == 1 {
+                tx.as_web_sys().abort()?;
+            }
+            tx.await.into_result()
+        })
+        .await;
+        out.expect("retried after the abort");
+        assert_eq!(attempts.get(), 2, "attempts");
+
+        let tx = db.transaction_on_one(&store).unwrap();
+        let value = tx.object_store(&store).unwrap().get_owned("k").unwrap().await.unwrap();
+        assert_eq!(value, Some(JsValue::from(2u32)), "second attempt committed");
+    });
+
+    test_case!(delay => {
+        let policy = RetryPolicy::default()
+            .initial_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(300));
+        let delays = (1..=4).map(|n| policy.delay(n).as_millis()).collect::<Vec<_>>();
+        assert_eq!(delays, vec![100, 200, 300, 300]);
+    });
+}
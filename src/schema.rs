@@ -0,0 +1,414 @@
+//! Declarative database schemas
+//!
+//! Features required: `schema`
+//!
+//! A [DbSchema] describes the object stores and indices a database should have. Applying it from
+//! an `upgradeneeded` handler creates whatever is missing, so the same schema can be used to
+//! create a fresh database and to upgrade an existing one.
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::schema::{DbSchema, IndexSchema, StoreSchema};
+//! use web_sys::DomException;
+//!
+//! # #[allow(dead_code)]
+//! fn open() -> Result<OpenDbRequest, DomException> {
+//!     let schema = DbSchema::new().store(
+//!         StoreSchema::new("users")
+//!             .key_path(Some(IdbKeyPath::str("id")))
+//!             .auto_increment(true)
+//!             .index(IndexSchema::new("email", IdbKeyPath::str("email")).unique(true)),
+//!     );
+//!
+//!     let mut req = IdbDatabase::open_u32("my_db", 1)?;
+//!     req.set_on_upgrade_needed(Some(move |evt: &IdbVersionChangeEvent| {
+//!         schema.apply(evt)?;
+//!         Ok(())
+//!     }));
+//!     Ok(req)
+//! }
+//! ```
+
+use web_sys::{DomException, IdbIndexParameters};
+
+pub use check::*;
+pub use dexie::*;
+pub use manager::*;
+pub use recovery::*;
+pub use retention::*;
+#[cfg(feature = "cursors")]
+pub use retention_job::*;
+
+use crate::idb_database::IdbVersionChangeEvent;
+use crate::idb_key_path::IdbKeyPath;
+use crate::idb_object_store::{IdbObjectStore, IdbObjectStoreParameters};
+
+mod check;
+mod dexie;
+mod manager;
+mod recovery;
+mod retention;
+#[cfg(feature = "cursors")]
+mod retention_job;
+
+/// Schema of a whole database
+///
+/// Features required: `schema`
+#[derive(Debug, Clone, Default)]
+pub struct DbSchema {
+    /// The database's object stores
+    pub stores: Vec<StoreSchema>,
+}
+
+/// Schema of an object store
+///
+/// Features required: `schema`
+#[derive(Debug, Clone)]
+pub struct StoreSchema {
+    /// The object store name
+    pub name: String,
+    /// The store's key path; `None` for out-of-line keys
+    pub key_path: Option<IdbKeyPath>,
+    /// Whether the store has a key generator
+    pub auto_increment: bool,
+    /// The store's indices
+    pub indices: Vec<IndexSchema>,
+    /// Limits on what the store holds, enforced by a [RetentionJob]
+    pub retention: Retention,
+}
+
+/// Schema of an index
+///
+/// Features required: `schema`
+#[derive(Debug, Clone)]
+pub struct IndexSchema {
+    /// The index name
+    pub name: String,
+    /// The key path the index is populated from
+    pub key_path: IdbKeyPath,
+    /// Whether the index disallows duplicate keys
+    pub unique: bool,
+    /// Whether array key path values produce one index record per item
+    pub multi_entry: bool,
+}
+
+impl DbSchema {
+    /// Create an empty schema
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an object store to the schema
+    pub fn store(mut self, store: StoreSchema) -> Self {
+        self.stores.push(store);
+        self
+    }
+
+    /// Look up a store by its name
+    pub fn get_store(&self, name: &str) -> Option<&StoreSchema> {
+        self.stores.iter().find(move |s| s.name == name)
+    }
+
+    /// Create the object stores and indices that don't exist yet. Must be called from an
+    /// `upgradeneeded` handler. Existing stores and indices are left untouched, even if their
+    /// parameters differ from the schema.
+    pub fn apply(&self, evt: &IdbVersionChangeEvent) -> Result<(), DomException> {
+        let db = evt.db();
+        let existing: Vec<String> = db.object_store_names().collect();
+        let upgrade_tx = evt.transaction();
+
+        for store_schema in self.stores.iter() {
+            if existing.contains(&store_schema.name) {
+                if let Some(tx) = upgrade_tx.as_ref() {
+                    let store = tx.object_store(&store_schema.name)?;
+                    store_schema.create_missing_indices(&store)?;
+                }
+            } else {
+                let store = db.create_object_store_with_params(
+                    &store_schema.name,
+                    &store_schema.to_params(),
+                )?;
+                store_schema.create_missing_indices(&store)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl StoreSchema {
+    /// Describe an object store with out-of-line keys, no key generator and no indices
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            key_path: None,
+            auto_increment: false,
+            indices: Vec::new(),
+            retention: Retention::default(),
+        }
+    }
+
+    /// Set the key path
+    pub fn key_path(mut self, key_path: Option<IdbKeyPath>) -> Self {
+        self.key_path = key_path;
+        self
+    }
+
+    /// Set the auto increment flag
+    pub fn auto_increment(mut self, auto_increment: bool) -> Self {
+        self.auto_increment = auto_increment;
+        self
+    }
+
+    /// Add an index
+    pub fn index(mut self, index: IndexSchema) -> Self {
+        self.indices.push(index);
+        self
+    }
+
+    /// Convert the schema into object store creation parameters
+    pub fn to_params(&self) -> IdbObjectStoreParameters {
+        let mut params = IdbObjectStoreParameters::new();
+        params
+            .auto_increment(self.auto_increment)
+            .key_path(self.key_path.as_ref());
+        params
+    }
+
+    fn create_missing_indices(&self, store: &IdbObjectStore) -> Result<(), DomException> {
+        let existing: Vec<String> = store.index_names().collect();
+        for index in self.indices.iter() {
+            if !existing.contains(&index.name) {
+                store.create_index_with_params(&index.name, &index.key_path, &index.to_params())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl IndexSchema {
+    /// Describe a non-unique, non-multi-entry index
+    pub fn new(name: &str, key_path: IdbKeyPath) -> Self {
+        Self {
+            name: name.into(),
+            key_path,
+            unique: false,
+            multi_entry: false,
+        }
+    }
+
+    /// Set the unique flag
+    pub fn unique(mut self, unique: bool) -> Self {
+        self.unique = unique;
+        self
+    }
+
+    /// Set the multi entry flag
+    pub fn multi_entry(mut self, multi_entry: bool) -> Self {
+        self.multi_entry = multi_entry;
+        self
+    }
+
+    /// Convert the schema into index creation parameters
+    pub fn to_params(&self) -> IdbIndexParameters {
+        let mut params = IdbIndexParameters::new();
+        params.unique(self.unique).multi_entry(self.multi_entry);
+        params
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::{open_db_with, open_db_with_stores, unique_name};
+
+    use super::*;
+
+    test_mod_init!();
+
+    test_case!(async apply_creates_missing => {
+        let db_name = unique_name();
+        let schema_v1 = DbSchema::new().store(StoreSchema::new("s1"));
+        let schema_v2 = DbSchema::new()
+            .store(StoreSchema::new("s1").index(IndexSchema::new("foo", IdbKeyPath::str("foo"))))
+            .store(
+                StoreSchema::new("s2")
+                    .key_path(Some(IdbKeyPath::str("id")))
+                    .auto_increment(true)
+                    .index(IndexSchema::new("bar", IdbKeyPath::str("bar")).unique(true)),
+            );
+
+        let mut req = IdbDatabase::open_u32(&db_name, 1).expect("open 1");
+        req.set_on_upgrade_needed(Some(move |evt: &IdbVersionChangeEvent| {
+            schema_v1.apply(evt)?;
+            Ok(())
+        }));
+        req.into_future().await.expect("db 1").close();
+
+        let mut req = IdbDatabase::open_u32(&db_name, 2).expect("open 2");
+        req.set_on_upgrade_needed(Some(move |evt: &IdbVersionChangeEvent| {
+            schema_v2.apply(evt)?;
+            Ok(())
+        }));
+        let db = req.into_future().await.expect("db 2");
+
+        let tx = db.transaction_on_multi(&["s1", "s2"]).expect("tx");
+        let s1 = tx.object_store("s1").expect("s1");
+        let s2 = tx.object_store("s2").expect("s2");
+
+        assert_eq!(s1.index_names().collect::<Vec<_>>(), vec!["foo"], "s1 indices");
+        assert_eq!(s2.key_path(), Some(IdbKeyPath::str("id")), "s2 key path");
+        assert!(s2.auto_increment(), "s2 auto increment");
+        assert!(s2.index("bar").expect("bar").unique(), "s2 unique");
+    });
+
+    test_case!(async check => {
+        let db_name = unique_name();
+        let schema = DbSchema::new().store(
+            StoreSchema::new("s1").index(IndexSchema::new("foo", IdbKeyPath::str("foo"))),
+        );
+        let schema_cb = schema.clone();
+        let mut req = IdbDatabase::open_u32(&db_name, 1).expect("open");
+        req.set_on_upgrade_needed(Some(move |evt: &IdbVersionChangeEvent| {
+            schema_cb.apply(evt)?;
+            Ok(())
+        }));
+        let db = req.into_future().await.expect("db");
+
+        let tx = db.transaction_on_one_with_mode("s1", IdbTransactionMode::Readwrite).unwrap();
+        let store = tx.object_store("s1").unwrap();
+        store.put_key_val_owned(1u8, &js_sys::JSON::parse(r#"{"foo":1}"#).unwrap()).unwrap();
+        store.put_key_val_owned(2u8, &js_sys::JSON::parse(r#"{"foo":[1]}"#).unwrap()).unwrap();
+        store.put_key_val_owned(3u8, &js_sys::JSON::parse("{}").unwrap()).unwrap();
+        tx.await.into_result().unwrap();
+
+        assert_eq!(db.check(&schema).await.expect("check"), CheckReport::default(), "consistent");
+
+        let drifted = DbSchema::new()
+            .store(
+                StoreSchema::new("s1")
+                    .auto_increment(true)
+                    .index(IndexSchema::new("foo", IdbKeyPath::str("foo")).unique(true))
+                    .index(IndexSchema::new("bar", IdbKeyPath::str("bar"))),
+            )
+            .store(StoreSchema::new("s2"));
+        let issues = db.check(&drifted).await.expect("drifted").issues;
+        let store = || String::from("s1");
+        assert_eq!(issues, vec![
+            Inconsistency::StoreMismatch { store: store() },
+            Inconsistency::IndexMismatch { store: store(), index: "foo".into() },
+            Inconsistency::MissingIndex { store: store(), index: "bar".into() },
+            Inconsistency::MissingStore { store: "s2".into() },
+        ]);
+
+        let options = CheckOptions::new()
+            .validate("s1", |v| match js_sys::Reflect::get(v, &"foo".into()) {
+                Ok(foo) if foo.as_f64().is_some() => Ok(()),
+                _ => Err("foo must be a number".into()),
+            })
+            .repair(true);
+        let report = db.check_with(&schema, &options).await.expect("validate");
+        assert_eq!(report.repaired, 2, "repaired");
+        assert_eq!(report.issues.len(), 2, "invalid records");
+        assert!(db.check_with(&schema, &options).await.expect("again").is_ok(), "deleted");
+    });
+
+    test_case!(async recovery => {
+        let stale = open_db_with_stores(&["stale"]).await;
+        let name = stale.name();
+        stale.close();
+
+        let unknown = DomException::new_with_message_and_name("Internal error", "UnknownError").unwrap();
+        let version = DomException::new_with_message_and_name("Version", "VersionError").unwrap();
+        assert!(RecoveryPolicy::is_corruption(&unknown), "unknown error");
+        assert!(!RecoveryPolicy::is_corruption(&version), "version error");
+
+        let policy = RecoveryPolicy::new(DbSchema::new().store(StoreSchema::new("settings")))
+            .reseed(|evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+                let tx = evt.transaction().expect("upgrade tx");
+                tx.object_store("settings")?.put_key_val_owned("theme", &JsValue::from("dark"))?;
+                Ok(())
+            });
+        let db = policy.recreate(&name, Some(2)).await.expect("recreate");
+
+        assert_eq!(db.version(), 2, "version");
+        assert_eq!(db.object_store_names().collect::<Vec<_>>(), vec!["settings"], "stores");
+        let tx = db.transaction_on_one("settings").unwrap();
+        let theme = tx.object_store("settings").unwrap().get_owned("theme").unwrap().await.unwrap();
+        assert_eq!(theme, Some(JsValue::from("dark")), "reseeded");
+    });
+
+    #[cfg(feature = "cursors")]
+    test_case!(async retention => {
+        use crate::maintenance::MaintenanceScheduler;
+        use std::time::Duration;
+
+        let schema = DbSchema::new()
+            .store(StoreSchema::new("meta"))
+            .store(StoreSchema::new("logs").max_records(3))
+            .store(
+                StoreSchema::new("responses")
+                    .retain(Duration::from_secs(3600), "at")
+                    .index(IndexSchema::new("at", IdbKeyPath::str("at"))),
+            )
+            .store(StoreSchema::new("scanned").retain(Duration::from_secs(3600), "at"));
+        let schema_cb = schema.clone();
+        let db = open_db_with(move |evt: &IdbVersionChangeEvent| {
+            schema_cb.apply(evt)?;
+            Ok(())
+        }).await;
+
+        let now = js_sys::Date::now();
+        let stale = format!(r#"{{"at":{}}}"#, now - 7_200_000.0);
+        let fresh = format!(r#"{{"at":{}}}"#, now);
+        let tx = db.transaction_on_multi_with_mode(&["logs", "responses", "scanned"], IdbTransactionMode::Readwrite).unwrap();
+        for i in 0..5u32 {
+            tx.object_store("logs").unwrap().put_key_val_owned(i, &JsValue::from(i)).unwrap();
+        }
+        for name in &["responses", "scanned"] {
+            let store = tx.object_store(name).unwrap();
+            for (i, json) in [&stale, &stale, &fresh].iter().enumerate() {
+                store.put_key_val_owned(i as u32, &js_sys::JSON::parse(json).unwrap()).unwrap();
+            }
+        }
+        tx.await.into_result().unwrap();
+
+        MaintenanceScheduler::new("meta")
+            .job(schema.retention_job())
+            .chunk_size(1)
+            .idle_timeout(Duration::from_millis(10))
+            .run_pass(&db)
+            .await
+            .expect("pass");
+
+        let tx = db.transaction_on_multi(&["logs", "responses", "scanned"]).unwrap();
+        let logs = tx.object_store("logs").unwrap().get_all_keys().unwrap().await.unwrap();
+        assert_eq!(logs.iter().collect::<Vec<_>>(), vec![JsValue::from(2), JsValue::from(3), JsValue::from(4)], "logs");
+        for name in &["responses", "scanned"] {
+            let keys = tx.object_store(name).unwrap().get_all_keys().unwrap().await.unwrap();
+            assert_eq!(keys.iter().collect::<Vec<_>>(), vec![JsValue::from(2)], "{}", name);
+        }
+    });
+
+    test_case!(async db_manager => {
+        let prefix = format!("{}-", unique_name());
+        let schema = DbSchema::new().store(StoreSchema::new("notes"));
+        let manager = DbManager::new(&prefix, 1, schema);
+
+        let db1 = manager.open_for("alice").await.expect("alice");
+        let db2 = manager.open_for("alice").await.expect("alice again");
+        manager.open_for("bob").await.expect("bob");
+
+        assert!(std::rc::Rc::ptr_eq(&db1, &db2), "shared connection");
+        assert_eq!(db1.name(), format!("{}alice", prefix), "name");
+        assert_eq!(db1.object_store_names().collect::<Vec<_>>(), vec!["notes"], "schema");
+        assert_eq!(manager.open_tenants(), vec!["alice", "bob"], "open");
+
+        manager.delete_tenant("alice").await.expect("delete alice");
+        assert_eq!(manager.tenants().await.expect("tenants"), vec!["bob"], "tenants");
+
+        manager.close_all();
+        assert!(manager.open_tenants().is_empty(), "closed");
+    });
+}
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::idb_database::{factory, IdbDatabase};
+use crate::idb_index::IdbIndex;
+use crate::idb_key_path::IdbKeyPath;
+use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::keys_eq;
+
+use super::{DbSchema, IndexSchema, StoreSchema};
+
+type Validator = Box<dyn Fn(&JsValue) -> Result<(), String>>;
+
+/// Options for [IdbDatabase::check_with]
+///
+/// Features required: `schema`
+#[derive(Default)]
+pub struct CheckOptions {
+    validators: HashMap<String, Validator>,
+    repair: bool,
+}
+
+impl CheckOptions {
+    /// Check the schema and index entries only
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Validate every record in the given store, reporting the ones for which the validator
+    /// returns an error
+    pub fn validate<F>(mut self, store: &str, validator: F) -> Self
+    where
+        F: Fn(&JsValue) -> Result<(), String> + 'static,
+    {
+        self.validators.insert(store.into(), Box::new(validator));
+        self
+    }
+
+    /// Report the records in the given store that fail to deserialise as `T`
+    ///
+    /// Features required: `schema`, `serde`
+    #[cfg(feature = "serde")]
+    pub fn deserialize<T: serde::de::DeserializeOwned>(self, store: &str) -> Self {
+        self.validate(store, |value| {
+            crate::values::from_value::<T>(value.clone())
+                .map(drop)
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    /// Repair what can be repaired outside of an upgrade: records missing from an index get
+    /// written back so that the browser re-indexes them, and records that fail validation get
+    /// deleted. Defaults to `false`.
+    pub fn repair(mut self, repair: bool) -> Self {
+        self.repair = repair;
+        self
+    }
+}
+
+impl fmt::Debug for CheckOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CheckOptions")
+            .field("validators", &self.validators.keys().collect::<Vec<_>>())
+            .field("repair", &self.repair)
+            .finish()
+    }
+}
+
+/// A problem found by [IdbDatabase::check]
+///
+/// Features required: `schema`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inconsistency {
+    /// The schema's store doesn't exist
+    MissingStore {
+        /// Store name
+        store: String,
+    },
+    /// The store's key path or key generator differs from the schema
+    StoreMismatch {
+        /// Store name
+        store: String,
+    },
+    /// The schema's index doesn't exist
+    MissingIndex {
+        /// Store name
+        store: String,
+        /// Index name
+        index: String,
+    },
+    /// The index's key path, unique or multi entry flag differs from the schema
+    IndexMismatch {
+        /// Store name
+        store: String,
+        /// Index name
+        index: String,
+    },
+    /// A record's value at the index's key path has no entry in the index
+    MissingIndexEntry {
+        /// Store name
+        store: String,
+        /// Index name
+        index: String,
+        /// Primary key of the record
+        primary_key: JsValue,
+    },
+    /// The index has a different number of entries than the records' key path values call for
+    IndexCountMismatch {
+        /// Store name
+        store: String,
+        /// Index name
+        index: String,
+        /// The number of entries the records call for
+        expected: u32,
+        /// The number of entries in the index
+        actual: u32,
+    },
+    /// A record failed its store's [validator][CheckOptions::validate]
+    InvalidRecord {
+        /// Store name
+        store: String,
+        /// Primary key of the record
+        primary_key: JsValue,
+        /// The validator's error
+        message: String,
+    },
+}
+
+/// Result of [IdbDatabase::check]
+///
+/// Features required: `schema`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CheckReport {
+    /// Every problem found, including the repaired ones
+    pub issues: Vec<Inconsistency>,
+    /// The number of problems that got [repaired][CheckOptions::repair]
+    pub repaired: usize,
+}
+
+impl CheckReport {
+    /// Whether no problems were found
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl IdbDatabase {
+    /// Check the database against the schema: that its stores and indices exist with the
+    /// schema's parameters and that every record's key path values are in the indices. Reads
+    /// every record, so it's best run after a crash or a bug, not on every startup.
+    ///
+    /// Features required: `schema`
+    pub async fn check(&self, schema: &DbSchema) -> Result<CheckReport, DomException> {
+        self.check_with(schema, &CheckOptions::new()).await
+    }
+
+    /// [Check][IdbDatabase::check] the database, additionally validating records and optionally
+    /// repairing problems
+    ///
+    /// Features required: `schema`
+    pub async fn check_with(
+        &self,
+        schema: &DbSchema,
+        options: &CheckOptions,
+    ) -> Result<CheckReport, DomException> {
+        let mut report = CheckReport::default();
+        let existing: Vec<String> = self.object_store_names().collect();
+        let mode = if options.repair {
+            IdbTransactionMode::Readwrite
+        } else {
+            IdbTransactionMode::Readonly
+        };
+
+        for store_schema in schema.stores.iter() {
+            if !existing.contains(&store_schema.name) {
+                report.issues.push(Inconsistency::MissingStore {
+                    store: store_schema.name.clone(),
+                });
+                continue;
+            }
+            let tx = self.transaction_on_one_with_mode(&store_schema.name, mode)?;
+            let store = tx.object_store(&store_schema.name)?;
+            check_store(&store, store_schema, options, &mut report).await?;
+            tx.await.into_result()?;
+        }
+
+        Ok(report)
+    }
+}
+
+async fn check_store(
+    store: &IdbObjectStore<'_>,
+    schema: &StoreSchema,
+    options: &CheckOptions,
+    report: &mut CheckReport,
+) -> Result<(), DomException> {
+    let name = &schema.name;
+    if !key_paths_eq(store.key_path().as_ref(), schema.key_path.as_ref())
+        || store.auto_increment() != schema.auto_increment
+    {
+        report.issues.push(Inconsistency::StoreMismatch {
+            store: name.clone(),
+        });
+    }
+
+    let keys = store.get_all_keys()?.await?;
+    let values = store.get_all()?.await?;
+    let mut invalid = Vec::new();
+    if let Some(validator) = options.validators.get(name) {
+        for (key, value) in keys.iter().zip(values.iter()) {
+            if let Err(message) = validator(&value) {
+                report.issues.push(Inconsistency::InvalidRecord {
+                    store: name.clone(),
+                    primary_key: key.clone(),
+                    message,
+                });
+                invalid.push(key);
+            }
+        }
+    }
+
+    let existing: Vec<String> = store.index_names().collect();
+    let mut rewrite = Vec::new();
+    for index_schema in schema.indices.iter() {
+        if !existing.contains(&index_schema.name) {
+            report.issues.push(Inconsistency::MissingIndex {
+                store: name.clone(),
+                index: index_schema.name.clone(),
+            });
+            continue;
+        }
+        let index = store.index(&index_schema.name)?;
+        if !index_matches(&index, index_schema) {
+            report.issues.push(Inconsistency::IndexMismatch {
+                store: name.clone(),
+                index: index_schema.name.clone(),
+            });
+        }
+
+        let mut expected = 0;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            let entries = index_entries(&index, &value);
+            expected += entries.len() as u32;
+            let mut missing = false;
+            for entry in entries {
+                let primary_keys = index.get_all_keys_with_key(&entry)?.await?;
+                if !primary_keys.iter().any(|k| keys_eq(&k, &key)) {
+                    missing = true;
+                }
+            }
+            if missing {
+                report.issues.push(Inconsistency::MissingIndexEntry {
+                    store: name.clone(),
+                    index: index_schema.name.clone(),
+                    primary_key: key.clone(),
+                });
+                if !rewrite.iter().any(|(k, _)| keys_eq(k, &key)) {
+                    rewrite.push((key.clone(), value.clone()));
+                }
+            }
+        }
+
+        let actual = index.count()?.await?;
+        if actual != expected {
+            report.issues.push(Inconsistency::IndexCountMismatch {
+                store: name.clone(),
+                index: index_schema.name.clone(),
+                expected,
+                actual,
+            });
+        }
+    }
+
+    // Repairs happen last so that the checks above all see the same records
+    if options.repair {
+        for key in invalid.iter() {
+            store.delete(key)?;
+            report.repaired += 1;
+        }
+        for (key, value) in rewrite {
+            if invalid.iter().any(|k| keys_eq(k, &key)) {
+                continue;
+            }
+            match store.key_path() {
+                Some(_) => store.put_val(&value)?,
+                None => store.put_key_val(&key, &value)?,
+            };
+            report.repaired += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// The index keys the browser would derive from the value
+fn index_entries(index: &IdbIndex, value: &JsValue) -> Vec<JsValue> {
+    let key = match index.key_path().and_then(|path| path.evaluate(value)) {
+        Some(key) => key,
+        None => return Vec::new(),
+    };
+    if index.multi_entry() && js_sys::Array::is_array(&key) {
+        let mut out: Vec<JsValue> = Vec::new();
+        for item in js_sys::Array::from(&key).iter() {
+            if is_valid_key(&item) && !out.iter().any(|k| keys_eq(k, &item)) {
+                out.push(item);
+            }
+        }
+        out
+    } else if is_valid_key(&key) {
+        vec![key]
+    } else {
+        Vec::new()
+    }
+}
+
+fn is_valid_key(value: &JsValue) -> bool {
+    matches!(factory().map(|f| f.cmp(value, value)), Ok(Ok(_)))
+}
+
+fn index_matches(index: &IdbIndex, schema: &IndexSchema) -> bool {
+    key_paths_eq(index.key_path().as_ref(), Some(&schema.key_path))
+        && index.unique() == schema.unique
+        && index.multi_entry() == schema.multi_entry
+}
+
+fn key_paths_eq(a: Option<&IdbKeyPath>, b: Option<&IdbKeyPath>) -> bool {
+    let stringify = |path: Option<&IdbKeyPath>| {
+        path.and_then(|p| js_sys::JSON::stringify(p.as_js_value()).ok())
+            .and_then(|s| s.as_string())
+    };
+    stringify(a) == stringify(b)
+}
@@ -0,0 +1,210 @@
+use std::fmt;
+
+use crate::idb_key_path::IdbKeyPath;
+
+use super::{DbSchema, IndexSchema, StoreSchema};
+
+/// An error encountered while parsing a [Dexie](https://dexie.org/docs/Version/Version.stores())
+/// schema string
+///
+/// Features required: `schema`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DexieSchemaError {
+    /// The store whose schema string failed to parse
+    pub store: String,
+    /// The offending comma-separated entry
+    pub entry: String,
+    /// What's wrong with the entry
+    pub reason: &'static str,
+}
+
+impl fmt::Display for DexieSchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid schema entry `{}` for store `{}`: {}",
+            self.entry, self.store, self.reason
+        )
+    }
+}
+
+impl std::error::Error for DexieSchemaError {}
+
+impl DbSchema {
+    /// Parse Dexie-style store definitions, as passed to Dexie's `db.version(n).stores({...})`,
+    /// given as `(store name, schema string)` pairs.
+    ///
+    /// Features required: `schema`
+    pub fn from_dexie<'a, I>(stores: I) -> Result<Self, DexieSchemaError>
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        let mut out = Self::new();
+        for (name, spec) in stores {
+            out = out.store(StoreSchema::from_dexie(name, spec)?);
+        }
+        Ok(out)
+    }
+}
+
+impl StoreSchema {
+    /// Parse a Dexie-style schema string, e.g. `"++id, name, &email, *tags, [first+last]"`.
+    ///
+    /// The first entry describes the primary key:
+    ///
+    /// - `++id` - auto-incremented key at the `id` key path
+    /// - `++` - auto-incremented out-of-line key
+    /// - `id` - key path without a key generator
+    /// - an empty entry - out-of-line key without a key generator
+    ///
+    /// Subsequent entries describe indices, named after their key path: `&` marks them as unique,
+    /// `*` as multi-entry and `[a+b]` describes a compound key path.
+    ///
+    /// Features required: `schema`
+    pub fn from_dexie(name: &str, spec: &str) -> Result<Self, DexieSchemaError> {
+        let err = move |entry: &str, reason: &'static str| DexieSchemaError {
+            store: name.into(),
+            entry: entry.into(),
+            reason,
+        };
+
+        let mut entries = spec.split(',').map(str::trim);
+        let primary = entries.next().unwrap_or("");
+        let mut out = Self::new(name);
+
+        if let Some(path) = primary.strip_prefix("++") {
+            out.auto_increment = true;
+            if !path.is_empty() {
+                out.key_path =
+                    Some(parse_key_path(path).ok_or_else(|| err(primary, "bad key path"))?);
+            }
+        } else if primary.starts_with('&') || primary.starts_with('*') {
+            return Err(err(
+                primary,
+                "primary keys can't be unique or multi-entry indices",
+            ));
+        } else if !primary.is_empty() {
+            out.key_path =
+                Some(parse_key_path(primary).ok_or_else(|| err(primary, "bad key path"))?);
+        }
+
+        for entry in entries.filter(|e| !e.is_empty()) {
+            let mut index_spec = entry;
+            let mut unique = false;
+            let mut multi_entry = false;
+
+            if let Some(rest) = index_spec.strip_prefix('&') {
+                unique = true;
+                index_spec = rest;
+            } else if let Some(rest) = index_spec.strip_prefix('*') {
+                multi_entry = true;
+                index_spec = rest;
+            } else if index_spec.starts_with("++") {
+                return Err(err(entry, "only the primary key can be auto-incremented"));
+            }
+
+            let key_path = parse_key_path(index_spec).ok_or_else(|| err(entry, "bad key path"))?;
+            if multi_entry && key_path.as_js_value().as_string().is_none() {
+                return Err(err(entry, "compound indices can't be multi-entry"));
+            }
+
+            let index = IndexSchema::new(index_spec, key_path)
+                .unique(unique)
+                .multi_entry(multi_entry);
+            out = out.index(index);
+        }
+
+        Ok(out)
+    }
+}
+
+/// Parse `foo`, `foo.bar` or `[foo+bar.qux]`
+fn parse_key_path(spec: &str) -> Option<IdbKeyPath> {
+    if let Some(compound) = spec.strip_prefix('[') {
+        let parts: Vec<&str> = compound
+            .strip_suffix(']')?
+            .split('+')
+            .map(str::trim)
+            .collect();
+        if parts.iter().any(|p| !is_valid_path(p)) {
+            None
+        } else {
+            Some(IdbKeyPath::str_sequence(&parts))
+        }
+    } else if is_valid_path(spec) {
+        Some(IdbKeyPath::str(spec))
+    } else {
+        None
+    }
+}
+
+fn is_valid_path(path: &str) -> bool {
+    !path.is_empty()
+        && path.split('.').all(|p| {
+            !p.is_empty() && !p.contains(|c: char| c.is_whitespace() || "[]+&*,".contains(c))
+        })
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    pub mod parse {
+        test_mod_init!();
+
+        fn key_path_str(path: &Option<IdbKeyPath>) -> Option<String> {
+            path.as_ref()?.as_js_value().as_string()
+        }
+
+        test_case!(primary_keys => {
+            let s = StoreSchema::from_dexie("s", "++id").unwrap();
+            assert!(s.auto_increment, "++id auto increment");
+            assert_eq!(key_path_str(&s.key_path), Some("id".into()), "++id key path");
+
+            let s = StoreSchema::from_dexie("s", "++").unwrap();
+            assert!(s.auto_increment && s.key_path.is_none(), "++");
+
+            let s = StoreSchema::from_dexie("s", "id").unwrap();
+            assert!(!s.auto_increment, "id auto increment");
+            assert_eq!(key_path_str(&s.key_path), Some("id".into()), "id key path");
+
+            let s = StoreSchema::from_dexie("s", ", name").unwrap();
+            assert!(!s.auto_increment && s.key_path.is_none(), "outbound");
+        });
+
+        test_case!(indices => {
+            let s = StoreSchema::from_dexie("s", "++id, name, &email, *tags, [first+last]").unwrap();
+            let summary: Vec<(String, bool, bool)> = s.indices
+                .iter()
+                .map(|i| (i.name.clone(), i.unique, i.multi_entry))
+                .collect();
+
+            assert_eq!(summary, vec![
+                ("name".into(), false, false),
+                ("email".into(), true, false),
+                ("tags".into(), false, true),
+                ("[first+last]".into(), false, false),
+            ]);
+
+            let compound: js_sys::Array = s.indices[3].key_path.as_js_value().clone().unchecked_into();
+            assert_eq!(compound.length(), 2, "compound length");
+        });
+
+        test_case!(db_schema => {
+            let schema = DbSchema::from_dexie(vec![("friends", "++id, name"), ("pets", "")]).unwrap();
+            assert_eq!(schema.stores.len(), 2, "stores");
+            assert_eq!(schema.get_store("friends").unwrap().indices.len(), 1, "friends indices");
+        });
+
+        test_case!(errors => {
+            assert!(StoreSchema::from_dexie("s", "&id").is_err(), "unique pk");
+            assert!(StoreSchema::from_dexie("s", "id, ++foo").is_err(), "auto increment index");
+            assert!(StoreSchema::from_dexie("s", "id, *[a+b]").is_err(), "multi entry compound");
+            assert!(StoreSchema::from_dexie("s", "id, [a+]").is_err(), "bad compound");
+
+            let err = StoreSchema::from_dexie("s", "id, foo bar").unwrap_err();
+            assert_eq!(err.entry, "foo bar", "entry");
+            assert_eq!(err.store, "s", "store");
+        });
+    }
+}
@@ -0,0 +1,217 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::idb_database::{IdbDatabase, IdbVersionChangeEvent};
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::{ClosureKind, ClosureToken};
+use crate::request::IdbOpenDbRequestLike;
+
+use super::DbSchema;
+
+const REGISTRY_STORE: &str = "tenants";
+
+struct OpenTenant {
+    db: Rc<IdbDatabase>,
+    closed: Rc<Cell<bool>>,
+    _on_version_change: Closure<dyn Fn()>,
+}
+
+/// Opens per-tenant databases, e.g. one per user account, created from a shared template
+/// [schema][DbSchema], and keeps track of the tenants in a registry database.
+///
+/// Tenant databases are named `{prefix}{tenant}` and the registry `{prefix}__registry`. Open
+/// connections are shared: opening the same tenant twice returns the same connection. They close
+/// themselves when another connection wants to upgrade or delete their database.
+///
+/// Features required: `schema`
+pub struct DbManager {
+    prefix: String,
+    version: u32,
+    schema: Rc<DbSchema>,
+    open: RefCell<BTreeMap<String, OpenTenant>>,
+}
+
+impl DbManager {
+    /// Create a manager whose tenant databases are opened at the given version and upgraded to
+    /// the given schema
+    pub fn new(prefix: &str, version: u32, schema: DbSchema) -> Self {
+        Self {
+            prefix: prefix.into(),
+            version,
+            schema: Rc::new(schema),
+            open: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// The name of the given tenant's database
+    #[inline]
+    pub fn db_name(&self, tenant: &str) -> String {
+        format!("{}{}", self.prefix, tenant)
+    }
+
+    /// The name of the registry database
+    #[inline]
+    pub fn registry_name(&self) -> String {
+        format!("{}__registry", self.prefix)
+    }
+
+    /// Open the tenant's database, creating and registering it if it doesn't exist yet, or
+    /// return the already open connection.
+    pub async fn open_for(&self, tenant: &str) -> Result<Rc<IdbDatabase>, DomException> {
+        if let Some(db) = self.get_open(tenant) {
+            return Ok(db);
+        }
+
+        let schema = self.schema.clone();
+        let mut req = IdbDatabase::open_u32(&self.db_name(tenant), self.version)?;
+        req.set_on_upgrade_needed(Some(move |evt: &IdbVersionChangeEvent| {
+            schema.apply(evt)?;
+            Ok(())
+        }));
+        let db = req.into_future().await?;
+        self.register(tenant).await?;
+
+        // Another open call for the same tenant may have finished while this one was waiting
+        if let Some(existing) = self.get_open(tenant) {
+            db.close();
+            return Ok(existing);
+        }
+
+        let raw = db.as_web_sys().clone();
+        let closed = Rc::new(Cell::new(false));
+        let closed_cb = closed.clone();
+        let token = ClosureToken::new(ClosureKind::Database);
+        let on_version_change = Closure::wrap(Box::new(move || {
+            token.hold();
+            raw.close();
+            closed_cb.set(true);
+        }) as Box<dyn Fn()>);
+        db.as_web_sys()
+            .set_onversionchange(Some(on_version_change.as_ref().unchecked_ref()));
+
+        let db = Rc::new(db);
+        self.open.borrow_mut().insert(
+            tenant.into(),
+            OpenTenant {
+                db: db.clone(),
+                closed,
+                _on_version_change: on_version_change,
+            },
+        );
+        Ok(db)
+    }
+
+    /// The tenants with a connection currently open through this manager
+    pub fn open_tenants(&self) -> Vec<String> {
+        self.open
+            .borrow()
+            .iter()
+            .filter(|(_, open)| !open.closed.get())
+            .map(|(tenant, _)| tenant.clone())
+            .collect()
+    }
+
+    /// All registered tenants
+    pub async fn tenants(&self) -> Result<Vec<String>, DomException> {
+        let registry = self.open_registry().await?;
+        let tx = registry.transaction_on_one(REGISTRY_STORE)?;
+        let keys = tx.object_store(REGISTRY_STORE)?.get_all_keys()?.await?;
+        registry.close();
+        Ok(keys.iter().filter_map(|k| k.as_string()).collect())
+    }
+
+    /// Close the tenant's connection, if one is open
+    pub fn close(&self, tenant: &str) {
+        if let Some(open) = self.open.borrow_mut().remove(tenant) {
+            open.db.close();
+        }
+    }
+
+    /// Close every open tenant connection
+    pub fn close_all(&self) {
+        for (_, open) in std::mem::take(&mut *self.open.borrow_mut()) {
+            open.db.close();
+        }
+    }
+
+    /// Close and delete the tenant's database and remove it from the registry
+    pub async fn delete_tenant(&self, tenant: &str) -> Result<(), DomException> {
+        self.close(tenant);
+        IdbDatabase::delete_by_name(&self.db_name(tenant))?
+            .into_future()
+            .await?;
+
+        let registry = self.open_registry().await?;
+        let tx =
+            registry.transaction_on_one_with_mode(REGISTRY_STORE, IdbTransactionMode::Readwrite)?;
+        tx.object_store(REGISTRY_STORE)?.delete_owned(tenant)?;
+        let res = tx.await.into_result();
+        registry.close();
+        res
+    }
+
+    /// Get the tenant's open connection, forgetting it if it got closed by a versionchange event
+    fn get_open(&self, tenant: &str) -> Option<Rc<IdbDatabase>> {
+        let mut open = self.open.borrow_mut();
+        match open.get(tenant) {
+            Some(entry) if entry.closed.get() => {
+                open.remove(tenant);
+                None
+            }
+            Some(entry) => Some(entry.db.clone()),
+            None => None,
+        }
+    }
+
+    async fn register(&self, tenant: &str) -> Result<(), DomException> {
+        let record = js_sys::Object::new();
+        js_sys::Reflect::set(
+            &record,
+            &JsValue::from_str("dbName"),
+            &JsValue::from(self.db_name(tenant)),
+        )?;
+        js_sys::Reflect::set(
+            &record,
+            &JsValue::from_str("lastOpened"),
+            &JsValue::from(js_sys::Date::now()),
+        )?;
+
+        let registry = self.open_registry().await?;
+        let tx =
+            registry.transaction_on_one_with_mode(REGISTRY_STORE, IdbTransactionMode::Readwrite)?;
+        tx.object_store(REGISTRY_STORE)?
+            .put_key_val_owned(tenant, &record)?;
+        let res = tx.await.into_result();
+        registry.close();
+        res
+    }
+
+    async fn open_registry(&self) -> Result<IdbDatabase, DomException> {
+        let mut req = IdbDatabase::open_u32(&self.registry_name(), 1)?;
+        req.set_on_upgrade_needed(Some(|evt: &IdbVersionChangeEvent| {
+            evt.db().create_object_store(REGISTRY_STORE)?;
+            Ok(())
+        }));
+        Ok(req.into_future().await?)
+    }
+}
+
+impl Drop for DbManager {
+    fn drop(&mut self) {
+        self.close_all();
+    }
+}
+
+impl std::fmt::Debug for DbManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DbManager")
+            .field("prefix", &self.prefix)
+            .field("version", &self.version)
+            .field("open", &self.open_tenants())
+            .finish()
+    }
+}
@@ -0,0 +1,111 @@
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use web_sys::DomException;
+
+use crate::idb_database::{IdbDatabase, IdbVersionChangeEvent};
+use crate::request::IdbOpenDbRequestLike;
+
+use super::DbSchema;
+
+type ReseedCb = Rc<dyn Fn(&IdbVersionChangeEvent) -> Result<(), JsValue>>;
+
+/// What [OpenOptions][crate::idb_database::OpenOptions] does when a database fails to open with
+/// an error that points at a corrupted backing store, set with
+/// [recover_with][crate::idb_database::OpenOptions::recover_with]: delete the database and create
+/// it afresh from the schema instead of failing the same way on every open.
+///
+/// Everything in the database is lost, so only opt in for databases that can be rebuilt, e.g.
+/// caches of server data. The fresh database skips the usual upgrade callback; the
+/// [reseed][RecoveryPolicy::reseed] callback runs in its place, after the schema has been applied.
+///
+/// ```rust
+/// use indexed_db_futures::prelude::*;
+/// use indexed_db_futures::schema::{DbSchema, RecoveryPolicy, StoreSchema};
+/// use wasm_bindgen::prelude::*;
+///
+/// async fn open() -> Result<IdbDatabase, OpenError> {
+///     let schema = DbSchema::new().store(StoreSchema::new("settings"));
+///     let reseed = |evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+///         let tx = evt.transaction().expect("upgrade transaction");
+///         tx.object_store("settings")?.put_key_val_owned("theme", &JsValue::from("dark"))?;
+///         Ok(())
+///     };
+///     let policy = RecoveryPolicy::new(schema.clone()).reseed(reseed);
+///
+///     OpenOptions::new("my_db")
+///         .version(1)
+///         .on_upgrade(move |evt: &IdbVersionChangeEvent| {
+///             schema.apply(evt)?;
+///             Ok(())
+///         })
+///         .recover_with(policy)
+///         .open()
+///         .await
+/// }
+/// ```
+///
+/// Features required: `schema`
+#[derive(Clone)]
+pub struct RecoveryPolicy {
+    schema: Rc<DbSchema>,
+    reseed: Option<ReseedCb>,
+}
+
+impl RecoveryPolicy {
+    /// Recreate corrupted databases from the given schema
+    pub fn new(schema: DbSchema) -> Self {
+        Self {
+            schema: Rc::new(schema),
+            reseed: None,
+        }
+    }
+
+    /// Seed the recreated database from its upgrade transaction, e.g. with defaults, once the
+    /// schema has been applied
+    pub fn reseed<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&IdbVersionChangeEvent) -> Result<(), JsValue> + 'static,
+    {
+        self.reseed = Some(Rc::new(callback));
+        self
+    }
+
+    /// Whether the open error points at a corrupted database rather than e.g. a version
+    /// mismatch, a blocked upgrade or a full disk. Browsers report a backing store they can't
+    /// read as an `UnknownError` or `NotReadableError`, sometimes with "corrupt" in the message.
+    pub fn is_corruption(error: &DomException) -> bool {
+        matches!(error.name().as_str(), "UnknownError" | "NotReadableError")
+            || error.message().to_lowercase().contains("corrupt")
+    }
+
+    /// Delete the database and create it afresh at the given version, or version 1
+    pub(crate) async fn recreate(
+        &self,
+        name: &str,
+        version: Option<u32>,
+    ) -> Result<IdbDatabase, DomException> {
+        IdbDatabase::delete_by_name(name)?.into_future().await?;
+
+        let schema = self.schema.clone();
+        let reseed = self.reseed.clone();
+        let mut req = IdbDatabase::open_u32(name, version.unwrap_or(1))?;
+        req.set_on_upgrade_needed(Some(move |evt: &IdbVersionChangeEvent| {
+            schema.apply(evt)?;
+            match reseed.as_ref() {
+                Some(reseed) => reseed(evt),
+                None => Ok(()),
+            }
+        }));
+        Ok(req.into_future().await?)
+    }
+}
+
+impl std::fmt::Debug for RecoveryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecoveryPolicy")
+            .field("schema", &self.schema)
+            .field("reseed", &self.reseed.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use crate::idb_key_path::IdbKeyPath;
+
+use super::StoreSchema;
+
+/// How long a store's records are kept, read from the record's timestamp
+///
+/// Features required: `schema`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaxAge {
+    /// How long records are kept
+    pub duration: Duration,
+    /// Key path of the record's timestamp, in milliseconds since the epoch
+    pub timestamp: IdbKeyPath,
+}
+
+/// Limits on what a store holds, declared with [StoreSchema::retain] and
+/// [StoreSchema::max_records] and enforced by [RetentionJob][super::RetentionJob]
+///
+/// Features required: `schema`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Retention {
+    /// Delete records older than this
+    pub max_age: Option<MaxAge>,
+    /// Delete the records with the lowest keys beyond this many, which for auto-incremented or
+    /// timestamp keys are the oldest
+    pub max_records: Option<u32>,
+}
+
+impl Retention {
+    /// Whether there's no limit
+    #[inline]
+    pub fn is_unlimited(&self) -> bool {
+        self.max_age.is_none() && self.max_records.is_none()
+    }
+}
+
+impl StoreSchema {
+    /// Delete records once their timestamp, found at the given dotted key path in milliseconds
+    /// since the epoch, is older than `max_age`. Records without a numeric timestamp are kept.
+    /// An index on the same key path, if the schema declares one, saves scanning the whole store.
+    ///
+    /// Features required: `schema`
+    pub fn retain(mut self, max_age: Duration, timestamp_path: &str) -> Self {
+        self.retention.max_age = Some(MaxAge {
+            duration: max_age,
+            timestamp: IdbKeyPath::str(timestamp_path),
+        });
+        self
+    }
+
+    /// Keep at most `max_records` records, deleting the ones with the lowest keys first
+    ///
+    /// Features required: `schema`
+    pub fn max_records(mut self, max_records: u32) -> Self {
+        self.retention.max_records = Some(max_records);
+        self
+    }
+}
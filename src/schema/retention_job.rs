@@ -0,0 +1,212 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbKeyRange, IdbTransactionMode};
+
+use crate::clock::{Clock, SharedClock};
+use crate::idb_database::IdbDatabase;
+use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+use crate::maintenance::{JobFuture, JobStep, MaintenanceJob};
+
+use super::{DbSchema, MaxAge, StoreSchema};
+
+impl StoreSchema {
+    /// Delete up to `limit` records the retention policy no longer allows as of `now`, in one
+    /// transaction. Resolves to the number deleted.
+    async fn enforce_retention(
+        &self,
+        db: &IdbDatabase,
+        now: f64,
+        limit: u32,
+    ) -> Result<u32, DomException> {
+        let tx = db.transaction_on_one_with_mode(&self.name, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(&self.name)?;
+        let mut deleted = 0;
+        if let Some(max_age) = self.retention.max_age.as_ref() {
+            deleted += self.delete_expired(&store, max_age, now, limit).await?;
+        }
+        if let Some(max_records) = self.retention.max_records {
+            if deleted < limit {
+                deleted += delete_excess(&store, max_records, limit - deleted).await?;
+            }
+        }
+        tx.await.into_result()?;
+        Ok(deleted)
+    }
+
+    async fn delete_expired(
+        &self,
+        store: &IdbObjectStore<'_>,
+        max_age: &MaxAge,
+        now: f64,
+        limit: u32,
+    ) -> Result<u32, DomException> {
+        let cutoff = now - max_age.duration.as_millis() as f64;
+        let mut deleted = 0;
+
+        let index = self
+            .indices
+            .iter()
+            .find(|index| index.key_path == max_age.timestamp && !index.multi_entry);
+        if let Some(index) = index {
+            let range = IdbKeyRange::upper_bound_with_open(&cutoff.into(), true)?;
+            let index = store.index(&index.name)?;
+            if let Some(cursor) = index.open_key_cursor_with_range(&range)?.await? {
+                while deleted < limit {
+                    cursor.delete()?;
+                    deleted += 1;
+                    if !cursor.continue_cursor()?.await? {
+                        break;
+                    }
+                }
+            }
+            return Ok(deleted);
+        }
+
+        if let Some(cursor) = store.open_cursor()?.await? {
+            while deleted < limit {
+                let timestamp = max_age.timestamp.evaluate(&cursor.value());
+                if matches!(timestamp.and_then(|t| t.as_f64()), Some(t) if t < cutoff) {
+                    cursor.delete()?;
+                    deleted += 1;
+                }
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+        Ok(deleted)
+    }
+}
+
+/// Delete up to `limit` of the lowest-keyed records beyond `max_records` with a single range
+/// deletion
+async fn delete_excess(
+    store: &IdbObjectStore<'_>,
+    max_records: u32,
+    limit: u32,
+) -> Result<u32, DomException> {
+    let count = store.count()?.await?;
+    let excess = count.saturating_sub(max_records).min(limit);
+    if excess == 0 {
+        return Ok(0);
+    }
+    let cursor = match store.open_key_cursor()?.await? {
+        Some(cursor) => cursor,
+        None => return Ok(0),
+    };
+    if excess > 1 && !cursor.advance(excess - 1)?.await? {
+        return Ok(0);
+    }
+    match cursor.primary_key() {
+        Some(last) => {
+            store.delete(&IdbKeyRange::upper_bound(&last)?)?;
+            Ok(excess)
+        }
+        None => Ok(0),
+    }
+}
+
+/// A [MaintenanceJob] enforcing the [retention policies][Retention] of every store in a schema,
+/// one store at a time, deleting at most a chunk's worth of records per transaction
+///
+/// ```rust
+/// use indexed_db_futures::maintenance::MaintenanceScheduler;
+/// use indexed_db_futures::schema::{DbSchema, StoreSchema};
+/// use std::time::Duration;
+///
+/// fn scheduler() -> MaintenanceScheduler {
+///     let schema = DbSchema::new()
+///         .store(StoreSchema::new("meta"))
+///         .store(StoreSchema::new("logs").auto_increment(true).max_records(10_000))
+///         .store(StoreSchema::new("responses").retain(Duration::from_secs(86_400), "fetchedAt"));
+///     MaintenanceScheduler::new("meta").job(schema.retention_job())
+/// }
+/// ```
+///
+/// Features required: `schema`, `cursors`
+#[derive(Debug, Clone)]
+pub struct RetentionJob {
+    stores: Vec<StoreSchema>,
+    clock: SharedClock,
+}
+
+impl DbSchema {
+    /// A maintenance job enforcing the schema's retention policies
+    ///
+    /// Features required: `schema`, `cursors`
+    pub fn retention_job(&self) -> RetentionJob {
+        RetentionJob {
+            stores: self
+                .stores
+                .iter()
+                .filter(|store| !store.retention.is_unlimited())
+                .cloned()
+                .collect(),
+            clock: SharedClock::default(),
+        }
+    }
+}
+
+impl RetentionJob {
+    /// Use the given clock instead of the system time as the current time maximum ages count
+    /// back from
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// Enforce every policy once, deleting at most `batch_size` records per transaction.
+    /// Resolves to the number of records deleted.
+    pub async fn run(&self, db: &IdbDatabase, batch_size: u32) -> Result<u32, DomException> {
+        let mut deleted = 0;
+        for store in self.stores.iter() {
+            loop {
+                let batch = store
+                    .enforce_retention(db, self.clock.now(), batch_size.max(1))
+                    .await?;
+                deleted += batch;
+                if batch < batch_size.max(1) {
+                    break;
+                }
+            }
+        }
+        Ok(deleted)
+    }
+}
+
+/// The checkpoint is the position of the store being worked on
+impl MaintenanceJob for RetentionJob {
+    fn name(&self) -> String {
+        "retention".into()
+    }
+
+    fn run_chunk<'a>(
+        &'a self,
+        db: &'a IdbDatabase,
+        checkpoint: Option<JsValue>,
+        chunk_size: u32,
+    ) -> JobFuture<'a> {
+        Box::pin(async move {
+            let position = checkpoint.and_then(|c| c.as_f64()).unwrap_or(0.0) as usize;
+            let store = match self.stores.get(position) {
+                Some(store) => store,
+                None => return Ok(JobStep::Done),
+            };
+            let chunk_size = chunk_size.max(1);
+            let next = if store
+                .enforce_retention(db, self.clock.now(), chunk_size)
+                .await?
+                < chunk_size
+            {
+                position + 1
+            } else {
+                position
+            };
+            Ok(if next < self.stores.len() {
+                JobStep::Continue(Some(JsValue::from(next as u32)))
+            } else {
+                JobStep::Done
+            })
+        })
+    }
+}
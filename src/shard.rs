@@ -0,0 +1,176 @@
+//! Full-store computations split into shards processed concurrently
+//!
+//! Features required: `cursors`
+//!
+//! A single cursor over a huge store visits one record per request round trip. [shards] splits
+//! the store's key space into contiguous ranges of roughly equal size, using
+//! [sample_keys][crate::idb_object_store::IdbObjectStore::sample_keys], and [fold_shards] walks
+//! every shard with its own cursor in its own readonly transaction, all at the same time, so
+//! that the browser can serve the requests in parallel. Readonly transactions over the same
+//! store don't block each other. This suits computations like exports, statistics or deciding
+//! what to reindex, where the per-shard results can be combined afterwards.
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::shard::fold_shards;
+//! use web_sys::DomException;
+//!
+//! async fn total_size(db: &IdbDatabase) -> Result<f64, DomException> {
+//!     let sizes = fold_shards(db, "files", 4, 0.0, |total, record| {
+//!         let size = js_sys::Reflect::get(record.value(), &"size".into()).ok();
+//!         total + size.and_then(|s| s.as_f64()).unwrap_or(0.0)
+//!     })
+//!     .await?;
+//!     Ok(sizes.into_iter().sum())
+//! }
+//! ```
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbKeyRange};
+
+use crate::idb_cursor::KeyVal;
+use crate::idb_database::IdbDatabase;
+use crate::idb_query_source::IdbQuerySource;
+use crate::request::join_all;
+
+/// A contiguous part of a store's key space, from `lower` (inclusive) to `upper` (exclusive).
+/// `None` leaves that end unbounded.
+///
+/// Features required: `cursors`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shard {
+    /// The first key in the shard
+    pub lower: Option<JsValue>,
+    /// The first key after the shard
+    pub upper: Option<JsValue>,
+}
+
+impl Shard {
+    /// The shard's key range, usable with cursor and `get_all` methods; `undefined` if the shard
+    /// is unbounded on both ends
+    pub fn range(&self) -> Result<JsValue, DomException> {
+        let range = match (&self.lower, &self.upper) {
+            (Some(lower), Some(upper)) => {
+                IdbKeyRange::bound_with_lower_open_and_upper_open(lower, upper, false, true)?
+            }
+            (Some(lower), None) => IdbKeyRange::lower_bound(lower)?,
+            (None, Some(upper)) => IdbKeyRange::upper_bound_with_open(upper, true)?,
+            (None, None) => return Ok(JsValue::UNDEFINED),
+        };
+        Ok(range.into())
+    }
+}
+
+/// Split the store's key space into up to `n` shards holding about the same number of records.
+/// The first and last shards are unbounded, so records written afterwards still fall into a
+/// shard; an empty store gets a single shard.
+///
+/// Features required: `cursors`
+pub async fn shards(
+    db: &IdbDatabase,
+    store_name: &str,
+    n: u32,
+) -> Result<Vec<Shard>, DomException> {
+    let tx = db.transaction_on_one(store_name)?;
+    let mut bounds = tx
+        .object_store(store_name)?
+        .sample_keys(n.max(1))
+        .await?
+        .into_iter()
+        .skip(1)
+        .map(Some)
+        .collect::<Vec<_>>();
+    bounds.push(None);
+
+    let mut lower = None;
+    Ok(bounds
+        .into_iter()
+        .map(|upper| {
+            let shard = Shard {
+                lower: lower.take(),
+                upper,
+            };
+            lower = shard.upper.clone();
+            shard
+        })
+        .collect())
+}
+
+/// Split the store into up to `n` [shards] and fold every shard's records, in key order, starting
+/// from a copy of `init`. All the shards are walked at the same time, each in its own readonly
+/// transaction. Resolves to the per-shard results in key order.
+///
+/// Features required: `cursors`
+pub async fn fold_shards<A, F>(
+    db: &IdbDatabase,
+    store_name: &str,
+    n: u32,
+    init: A,
+    fold: F,
+) -> Result<Vec<A>, DomException>
+where
+    A: Clone,
+    F: Fn(A, &KeyVal) -> A,
+{
+    let shards = shards(db, store_name, n).await?;
+    let walks = shards
+        .iter()
+        .map(|shard| fold_shard(db, store_name, shard, init.clone(), &fold));
+    join_all(walks).await.into_iter().collect()
+}
+
+async fn fold_shard<A, F>(
+    db: &IdbDatabase,
+    store_name: &str,
+    shard: &Shard,
+    mut acc: A,
+    fold: &F,
+) -> Result<A, DomException>
+where
+    F: Fn(A, &KeyVal) -> A,
+{
+    let tx = db.transaction_on_one(store_name)?;
+    let store = tx.object_store(store_name)?;
+    if let Some(cursor) = store.open_cursor_with_range(&shard.range()?)?.await? {
+        while let Some(key) = cursor.key() {
+            acc = fold(acc, &KeyVal::new(key, cursor.value()));
+            if !cursor.continue_cursor()?.await? {
+                break;
+            }
+        }
+    }
+    Ok(acc)
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::open_any_db;
+
+    use super::*;
+
+    test_mod_init!();
+
+    test_case!(async fold_shards => {
+        let (db, store_name) = open_any_db().await;
+        let empty = shards(&db, &store_name, 4).await.expect("empty shards");
+        assert_eq!(empty, vec![Shard { lower: None, upper: None }], "empty");
+
+        let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).unwrap();
+        let store = tx.object_store(&store_name).unwrap();
+        for i in 0..40u32 {
+            store.put_key_val_owned(i, &JsValue::from(i)).expect("put");
+        }
+        tx.await.into_result().expect("tx");
+
+        let split = shards(&db, &store_name, 4).await.expect("shards");
+        let uppers: Vec<Option<JsValue>> = split.iter().map(|s| s.upper.clone()).collect();
+        let expected = [Some(10u32), Some(20), Some(30), None];
+        assert_eq!(uppers, expected.iter().map(|u| u.map(JsValue::from)).collect::<Vec<_>>(), "bounds");
+
+        let sums = super::fold_shards(&db, &store_name, 4, 0.0, |sum, kv| sum + kv.value().as_f64().unwrap())
+            .await
+            .expect("fold");
+        assert_eq!(sums, vec![45.0, 145.0, 245.0, 345.0], "sums");
+    });
+}
@@ -0,0 +1,279 @@
+//! Large values spilled over into the Origin Private File System
+//!
+//! IndexedDB is fine for small and medium values but slow for very large ones, while the
+//! [Origin Private File System](https://developer.mozilla.org/en-US/docs/Web/API/File_System_API/Origin_private_file_system)
+//! (OPFS) handles big files well but can't be queried or written transactionally. A
+//! [SpilloverStore] keeps values below a size threshold inline in an object store and writes
+//! larger ones to OPFS files, with the object store holding a `{file, size}` record pointing at
+//! the file, so callers see one key-value store either way.
+//!
+//! Files are written before their record and deleted after it, so a record never points at a
+//! missing file; an interrupted write can at worst leave an orphaned file behind, which the next
+//! write or delete of the same key replaces or removes. OPFS calls are promise-based and would let
+//! a transaction auto-commit if awaited in the middle of it, so every record update runs in its
+//! own short transaction. The store must use out-of-line keys.
+//!
+//! Writing files needs `FileSystemFileHandle.createWritable`, which some browsers only offer to
+//! workers.
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::spillover::SpilloverStore;
+//! use web_sys::DomException;
+//!
+//! async fn example(db: &IdbDatabase, video: &[u8]) -> Result<(), DomException> {
+//!     let assets = SpilloverStore::new(db, "assets").threshold(1024 * 1024);
+//!     assets.put("intro.webm", video).await?;
+//!     let _video: Option<Vec<u8>> = assets.get("intro.webm").await?;
+//!     Ok(())
+//! }
+//! ```
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::idb_database::IdbDatabase;
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::{await_promise, dom_exception};
+
+/// The default size above which values get spilled over into OPFS, 4 MiB
+pub const DEFAULT_THRESHOLD: u32 = 4 * 1024 * 1024;
+
+/// Where a [SpilloverStore] value lives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    /// In the object store
+    Inline,
+    /// In an OPFS file
+    File,
+}
+
+/// A key-value store for bytes backed by IndexedDB and OPFS. See the [module docs][self].
+#[derive(Debug)]
+pub struct SpilloverStore<'a> {
+    db: &'a IdbDatabase,
+    store_name: String,
+    directory: String,
+    threshold: u32,
+}
+
+impl<'a> SpilloverStore<'a> {
+    /// Use the given store, spilling values above [DEFAULT_THRESHOLD] into an OPFS directory named
+    /// after it
+    pub fn new(db: &'a IdbDatabase, store_name: &str) -> Self {
+        Self {
+            db,
+            store_name: store_name.into(),
+            directory: format!("idb-spillover-{}", store_name),
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+
+    /// Spill over values larger than the given number of bytes
+    pub fn threshold(mut self, bytes: u32) -> Self {
+        self.threshold = bytes;
+        self
+    }
+
+    /// Keep spilled-over files in the given OPFS directory
+    pub fn directory(mut self, name: &str) -> Self {
+        self.directory = name.into();
+        self
+    }
+
+    /// Store the bytes at the key, replacing any value already there. Resolves to where the value
+    /// was stored.
+    pub async fn put(&self, key: &str, data: &[u8]) -> Result<Location, DomException> {
+        let bytes = js_sys::Uint8Array::from(data);
+        let record = js_sys::Object::new();
+        let location = if data.len() > self.threshold as usize {
+            let file = file_name(key);
+            let writable =
+                call(&self.file_handle(&file, true).await?, "createWritable", &[]).await?;
+            call(&writable, "write", &[bytes.into()]).await?;
+            call(&writable, "close", &[]).await?;
+            js_sys::Reflect::set(&record, &"file".into(), &file.into())?;
+            Location::File
+        } else {
+            js_sys::Reflect::set(&record, &"data".into(), &bytes)?;
+            Location::Inline
+        };
+        js_sys::Reflect::set(&record, &"size".into(), &(data.len() as f64).into())?;
+
+        let previous = self.write_record(key, Some(&record)).await?;
+        if location == Location::Inline && previous == Some(Location::File) {
+            self.remove_file(key).await?;
+        }
+        Ok(location)
+    }
+
+    /// The bytes stored at the key
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, DomException> {
+        let tx = self.db.transaction_on_one(&self.store_name)?;
+        let record = tx
+            .object_store(&self.store_name)?
+            .get(&JsValue::from_str(key))?
+            .await?;
+        let record = match record {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        let bytes = match location_of(&record) {
+            Location::Inline => js_sys::Reflect::get(&record, &"data".into())?,
+            Location::File => {
+                let file = call(
+                    &self.file_handle(&file_name(key), false).await?,
+                    "getFile",
+                    &[],
+                )
+                .await?;
+                call(&file, "arrayBuffer", &[]).await?
+            }
+        };
+        Ok(Some(js_sys::Uint8Array::new(&bytes).to_vec()))
+    }
+
+    /// The size in bytes and location of the value at the key
+    pub async fn stat(&self, key: &str) -> Result<Option<(f64, Location)>, DomException> {
+        let tx = self.db.transaction_on_one(&self.store_name)?;
+        let record = tx
+            .object_store(&self.store_name)?
+            .get(&JsValue::from_str(key))?
+            .await?;
+        Ok(record.map(|r| {
+            let size = js_sys::Reflect::get(&r, &"size".into())
+                .ok()
+                .and_then(|s| s.as_f64())
+                .unwrap_or(0.0);
+            (size, location_of(&r))
+        }))
+    }
+
+    /// Delete the value at the key, including its file if it has one
+    pub async fn delete(&self, key: &str) -> Result<(), DomException> {
+        if self.write_record(key, None).await? == Some(Location::File) {
+            self.remove_file(key).await?;
+        }
+        Ok(())
+    }
+
+    /// Put or delete the record at the key, resolving to where the previous value was
+    async fn write_record(
+        &self,
+        key: &str,
+        record: Option<&js_sys::Object>,
+    ) -> Result<Option<Location>, DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(&self.store_name)?;
+        let key = JsValue::from_str(key);
+        let previous = store.get(&key)?.await?.map(|r| location_of(&r));
+        match record {
+            Some(record) => store.put_key_val(&key, record)?,
+            None => store.delete(&key)?,
+        };
+        tx.await.into_result()?;
+        Ok(previous)
+    }
+
+    async fn directory_handle(&self) -> Result<JsValue, DomException> {
+        let navigator = js_sys::Reflect::get(&js_sys::global(), &"navigator".into())?;
+        let storage = match navigator.is_object() {
+            true => js_sys::Reflect::get(&navigator, &"storage".into())?,
+            false => JsValue::UNDEFINED,
+        };
+        let has_opfs = storage.is_object()
+            && js_sys::Reflect::get(&storage, &"getDirectory".into())?.is_function();
+        if !has_opfs {
+            return Err(dom_exception(
+                "The Origin Private File System is unavailable",
+                "NotSupportedError",
+            ));
+        }
+        let root = call(&storage, "getDirectory", &[]).await?;
+        call(
+            &root,
+            "getDirectoryHandle",
+            &[self.directory.as_str().into(), create_options(true)],
+        )
+        .await
+    }
+
+    async fn file_handle(&self, file: &str, create: bool) -> Result<JsValue, DomException> {
+        let dir = self.directory_handle().await?;
+        call(
+            &dir,
+            "getFileHandle",
+            &[file.into(), create_options(create)],
+        )
+        .await
+    }
+
+    async fn remove_file(&self, key: &str) -> Result<(), DomException> {
+        let dir = self.directory_handle().await?;
+        match call(&dir, "removeEntry", &[file_name(key).into()]).await {
+            Err(e) if e.name() == "NotFoundError" => Ok(()),
+            other => other.map(|_| ()),
+        }
+    }
+}
+
+fn location_of(record: &JsValue) -> Location {
+    match js_sys::Reflect::has(record, &"file".into()) {
+        Ok(true) => Location::File,
+        _ => Location::Inline,
+    }
+}
+
+/// Keys can contain characters file names can't, so they get percent-encoded
+fn file_name(key: &str) -> String {
+    String::from(js_sys::encode_uri_component(key))
+}
+
+fn create_options(create: bool) -> JsValue {
+    let options = js_sys::Object::new();
+    let _ = js_sys::Reflect::set(&options, &"create".into(), &create.into());
+    options.into()
+}
+
+/// Call the promise-returning method and await its result
+async fn call(target: &JsValue, method: &str, args: &[JsValue]) -> Result<JsValue, DomException> {
+    let function: js_sys::Function = js_sys::Reflect::get(target, &method.into())?
+        .dyn_into()
+        .map_err(|_| dom_exception(&format!("{} is not supported", method), "NotSupportedError"))?;
+    let args: js_sys::Array = args.iter().collect();
+    let promise = function.apply(target, &args)?;
+    await_promise(promise.unchecked_into()).await
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::test_utils::open_any_db;
+
+    use super::*;
+
+    test_mod_init!();
+
+    fn bytes(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    test_case!(async spills_over => {
+        let (db, store_name) = open_any_db().await;
+        let store = SpilloverStore::new(&db, &store_name).threshold(16);
+
+        assert_eq!(store.put("small", &bytes(16)).await.expect("put small"), Location::Inline, "small");
+        assert_eq!(store.put("large/file", &bytes(100)).await.expect("put large"), Location::File, "large");
+        assert_eq!(store.get("small").await.expect("get small"), Some(bytes(16)), "small value");
+        assert_eq!(store.get("large/file").await.expect("get large"), Some(bytes(100)), "large value");
+        assert_eq!(store.stat("large/file").await.expect("stat"), Some((100.0, Location::File)), "stat");
+
+        assert_eq!(store.put("large/file", &bytes(3)).await.expect("shrink"), Location::Inline, "shrunk");
+        assert_eq!(store.get("large/file").await.expect("get shrunk"), Some(bytes(3)), "shrunk value");
+        store.put("large/file", &bytes(50)).await.expect("grow");
+        store.delete("large/file").await.expect("delete");
+        assert_eq!(store.get("large/file").await.expect("get deleted"), None, "deleted");
+        assert_eq!(store.get("missing").await.expect("get missing"), None, "missing");
+    });
+}
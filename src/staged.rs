@@ -0,0 +1,334 @@
+//! Staging writes in memory and reading them back before they're committed
+//!
+//! An IndexedDB transaction lets its own requests see each other's writes, but only while it's
+//! alive, and it auto-commits as soon as an operation awaits anything else. Code that builds up
+//! a change across several helper layers, awaiting other things in between, can stage the
+//! writes in [StagedWrites] instead: reads through it see the staged writes on top of what's in
+//! the store, and [flush][StagedWrites::flush] applies all of them, in the order they were
+//! staged, in a single transaction.
+//!
+//! [Savepoints][StagedWrites::savepoint] mark a position in the staged writes that a multi-step
+//! operation can [roll back to][StagedWrites::rollback_to], undoing only the writes staged since,
+//! without giving up on the rest.
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::staged::StagedWrites;
+//! use wasm_bindgen::prelude::*;
+//! use web_sys::DomException;
+//!
+//! async fn example(db: &IdbDatabase) -> Result<(), DomException> {
+//!     let mut staged = StagedWrites::new();
+//!     staged.put("users", &String::from("ann"), &JsValue::from(1));
+//!     staged.delete("users", &String::from("bob"));
+//!
+//!     let tx = db.transaction_on_one("users")?;
+//!     let users = tx.object_store("users")?;
+//!     assert_eq!(staged.get(&users, &String::from("ann")).await?, Some(JsValue::from(1)));
+//!     assert_eq!(staged.get(&users, &String::from("bob")).await?, None);
+//!     drop(users);
+//!     drop(tx);
+//!
+//!     staged.flush(db).await
+//! }
+//! ```
+
+use std::cmp::Ordering;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbTransactionMode};
+
+use crate::idb_database::IdbDatabase;
+use crate::idb_key::IdbKey;
+use crate::idb_object_store::IdbObjectStore;
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::{dom_exception, keys_eq};
+
+/// A staged write
+#[derive(Debug, Clone)]
+pub enum StagedOp {
+    /// Put the value at the key
+    Put {
+        /// The record's key. For stores with in-line keys, it must match the value's key.
+        key: JsValue,
+        /// The value to put
+        value: JsValue,
+    },
+    /// Delete the record at the key
+    Delete(JsValue),
+}
+
+impl StagedOp {
+    /// The key the operation writes to
+    #[inline]
+    pub fn key(&self) -> &JsValue {
+        match self {
+            Self::Put { key, .. } | Self::Delete(key) => key,
+        }
+    }
+
+    /// The value the key has after the operation; `None` for deletions
+    #[inline]
+    pub fn value(&self) -> Option<&JsValue> {
+        match self {
+            Self::Put { value, .. } => Some(value),
+            Self::Delete(_) => None,
+        }
+    }
+}
+
+/// Writes to one or more object stores, staged in memory until they're
+/// [flushed][StagedWrites::flush]. See the [module docs][self].
+#[derive(Debug, Clone, Default)]
+pub struct StagedWrites {
+    ops: Vec<(String, StagedOp)>,
+    savepoints: Vec<(String, usize)>,
+}
+
+impl StagedWrites {
+    /// Stage nothing yet
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stage putting the value at the key of the given store
+    pub fn put<K: IdbKey>(&mut self, store: &str, key: &K, value: &JsValue) {
+        self.push(
+            store,
+            StagedOp::Put {
+                key: key.to_js_key(),
+                value: value.clone(),
+            },
+        );
+    }
+
+    /// Stage deleting the record at the key of the given store
+    pub fn delete<K: IdbKey>(&mut self, store: &str, key: &K) {
+        self.push(store, StagedOp::Delete(key.to_js_key()));
+    }
+
+    /// Stage an operation on the given store
+    #[inline]
+    pub fn push(&mut self, store: &str, op: StagedOp) {
+        self.ops.push((store.into(), op));
+    }
+
+    /// The number of staged operations
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Whether nothing is staged
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// The staged operations in the order they were staged, with their stores
+    pub fn ops(&self) -> impl Iterator<Item = (&str, &StagedOp)> {
+        self.ops.iter().map(|(store, op)| (store.as_str(), op))
+    }
+
+    /// The stores with staged operations, in the order they were first written to
+    pub fn stores(&self) -> Vec<&str> {
+        let mut stores = Vec::new();
+        for (store, _) in self.ops.iter() {
+            if !stores.contains(&store.as_str()) {
+                stores.push(store.as_str());
+            }
+        }
+        stores
+    }
+
+    /// Discard every staged operation and savepoint
+    #[inline]
+    pub fn clear(&mut self) {
+        self.ops.clear();
+        self.savepoints.clear();
+    }
+
+    /// Mark the current position with a named savepoint. Reusing a name shadows the earlier
+    /// savepoint until the new one is released or rolled past.
+    pub fn savepoint(&mut self, name: &str) {
+        self.savepoints.push((name.into(), self.ops.len()));
+    }
+
+    /// The savepoints' names, oldest first
+    pub fn savepoints(&self) -> impl Iterator<Item = &str> {
+        self.savepoints.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Discard the operations staged since the most recent savepoint with the given name, along
+    /// with the savepoints set after it. The savepoint itself is kept, so it can be rolled back
+    /// to again. Returns the number of operations discarded; fails with a `NotFoundError` if
+    /// there's no such savepoint.
+    pub fn rollback_to(&mut self, name: &str) -> Result<usize, DomException> {
+        let idx = self.savepoint_index(name)?;
+        let position = self.savepoints[idx].1;
+        self.savepoints.truncate(idx + 1);
+        let discarded = self.ops.len() - position;
+        self.ops.truncate(position);
+        Ok(discarded)
+    }
+
+    /// Forget the most recent savepoint with the given name and the ones set after it, keeping
+    /// the operations staged since. Fails with a `NotFoundError` if there's no such savepoint.
+    pub fn release(&mut self, name: &str) -> Result<(), DomException> {
+        let idx = self.savepoint_index(name)?;
+        self.savepoints.truncate(idx);
+        Ok(())
+    }
+
+    fn savepoint_index(&self, name: &str) -> Result<usize, DomException> {
+        self.savepoints
+            .iter()
+            .rposition(|(n, _)| n == name)
+            .ok_or_else(|| dom_exception(&format!("No savepoint named {}", name), "NotFoundError"))
+    }
+
+    /// The staged state of the key: `None` if nothing is staged for it, `Some(None)` if it's
+    /// staged for deletion and `Some(Some(value))` if a value is staged
+    pub fn staged<K: IdbKey>(&self, store: &str, key: &K) -> Option<Option<&JsValue>> {
+        let key = key.to_js_key();
+        self.ops
+            .iter()
+            .rev()
+            .find(|(s, op)| s == store && keys_eq(op.key(), &key))
+            .map(|(_, op)| op.value())
+    }
+
+    /// Get the record at the key as it will be once the staged writes are flushed: the staged
+    /// value if there is one, otherwise the record in the store
+    pub async fn get<K: IdbKey>(
+        &self,
+        store: &IdbObjectStore<'_>,
+        key: &K,
+    ) -> Result<Option<JsValue>, DomException> {
+        match self.staged(&store.name(), key) {
+            Some(staged) => Ok(staged.cloned()),
+            None => store.get(&key.to_js_key())?.await,
+        }
+    }
+
+    /// Get every `(key, value)` pair of the store as it will be once the staged writes are
+    /// flushed, in key order
+    pub async fn get_all(
+        &self,
+        store: &IdbObjectStore<'_>,
+    ) -> Result<Vec<(JsValue, JsValue)>, DomException> {
+        let keys = store.get_all_keys()?.await?;
+        let values = store.get_all()?.await?;
+        let mut records = keys.iter().zip(values.iter()).collect::<Vec<_>>();
+
+        let name = store.name();
+        for (_, op) in self.ops.iter().filter(|(s, _)| *s == name) {
+            records.retain(|(key, _)| !keys_eq(key, op.key()));
+            if let StagedOp::Put { key, value } = op {
+                records.push((key.clone(), value.clone()));
+            }
+        }
+        records
+            .sort_by(|a, b| crate::key_order::compare_keys(&a.0, &b.0).unwrap_or(Ordering::Equal));
+        Ok(records)
+    }
+
+    /// Apply the staged operations in the order they were staged, in a single readwrite
+    /// transaction on every store written to. Nothing is applied if any of them fails. The
+    /// operations stay staged if the flush fails, and are cleared along with the savepoints once
+    /// it succeeds.
+    pub async fn flush(&mut self, db: &IdbDatabase) -> Result<(), DomException> {
+        if self.ops.is_empty() {
+            return Ok(());
+        }
+        let names = self.stores();
+        let tx = db.transaction_on_multi_with_mode(&names, IdbTransactionMode::Readwrite)?;
+        let stores = names
+            .iter()
+            .map(|name| tx.object_store(name))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (name, op) in self.ops.iter() {
+            let store = match names.iter().position(|n| n == name) {
+                Some(idx) => &stores[idx],
+                None => continue,
+            };
+            match op {
+                StagedOp::Put { key, value } => match store.key_path() {
+                    Some(_) => store.put_val(value)?,
+                    None => store.put_key_val(key, value)?,
+                },
+                StagedOp::Delete(key) => store.delete(key)?,
+            };
+        }
+        drop(stores);
+        tx.await.into_result()?;
+        self.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::open_any_db;
+
+    use super::*;
+
+    test_mod_init!();
+
+    test_case!(async staged_writes => {
+        let (db, store_name) = open_any_db().await;
+        let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).unwrap();
+        let store = tx.object_store(&store_name).unwrap();
+        store.put_key_val_owned("a", &JsValue::from(1)).unwrap();
+        store.put_key_val_owned("b", &JsValue::from(2)).unwrap();
+        drop(store);
+        tx.await.into_result().unwrap();
+
+        let mut staged = StagedWrites::new();
+        staged.put(&store_name, &String::from("c"), &JsValue::from(3));
+        staged.delete(&store_name, &String::from("a"));
+        staged.put(&store_name, &String::from("b"), &JsValue::from(20));
+        assert_eq!(staged.len(), 3, "len");
+        assert_eq!(staged.stores(), vec![store_name.as_str()], "stores");
+
+        let tx = db.transaction_on_one(&store_name).unwrap();
+        let store = tx.object_store(&store_name).unwrap();
+        assert_eq!(staged.get(&store, &String::from("a")).await.unwrap(), None, "staged delete");
+        assert_eq!(staged.get(&store, &String::from("b")).await.unwrap(), Some(JsValue::from(20)), "staged put");
+        let all = staged.get_all(&store).await.unwrap();
+        let keys = all.iter().map(|(k, _)| k.as_string().unwrap()).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["b", "c"], "overlay");
+        assert_eq!(store.count().unwrap().await.unwrap(), 2, "not written yet");
+        drop(store);
+        drop(tx);
+
+        staged.flush(&db).await.expect("flush");
+        assert!(staged.is_empty(), "cleared");
+        let tx = db.transaction_on_one(&store_name).unwrap();
+        let store = tx.object_store(&store_name).unwrap();
+        assert_eq!(store.get_owned("a").unwrap().await.unwrap(), None, "deleted");
+        assert_eq!(store.get_owned("b").unwrap().await.unwrap(), Some(JsValue::from(20)), "updated");
+        assert_eq!(store.get_owned("c").unwrap().await.unwrap(), Some(JsValue::from(3)), "added");
+    });
+
+    test_case!(savepoints => {
+        let mut staged = StagedWrites::new();
+        staged.put("s", &String::from("a"), &JsValue::from(1));
+        staged.savepoint("one");
+        staged.put("s", &String::from("b"), &JsValue::from(2));
+        staged.savepoint("two");
+        staged.delete("s", &String::from("a"));
+
+        assert_eq!(staged.rollback_to("one").expect("rollback"), 2, "discarded");
+        assert_eq!(staged.len(), 1, "kept");
+        assert_eq!(staged.savepoints().collect::<Vec<_>>(), vec!["one"], "later savepoints dropped");
+        assert!(staged.staged("s", &String::from("b")).is_none(), "rolled back");
+        assert_eq!(staged.rollback_to("two").expect_err("gone").name(), "NotFoundError", "unknown");
+
+        staged.put("s", &String::from("c"), &JsValue::from(3));
+        staged.release("one").expect("release");
+        assert_eq!((staged.len(), staged.savepoints().count()), (2, 0), "released");
+    });
+}
@@ -0,0 +1,134 @@
+//! Scaffolding for browser tests against a real IndexedDB
+//!
+//! Features required: `test-utils`
+//!
+//! The helpers this crate's own `wasm-bindgen-test` suite is built on: databases with unique
+//! names, so that tests don't see each other's data, a [test_case][crate::test_case] macro that
+//! declares sync and async tests alike, and [seed] for filling a store in one transaction.
+//! Downstream crates still need `wasm-bindgen-test` as a dev-dependency and
+//! `wasm_bindgen_test_configure!(run_in_browser)` in their test crate.
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::test_utils::{open_any_db, seed};
+//!
+//! indexed_db_futures::test_case!(async counts_records => {
+//!     let (db, store) = open_any_db().await;
+//!     seed(&db, &store, vec![("a", 1), ("b", 2)]).await.unwrap();
+//!
+//!     let tx = db.transaction_on_one(&store).unwrap();
+//!     let count = tx.object_store(&store).unwrap().count().unwrap().await.unwrap();
+//!     assert_eq!(count, 2);
+//! });
+//! ```
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbTransactionMode};
+
+pub use wasm_bindgen_test;
+
+use crate::idb_database::{IdbDatabase, IdbVersionChangeEvent};
+use crate::request::IdbOpenDbRequestLike;
+
+/// Declare a `wasm-bindgen-test`, e.g. `test_case!(name => { ... })` or
+/// `test_case!(async name => { ... })`
+///
+/// Features required: `test-utils`
+#[macro_export]
+macro_rules! test_case {
+    ($name: ident => $body: block) => {
+        #[$crate::test_utils::wasm_bindgen_test::wasm_bindgen_test]
+        fn $name() {
+            $body
+        }
+    };
+    (async $name: ident => $body: block) => {
+        #[$crate::test_utils::wasm_bindgen_test::wasm_bindgen_test]
+        async fn $name() {
+            $body
+        }
+    };
+}
+
+/// A random name for a database or object store
+///
+/// Features required: `test-utils`
+#[inline]
+pub fn unique_name() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Open a new database with a single object store with out-of-line keys. Resolves to the
+/// database and the store's name.
+///
+/// Features required: `test-utils`
+pub async fn open_any_db() -> (IdbDatabase, String) {
+    let store = unique_name();
+    let db = open_db_with_stores(&[&store]).await;
+    (db, store)
+}
+
+/// Open a new database with the given object stores, all with out-of-line keys
+///
+/// Features required: `test-utils`
+pub async fn open_db_with_stores(stores: &[&str]) -> IdbDatabase {
+    let stores = stores.iter().map(|s| String::from(*s)).collect::<Vec<_>>();
+    open_db_with(move |evt| {
+        for store in stores.iter() {
+            evt.db().create_object_store(store)?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Open a new database, setting up its schema with the given `upgradeneeded` callback, e.g. for
+/// stores with key paths or indices
+///
+/// Features required: `test-utils`
+pub async fn open_db_with<F>(on_upgrade_needed: F) -> IdbDatabase
+where
+    F: FnMut(&IdbVersionChangeEvent) -> Result<(), JsValue> + 'static,
+{
+    let mut req = IdbDatabase::open_u32(&unique_name(), 1).expect("db open");
+    req.set_on_upgrade_needed(Some(on_upgrade_needed));
+    req.into_future().await.expect("db open future")
+}
+
+/// Put the given `(key, value)` pairs into a store with out-of-line keys in a single
+/// transaction
+///
+/// Features required: `test-utils`
+pub async fn seed<K, V, I>(db: &IdbDatabase, store: &str, records: I) -> Result<(), DomException>
+where
+    K: Into<JsValue>,
+    V: Into<JsValue>,
+    I: IntoIterator<Item = (K, V)>,
+{
+    let tx = db.transaction_on_one_with_mode(store, IdbTransactionMode::Readwrite)?;
+    let object_store = tx.object_store(store)?;
+    for (key, value) in records {
+        object_store.put_key_val_owned(key, &value.into())?;
+    }
+    tx.await.into_result()
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+
+    use super::*;
+
+    test_mod_init!();
+
+    test_case!(async seeded => {
+        let db = open_db_with_stores(&["a", "b"]).await;
+        assert_eq!(db.object_store_names().collect::<Vec<_>>(), vec!["a", "b"], "stores");
+
+        seed(&db, "b", vec![("x", 1), ("y", 2)]).await.expect("seed");
+        let tx = db.transaction_on_one("b").unwrap();
+        let store = tx.object_store("b").unwrap();
+        assert_eq!(store.count().unwrap().await.unwrap(), 2, "count");
+        assert_eq!(store.get_owned("y").unwrap().await.unwrap(), Some(JsValue::from(2)), "value");
+    });
+}
@@ -0,0 +1,391 @@
+//! Time-series storage on top of a plain object store
+//!
+//! A [TimeSeriesStore] keys every point by a `[series_id, timestamp]` compound key, so the points
+//! of each series are stored next to each other in timestamp order and a time range of one series
+//! is a single key range. The object store must use out-of-line keys and can hold any number of
+//! series.
+//!
+//! Every operation runs in its own short transaction.
+
+use std::ops::Range;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{DomException, IdbKeyRange, IdbTransactionMode};
+
+use crate::clock::{Clock, SharedClock};
+use crate::idb_database::IdbDatabase;
+use crate::idb_key::IdbKey;
+use crate::idb_query_source::IdbQuerySource;
+use crate::internal_utils::dom_exception;
+
+/// A single point of a series
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+    /// The point's timestamp, typically in milliseconds since the epoch
+    pub ts: f64,
+    /// The point's value
+    pub value: JsValue,
+}
+
+/// How [TimeSeriesStore::downsample] combines the numeric values in a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    /// The arithmetic mean
+    Mean,
+    /// The smallest value
+    Min,
+    /// The largest value
+    Max,
+    /// The sum of all values
+    Sum,
+    /// The number of values
+    Count,
+    /// The value with the lowest timestamp
+    First,
+    /// The value with the highest timestamp
+    Last,
+}
+
+/// A bucket of points produced by [TimeSeriesStore::downsample]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bucket {
+    /// The timestamp the bucket starts at
+    pub start: f64,
+    /// The aggregated value
+    pub value: f64,
+    /// The number of points that went into the bucket
+    pub count: u32,
+}
+
+/// Which points [TimeSeriesStore::apply_retention] removes. Both limits apply if both are set.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Retention {
+    max_age: Option<f64>,
+    max_points: Option<u32>,
+}
+
+impl Retention {
+    /// A policy that retains everything
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove points older than the given number of milliseconds
+    #[inline]
+    pub fn max_age(mut self, ms: f64) -> Self {
+        self.max_age = Some(ms);
+        self
+    }
+
+    /// Retain only the given number of most recent points per series
+    #[inline]
+    pub fn max_points(mut self, max_points: u32) -> Self {
+        self.max_points = Some(max_points);
+        self
+    }
+}
+
+/// A store of time series. See the [module docs][self].
+#[derive(Debug)]
+pub struct TimeSeriesStore<'a> {
+    db: &'a IdbDatabase,
+    store_name: String,
+    retention: Retention,
+    clock: SharedClock,
+}
+
+impl<'a> TimeSeriesStore<'a> {
+    /// Use the given object store, which must have out-of-line keys, for time series
+    pub fn new(db: &'a IdbDatabase, store_name: &str) -> Self {
+        Self {
+            db,
+            store_name: store_name.into(),
+            retention: Retention::default(),
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Set the policy [apply_retention][TimeSeriesStore::apply_retention] enforces
+    #[inline]
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Use the given clock instead of the system time as the current time the retention policy's
+    /// maximum age counts back from
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// Add a point to the series, replacing any existing point with the same timestamp
+    pub async fn append(&self, series: &str, ts: f64, value: &JsValue) -> Result<(), DomException> {
+        self.append_all(
+            series,
+            &[Point {
+                ts,
+                value: value.clone(),
+            }],
+        )
+        .await
+    }
+
+    /// Add several points to the series in one transaction
+    pub async fn append_all(&self, series: &str, points: &[Point]) -> Result<(), DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(&self.store_name)?;
+        for point in points {
+            store.put_key_val(&point_key(series, point.ts), &point.value)?;
+        }
+        tx.await.into_result()
+    }
+
+    /// Get the series' points with timestamps in the given range, in timestamp order
+    pub async fn range(&self, series: &str, range: Range<f64>) -> Result<Vec<Point>, DomException> {
+        let key_range = time_range(series, range)?;
+
+        let tx = self.db.transaction_on_one(&self.store_name)?;
+        let store = tx.object_store(&self.store_name)?;
+        let keys = store.get_all_keys_with_key(&key_range)?;
+        let values = store.get_all_with_key(&key_range)?;
+        let (keys, values) = (keys.await?, values.await?);
+
+        keys.iter()
+            .zip(values.iter())
+            .map(|(key, value)| match <(String, f64)>::from_js_key(key) {
+                Some((_, ts)) => Ok(Point { ts, value }),
+                None => Err(dom_exception(
+                    "Time series key is not a [series, timestamp] pair",
+                    "DataError",
+                )),
+            })
+            .collect()
+    }
+
+    /// Get the series' points in the given range, grouped into buckets of `bucket` milliseconds
+    /// aligned to multiples of `bucket`, e.g. per-minute averages for a chart. Points with
+    /// non-numeric values are skipped and empty buckets are left out.
+    pub async fn downsample(
+        &self,
+        series: &str,
+        range: Range<f64>,
+        bucket: f64,
+        aggregate: Aggregate,
+    ) -> Result<Vec<Bucket>, DomException> {
+        if bucket.is_nan() || bucket <= 0.0 {
+            return Err(dom_exception(
+                "The bucket size must be positive",
+                "DataError",
+            ));
+        }
+        let points = self.range(series, range).await?;
+        Ok(downsample_points(&points, bucket, aggregate))
+    }
+
+    /// Delete the series' points with timestamps in the given range
+    pub async fn delete_range(&self, series: &str, range: Range<f64>) -> Result<(), DomException> {
+        let key_range = time_range(series, range)?;
+        self.delete_key_range(&key_range).await
+    }
+
+    /// Remove the series' points that fall outside the [retention policy][Retention], returning
+    /// how many were removed
+    pub async fn apply_retention(&self, series: &str) -> Result<u32, DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(&self.store_name)?;
+        let all = series_range(series)?;
+        let before = store.count_with_key(&all)?.await?;
+
+        if let Some(max_age) = self.retention.max_age {
+            let cutoff = self.clock.now() - max_age;
+            let expired = IdbKeyRange::bound_with_lower_open_and_upper_open(
+                &series_start(series),
+                &point_key(series, cutoff),
+                false,
+                true,
+            )?;
+            store.delete(&expired)?;
+        }
+
+        if let Some(max_points) = self.retention.max_points {
+            let remaining = store.count_with_key(&all)?.await?;
+            if remaining > max_points {
+                let excess = store
+                    .get_all_keys_with_key_and_limit(&all, remaining - max_points)?
+                    .await?;
+                let oldest =
+                    IdbKeyRange::bound(&series_start(series), &excess.get(excess.length() - 1))?;
+                store.delete(&oldest)?;
+            }
+        }
+
+        let after = store.count_with_key(&all)?.await?;
+        tx.await.into_result()?;
+        Ok(before - after)
+    }
+
+    async fn delete_key_range(&self, key_range: &IdbKeyRange) -> Result<(), DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(&self.store_name, IdbTransactionMode::Readwrite)?;
+        tx.object_store(&self.store_name)?.delete(key_range)?;
+        tx.await.into_result()
+    }
+}
+
+fn point_key(series: &str, ts: f64) -> JsValue {
+    (series.to_string(), ts).to_js_key()
+}
+
+/// The series' keys with timestamps in the range
+fn time_range(series: &str, range: Range<f64>) -> Result<IdbKeyRange, JsValue> {
+    IdbKeyRange::bound_with_lower_open_and_upper_open(
+        &point_key(series, range.start),
+        &point_key(series, range.end),
+        false,
+        true,
+    )
+}
+
+/// `[series]` sorts before any `[series, ts]` key
+fn series_start(series: &str) -> JsValue {
+    js_sys::Array::of1(&series.into()).into()
+}
+
+/// Every key of the series. Arrays sort after numbers, so `[series, []]` follows every
+/// `[series, ts]` key.
+fn series_range(series: &str) -> Result<IdbKeyRange, JsValue> {
+    let end = js_sys::Array::of2(&series.into(), &js_sys::Array::new());
+    IdbKeyRange::bound(&series_start(series), &end)
+}
+
+fn downsample_points(points: &[Point], bucket: f64, aggregate: Aggregate) -> Vec<Bucket> {
+    let mut out: Vec<Bucket> = Vec::new();
+    for point in points {
+        let value = match point.value.as_f64() {
+            Some(value) => value,
+            None => continue,
+        };
+        let start = (point.ts / bucket).floor() * bucket;
+        match out.last_mut() {
+            Some(last) if last.start == start => {
+                last.count += 1;
+                last.value = match aggregate {
+                    Aggregate::Mean => last.value + (value - last.value) / f64::from(last.count),
+                    Aggregate::Min => last.value.min(value),
+                    Aggregate::Max => last.value.max(value),
+                    Aggregate::Sum => last.value + value,
+                    Aggregate::Count => f64::from(last.count),
+                    Aggregate::First => last.value,
+                    Aggregate::Last => value,
+                };
+            }
+            _ => out.push(Bucket {
+                start,
+                value: if aggregate == Aggregate::Count {
+                    1.0
+                } else {
+                    value
+                },
+                count: 1,
+            }),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::test_utils::open_any_db;
+
+    use super::*;
+
+    test_mod_init!();
+
+    fn ts_of(points: &[Point]) -> Vec<f64> {
+        points.iter().map(|p| p.ts).collect()
+    }
+
+    test_case!(async append_and_range => {
+        let (db, store_name) = open_any_db().await;
+        let series = TimeSeriesStore::new(&db, &store_name);
+        series.append("cpu", 20.0, &JsValue::from(2u8)).await.expect("append 20");
+        series.append("cpu", 10.0, &JsValue::from(1u8)).await.expect("append 10");
+        series.append("mem", 15.0, &JsValue::from(9u8)).await.expect("append mem");
+        series.append("cpu", 30.0, &JsValue::from(3u8)).await.expect("append 30");
+
+        let cpu = series.range("cpu", 0.0..30.0).await.expect("range");
+        assert_eq!(ts_of(&cpu), vec![10.0, 20.0], "cpu");
+        assert_eq!(cpu[0].value, JsValue::from(1u8), "value");
+
+        let mem = series.range("mem", f64::NEG_INFINITY..f64::INFINITY).await.expect("mem");
+        assert_eq!(ts_of(&mem), vec![15.0], "mem");
+
+        series.delete_range("cpu", 15.0..25.0).await.expect("delete_range");
+        let cpu = series.range("cpu", 0.0..100.0).await.expect("range after delete");
+        assert_eq!(ts_of(&cpu), vec![10.0, 30.0], "after delete");
+    });
+
+    test_case!(async downsample => {
+        let (db, store_name) = open_any_db().await;
+        let series = TimeSeriesStore::new(&db, &store_name);
+        let points: Vec<Point> = [(0.0, 1.0), (5.0, 3.0), (10.0, 10.0), (25.0, 4.0), (27.0, 8.0)]
+            .iter()
+            .map(|&(ts, v)| Point { ts, value: JsValue::from(v) })
+            .collect();
+        series.append_all("s", &points).await.expect("append_all");
+        series.append("s", 12.0, &JsValue::from("n/a")).await.expect("append non-numeric");
+
+        let mean = series.downsample("s", 0.0..100.0, 10.0, Aggregate::Mean).await.expect("mean");
+        let summary: Vec<(f64, f64, u32)> = mean.iter().map(|b| (b.start, b.value, b.count)).collect();
+        assert_eq!(summary, vec![(0.0, 2.0, 2), (10.0, 10.0, 1), (20.0, 6.0, 2)], "mean");
+
+        let max = series.downsample("s", 0.0..100.0, 10.0, Aggregate::Max).await.expect("max");
+        assert_eq!(max.iter().map(|b| b.value).collect::<Vec<_>>(), vec![3.0, 10.0, 8.0], "max");
+
+        let count = series.downsample("s", 0.0..100.0, 30.0, Aggregate::Count).await.expect("count");
+        assert_eq!(count.iter().map(|b| b.value).collect::<Vec<_>>(), vec![5.0], "count");
+
+        assert!(series.downsample("s", 0.0..100.0, 0.0, Aggregate::Sum).await.is_err(), "bucket 0");
+    });
+
+    test_case!(async retention => {
+        let (db, store_name) = open_any_db().await;
+        let now = js_sys::Date::now();
+        let series = TimeSeriesStore::new(&db, &store_name)
+            .with_retention(Retention::new().max_age(60_000.0).max_points(2));
+        for (i, age) in [120_000.0, 30_000.0, 20_000.0, 10_000.0].iter().enumerate() {
+            series.append("s", now - age, &JsValue::from(i as u32)).await.expect("append");
+        }
+        series.append("other", now - 120_000.0, &JsValue::from(0u8)).await.expect("append other");
+
+        let removed = series.apply_retention("s").await.expect("apply_retention");
+        assert_eq!(removed, 2, "removed");
+
+        let left = series.range("s", f64::NEG_INFINITY..f64::INFINITY).await.expect("range");
+        let values: Vec<JsValue> = left.into_iter().map(|p| p.value).collect();
+        assert_eq!(values, vec![JsValue::from(2u32), JsValue::from(3u32)], "left");
+
+        let other = series.range("other", f64::NEG_INFINITY..f64::INFINITY).await.expect("other");
+        assert_eq!(other.len(), 1, "other series untouched");
+    });
+
+    test_case!(async retention_with_fake_clock => {
+        let (db, store_name) = open_any_db().await;
+        let clock = crate::clock::FakeClock::new(1_000_000.0);
+        let series = TimeSeriesStore::new(&db, &store_name)
+            .with_retention(Retention::new().max_age(60_000.0))
+            .with_clock(clock.clone());
+        series.append("s", 990_000.0, &JsValue::from(1u8)).await.expect("append");
+        assert_eq!(series.apply_retention("s").await.expect("fresh"), 0, "fresh");
+
+        clock.advance(std::time::Duration::from_secs(60));
+        assert_eq!(series.apply_retention("s").await.expect("expired"), 1, "expired");
+    });
+}
@@ -0,0 +1,78 @@
+//! Value validation on write
+//!
+//! Features required: `serde`
+//!
+//! Implement [Validate] for record types and write them via
+//! [TypedObjectStore::put_validated][crate::idb_object_store::TypedObjectStore::put_validated]
+//! or [add_validated][crate::idb_object_store::TypedObjectStore::add_validated] to reject
+//! malformed records before they reach the store.
+
+use std::fmt;
+
+/// A record type that can check itself before being written
+///
+/// Features required: `serde`
+pub trait Validate {
+    /// Check the record, listing every problem found
+    fn validate(&self) -> Result<(), ValidationError>;
+}
+
+/// A single problem found while validating a record
+///
+/// Features required: `serde`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldProblem {
+    /// The offending field, as a dot-separated path
+    pub field: String,
+    /// What's wrong with the field
+    pub message: String,
+}
+
+/// A record failed validation
+///
+/// Features required: `serde`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ValidationError {
+    /// The problems found
+    pub problems: Vec<FieldProblem>,
+}
+
+impl ValidationError {
+    /// Create an error with no problems
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a problem
+    pub fn problem(mut self, field: &str, message: &str) -> Self {
+        self.problems.push(FieldProblem {
+            field: field.into(),
+            message: message.into(),
+        });
+        self
+    }
+
+    /// `Ok` if no problems were added, `Err(self)` otherwise. Handy for collecting problems and
+    /// returning them all at once from [Validate::validate].
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.problems.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Validation failed")?;
+        for (i, problem) in self.problems.iter().enumerate() {
+            let sep = if i == 0 { ": " } else { "; " };
+            write!(f, "{}`{}` {}", sep, problem.field, problem.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
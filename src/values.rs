@@ -0,0 +1,273 @@
+//! Helpers for building and reading back dynamic, schemaless store values
+//!
+//! `js_sys::Map`s can be stored as they are, but key paths can't see into them, so documents
+//! meant to be indexed should be plain objects. These helpers convert between plain objects and
+//! Rust maps, JS maps and, with the `serde_json` feature, `serde_json::Value`s.
+//!
+//! ## Serde encoding
+//!
+//! With the `serde` feature, [to_value] and [from_value] convert between Rust types and store
+//! values. They're what the serde-based [TypedObjectStore][crate::idb_object_store::TypedObjectStore]
+//! methods and the default [Codec][crate::idb_object_store::Codec] use, and encode values the
+//! way `serde_json` would, as plain JS values:
+//!
+//! | Rust | Written as | Read back from |
+//! |------|------------|----------------|
+//! | `None`, `()` and unit structs | `null` | `null` or `undefined` |
+//! | `Some(x)` | `x` | anything but `null` and `undefined` |
+//! | `Option` struct fields | always present, `null` if `None` | a missing property, `null` or `undefined` |
+//! | structs | plain objects | plain objects |
+//! | maps | plain objects, so that key paths can see into them | plain objects or [Map][js_sys::Map]s |
+//! | unit enum variants, e.g. `Status::Active` | the variant name, `"Active"` | the variant name |
+//! | other enum variants | `{"Variant": content}` | the same |
+//! | `#[serde(tag = "type")]` enums | `{"type": "Variant", ...fields}` | the same |
+//! | `#[serde(untagged)]` enums | the content | the first variant that matches |
+//!
+//! Because `None` is written as `null`, a stored `Option<T>` can tell a `None` record apart from a
+//! missing one: reading it back as `Option<T>` through
+//! [get_de][crate::idb_object_store::TypedObjectStore::get_de] results in `Some(None)` for the
+//! former and `None` for the latter. Fields marked
+//! `#[serde(skip_serializing_if = "Option::is_none")]` get left out instead, which keeps them out
+//! of indices, as records without a value at an index's key path don't get indexed.
+
+use std::collections::HashMap;
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::DomException;
+
+/// Build a plain object from string keys and JS values
+pub fn object_from_entries<I, K>(entries: I) -> js_sys::Object
+where
+    I: IntoIterator<Item = (K, JsValue)>,
+    K: AsRef<str>,
+{
+    let obj = js_sys::Object::new();
+    for (key, value) in entries {
+        // Setting a property on a fresh plain object can't fail
+        let _ = js_sys::Reflect::set(&obj, &JsValue::from_str(key.as_ref()), &value);
+    }
+    obj
+}
+
+/// Build a plain object from a map of JS values
+#[inline]
+pub fn object_from_map(map: &HashMap<String, JsValue>) -> js_sys::Object {
+    object_from_entries(map.iter().map(|(k, v)| (k, v.clone())))
+}
+
+/// Read a plain object's own enumerable properties into a map. Returns `None` if the value isn't
+/// an object.
+pub fn object_to_map(value: &JsValue) -> Option<HashMap<String, JsValue>> {
+    let obj: &js_sys::Object = value.dyn_ref()?;
+    let entries = js_sys::Object::entries(obj);
+    let mut out = HashMap::with_capacity(entries.length() as usize);
+    for entry in entries.iter() {
+        let entry: js_sys::Array = entry.unchecked_into();
+        out.insert(entry.get(0).as_string()?, entry.get(1));
+    }
+    Some(out)
+}
+
+/// Build a plain object from a JS map. Non-string keys get converted to strings.
+pub fn object_from_js_map(map: &js_sys::Map) -> Result<js_sys::Object, DomException> {
+    Ok(js_sys::Object::from_entries(map)?)
+}
+
+/// Read a plain object's own enumerable properties into a JS map. Returns `None` if the value
+/// isn't an object.
+pub fn object_to_js_map(value: &JsValue) -> Option<js_sys::Map> {
+    let obj: &js_sys::Object = value.dyn_ref()?;
+    let map = js_sys::Map::new();
+    for entry in js_sys::Object::entries(obj).iter() {
+        let entry: js_sys::Array = entry.unchecked_into();
+        map.set(&entry.get(0), &entry.get(1));
+    }
+    Some(map)
+}
+
+/// Serialise a value into a store value with the [serde encoding][self#serde-encoding]
+///
+/// Features required: `serde`
+#[cfg(feature = "serde")]
+#[inline]
+pub fn to_value<T: serde::Serialize + ?Sized>(
+    value: &T,
+) -> Result<JsValue, serde_wasm_bindgen::Error> {
+    value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+}
+
+/// Deserialise a store value with the [serde encoding][self#serde-encoding]
+///
+/// Features required: `serde`
+#[cfg(feature = "serde")]
+#[inline]
+pub fn from_value<T: serde::de::DeserializeOwned>(
+    value: JsValue,
+) -> Result<T, serde_wasm_bindgen::Error> {
+    serde_wasm_bindgen::from_value(value)
+}
+
+/// Convert a JSON value into a store value, with JSON objects becoming plain objects
+///
+/// Features required: `serde_json`
+#[cfg(feature = "serde_json")]
+#[inline]
+pub fn from_json(value: &serde_json::Value) -> Result<JsValue, serde_wasm_bindgen::Error> {
+    to_value(value)
+}
+
+/// Convert a store value back into a JSON value
+///
+/// Features required: `serde_json`
+#[cfg(feature = "serde_json")]
+#[inline]
+pub fn to_json(value: &JsValue) -> Result<serde_json::Value, serde_wasm_bindgen::Error> {
+    from_value(value.clone())
+}
+
+#[cfg(test)]
+pub mod test {
+    test_mod_init!();
+
+    fn sample_map() -> HashMap<String, JsValue> {
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), JsValue::from(1u8));
+        map.insert("b".to_string(), JsValue::from_str("x"));
+        map
+    }
+
+    test_case!(map_round_trip => {
+        let obj = object_from_map(&sample_map());
+        assert_eq!(js_sys::Reflect::get(&obj, &"a".into()).unwrap(), JsValue::from(1u8));
+        assert_eq!(object_to_map(&obj), Some(sample_map()));
+    });
+
+    test_case!(js_map_round_trip => {
+        let map = js_sys::Map::new();
+        map.set(&"a".into(), &JsValue::from(1u8));
+        map.set(&JsValue::from(2u8), &JsValue::from_str("x"));
+
+        let obj = object_from_js_map(&map).expect("object_from_js_map");
+        assert_eq!(js_sys::Reflect::get(&obj, &"2".into()).unwrap(), JsValue::from_str("x"));
+
+        let back = object_to_js_map(&obj).expect("object_to_js_map");
+        assert_eq!(back.size(), 2, "size");
+        assert_eq!(back.get(&"a".into()), JsValue::from(1u8));
+    });
+
+    test_case!(non_objects => {
+        assert!(object_to_map(&JsValue::from(1u8)).is_none(), "map");
+        assert!(object_to_js_map(&JsValue::NULL).is_none(), "js map");
+    });
+
+    #[cfg(feature = "serde_json")]
+    test_case!(json_round_trip => {
+        let json = serde_json::json!({"a": 1, "b": {"c": [true, null, "x"]}});
+        let js = from_json(&json).expect("from_json");
+        assert!(js_sys::Reflect::get(&js, &"b".into()).unwrap().is_object(), "plain object");
+        assert_eq!(to_json(&js).expect("to_json"), json);
+    });
+
+    #[cfg(feature = "serde")]
+    pub mod serde_encoding {
+        use serde::{Deserialize, Serialize};
+
+        use crate::prelude::*;
+        use crate::test_utils::open_any_db;
+        use crate::values::{from_value, to_value};
+
+        test_mod_init!();
+
+        #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+        enum Status {
+            Active,
+            Banned,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        enum Shape {
+            Point,
+            Circle(f64),
+            Rect { w: f64, h: f64 },
+        }
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        #[serde(tag = "type")]
+        enum Event {
+            Login { user: String },
+            Logout,
+        }
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        struct Record {
+            nickname: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            email: Option<String>,
+            status: Status,
+        }
+
+        fn prop(value: &JsValue, name: &str) -> JsValue {
+            js_sys::Reflect::get(value, &name.into()).unwrap()
+        }
+
+        fn json(value: &JsValue) -> String {
+            js_sys::JSON::stringify(value).unwrap().as_string().unwrap()
+        }
+
+        fn round_trip<T>(value: T) -> JsValue
+        where
+            T: Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+        {
+            let js = to_value(&value).expect("to_value");
+            assert_eq!(
+                from_value::<T>(js.clone()).expect("from_value"),
+                value,
+                "round trip"
+            );
+            js
+        }
+
+        test_case!(options => {
+            assert!(round_trip(None::<u8>).is_null(), "None");
+            assert_eq!(round_trip(Some(1u8)), JsValue::from(1u8), "Some");
+            assert!(round_trip(()).is_null(), "unit");
+            assert_eq!(from_value::<Option<u8>>(JsValue::UNDEFINED).unwrap(), None, "undefined");
+
+            let record = Record { nickname: None, email: None, status: Status::Active };
+            let js = round_trip(record.clone());
+            assert!(prop(&js, "nickname").is_null(), "None field");
+            assert!(!js_sys::Reflect::has(&js, &"email".into()).unwrap(), "skipped field");
+
+            let missing = js_sys::JSON::parse(r#"{"status":"Active"}"#).unwrap();
+            assert_eq!(from_value::<Record>(missing).unwrap(), record, "missing fields");
+            let undefined = js_sys::JSON::parse(r#"{"status":"Active"}"#).unwrap();
+            js_sys::Reflect::set(&undefined, &"nickname".into(), &JsValue::UNDEFINED).unwrap();
+            assert_eq!(from_value::<Record>(undefined).unwrap(), record, "undefined field");
+        });
+
+        test_case!(enums => {
+            assert_eq!(round_trip(Status::Banned), JsValue::from("Banned"), "unit variant");
+            assert_eq!(json(&round_trip(Shape::Point)), r#""Point""#, "unit variant of data enum");
+            assert_eq!(json(&round_trip(Shape::Circle(1.5))), r#"{"Circle":1.5}"#, "newtype variant");
+            assert_eq!(json(&round_trip(Shape::Rect { w: 1.0, h: 2.0 })), r#"{"Rect":{"w":1,"h":2}}"#, "struct variant");
+            let login = round_trip(Event::Login { user: "a".into() });
+            assert_eq!(json(&login), r#"{"type":"Login","user":"a"}"#, "internally tagged");
+            assert_eq!(json(&round_trip(Event::Logout)), r#"{"type":"Logout"}"#, "internally tagged unit");
+        });
+
+        test_case!(async stored_options => {
+            let (db, store_name) = open_any_db().await;
+            let tx = db.transaction_on_one_with_mode(&store_name, IdbTransactionMode::Readwrite).unwrap();
+            let store = tx.object_store(&store_name).unwrap().typed::<String>();
+            store.put_ser(&"none".to_string(), &None::<Status>).unwrap();
+            store.put_ser(&"some".to_string(), &Some(Status::Active)).unwrap();
+
+            let none: Option<Option<Status>> = store.get_de(&"none".to_string()).unwrap().await.unwrap();
+            let some: Option<Option<Status>> = store.get_de(&"some".to_string()).unwrap().await.unwrap();
+            let missing: Option<Option<Status>> = store.get_de(&"missing".to_string()).unwrap().await.unwrap();
+            assert_eq!(none, Some(None), "stored None");
+            assert_eq!(some, Some(Some(Status::Active)), "stored Some");
+            assert_eq!(missing, None, "missing record");
+        });
+    }
+}
@@ -0,0 +1,236 @@
+//! Erasing all of the origin's local data, e.g. on log out
+//!
+//! [wipe_all] closes the connections the crate knows about, deletes every database listed by
+//! [IdbDatabase::databases] and, with the `cache-storage` feature, can clear Cache Storage as well.
+//! Getting this right by hand is fiddly: a deletion only completes once every connection to the
+//! database has been closed, so a single forgotten connection leaves the wipe hanging; and a wipe
+//! that runs against the wrong origin, e.g. because of a misconfigured test or a shared dev
+//! server, erases someone else's data.
+//!
+//! For the former, a deletion that gets blocked is only waited for up to a
+//! [timeout][WipeOptions::blocked_timeout]; the wipe then moves on and reports the database as
+//! [blocked][WipeReport::blocked_databases] instead of hanging.
+//!
+//! To guard against the latter, the wipe requires the current origin, e.g.
+//! `https://app.example.com`, as a confirmation token and refuses to run if it doesn't match.
+//!
+//! ```rust
+//! use indexed_db_futures::prelude::*;
+//! use indexed_db_futures::wipe::WipeOptions;
+//! use web_sys::DomException;
+//!
+//! async fn log_out(db: IdbDatabase) -> Result<Vec<String>, DomException> {
+//!     let report = WipeOptions::new("https://app.example.com")
+//!         .close(db)
+//!         .run()
+//!         .await?;
+//!     Ok(report.deleted_databases)
+//! }
+//! ```
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use wasm_bindgen::prelude::*;
+use web_sys::DomException;
+
+use crate::idb_database::{close_shared, IdbDatabase};
+use crate::internal_utils::{dom_exception, WithTimeout};
+use crate::request::IdbOpenDbRequestLike;
+
+/// What a wipe deleted
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WipeReport {
+    /// The number of connections that got closed
+    pub closed_connections: usize,
+    /// The names of the deleted databases
+    pub deleted_databases: Vec<String>,
+    /// The names of the databases whose deletion got blocked by a connection left open and
+    /// didn't complete within the [blocked timeout][WipeOptions::blocked_timeout]. The deletion
+    /// stays queued and completes once those connections are closed.
+    pub blocked_databases: Vec<String>,
+    /// The names of the deleted Cache Storage caches
+    pub deleted_caches: Vec<String>,
+}
+
+/// Options for [wiping][WipeOptions::run] the origin's data
+#[derive(Debug)]
+pub struct WipeOptions {
+    origin_confirm_token: String,
+    connections: Vec<IdbDatabase>,
+    blocked_timeout: Duration,
+    #[cfg(feature = "cache-storage")]
+    clear_caches: bool,
+}
+
+impl WipeOptions {
+    /// Wipe the data of the given origin, which must be the current one
+    pub fn new(origin_confirm_token: &str) -> Self {
+        Self {
+            origin_confirm_token: origin_confirm_token.into(),
+            connections: Vec::new(),
+            blocked_timeout: Duration::from_secs(1),
+            #[cfg(feature = "cache-storage")]
+            clear_caches: false,
+        }
+    }
+
+    /// Close the given connection before deleting the databases. Connections opened through
+    /// [IdbDatabase::open_shared] get closed anyway; any other connection left open blocks the
+    /// deletion of its database until it's closed.
+    pub fn close(mut self, db: IdbDatabase) -> Self {
+        self.connections.push(db);
+        self
+    }
+
+    /// How long to wait for the deletion of a database that's blocked by a connection left open
+    /// before reporting it as [blocked][WipeReport::blocked_databases]. Defaults to 1 second.
+    #[inline]
+    pub fn blocked_timeout(mut self, blocked_timeout: Duration) -> Self {
+        self.blocked_timeout = blocked_timeout;
+        self
+    }
+
+    /// Delete every Cache Storage cache too. Defaults to `false`.
+    ///
+    /// Features required: `cache-storage`
+    #[cfg(feature = "cache-storage")]
+    #[inline]
+    pub fn clear_caches(mut self, clear_caches: bool) -> Self {
+        self.clear_caches = clear_caches;
+        self
+    }
+
+    /// Run the wipe. Fails with a `NotAllowedError` without touching anything if the token isn't
+    /// the current origin, and with a `NotSupportedError` if the browser can't list its
+    /// databases.
+    pub async fn run(self) -> Result<WipeReport, DomException> {
+        let origin = current_origin();
+        if origin.as_deref() != Some(self.origin_confirm_token.as_str()) {
+            return Err(dom_exception(
+                &format!(
+                    "Refusing to wipe {}: the current origin is {}",
+                    self.origin_confirm_token,
+                    origin.as_deref().unwrap_or("unknown")
+                ),
+                "NotAllowedError",
+            ));
+        }
+
+        let databases = IdbDatabase::databases().await?;
+
+        let mut report = WipeReport {
+            closed_connections: self.connections.len() + close_shared(),
+            ..WipeReport::default()
+        };
+        for db in self.connections {
+            db.close();
+        }
+        for info in databases {
+            let blocked = Rc::new(Cell::new(false));
+            let req = IdbDatabase::delete_by_name(&info.name)?.with_on_blocked({
+                let blocked = blocked.clone();
+                move |_| {
+                    blocked.set(true);
+                    Ok(())
+                }
+            });
+            let mut deletion = Box::pin(req.into_future());
+            let outcome = match WithTimeout::new(&mut deletion, Some(self.blocked_timeout))?.await {
+                Some(outcome) => outcome,
+                None if blocked.get() => {
+                    report.blocked_databases.push(info.name);
+                    continue;
+                }
+                None => deletion.await,
+            };
+            outcome?;
+            report.deleted_databases.push(info.name);
+        }
+
+        #[cfg(feature = "cache-storage")]
+        if self.clear_caches {
+            report.deleted_caches = delete_caches().await?;
+        }
+        Ok(report)
+    }
+}
+
+/// Close connections, then delete every database of the current origin, which must match the
+/// token; the same as [WipeOptions::new] followed by [run][WipeOptions::run]
+pub async fn wipe_all(origin_confirm_token: &str) -> Result<WipeReport, DomException> {
+    WipeOptions::new(origin_confirm_token).run().await
+}
+
+/// The origin of the current window or worker
+fn current_origin() -> Option<String> {
+    js_sys::Reflect::get(&js_sys::global(), &"origin".into())
+        .ok()?
+        .as_string()
+}
+
+#[cfg(feature = "cache-storage")]
+async fn delete_caches() -> Result<Vec<String>, DomException> {
+    use wasm_bindgen::JsCast;
+
+    use crate::internal_utils::{await_promise, js_error_into_dom_exception};
+
+    let caches: web_sys::CacheStorage = js_sys::Reflect::get(&js_sys::global(), &"caches".into())
+        .map_err(js_error_into_dom_exception)?
+        .unchecked_into();
+    let names: js_sys::Array = await_promise(caches.keys()).await?.unchecked_into();
+    let mut deleted = Vec::with_capacity(names.length() as usize);
+    for name in names.iter().filter_map(|name| name.as_string()) {
+        await_promise(caches.delete(&name)).await?;
+        deleted.push(name);
+    }
+    Ok(deleted)
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::prelude::*;
+    use crate::test_utils::open_db_with_stores;
+
+    use super::*;
+
+    test_mod_init!();
+
+    test_case!(async wipe_all => {
+        let err = super::wipe_all("https://not-this-origin.example").await.expect_err("wrong origin");
+        assert_eq!(err.name(), "NotAllowedError", "refused");
+
+        let db = open_db_with_stores(&[]).await;
+        let name = db.name();
+        let shared = IdbDatabase::open_shared(&format!("{}-shared", name), 1, None::<fn(&IdbVersionChangeEvent) -> Result<(), JsValue>>)
+            .await
+            .expect("open shared");
+
+        let report = WipeOptions::new(&current_origin().expect("origin"))
+            .close(db)
+            .run()
+            .await
+            .expect("wipe");
+        assert!(report.closed_connections >= 2, "closed");
+        assert!(report.deleted_databases.contains(&name), "deleted");
+        assert!(report.deleted_databases.contains(&shared.name()), "deleted shared");
+
+        let left = IdbDatabase::databases().await.expect("databases");
+        assert!(left.iter().all(|info| info.name != name), "gone");
+    });
+
+    test_case!(async blocked => {
+        let db = open_db_with_stores(&[]).await;
+        let name = db.name();
+
+        let report = WipeOptions::new(&current_origin().expect("origin"))
+            .blocked_timeout(Duration::from_millis(50))
+            .run()
+            .await
+            .expect("wipe");
+        assert!(report.blocked_databases.contains(&name), "blocked");
+        assert!(!report.deleted_databases.contains(&name), "not deleted");
+        db.close();
+    });
+}